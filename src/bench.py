@@ -0,0 +1,45 @@
+"""Cook time benchmark run with hython by 'cargo hdk bench'.
+
+The benchmark is given by the CARGO_HDK_BENCH_* environment variables. The node is cooked once to
+cook its inputs and warm up caches, then forced to cook the given number of times. The cook time
+of each iteration is written to the output file in seconds, one per line.
+"""
+
+import os
+import runpy
+import sys
+import time
+
+import hou
+
+
+def main():
+    env = os.environ
+    hip = env.get("CARGO_HDK_BENCH_HIP")
+    script = env.get("CARGO_HDK_BENCH_SCRIPT")
+    node_path = env["CARGO_HDK_BENCH_NODE"]
+    iterations = int(env["CARGO_HDK_BENCH_ITERATIONS"])
+    output = env["CARGO_HDK_BENCH_OUTPUT"]
+
+    if hip:
+        hou.hipFile.load(hip, suppress_save_prompt=True, ignore_load_warnings=True)
+    if script:
+        runpy.run_path(script, run_name="__main__")
+
+    node = hou.node(node_path)
+    if node is None:
+        print("Node %s doesn't exist." % node_path)
+        sys.exit(1)
+
+    node.cook(force=True)
+    times = []
+    for _ in range(iterations):
+        start = time.perf_counter()
+        node.cook(force=True)
+        times.append(time.perf_counter() - start)
+
+    with open(output, "w") as f:
+        f.write("".join("%r\n" % t for t in times))
+
+
+main()
@@ -0,0 +1,219 @@
+//! Cook time benchmarks of the plugin run with hython using `cargo hdk bench`.
+//!
+//! Benchmarks are declared in the `benches` table of the configuration. Each one loads a scene
+//! and/or runs a script building a node network, and times repeated forced cooks of a node. The
+//! results are compared with a baseline file, which is written with `--save-baseline`, and the
+//! command fails if a benchmark got slower than its threshold allows.
+
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use anyhow::{Context, Result};
+use log::*;
+use serde::{Deserialize, Serialize};
+
+use crate::config::Bench;
+use crate::launch::App;
+
+/// Script timing the cooks of a benchmark.
+const DRIVER: &str = include_str!("bench.py");
+
+/// Default number of timed cooks.
+const DEFAULT_ITERATIONS: u32 = 10;
+
+/// Default slowdown of the mean cook time relative to the baseline in percent that is reported
+/// as a regression.
+const DEFAULT_THRESHOLD: f64 = 10.0;
+
+/// Cook time statistics of a benchmark in milliseconds.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub struct Stats {
+    pub mean_ms: f64,
+    pub min_ms: f64,
+    pub max_ms: f64,
+}
+
+impl Stats {
+    fn from_times(times: &[f64]) -> Stats {
+        let ms = times.iter().map(|t| t * 1000.0);
+        Stats {
+            mean_ms: ms.clone().sum::<f64>() / times.len() as f64,
+            min_ms: ms.clone().fold(f64::INFINITY, f64::min),
+            max_ms: ms.fold(0.0, f64::max),
+        }
+    }
+}
+
+/// Check that each benchmark describes how to build its node network.
+pub fn validate(benches: &BTreeMap<String, Bench>) -> Result<()> {
+    for (name, bench) in benches {
+        if bench.hip.is_none() && bench.script.is_none() {
+            bail!(
+                "Benchmark '{}' needs a 'hip' file or a 'script' building the node network.",
+                name
+            );
+        }
+        if bench.iterations == Some(0) {
+            bail!("Benchmark '{}' needs at least one iteration.", name);
+        }
+    }
+    Ok(())
+}
+
+/// Read the baseline results, which are empty if the file doesn't exist yet.
+fn read_baseline(path: &Path) -> Result<BTreeMap<String, Stats>> {
+    match fs::read_to_string(path) {
+        Ok(contents) => toml::from_str(&contents)
+            .with_context(|| format!("Failed to parse benchmark baseline {}", path.display())),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+        Err(err) => Err(err)
+            .with_context(|| format!("Failed to read benchmark baseline {}", path.display())),
+    }
+}
+
+/// Run a single benchmark, returning the cook time of each iteration in seconds.
+fn run_one(
+    hython: &Path,
+    name: &str,
+    bench: &Bench,
+    driver: &Path,
+    dir: &Path,
+    out_dir: &Path,
+    envs: &[(&str, OsString)],
+) -> Result<Vec<f64>> {
+    let output_path = out_dir.join(format!("{}.times", name));
+    let iterations = bench.iterations.unwrap_or(DEFAULT_ITERATIONS);
+    let mut cmd = Command::new(hython);
+    cmd.arg(driver)
+        .current_dir(dir)
+        .envs(envs.iter().map(|(key, value)| (key, value)))
+        .env("CARGO_HDK_BENCH_NODE", &bench.node)
+        .env("CARGO_HDK_BENCH_ITERATIONS", iterations.to_string())
+        .env("CARGO_HDK_BENCH_OUTPUT", &output_path);
+    if let Some(hip) = &bench.hip {
+        cmd.env("CARGO_HDK_BENCH_HIP", hip);
+    }
+    if let Some(script) = &bench.script {
+        cmd.env("CARGO_HDK_BENCH_SCRIPT", script);
+    }
+    debug!("Running {:?}", cmd);
+    let output = cmd
+        .output()
+        .with_context(|| format!("Failed to run {}", hython.display()))?;
+
+    let mut log = output.stdout;
+    log.extend_from_slice(&output.stderr);
+    let log_path = out_dir.join(format!("{}.log", name));
+    fs::write(&log_path, &log)
+        .with_context(|| format!("Failed to write benchmark log {}", log_path.display()))?;
+    if !output.status.success() {
+        bail!(
+            "Benchmark '{}' failed with {}:\n{}",
+            name,
+            output.status,
+            String::from_utf8_lossy(&log)
+        );
+    }
+
+    let times = fs::read_to_string(&output_path)
+        .with_context(|| format!("Failed to read benchmark results {}", output_path.display()))?;
+    times
+        .lines()
+        .map(|line| {
+            line.trim().parse().with_context(|| {
+                format!("Invalid cook time '{}' in {}", line, output_path.display())
+            })
+        })
+        .collect()
+}
+
+/// Run the benchmarks with hython and print a comparison with the baseline.
+///
+/// The driver script, logs and raw cook times are written to `out_dir`. With `save_baseline`, the
+/// results are written to the baseline file instead of failing on regressions.
+pub fn run(
+    hfs: &str,
+    benches: &BTreeMap<String, Bench>,
+    dir: &Path,
+    out_dir: &Path,
+    envs: &[(&str, OsString)],
+    baseline_path: &Path,
+    save_baseline: bool,
+) -> Result<()> {
+    let hython = App::Hython.path(hfs);
+    fs::create_dir_all(out_dir).with_context(|| {
+        format!(
+            "Failed to create benchmark directory: {}",
+            out_dir.display()
+        )
+    })?;
+    let driver = &out_dir.join("bench.py");
+    fs::write(driver, DRIVER)
+        .with_context(|| format!("Failed to write benchmark driver {}", driver.display()))?;
+    let mut baseline = read_baseline(baseline_path)?;
+
+    println!();
+    println!(
+        "{:<24} {:>12} {:>12} {:>12} {:>12} {:>9}",
+        "benchmark", "mean", "min", "max", "baseline", "change"
+    );
+    let mut regressions = Vec::new();
+    for (name, bench) in benches {
+        info!("Running benchmark {}.", name);
+        let times = run_one(&hython, name, bench, driver, dir, out_dir, envs)?;
+        let stats = Stats::from_times(&times);
+        let (base, change, regressed) = match baseline.get(name) {
+            Some(base) => {
+                let change = (stats.mean_ms / base.mean_ms - 1.0) * 100.0;
+                let threshold = bench.threshold.unwrap_or(DEFAULT_THRESHOLD);
+                (
+                    format!("{:.3} ms", base.mean_ms),
+                    format!("{:+.1}%", change),
+                    change > threshold,
+                )
+            }
+            None => ("-".to_string(), "-".to_string(), false),
+        };
+        println!(
+            "{:<24} {:>12} {:>12} {:>12} {:>12} {:>9}{}",
+            name,
+            format!("{:.3} ms", stats.mean_ms),
+            format!("{:.3} ms", stats.min_ms),
+            format!("{:.3} ms", stats.max_ms),
+            base,
+            change,
+            if regressed { "  REGRESSED" } else { "" }
+        );
+        if regressed {
+            regressions.push(name.as_str());
+        }
+        baseline.insert(name.clone(), stats);
+    }
+    println!();
+
+    if save_baseline {
+        if let Some(dir) = baseline_path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create directory {}", dir.display()))?;
+        }
+        let contents =
+            toml::to_string(&baseline).context("Failed to serialize the benchmark baseline")?;
+        fs::write(baseline_path, contents).with_context(|| {
+            format!(
+                "Failed to write benchmark baseline {}",
+                baseline_path.display()
+            )
+        })?;
+        println!("Saved the baseline to {}", baseline_path.display());
+    } else if !regressions.is_empty() {
+        bail!(
+            "Benchmarks slower than the baseline {}: {}",
+            baseline_path.display(),
+            regressions.join(", ")
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,40 @@
+//! Reading the little-endian integers of binary formats like ELF and PE.
+
+/// The `u16` at `offset` of `data`, or None if `data` is too short.
+pub fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    let bytes = data.get(offset..offset.checked_add(2)?)?;
+    Some(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+/// The `u32` at `offset` of `data`, or None if `data` is too short.
+pub fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset.checked_add(4)?)?;
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/// The `u64` at `offset` of `data`, or None if `data` is too short.
+pub fn read_u64(data: &[u8], offset: usize) -> Option<u64> {
+    Some(read_u32(data, offset)? as u64 | (read_u32(data, offset.checked_add(4)?)? as u64) << 32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn little_endian_integers() {
+        let data = [0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08];
+        assert_eq!(read_u16(&data, 0), Some(0x0201));
+        assert_eq!(read_u32(&data, 4), Some(0x0807_0605));
+        assert_eq!(read_u64(&data, 0), Some(0x0807_0605_0403_0201));
+    }
+
+    #[test]
+    fn out_of_bounds() {
+        let data = [0u8; 4];
+        assert_eq!(read_u16(&data, 3), None);
+        assert_eq!(read_u32(&data, 1), None);
+        assert_eq!(read_u64(&data, 0), None);
+        assert_eq!(read_u32(&data, usize::MAX), None);
+    }
+}
@@ -0,0 +1,173 @@
+//! Syncing the C and C++ headers generated for the Rust code into the HDK plugin with
+//! `cargo hdk bindgen`.
+//!
+//! Build scripts using cxx or cbindgen write their headers to the 'OUT_DIR', which changes with
+//! the profile and target and isn't known to IDEs. The headers found in the exported 'OUT_DIR's
+//! are copied to 'include/generated' in the HDK plugin directory instead, keeping their include
+//! paths, e.g. '<crate>/src/lib.rs.h' and 'rust/cxx.h' for cxx. Crates with a 'cbindgen.toml'
+//! whose build script doesn't generate headers get them from the cbindgen CLI.
+//!
+//! Only changed headers are written, so the C++ code isn't rebuilt needlessly, and headers that
+//! are no longer generated are removed.
+
+use std::collections::BTreeMap;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{Context, Result};
+use cargo_metadata::camino::Utf8PathBuf;
+use log::*;
+
+use crate::tools;
+
+/// The directory of the generated headers relative to the HDK plugin directory.
+pub const GENERATED_DIR: &str = "include/generated";
+
+/// Returns true if the file is a C or C++ header.
+fn is_header(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(|ext| ext.to_str()),
+        Some("h" | "hh" | "hpp" | "hxx")
+    )
+}
+
+/// Find the headers in the given directory, along with their include paths.
+///
+/// Symbolic links are skipped, since cxx links the source directories of crates into the
+/// 'OUT_DIR'. The headers of cxx are included relative to 'cxxbridge/include'.
+fn find_headers(out_dir: &Path) -> Vec<(PathBuf, PathBuf)> {
+    let mut headers = Vec::new();
+    let mut dirs = vec![out_dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let entries = match dir.read_dir() {
+            Ok(entries) => entries,
+            Err(err) => {
+                debug!("Skipping {}: {}", dir.display(), err);
+                continue;
+            }
+        };
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            let path = entry.path();
+            match path.symlink_metadata() {
+                Ok(meta) if meta.is_dir() => dirs.push(path),
+                Ok(meta) if meta.is_file() && is_header(&path) => {
+                    let relative = path.strip_prefix(out_dir).unwrap_or(&path);
+                    let include = relative
+                        .strip_prefix("cxxbridge/include")
+                        .unwrap_or(relative)
+                        .to_path_buf();
+                    headers.push((include, path));
+                }
+                _ => {}
+            }
+        }
+    }
+    headers
+}
+
+/// Generate the header of the crate with the cbindgen CLI into the given directory.
+fn run_cbindgen(manifest_dir: &Path, package: &str, dir: &Path) -> Result<PathBuf> {
+    let cbindgen = tools::find_program("cbindgen").context(
+        "Couldn't find 'cbindgen' on the 'PATH' for the 'cbindgen.toml' of the crate. Install it with 'cargo install cbindgen'.",
+    )?;
+    fs::create_dir_all(dir)
+        .with_context(|| format!("Failed to create directory {}", dir.display()))?;
+    let header = dir.join(format!("{}.h", package.replace('-', "_")));
+    info!("Generating {} with cbindgen.", header.display());
+    let status = Command::new(&cbindgen)
+        .current_dir(manifest_dir)
+        .args(["--config", "cbindgen.toml", "--crate", package, "--output"])
+        .arg(&header)
+        .status()
+        .with_context(|| format!("Failed to run {}", cbindgen.display()))?;
+    if !status.success() {
+        bail!("cbindgen failed to generate the header of {}", package);
+    }
+    Ok(header)
+}
+
+/// Copy the headers generated in the given 'OUT_DIR's into `dest`, removing the headers that are
+/// no longer generated.
+///
+/// If the 'OUT_DIR' of `package` has no headers but its manifest directory has a
+/// 'cbindgen.toml', the header is generated with cbindgen in `build_dir` first.
+pub fn sync(
+    out_dirs: &[(String, Utf8PathBuf)],
+    package: &str,
+    manifest_dir: &Path,
+    build_dir: &Path,
+    dest: &Path,
+) -> Result<()> {
+    let mut headers: BTreeMap<PathBuf, PathBuf> = BTreeMap::new();
+    let mut package_has_headers = false;
+    for (name, out_dir) in out_dirs {
+        for (include, path) in find_headers(out_dir.as_std_path()) {
+            package_has_headers |= name == package;
+            if let Some(other) = headers.get(&include) {
+                warn!(
+                    "Skipping {}, since {} is already generated by {}",
+                    path.display(),
+                    include.display(),
+                    other.display()
+                );
+                continue;
+            }
+            headers.insert(include, path);
+        }
+    }
+    if !package_has_headers && manifest_dir.join("cbindgen.toml").is_file() {
+        let header = run_cbindgen(manifest_dir, package, &build_dir.join("bindgen"))?;
+        let include = PathBuf::from(header.file_name().unwrap_or(OsStr::new(package)));
+        headers.insert(include, header);
+    }
+    if headers.is_empty() {
+        bail!(
+            "No generated headers found in the 'OUT_DIR' of {}. Generate them with cxx or cbindgen in its build script, or add a 'cbindgen.toml' next to its 'Cargo.toml'.",
+            package
+        );
+    }
+
+    let mut updated = 0;
+    for (include, path) in &headers {
+        let target = dest.join(include);
+        let contents =
+            fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        if fs::read(&target).ok().as_ref() == Some(&contents) {
+            trace!("{} is up to date", target.display());
+            continue;
+        }
+        if let Some(dir) = target.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create directory {}", dir.display()))?;
+        }
+        fs::write(&target, &contents)
+            .with_context(|| format!("Failed to write {}", target.display()))?;
+        debug!("Copied {} to {}", path.display(), target.display());
+        updated += 1;
+    }
+
+    let mut removed = 0;
+    for (include, path) in find_headers(dest) {
+        if !headers.contains_key(&include) {
+            fs::remove_file(&path)
+                .with_context(|| format!("Failed to remove {}", path.display()))?;
+            debug!("Removed stale header {}", path.display());
+            // Only succeeds once the directory is empty.
+            if let Some(dir) = path.parent().filter(|dir| *dir != dest) {
+                let _ = fs::remove_dir(dir);
+            }
+            removed += 1;
+        }
+    }
+
+    info!(
+        "Synced {} generated headers into {} ({} updated, {} removed).",
+        headers.len(),
+        dest.display(),
+        updated,
+        removed
+    );
+    Ok(())
+}
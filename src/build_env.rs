@@ -0,0 +1,170 @@
+//! The resolved build environment printed by `cargo hdk env`.
+//!
+//! This describes a build without running it, so IDE tasks and pipeline scripts can find the
+//! Houdini installation, the build directory and the files produced by the build. Library paths
+//! are those cargo builds for the selected profile and target, and 'OUT_DIR' paths are read from
+//! the files exported by the last build.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use cargo_metadata::camino::{Utf8Path, Utf8PathBuf};
+use cargo_metadata::Package;
+use clap::ValueEnum;
+use log::*;
+use serde::Serialize;
+
+use crate::cmake;
+
+/// Output formats of `cargo hdk env`.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// 'export NAME='value'' lines for POSIX shells.
+    Sh,
+    /// '$env:NAME = 'value'' lines for PowerShell.
+    Powershell,
+    /// A single JSON object.
+    Json,
+}
+
+/// The resolved build environment.
+#[derive(Debug, Serialize)]
+pub struct BuildEnv {
+    pub hfs: String,
+    pub houdini_version: Option<String>,
+    pub profile: String,
+    pub build_type: String,
+    pub manifest_dir: Utf8PathBuf,
+    pub target_dir: Utf8PathBuf,
+    pub build_dir: Utf8PathBuf,
+    /// The CMake generator of the build directory, or `None` for the platform default.
+    pub generator: Option<String>,
+    /// Libraries built by cargo for the HDK plugin to link.
+    pub artifacts: Vec<Utf8PathBuf>,
+    /// Exported 'OUT_DIR' paths by crate name.
+    pub out_dirs: BTreeMap<String, Utf8PathBuf>,
+}
+
+impl BuildEnv {
+    /// The environment as variables, named like the variables set for hooks.
+    fn vars(&self) -> Result<Vec<(String, String)>> {
+        let mut vars = vec![("HFS".to_string(), self.hfs.clone())];
+        let mut push = |name: &str, value: &str| vars.push((name.to_string(), value.to_string()));
+        if let Some(version) = &self.houdini_version {
+            push("CARGO_HDK_HOUDINI_BUILD_VERSION", version);
+        }
+        push("CARGO_HDK_PROFILE", &self.profile);
+        push("CARGO_HDK_BUILD_TYPE", &self.build_type);
+        push("CARGO_HDK_MANIFEST_DIR", self.manifest_dir.as_str());
+        push("CARGO_HDK_TARGET_DIR", self.target_dir.as_str());
+        push("CARGO_HDK_CMAKE_BINARY_DIR", self.build_dir.as_str());
+        if let Some(generator) = &self.generator {
+            push("CARGO_HDK_CMAKE_GENERATOR", generator);
+        }
+        let artifacts = std::env::join_paths(&self.artifacts)
+            .context("Failed to join the Rust library paths")?;
+        push("CARGO_HDK_RUST_ARTIFACTS", &artifacts.to_string_lossy());
+        for (name, out_dir) in &self.out_dirs {
+            push(
+                &format!("{}_OUT_DIR", cmake::var_prefix(name)),
+                out_dir.as_str(),
+            );
+        }
+        Ok(vars)
+    }
+
+    /// Print the environment in the given format.
+    pub fn print(&self, format: Format) -> Result<()> {
+        let output = match format {
+            Format::Json => serde_json::to_string_pretty(self)
+                .context("Failed to serialize the build environment")?,
+            Format::Sh | Format::Powershell => {
+                let mut output = String::new();
+                for (name, value) in self.vars()? {
+                    // Both shells take single quoted strings literally.
+                    if format == Format::Sh {
+                        let value = value.replace('\'', r"'\''");
+                        writeln!(output, "export {}='{}'", name, value)?;
+                    } else {
+                        let value = value.replace('\'', "''");
+                        writeln!(output, "$env:{} = '{}'", name, value)?;
+                    }
+                }
+                output.trim_end().to_string()
+            }
+        };
+        println!("{}", output);
+        Ok(())
+    }
+}
+
+/// The CMake generator used for the build directory.
+///
+/// This is the generator of an already configured build directory, or the one the next build
+/// will select.
+pub fn generator(
+    requested: Option<&str>,
+    cmake_args: &[String],
+    build_dir: &Path,
+) -> Option<String> {
+    if let Some(generator) = cmake::cache_value(build_dir, "CMAKE_GENERATOR") {
+        return Some(generator);
+    }
+    if let Some(generator) = cmake::select_generator(requested, cmake_args, build_dir) {
+        return Some(generator);
+    }
+    let mut args = cmake_args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "-G" {
+            return args.next().cloned();
+        }
+        if let Some(generator) = arg.strip_prefix("-G") {
+            return Some(generator.to_string());
+        }
+    }
+    std::env::var("CMAKE_GENERATOR").ok()
+}
+
+/// Paths of the libraries cargo builds for the given package into `dir`, following the naming
+/// conventions of the target platform.
+pub fn lib_artifacts(package: &Package, dir: &Utf8Path, target: Option<&str>) -> Vec<Utf8PathBuf> {
+    let target = target.unwrap_or(if cfg!(windows) {
+        "windows"
+    } else if cfg!(target_os = "macos") {
+        "apple"
+    } else {
+        "linux"
+    });
+    let lib = package
+        .targets
+        .iter()
+        .find(|t| t.kind.iter().any(|k| k == "staticlib" || k == "cdylib"));
+    let lib = match lib {
+        Some(lib) => lib,
+        None => return Vec::new(),
+    };
+    let name = lib.name.replace('-', "_");
+    let mut artifacts = Vec::new();
+    for kind in &lib.crate_types {
+        let files = match (kind.as_str(), target) {
+            ("staticlib", t) if t.contains("windows-msvc") || t == "windows" => {
+                vec![format!("{}.lib", name)]
+            }
+            ("staticlib", _) => vec![format!("lib{}.a", name)],
+            ("cdylib", t) if t.contains("windows-msvc") || t == "windows" => {
+                vec![format!("{}.dll", name), format!("{}.dll.lib", name)]
+            }
+            ("cdylib", t) if t.contains("windows") => {
+                vec![format!("{}.dll", name), format!("lib{}.dll.a", name)]
+            }
+            ("cdylib", t) if t.contains("apple") => vec![format!("lib{}.dylib", name)],
+            ("cdylib", _) => vec![format!("lib{}.so", name)],
+            _ => Vec::new(),
+        };
+        artifacts.extend(files.into_iter().map(|file| dir.join(file)));
+    }
+    debug!("Expecting the libraries {:?}", artifacts);
+    artifacts
+}
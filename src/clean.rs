@@ -0,0 +1,216 @@
+//! Removing build outputs with `--clean`.
+//!
+//! Besides the build directory of the current build, `--all-profiles` and `--all-versions` remove
+//! the build directories of other profiles and Houdini versions, which are found by matching the
+//! build directory template with those placeholders replaced by wildcards. Only matching
+//! directories containing a 'CMakeCache.txt' are removed, so an unusual template can't remove
+//! sources by accident.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use log::*;
+
+use crate::deps::glob_match;
+use crate::long_paths;
+
+/// Returns true if the path component contains glob wildcards.
+fn is_pattern(component: &str) -> bool {
+    component.contains('*') || component.contains('?')
+}
+
+/// Find the directories matching a path whose components may contain the wildcards `*` and `?`.
+pub fn find_dirs(pattern: &Path) -> Vec<PathBuf> {
+    let mut dirs = vec![PathBuf::new()];
+    for component in pattern.components() {
+        let component = component.as_os_str();
+        let glob = match component.to_str() {
+            Some(glob) if is_pattern(glob) => glob,
+            _ => {
+                for dir in &mut dirs {
+                    dir.push(component);
+                }
+                continue;
+            }
+        };
+        dirs = dirs
+            .iter()
+            .filter_map(|dir| dir.read_dir().ok())
+            .flatten()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| glob_match(glob, &entry.file_name().to_string_lossy()))
+            .map(|entry| entry.path())
+            .filter(|path| path.is_dir())
+            .collect();
+    }
+    dirs.retain(|dir| dir.is_dir());
+    dirs
+}
+
+/// The CMake build directories matching the given build directory pattern.
+pub fn build_dirs(pattern: &Path) -> Vec<PathBuf> {
+    let mut dirs: Vec<_> = find_dirs(pattern)
+        .into_iter()
+        .filter(|dir| dir.join("CMakeCache.txt").is_file())
+        .collect();
+    dirs.sort();
+    dirs
+}
+
+/// The cargo target directories of other Houdini versions created by '--houdini-target-dir'.
+pub fn houdini_target_dirs(target_dir: &Path) -> Vec<PathBuf> {
+    find_dirs(&target_dir.join("houdini*.*.*"))
+}
+
+/// Remove a directory, only warning if that fails.
+pub fn remove_dir(dir: &Path) {
+    if !dir.exists() {
+        return;
+    }
+    info!("Removing {}.", dir.display());
+    // Build trees on Windows often contain paths longer than the usual limit.
+    if let Err(e) = fs::remove_dir_all(long_paths::extended(dir)) {
+        warn!("Failed to remove {}: {}", dir.display(), e);
+    }
+}
+
+/// Remove a file if it exists, only warning if that fails.
+fn remove_file(path: &Path) {
+    match fs::remove_file(path) {
+        Ok(()) => debug!("Removed {}", path.display()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => warn!("Failed to remove {}: {}", path.display(), e),
+    }
+}
+
+/// Split the prefix of the files generated for CMake, like '--out-dir-file-prefix', into the
+/// directory of the files and the start of their names, relative to `build_dir`.
+///
+/// A prefix ending in a separator, like 'gen/', names all the files in that directory.
+pub fn split_prefix(build_dir: &Path, prefix: &str) -> (PathBuf, String) {
+    // `Path::file_name` ignores a trailing separator, so it is checked on the prefix itself.
+    if prefix.is_empty() || prefix.ends_with(std::path::is_separator) {
+        return (build_dir.join(prefix), String::new());
+    }
+    let path = build_dir.join(prefix);
+    match (path.parent(), path.file_name()) {
+        (Some(dir), Some(name)) => (dir.to_path_buf(), name.to_string_lossy().into_owned()),
+        _ => (path.clone(), String::new()),
+    }
+}
+
+/// Remove the files generated for CMake outside of the build directory, i.e. the 'OUT_DIR' and
+/// manifest directory files written with an absolute prefix and the scripts written to an
+/// absolute path.
+pub fn remove_generated(build_dir: &Path, prefixes: &[&str], scripts: &[&Path]) {
+    for prefix in prefixes {
+        let (dir, name) = split_prefix(build_dir, prefix);
+        if dir.starts_with(build_dir) {
+            continue;
+        }
+        let pattern = format!("{}*.txt", name);
+        let entries = match dir.read_dir() {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        for entry in entries.flatten() {
+            if glob_match(&pattern, &entry.file_name().to_string_lossy()) {
+                remove_file(&entry.path());
+            }
+        }
+    }
+    for script in scripts {
+        let path = build_dir.join(script);
+        if !path.starts_with(build_dir) {
+            remove_file(&path);
+        }
+    }
+}
+
+/// Remove the files installed from the given build directory, as recorded by CMake in its install
+/// manifests.
+pub fn remove_installed(build_dir: &Path) -> Result<()> {
+    let entries = match build_dir.read_dir() {
+        Ok(entries) => entries,
+        Err(_) => return Ok(()),
+    };
+    for entry in entries.flatten() {
+        // Installs of a single component are recorded in 'install_manifest_<component>.txt'.
+        if !glob_match(
+            "install_manifest*.txt",
+            &entry.file_name().to_string_lossy(),
+        ) {
+            continue;
+        }
+        let path = entry.path();
+        let manifest = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read install manifest {}", path.display()))?;
+        let files: Vec<_> = manifest.lines().filter(|line| !line.is_empty()).collect();
+        info!(
+            "Removing {} installed files listed in {}.",
+            files.len(),
+            path.display()
+        );
+        for file in files {
+            remove_file(Path::new(file));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_prefix_with_name() {
+        let build_dir = Path::new("/build");
+        assert_eq!(
+            split_prefix(build_dir, "out_dir_"),
+            (PathBuf::from("/build"), "out_dir_".to_string())
+        );
+        assert_eq!(
+            split_prefix(build_dir, "/tmp/gen/out_dir_"),
+            (PathBuf::from("/tmp/gen"), "out_dir_".to_string())
+        );
+    }
+
+    #[test]
+    fn split_prefix_ending_in_separator() {
+        let build_dir = Path::new("/build");
+        assert_eq!(
+            split_prefix(build_dir, "/tmp/gen/"),
+            (PathBuf::from("/tmp/gen/"), String::new())
+        );
+        assert_eq!(
+            split_prefix(build_dir, "gen/"),
+            (PathBuf::from("/build/gen/"), String::new())
+        );
+        assert_eq!(
+            split_prefix(build_dir, ""),
+            (PathBuf::from("/build"), String::new())
+        );
+    }
+
+    #[test]
+    fn remove_generated_in_prefix_directory() {
+        let root = std::env::temp_dir().join(format!("cargo-hdk-clean-{}", std::process::id()));
+        let build_dir = root.join("build");
+        let gen = root.join("gen");
+        fs::create_dir_all(&build_dir).unwrap();
+        fs::create_dir_all(&gen).unwrap();
+        let generated = gen.join("out_dir_plug.txt");
+        let unrelated = [root.join("general_notes.txt"), root.join("gen_keep.txt")];
+        for path in unrelated.iter().chain(std::iter::once(&generated)) {
+            fs::write(path, "").unwrap();
+        }
+
+        let prefix = format!("{}{}", gen.display(), std::path::MAIN_SEPARATOR);
+        remove_generated(&build_dir, &[prefix.as_str()], &[]);
+
+        assert!(!generated.exists());
+        assert!(unrelated.iter().all(|path| path.exists()));
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
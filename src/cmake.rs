@@ -0,0 +1,744 @@
+//! Generation of CMake scripts consumed by the HDK plugin's `CMakeLists.txt` and helpers for
+//! running CMake itself.
+
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use anyhow::{Context, Result};
+use cargo_metadata::camino::{Utf8Path, Utf8PathBuf};
+use cargo_metadata::Artifact;
+use log::*;
+
+use crate::long_paths;
+use crate::progress::Phase;
+
+/// Convert a crate name into a CMake variable prefix (e.g. `my-crate` becomes `MY_CRATE`).
+pub fn var_prefix(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Quote a path for use in a CMake script.
+///
+/// Backslashes are converted to forward slashes since CMake treats them as escape characters.
+pub fn quote_path(path: &Utf8Path) -> String {
+    let path = path.as_str().replace('\\', "/");
+    format!("\"{}\"", path.replace('"', "\\\"").replace('$', "\\$"))
+}
+
+/// Split the string given to '--cmake' into individual arguments.
+///
+/// Arguments are separated by whitespace and may be quoted with single or double quotes.
+/// Surrounding square brackets are stripped for backwards compatibility.
+pub fn parse_args(args: &str) -> Vec<String> {
+    let arg_str = if args.starts_with('[') && args.ends_with(']') {
+        &args[1..args.len() - 1]
+    } else {
+        args
+    };
+
+    // Quick and dirty quoation sensitive parser
+    let mut cmake_args = Vec::new();
+    let mut cur_arg = String::new();
+    let mut in_quoted_text = None;
+    for c in arg_str.chars() {
+        if let Some(quotation_char) = in_quoted_text {
+            if c == quotation_char {
+                // End quoted text
+                in_quoted_text = None;
+                continue; // Consume the qutation mark
+            }
+        } else if c == '\"' || c == '\'' {
+            in_quoted_text = Some(c);
+            continue;
+        } else if c.is_whitespace() {
+            if !cur_arg.is_empty() {
+                cmake_args.push(std::mem::take(&mut cur_arg));
+            }
+            continue;
+        }
+        cur_arg.push(c);
+    }
+    // Push any remainder arg to cmake_args.
+    if !cur_arg.is_empty() {
+        cmake_args.push(cur_arg);
+    }
+    cmake_args
+}
+
+/// Determine which generator to pass to the CMake configure step, if any.
+///
+/// An explicitly requested generator always wins. Otherwise Ninja is preferred if available,
+/// unless a generator is already given in the CMake arguments, the `CMAKE_GENERATOR`
+/// environment variable, or the cache of an existing build directory.
+pub fn select_generator(
+    requested: Option<&str>,
+    cmake_args: &[String],
+    build_dir: &Path,
+) -> Option<String> {
+    if let Some(generator) = requested {
+        return Some(generator.to_string());
+    }
+    if cmake_args.iter().any(|arg| arg.starts_with("-G")) {
+        debug!("Using the CMake generator given in the CMake arguments.");
+        return None;
+    }
+    if std::env::var_os("CMAKE_GENERATOR").is_some() {
+        debug!("Using the CMake generator given by CMAKE_GENERATOR.");
+        return None;
+    }
+    if build_dir.join("CMakeCache.txt").exists() {
+        debug!("Using the CMake generator cached in the build directory.");
+        return None;
+    }
+    if crate::tools::find_program("ninja").is_some() {
+        info!("Found Ninja, using it as the CMake generator.");
+        return Some("Ninja".to_string());
+    }
+    None
+}
+
+/// Read the value of a variable from the CMake cache in the given build directory.
+pub fn cache_value(build_dir: &Path, name: &str) -> Option<String> {
+    let cache = fs::read_to_string(build_dir.join("CMakeCache.txt")).ok()?;
+    cache.lines().find_map(|line| {
+        let (key, value) = line.split_once('=')?;
+        let key = key.split(':').next()?;
+        if key == name {
+            Some(value.to_string())
+        } else {
+            None
+        }
+    })
+}
+
+/// The files installed from the given build directory, as recorded in its CMake install
+/// manifests.
+pub fn installed_files(build_dir: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(build_dir).into_iter().flatten().flatten() {
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if !(name.starts_with("install_manifest") && name.ends_with(".txt")) {
+            continue;
+        }
+        let manifest = fs::read_to_string(entry.path()).unwrap_or_default();
+        files.extend(manifest.lines().map(PathBuf::from));
+    }
+    files
+}
+
+/// The client name of the queries to the CMake file API.
+const FILE_API_CLIENT: &str = "client-cargo-hdk";
+
+/// Ask CMake to describe the targets of the build in `build_dir` with the CMake file API, which
+/// it does on the next configure step. See [`library_artifacts`].
+pub fn request_codemodel(build_dir: &Path) -> Result<()> {
+    let query_dir = build_dir
+        .join(".cmake")
+        .join("api")
+        .join("v1")
+        .join("query")
+        .join(FILE_API_CLIENT);
+    fs::create_dir_all(&query_dir)
+        .with_context(|| format!("Failed to create {}", query_dir.display()))?;
+    let query = query_dir.join("codemodel-v2");
+    if !query.exists() {
+        fs::write(&query, "").with_context(|| format!("Failed to write {}", query.display()))?;
+    }
+    Ok(())
+}
+
+/// The files built by the shared and module library targets in `build_dir`, like the plugin
+/// libraries, as described by CMake in its reply to [`request_codemodel`], or None if there is no
+/// reply yet.
+pub fn library_artifacts(build_dir: &Path) -> Option<Vec<PathBuf>> {
+    let reply_dir = build_dir
+        .join(".cmake")
+        .join("api")
+        .join("v1")
+        .join("reply");
+    let read = |name: &str| -> Option<serde_json::Value> {
+        let contents = fs::read_to_string(reply_dir.join(name)).ok()?;
+        serde_json::from_str(&contents).ok()
+    };
+    // The name of the latest index file sorts last.
+    let index = reply_dir
+        .read_dir()
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .filter(|name| name.starts_with("index-") && name.ends_with(".json"))
+        .max()?;
+    let index = read(&index)?;
+    let codemodel = read(index["reply"][FILE_API_CLIENT]["codemodel-v2"]["jsonFile"].as_str()?)?;
+    let mut artifacts = Vec::new();
+    for configuration in codemodel["configurations"].as_array()? {
+        for target in configuration["targets"].as_array().into_iter().flatten() {
+            let target = match target["jsonFile"].as_str().and_then(&read) {
+                Some(target) => target,
+                None => continue,
+            };
+            if !matches!(
+                target["type"].as_str(),
+                Some("SHARED_LIBRARY" | "MODULE_LIBRARY")
+            ) {
+                continue;
+            }
+            // Paths are relative to the build directory unless they are outside of it.
+            let paths = target["artifacts"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|artifact| artifact["path"].as_str());
+            artifacts.extend(paths.map(|path| build_dir.join(path)));
+        }
+    }
+    Some(artifacts)
+}
+
+/// Keep only the `libraries` built by the library targets of the CMake build containing
+/// `binary_dir`, dropping others like DLLs copied next to them, if CMake described its targets.
+pub fn retain_library_targets(binary_dir: &Path, libraries: &mut Vec<PathBuf>) {
+    // The binary directory of the plugin may be a subdirectory of the build directory.
+    let artifacts = binary_dir
+        .ancestors()
+        .find(|dir| dir.join("CMakeCache.txt").is_file())
+        .and_then(library_artifacts);
+    if let Some(artifacts) = artifacts {
+        libraries.retain(|library| artifacts.contains(library));
+    }
+}
+
+/// The C++ compiler CMake selected for a build directory.
+pub struct CxxCompiler {
+    /// The compiler ID, e.g. 'MSVC', 'GNU' or 'Clang'.
+    pub id: String,
+    /// The compiler version, e.g. '19.29.30133.0'.
+    pub version: String,
+    /// The ID and version of the compiler simulated by this one, like 'MSVC' for clang-cl.
+    pub simulate: Option<(String, String)>,
+    /// Path of the compiler.
+    pub path: Option<String>,
+}
+
+impl CxxCompiler {
+    /// The version of the MSVC compiler, or of the one simulated by clang-cl, whose standard
+    /// library is used.
+    pub fn msvc_version(&self) -> Option<&str> {
+        if self.id == "MSVC" {
+            return Some(&self.version);
+        }
+        match &self.simulate {
+            Some((id, version)) if id == "MSVC" => Some(version),
+            _ => None,
+        }
+    }
+
+    /// The major version of GCC, or for clang of the GCC installation whose libstdc++ it uses,
+    /// as reported by 'clang -v'.
+    pub fn gcc_major(&self) -> Option<u32> {
+        let major = |version: &str| {
+            let digits: String = version.chars().take_while(char::is_ascii_digit).collect();
+            digits.parse().ok()
+        };
+        match self.id.as_str() {
+            "GNU" => major(&self.version),
+            "Clang" => {
+                let output = Command::new(self.path.as_ref()?).arg("-v").output().ok()?;
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                let installation = stderr
+                    .lines()
+                    .find_map(|line| line.strip_prefix("Selected GCC installation: "))?;
+                major(installation.trim().rsplit('/').next()?)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Read the C++ compiler CMake found when configuring the given build directory from the
+/// 'CMakeFiles/<version>/CMakeCXXCompiler.cmake' file it writes, using the newest one if CMake
+/// was upgraded since the first configure.
+pub fn cxx_compiler(build_dir: &Path) -> Option<CxxCompiler> {
+    let path = fs::read_dir(build_dir.join("CMakeFiles"))
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path().join("CMakeCXXCompiler.cmake"))
+        .filter_map(|path| Some((path.metadata().ok()?.modified().ok()?, path)))
+        .max()
+        .map(|(_, path)| path)?;
+    let contents = fs::read_to_string(path).ok()?;
+    let value = |name: &str| {
+        contents.lines().find_map(|line| {
+            let rest = line.trim().strip_prefix("set(")?.strip_prefix(name)?;
+            if !rest.starts_with(char::is_whitespace) {
+                return None;
+            }
+            let value = rest.trim().strip_suffix(')')?.trim().trim_matches('"');
+            Some(value.to_string()).filter(|value| !value.is_empty())
+        })
+    };
+    Some(CxxCompiler {
+        id: value("CMAKE_CXX_COMPILER_ID")?,
+        version: value("CMAKE_CXX_COMPILER_VERSION").unwrap_or_default(),
+        simulate: value("CMAKE_CXX_SIMULATE_ID")
+            .map(|id| (id, value("CMAKE_CXX_SIMULATE_VERSION").unwrap_or_default())),
+        path: value("CMAKE_CXX_COMPILER"),
+    })
+}
+
+/// Build a `-D<var>=<flags>` argument which updates a cached compiler flags variable.
+///
+/// The current flags are taken from the CMake cache if the build directory was already
+/// configured, or the given environment variable (e.g. `CXXFLAGS`) otherwise. All flags in
+/// `remove` are dropped before the flags in `add` are appended, so options that toggle flags
+/// can also turn them off again. Returns `None` if the flags are unchanged.
+pub fn flags_arg(
+    build_dir: &Path,
+    var: &str,
+    env_var: &str,
+    remove: &[&str],
+    add: &[&str],
+) -> Option<String> {
+    let current = cache_value(build_dir, var)
+        .or_else(|| std::env::var(env_var).ok())
+        .unwrap_or_default();
+    let mut flags: Vec<&str> = current
+        .split_whitespace()
+        .filter(|flag| !remove.contains(flag))
+        .collect();
+    flags.extend(add);
+    let new = flags.join(" ");
+    if new == current.trim() {
+        None
+    } else {
+        Some(format!("-D{}={}", var, new))
+    }
+}
+
+/// Like `flags_arg`, but if `cmake_args` set `var` already, e.g. since several options change the
+/// linker flags, the flags are changed in that argument instead.
+pub fn merge_flags_arg(
+    build_dir: &Path,
+    var: &str,
+    env_var: &str,
+    remove: &[&str],
+    add: &[&str],
+    cmake_args: &mut Vec<String>,
+) {
+    let sets_var = |arg: &String| {
+        arg.starts_with(&format!("-D{}=", var)) || arg.starts_with(&format!("-D{}:", var))
+    };
+    match cmake_args.iter_mut().find(|arg| sets_var(arg)) {
+        Some(arg) => {
+            let (name, value) = arg.split_once('=').unwrap_or((arg, ""));
+            let flags: Vec<_> = value
+                .split_whitespace()
+                .filter(|flag| !remove.contains(flag))
+                .chain(add.iter().copied())
+                .collect();
+            *arg = format!("{}={}", name, flags.join(" "));
+        }
+        None => cmake_args.extend(flags_arg(build_dir, var, env_var, remove, add)),
+    }
+}
+
+/// `-D<var>=<value>` arguments for the given variables, leaving out those without a value and
+/// those set in `cmake_args` already, which take precedence.
+pub fn default_var_args(vars: Vec<(&str, Option<String>)>, cmake_args: &[String]) -> Vec<String> {
+    vars.into_iter()
+        .filter(|(var, _)| {
+            !cmake_args.iter().any(|arg| {
+                arg.starts_with(&format!("-D{}=", var)) || arg.starts_with(&format!("-D{}:", var))
+            })
+        })
+        .filter_map(|(var, value)| Some(format!("-D{}={}", var, value?)))
+        .collect()
+}
+
+/// Write a file creating any missing parent directories.
+pub fn write_file(path: &Path, contents: &str) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(long_paths::extended(dir))
+            .with_context(|| format!("Failed to create directory: {}", dir.display()))?;
+    }
+    let mut file = fs::File::create(long_paths::extended(path))
+        .with_context(|| format!("Failed to create file: {}", path.display()))?;
+    file.write_all(contents.as_bytes())
+        .with_context(|| format!("Failed to write file: {}", path.display()))
+}
+
+/// Write a CMake script setting a `<NAME>_OUT_DIR` variable for each given dependency, and a
+/// `<NAME>_MANIFEST_DIR` variable for each given manifest directory.
+///
+/// The cargo target directory is stored in `CARGO_TARGET_DIR`. The resulting script can be
+/// loaded with a single `include()` call from the `CMakeLists.txt`.
+pub fn write_out_dirs_script(
+    path: &Path,
+    target_dir: &Utf8Path,
+    out_dir_deps: &[(String, Utf8PathBuf)],
+    manifest_dirs: &[(String, Utf8PathBuf)],
+) -> Result<()> {
+    debug!("Writing OUT_DIR CMake script: {}", path.display());
+    let mut script = String::from("# Generated by cargo-hdk. Do not edit.\n");
+    script.push_str(&format!(
+        "set(CARGO_TARGET_DIR {})\n",
+        quote_path(target_dir)
+    ));
+    for (dep, out_dir) in out_dir_deps {
+        script.push_str(&format!(
+            "set({}_OUT_DIR {})\n",
+            var_prefix(dep),
+            quote_path(out_dir)
+        ));
+    }
+    for (dep, manifest_dir) in manifest_dirs {
+        script.push_str(&format!(
+            "set({}_MANIFEST_DIR {})\n",
+            var_prefix(dep),
+            quote_path(manifest_dir)
+        ));
+    }
+    write_file(path, &script)
+}
+
+/// Libraries that a Rust static library needs to be linked against on each platform.
+const NATIVE_LIBS: &str = "\
+if(WIN32)
+    set(_cargo_hdk_native_libs ws2_32 userenv bcrypt ntdll)
+elseif(APPLE)
+    set(_cargo_hdk_native_libs System c m)
+else()
+    set(_cargo_hdk_native_libs pthread dl m)
+endif()
+";
+
+/// Returns true if the given artifact file is a shared library import library
+/// (e.g. `foo.dll.lib` or `libfoo.dll.a`).
+fn is_import_lib(path: &Utf8Path) -> bool {
+    let name = path.as_str();
+    name.ends_with(".dll.lib") || name.ends_with(".dll.a")
+}
+
+/// Returns true if the given artifact file is a static library.
+fn is_static_lib(path: &Utf8Path) -> bool {
+    !is_import_lib(path) && matches!(path.extension(), Some("a") | Some("lib"))
+}
+
+/// Returns true if the given artifact file is a shared library.
+fn is_shared_lib(path: &Utf8Path) -> bool {
+    matches!(path.extension(), Some("so") | Some("dylib") | Some("dll"))
+}
+
+/// Determine the CMake variable suffix for an artifact file produced for a library target with
+/// the given kinds.
+fn artifact_var_suffix(path: &Utf8Path, kinds: &[String]) -> Option<&'static str> {
+    if is_import_lib(path) {
+        Some("IMPLIB")
+    } else if is_static_lib(path) {
+        Some("STATICLIB")
+    } else if is_shared_lib(path) {
+        if kinds.iter().any(|k| k == "cdylib") {
+            Some("CDYLIB")
+        } else {
+            Some("DYLIB")
+        }
+    } else if path.extension() == Some("rlib") {
+        Some("RLIB")
+    } else {
+        None
+    }
+}
+
+/// Write a CMake script setting a variable with the path of each library file built by cargo.
+///
+/// Variables are named `<NAME>_<KIND>` where `<NAME>` is derived from the crate name and
+/// `<KIND>` is one of `STATICLIB`, `CDYLIB`, `DYLIB`, `RLIB` or `IMPLIB` (the import library
+/// of a shared library on Windows).
+///
+/// If the same crate is built more than once (e.g. with different features), the variables
+/// refer to the last artifact reported by cargo.
+pub fn write_artifacts_script(path: &Path, artifacts: &[Artifact]) -> Result<()> {
+    debug!("Writing artifacts CMake script: {}", path.display());
+    let mut script = String::from("# Generated by cargo-hdk. Do not edit.\n");
+    for artifact in artifacts {
+        let kinds = &artifact.target.kind;
+        let is_lib = kinds.iter().any(|k| {
+            matches!(
+                k.as_str(),
+                "lib" | "rlib" | "staticlib" | "cdylib" | "dylib"
+            )
+        });
+        if !is_lib {
+            continue;
+        }
+        for filename in &artifact.filenames {
+            if let Some(suffix) = artifact_var_suffix(filename, kinds) {
+                script.push_str(&format!(
+                    "set({}_{} {})\n",
+                    var_prefix(&artifact.target.name),
+                    suffix,
+                    quote_path(filename)
+                ));
+            }
+        }
+    }
+    write_file(path, &script)
+}
+
+/// Write a CMake script defining `IMPORTED` library targets for the given library artifacts.
+///
+/// A static library is exposed as `<name>::rust` together with the native libraries it depends
+/// on. A shared library is exposed as `<name>::rust` too, unless a static library was also
+/// built, in which case it is called `<name>::rust_shared`.
+pub fn write_rust_targets_script(path: &Path, name: &str, filenames: &[Utf8PathBuf]) -> Result<()> {
+    debug!("Writing Rust targets CMake script: {}", path.display());
+
+    let static_lib = filenames.iter().find(|f| is_static_lib(f));
+    let shared_lib = filenames.iter().find(|f| is_shared_lib(f));
+    let import_lib = filenames.iter().find(|f| is_import_lib(f));
+
+    if static_lib.is_none() && shared_lib.is_none() {
+        warn!(
+            "No static or shared library artifacts found for '{}'. \
+             Make sure the crate-type includes 'staticlib' or 'cdylib'.",
+            name
+        );
+    }
+
+    let mut script = String::from("# Generated by cargo-hdk. Do not edit.\n");
+    script.push_str(NATIVE_LIBS);
+
+    if let Some(lib) = static_lib {
+        let target = format!("{}::rust", name);
+        script.push_str(&format!(
+            "\nif(NOT TARGET {target})\n    add_library({target} STATIC IMPORTED GLOBAL)\n    \
+             set_target_properties({target} PROPERTIES\n        \
+             IMPORTED_LOCATION {}\n        \
+             INTERFACE_LINK_LIBRARIES \"${{_cargo_hdk_native_libs}}\")\nendif()\n",
+            quote_path(lib),
+            target = target
+        ));
+    }
+
+    if let Some(lib) = shared_lib {
+        let target = if static_lib.is_some() {
+            format!("{}::rust_shared", name)
+        } else {
+            format!("{}::rust", name)
+        };
+        script.push_str(&format!(
+            "\nif(NOT TARGET {target})\n    add_library({target} SHARED IMPORTED GLOBAL)\n    \
+             set_target_properties({target} PROPERTIES\n        IMPORTED_LOCATION {})\n",
+            quote_path(lib),
+            target = target
+        ));
+        if let Some(implib) = import_lib {
+            script.push_str(&format!(
+                "    set_target_properties({} PROPERTIES IMPORTED_IMPLIB {})\n",
+                target,
+                quote_path(implib)
+            ));
+        }
+        script.push_str("endif()\n");
+    }
+
+    write_file(path, &script)
+}
+
+/// Copy everything from `src` into both `dst` and the shared `log` file.
+fn tee<R, W>(mut src: R, mut dst: W, log: Arc<Mutex<fs::File>>) -> thread::JoinHandle<()>
+where
+    R: Read + Send + 'static,
+    W: Write + Send + 'static,
+{
+    thread::spawn(move || {
+        let mut buf = [0u8; 4096];
+        loop {
+            let n = match src.read(&mut buf) {
+                Ok(0) | Err(_) => break,
+                Ok(n) => n,
+            };
+            let _ = dst.write_all(&buf[..n]).and_then(|_| dst.flush());
+            if let Ok(mut log) = log.lock() {
+                let _ = log.write_all(&buf[..n]);
+            }
+        }
+    })
+}
+
+/// Run the given command streaming its output to the terminal while also recording it in the
+/// given log file.
+pub fn run_logged(cmd: &mut Command, log_path: &Path) -> Result<ExitStatus> {
+    run_logged_with_progress(cmd, log_path, &Phase::hidden())
+}
+
+/// Run the given command like [`run_logged`], showing its progress in `phase` instead of its
+/// output if progress bars are shown.
+///
+/// The phase is finished when the command succeeds. If it fails, the phase is removed and the
+/// output that wasn't shown is printed from the log.
+pub fn run_logged_with_progress(
+    cmd: &mut Command,
+    log_path: &Path,
+    phase: &Phase,
+) -> Result<ExitStatus> {
+    if let Some(dir) = log_path.parent() {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create log directory: {}", dir.display()))?;
+    }
+    let log = fs::File::create(log_path)
+        .with_context(|| format!("Failed to create log file: {}", log_path.display()))?;
+    let log = Arc::new(Mutex::new(log));
+
+    debug!("Running {:?}", cmd);
+    let mut child = cmd
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to run {:?}", cmd))?;
+
+    let stdout = child.stdout.take().expect("Child stdout is piped");
+    let stderr = child.stderr.take().expect("Child stderr is piped");
+    let (stdout_thread, stderr_thread) = if phase.is_shown() {
+        (
+            tee(stdout, phase.cmake_writer(), Arc::clone(&log)),
+            tee(stderr, phase.cmake_writer(), log),
+        )
+    } else {
+        (
+            tee(stdout, std::io::stdout(), Arc::clone(&log)),
+            tee(stderr, std::io::stderr(), log),
+        )
+    };
+
+    let status = child.wait()?;
+    let _ = stdout_thread.join();
+    let _ = stderr_thread.join();
+    if status.success() {
+        phase.finish();
+    } else if phase.is_shown() {
+        phase.abandon();
+        if let Ok(output) = fs::read_to_string(log_path) {
+            eprint!("{}", output);
+        }
+    }
+    Ok(status)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn library_artifacts_from_codemodel() {
+        let build_dir =
+            std::env::temp_dir().join(format!("cargo-hdk-codemodel-{}", std::process::id()));
+        let reply_dir = build_dir.join(".cmake/api/v1/reply");
+        fs::create_dir_all(&reply_dir).unwrap();
+        assert_eq!(library_artifacts(&build_dir), None);
+
+        let files = [
+            (
+                "index-2024-01-01T00-00-00-0000.json",
+                r#"{ "reply": { "client-cargo-hdk": { "codemodel-v2": { "jsonFile": "codemodel-v2-1.json" } } } }"#,
+            ),
+            (
+                "codemodel-v2-1.json",
+                r#"{ "configurations": [ { "name": "Debug", "targets": [
+                    { "name": "SOP_Plug", "jsonFile": "target-plug.json" },
+                    { "name": "copy_dlls", "jsonFile": "target-copy.json" }
+                ] } ] }"#,
+            ),
+            (
+                "target-plug.json",
+                r#"{ "type": "MODULE_LIBRARY", "artifacts": [ { "path": "dso/SOP_Plug.dll" }, { "path": "dso/SOP_Plug.pdb" } ] }"#,
+            ),
+            ("target-copy.json", r#"{ "type": "UTILITY" }"#),
+        ];
+        for (name, contents) in files {
+            fs::write(reply_dir.join(name), contents).unwrap();
+        }
+
+        let dso = build_dir.join("dso");
+        assert_eq!(
+            library_artifacts(&build_dir),
+            Some(vec![dso.join("SOP_Plug.dll"), dso.join("SOP_Plug.pdb")])
+        );
+        let mut libraries = vec![dso.join("SOP_Plug.dll"), dso.join("libcurl.dll")];
+        fs::write(build_dir.join("CMakeCache.txt"), "").unwrap();
+        retain_library_targets(&dso, &mut libraries);
+        assert_eq!(libraries, vec![dso.join("SOP_Plug.dll")]);
+        fs::remove_dir_all(&build_dir).unwrap();
+    }
+
+    #[test]
+    fn merge_flags_into_given_arg() {
+        let build_dir = Path::new("/nonexistent/cargo-hdk-build");
+        let remove = [
+            "-Werror",
+            "-D_GLIBCXX_USE_CXX11_ABI=0",
+            "-D_GLIBCXX_USE_CXX11_ABI=1",
+        ];
+        let add = ["-Werror", "-D_GLIBCXX_USE_CXX11_ABI=1"];
+        let mut cmake_args = vec![
+            "-DCMAKE_CXX_FLAGS=-Wall -D_GLIBCXX_USE_CXX11_ABI=0".to_string(),
+            "-DFOO=1".to_string(),
+        ];
+        merge_flags_arg(
+            build_dir,
+            "CMAKE_CXX_FLAGS",
+            "CXXFLAGS",
+            &remove,
+            &add,
+            &mut cmake_args,
+        );
+        assert_eq!(
+            cmake_args,
+            [
+                "-DCMAKE_CXX_FLAGS=-Wall -Werror -D_GLIBCXX_USE_CXX11_ABI=1",
+                "-DFOO=1"
+            ]
+        );
+
+        // Merging again doesn't repeat the flags.
+        merge_flags_arg(
+            build_dir,
+            "CMAKE_CXX_FLAGS",
+            "CXXFLAGS",
+            &remove,
+            &add,
+            &mut cmake_args,
+        );
+        assert_eq!(
+            cmake_args[0],
+            "-DCMAKE_CXX_FLAGS=-Wall -Werror -D_GLIBCXX_USE_CXX11_ABI=1"
+        );
+
+        let mut cmake_args = vec!["-DCMAKE_CXX_FLAGS:STRING=-Wall".to_string()];
+        merge_flags_arg(
+            build_dir,
+            "CMAKE_CXX_FLAGS",
+            "CXXFLAGS",
+            &remove,
+            &add,
+            &mut cmake_args,
+        );
+        assert_eq!(
+            cmake_args,
+            ["-DCMAKE_CXX_FLAGS:STRING=-Wall -Werror -D_GLIBCXX_USE_CXX11_ABI=1"]
+        );
+    }
+}
@@ -0,0 +1,199 @@
+//! Code signing and notarization of the plugin libraries on macOS.
+//!
+//! Unsigned libraries downloaded to other machines are increasingly blocked or quarantined by
+//! Gatekeeper. With '--codesign', the plugin libraries are signed with the hardened runtime after
+//! the build, and their installed copies again after '--cmake-install', since installing may
+//! change them (e.g. their rpaths). With '--notarize', the signed libraries are zipped and
+//! submitted to the Apple notary service with 'xcrun notarytool', waiting for the result. Tickets
+//! can't be stapled to bare libraries, so Gatekeeper looks them up online.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{Context, Result};
+use log::*;
+use serde::Deserialize;
+
+use crate::config::Codesign;
+use crate::tools;
+
+/// Identity for ad hoc signing, which needs no certificate but isn't trusted by Gatekeeper.
+const AD_HOC: &str = "-";
+
+/// The part of the output of 'xcrun notarytool submit --output-format json' checked here.
+#[derive(Deserialize)]
+struct Submission {
+    id: String,
+    status: String,
+    #[serde(default)]
+    message: String,
+}
+
+/// How the plugin libraries are signed and notarized.
+pub struct Signing {
+    identity: String,
+    entitlements: Option<PathBuf>,
+    args: Vec<String>,
+    /// The 'xcrun notarytool' keychain profile if the libraries are notarized.
+    keychain_profile: Option<String>,
+}
+
+impl Signing {
+    /// The signing settings for the identity given with '--codesign', if any, and '--notarize',
+    /// or `None` if neither is given.
+    pub fn new(
+        identity: Option<&Option<String>>,
+        notarize: bool,
+        config: Option<&Codesign>,
+    ) -> Result<Option<Signing>> {
+        if identity.is_none() && !notarize {
+            return Ok(None);
+        }
+        if !cfg!(target_os = "macos") {
+            bail!("Code signing with '--codesign' and '--notarize' is only supported on macOS");
+        }
+        let identity = identity
+            .cloned()
+            .flatten()
+            .or_else(|| config.and_then(|codesign| codesign.identity.clone()))
+            .context("No signing identity given. Pass '--codesign=<IDENTITY>' or set 'codesign.identity' in [package.metadata.hdk]. 'security find-identity -v -p codesigning' lists the available identities.")?;
+        let keychain_profile = if notarize {
+            if identity == AD_HOC {
+                bail!("Ad hoc signed libraries can't be notarized. Sign them with a 'Developer ID Application' identity.");
+            }
+            let profile = config
+                .and_then(|codesign| codesign.keychain_profile.clone())
+                .context("No keychain profile for notarization configured. Store the credentials with 'xcrun notarytool store-credentials <PROFILE>' and set 'codesign.keychain_profile' in [package.metadata.hdk].")?;
+            Some(profile)
+        } else {
+            None
+        };
+        Ok(Some(Signing {
+            identity,
+            entitlements: config.and_then(|codesign| codesign.entitlements.clone()),
+            args: config
+                .map(|codesign| codesign.args.clone())
+                .unwrap_or_default(),
+            keychain_profile,
+        }))
+    }
+
+    /// Sign the given libraries, replacing existing signatures, and verify the signatures.
+    pub fn sign(&self, files: &[PathBuf]) -> Result<()> {
+        if files.is_empty() {
+            return Ok(());
+        }
+        let codesign = tools::find_program("codesign")
+            .context("Couldn't find 'codesign' on the 'PATH'. It is installed with the Xcode command line tools.")?;
+        let mut cmd = Command::new(&codesign);
+        cmd.args(["--force", "--sign", &self.identity, "--options", "runtime"]);
+        // Ad hoc signatures can't be timestamped.
+        if self.identity != AD_HOC {
+            cmd.arg("--timestamp");
+        }
+        if let Some(entitlements) = &self.entitlements {
+            cmd.arg("--entitlements").arg(entitlements);
+        }
+        cmd.args(&self.args).args(files);
+        debug!("Running {:?}", cmd);
+        let output = cmd
+            .output()
+            .with_context(|| format!("Failed to run {}", codesign.display()))?;
+        if !output.status.success() {
+            bail!(
+                "Signing the HDK plugin with '{}' failed:\n{}",
+                self.identity,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        for file in files {
+            let output = Command::new(&codesign)
+                .args(["--verify", "--strict"])
+                .arg(file)
+                .output()
+                .with_context(|| format!("Failed to run {}", codesign.display()))?;
+            if !output.status.success() {
+                bail!(
+                    "The signature of {} is invalid:\n{}",
+                    file.display(),
+                    String::from_utf8_lossy(&output.stderr).trim()
+                );
+            }
+            info!("Signed {}", file.display());
+        }
+        Ok(())
+    }
+
+    /// Submit the given signed libraries for notarization in a zip archive created in
+    /// `work_dir`, if requested, and wait for the result.
+    pub fn notarize(&self, files: &[PathBuf], work_dir: &Path) -> Result<()> {
+        let profile = match &self.keychain_profile {
+            Some(profile) if !files.is_empty() => profile,
+            _ => return Ok(()),
+        };
+        // 'ditto' archives a directory the way the notary service expects.
+        let bundle = work_dir.join("notarize").join("plugin");
+        if bundle.exists() {
+            fs::remove_dir_all(&bundle)
+                .with_context(|| format!("Failed to remove {}", bundle.display()))?;
+        }
+        fs::create_dir_all(&bundle)
+            .with_context(|| format!("Failed to create {}", bundle.display()))?;
+        for file in files {
+            let target = bundle.join(file.file_name().unwrap_or_default());
+            fs::copy(file, &target).with_context(|| {
+                format!("Failed to copy {} to {}", file.display(), target.display())
+            })?;
+        }
+        let archive = bundle.with_extension("zip");
+        let status = Command::new("ditto")
+            .args(["-c", "-k", "--keepParent"])
+            .arg(&bundle)
+            .arg(&archive)
+            .status()
+            .context("Failed to run ditto")?;
+        if !status.success() {
+            bail!("Failed to create {} for notarization", archive.display());
+        }
+
+        println!(
+            "Submitting {} for notarization, which can take a few minutes",
+            archive.display()
+        );
+        let output = Command::new("xcrun")
+            .args(["notarytool", "submit"])
+            .arg(&archive)
+            .args([
+                "--keychain-profile",
+                profile,
+                "--wait",
+                "--output-format",
+                "json",
+            ])
+            .output()
+            .context("Failed to run 'xcrun notarytool'")?;
+        let submission: Submission = match serde_json::from_slice(&output.stdout) {
+            Ok(submission) => submission,
+            Err(_) => bail!(
+                "Submitting the HDK plugin for notarization failed:\n{}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+        };
+        if submission.status != "Accepted" {
+            bail!(
+                "Notarization of the HDK plugin finished with status '{}': {}\nSee 'xcrun notarytool log {} --keychain-profile {}' for details.",
+                submission.status,
+                submission.message,
+                submission.id,
+                profile
+            );
+        }
+        println!(
+            "Notarized {} (submission {})",
+            archive.display(),
+            submission.id
+        );
+        Ok(())
+    }
+}
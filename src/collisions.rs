@@ -0,0 +1,203 @@
+//! Detection of clashes between the plugin libraries and the libraries bundled with Houdini on
+//! Linux and macOS.
+//!
+//! Houdini ships its own builds of libraries like OpenSSL, TBB, zlib and OpenEXR. A plugin that
+//! links another version of one of them, e.g. the system's 'libssl.so.3' where Houdini loads its
+//! 'libssl.so.1.1', or that exports symbols one of them defines, e.g. from a statically linked
+//! zlib, ends up with calls bound to the wrong version in the Houdini process, which shows up as
+//! crashes far from the cause. After a build, the libraries the plugin links are compared by name
+//! with those in '$HFS/dsolib' (the 'Libraries' of the framework on macOS), and the symbols it
+//! exports with those the bundled libraries export.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use log::*;
+
+use crate::exports;
+use crate::houdini;
+
+/// Name of the file in the build directory caching the symbols exported by the Houdini libraries.
+const SYMBOLS_CACHE: &str = "houdini_symbols.txt";
+
+/// Number of symbols listed for each clashing library.
+const MAX_SYMBOLS: usize = 5;
+
+/// The name of a library without its version and extension, like 'libssl' for 'libssl.so.1.1'
+/// and 'libtbb' for 'libtbb.12.dylib'.
+fn base_name(file_name: &str) -> &str {
+    file_name.split('.').next().unwrap_or(file_name)
+}
+
+/// The shared libraries in `dir` by file name.
+fn libraries_in(dir: &Path) -> BTreeMap<String, PathBuf> {
+    let extension = if cfg!(target_os = "macos") {
+        ".dylib"
+    } else {
+        ".so"
+    };
+    fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let is_library = name.starts_with("lib")
+                && (name.ends_with(extension) || name.contains(&format!("{}.", extension)));
+            is_library.then(|| (name, entry.path()))
+        })
+        .collect()
+}
+
+/// The file names of the libraries the given plugin library links, according to 'readelf' on
+/// Linux and 'otool' on macOS.
+fn linked_libraries(plugin: &Path) -> Option<Vec<String>> {
+    if cfg!(target_os = "macos") {
+        let output = Command::new("otool").arg("-L").arg(plugin).output().ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        // The first line names the library itself.
+        let libraries = String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .skip(1)
+            .filter_map(|line| line.trim().split(" (").next())
+            .filter_map(|path| path.rsplit('/').next())
+            .map(String::from)
+            .collect();
+        Some(libraries)
+    } else {
+        let output = Command::new("readelf")
+            .arg("-d")
+            .arg(plugin)
+            .output()
+            .ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        let libraries = String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter(|line| line.contains("(NEEDED)"))
+            .filter_map(|line| line.split_once('[')?.1.split_once(']'))
+            .map(|(name, _)| name.to_string())
+            .collect();
+        Some(libraries)
+    }
+}
+
+/// The symbols exported by the libraries bundled with Houdini other than its own 'libHoudini*'
+/// libraries, with the library exporting each, cached in `build_dir` until the library directory
+/// changes.
+fn bundled_symbols(dir: &Path, build_dir: &Path) -> BTreeMap<String, String> {
+    let cache = build_dir.join(SYMBOLS_CACHE);
+    let modified = |path: &Path| path.metadata().and_then(|m| m.modified()).ok();
+    let header = dir.display().to_string();
+    if let (Some(cached), Some(changed)) = (modified(&cache), modified(dir)) {
+        if cached > changed {
+            if let Ok(contents) = fs::read_to_string(&cache) {
+                let mut lines = contents.lines();
+                if lines.next() == Some(header.as_str()) {
+                    return lines
+                        .filter_map(|line| line.split_once('\t'))
+                        .map(|(symbol, library)| (symbol.to_string(), library.to_string()))
+                        .collect();
+                }
+            }
+        }
+    }
+
+    debug!(
+        "Listing the symbols exported by the libraries in {}",
+        dir.display()
+    );
+    let mut symbols = BTreeMap::new();
+    let mut listed = Vec::new();
+    for (name, path) in libraries_in(dir) {
+        // Symbolic links to the same library are listed once.
+        let canonical = path.canonicalize().unwrap_or(path);
+        if name.starts_with("libHoudini") || listed.contains(&canonical) {
+            continue;
+        }
+        for symbol in exports::exported_symbols(&canonical).unwrap_or_default() {
+            symbols.entry(symbol).or_insert_with(|| name.clone());
+        }
+        listed.push(canonical);
+    }
+    let mut contents = header;
+    contents.push('\n');
+    for (symbol, library) in &symbols {
+        contents.push_str(&format!("{}\t{}\n", symbol, library));
+    }
+    if let Err(err) = fs::write(&cache, contents) {
+        debug!("Failed to write {}: {}", cache.display(), err);
+    }
+    symbols
+}
+
+/// Warn about libraries the given plugin libraries link that Houdini bundles another version of,
+/// and about symbols they export that the libraries bundled with Houdini export as well.
+///
+/// Symbols listed in `exports` are exported on purpose and aren't checked.
+pub fn check(plugins: &[PathBuf], hfs: &Path, build_dir: &Path, exports: &[String]) {
+    let dir = houdini::library_dir(hfs, false);
+    let bundled = libraries_in(&dir);
+    if bundled.is_empty() {
+        debug!("No libraries found in {}", dir.display());
+        return;
+    }
+    let mut houdini_symbols = None;
+    for plugin in plugins {
+        let mut problems = Vec::new();
+        for library in linked_libraries(plugin).unwrap_or_default() {
+            if bundled.contains_key(&library) {
+                continue;
+            }
+            let versions: Vec<_> = bundled
+                .keys()
+                .filter(|name| base_name(name) == base_name(&library))
+                .map(String::as_str)
+                .collect();
+            if !versions.is_empty() {
+                problems.push(format!(
+                    "  links {}, but Houdini loads its own {}",
+                    library,
+                    versions.join(", ")
+                ));
+            }
+        }
+
+        let unexpected = exports::unexpected_exports(plugin, exports).unwrap_or_default();
+        if !unexpected.is_empty() {
+            let symbols = houdini_symbols.get_or_insert_with(|| bundled_symbols(&dir, build_dir));
+            let mut clashes: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+            for symbol in &unexpected {
+                if let Some(library) = symbols.get(symbol) {
+                    clashes.entry(library).or_default().push(symbol);
+                }
+            }
+            for (library, symbols) in clashes {
+                let mut line = format!(
+                    "  exports symbols of {}: {}",
+                    library,
+                    symbols[..symbols.len().min(MAX_SYMBOLS)].join(", ")
+                );
+                if symbols.len() > MAX_SYMBOLS {
+                    line.push_str(&format!(" and {} more", symbols.len() - MAX_SYMBOLS));
+                }
+                problems.push(line);
+            }
+        }
+
+        if !problems.is_empty() {
+            warn!(
+                "{} clashes with the libraries bundled with Houdini in {}, so calls may be bound to the wrong version of them and crash:\n{}\nLink the libraries in {} instead of other versions, and hide the symbols of statically linked libraries with '--trim-exports'.",
+                plugin.display(),
+                dir.display(),
+                problems.join("\n"),
+                dir.display()
+            );
+        }
+    }
+}
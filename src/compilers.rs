@@ -0,0 +1,191 @@
+//! Selection of the C and C++ compilers of the HDK plugin with '--cc' and '--cxx'.
+//!
+//! A compiler given by name or path is passed to CMake as 'CMAKE_C_COMPILER' or
+//! 'CMAKE_CXX_COMPILER'. With 'auto', the installed compilers are searched for one matching the
+//! toolchain the Houdini installation is built with: on Linux a GCC with one of the major versions
+//! of the Houdini release, including the GCC toolsets of Red Hat based distributions in '/opt/rh',
+//! and on macOS the Apple clang of Xcode. On Windows the Visual Studio toolset is selected by the
+//! generator or the developer command prompt instead, so 'auto' leaves the compiler to CMake.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{Context, Result};
+use log::*;
+
+use crate::cmake;
+use crate::houdini::{self, Version};
+use crate::tools;
+
+/// The value of '--cc' and '--cxx' selecting a compiler matching the Houdini installation.
+pub const AUTO: &str = "auto";
+
+/// The major version of the GCC at `program`, or `None` if it isn't GCC.
+fn gcc_major(program: &Path) -> Option<u32> {
+    let output = Command::new(program).arg("--version").output().ok()?;
+    let text = String::from_utf8_lossy(&output.stdout);
+    let first = text.lines().next()?;
+    // clang answers to 'gcc' on macOS and some distributions.
+    if !output.status.success() || first.to_lowercase().contains("clang") {
+        return None;
+    }
+    let output = Command::new(program).arg("-dumpversion").output().ok()?;
+    String::from_utf8_lossy(&output.stdout)
+        .trim()
+        .split('.')
+        .next()?
+        .parse()
+        .ok()
+}
+
+/// The C and C++ compilers of a GCC installation with the given major version, if any.
+///
+/// The versioned names of Debian based distributions, the GCC toolsets of Red Hat based
+/// distributions and the default 'gcc' are tried in that order.
+fn find_gcc(major: u32) -> Option<(PathBuf, PathBuf)> {
+    let mut candidates = vec![(
+        tools::find_program(&format!("gcc-{}", major)),
+        tools::find_program(&format!("g++-{}", major)),
+    )];
+    for toolset in ["gcc-toolset", "devtoolset"] {
+        let bin = Path::new("/opt/rh")
+            .join(format!("{}-{}", toolset, major))
+            .join("root/usr/bin");
+        candidates.push((Some(bin.join("gcc")), Some(bin.join("g++"))));
+    }
+    candidates.push((tools::find_program("gcc"), tools::find_program("g++")));
+    candidates
+        .into_iter()
+        .find_map(|candidate| match candidate {
+            (Some(cc), Some(cxx))
+                if cc.is_file() && cxx.is_file() && gcc_major(&cxx) == Some(major) =>
+            {
+                Some((cc, cxx))
+            }
+            _ => None,
+        })
+}
+
+/// The clang of the selected Xcode or command line tools, which Houdini is built with on macOS.
+fn find_apple_clang() -> Option<(PathBuf, PathBuf)> {
+    let find = |name: &str| {
+        let output = Command::new("xcrun").args(["--find", name]).output().ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        let path = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        Some(PathBuf::from(path)).filter(|path| path.is_file())
+    };
+    Some((find("clang")?, find("clang++")?))
+}
+
+/// The installed C and C++ compilers matching the toolchain Houdini `version` is built with, or
+/// `None` on platforms where CMake selects them.
+fn auto_select(version: &Version) -> Result<Option<(PathBuf, PathBuf)>> {
+    if cfg!(target_os = "macos") {
+        return find_apple_clang()
+            .context("Couldn't find the clang of Xcode with 'xcrun', which '--cxx auto' selects on macOS. Install the Xcode command line tools with 'xcode-select --install'.")
+            .map(Some);
+    }
+    if !cfg!(target_os = "linux") {
+        warn!("'auto' doesn't select a compiler on Windows, where the Visual Studio toolset is selected by the generator or the developer command prompt.");
+        return Ok(None);
+    }
+    let (gcc_majors, _) = houdini::compilers(version);
+    // The newest version Houdini is built with is preferred.
+    match gcc_majors.iter().rev().find_map(|&major| find_gcc(major)) {
+        Some(compilers) => Ok(Some(compilers)),
+        None => {
+            let majors: Vec<_> = gcc_majors.iter().map(|major| major.to_string()).collect();
+            let newest = majors.last().map_or("", String::as_str);
+            bail!(
+                "None of the installed compilers matches Houdini {}, which is built with GCC {}. Install GCC {}, e.g. the 'g++-{}' package or the 'gcc-toolset-{}' of Red Hat based distributions, or pass the compilers with '--cc' and '--cxx'.",
+                version,
+                majors.join(" or "),
+                newest,
+                newest,
+                newest
+            );
+        }
+    }
+}
+
+/// The path of a compiler given by name or path to `option`.
+fn find(compiler: &str, option: &str) -> Result<PathBuf> {
+    let path = Path::new(compiler);
+    if path.components().count() > 1 {
+        if !path.is_file() {
+            bail!(
+                "The compiler {} given by '{}' doesn't exist",
+                compiler,
+                option
+            );
+        }
+        return Ok(path.to_path_buf());
+    }
+    tools::find_program(compiler).with_context(|| {
+        format!(
+            "Couldn't find the compiler '{}' given by '{}' on the 'PATH'",
+            compiler, option
+        )
+    })
+}
+
+/// The C and C++ compilers given by '--cc' and '--cxx', with 'auto' resolved for the Houdini
+/// installation at `hfs`.
+///
+/// 'auto' for one of them also selects the other, unless that is given explicitly.
+pub fn select(
+    cc: Option<&str>,
+    cxx: Option<&str>,
+    hfs: &Path,
+) -> Result<(Option<PathBuf>, Option<PathBuf>)> {
+    let auto = if cc == Some(AUTO) || cxx == Some(AUTO) {
+        let version = houdini::version(hfs)?;
+        let selected = auto_select(&version)?;
+        if let Some((_, cxx)) = &selected {
+            info!(
+                "Building the HDK plugin with {}, which matches Houdini {}.",
+                cxx.display(),
+                version
+            );
+        }
+        selected
+    } else {
+        None
+    };
+    let resolve = |given: Option<&str>, option: &str, auto: Option<PathBuf>| match given {
+        Some(AUTO) | None => Ok(auto),
+        Some(compiler) => find(compiler, option).map(Some),
+    };
+    let (auto_cc, auto_cxx) = match auto {
+        Some((cc, cxx)) => (Some(cc), Some(cxx)),
+        None => (None, None),
+    };
+    Ok((
+        resolve(cc, "--cc", auto_cc)?,
+        resolve(cxx, "--cxx", auto_cxx)?,
+    ))
+}
+
+/// CMake arguments selecting the compilers given by '--cc' and '--cxx', leaving out those set in
+/// `cmake_args`.
+pub fn cmake_args(
+    cc: Option<&str>,
+    cxx: Option<&str>,
+    hfs: &Path,
+    cmake_args: &[String],
+) -> Result<Vec<String>> {
+    if cc.is_none() && cxx.is_none() {
+        return Ok(Vec::new());
+    }
+    let (cc, cxx) = select(cc, cxx, hfs)?;
+    let arg = |path: Option<PathBuf>| path.map(|path| path.display().to_string());
+    Ok(cmake::default_var_args(
+        vec![
+            ("CMAKE_C_COMPILER", arg(cc)),
+            ("CMAKE_CXX_COMPILER", arg(cxx)),
+        ],
+        cmake_args,
+    ))
+}
@@ -0,0 +1,104 @@
+//! Shell completion scripts generated from the command line definition by
+//! `cargo hdk completions <SHELL>`.
+//!
+//! The commands selected by the first build argument (e.g. 'run' or 'test') are added as
+//! subcommands accepting the same options, so they complete like the rest of the command line.
+//! The versions of the Houdini installations found when the script is generated are offered as
+//! values of '--houdini-version'.
+
+use std::io;
+
+use anyhow::Result;
+use clap::{Arg, Command, ValueEnum};
+use clap_complete::Shell;
+use log::*;
+
+use crate::houdini;
+
+/// The commands given as the first build argument, with a short description.
+const COMMANDS: &[(&str, &str)] = &[
+    (
+        "check",
+        "Validate the build without compiling the HDK plugin",
+    ),
+    ("new", "Generate a new plugin crate"),
+    ("run", "Build the plugin and launch Houdini with it"),
+    (
+        "debug",
+        "Build the plugin and launch Houdini under a debugger",
+    ),
+    ("test", "Build the plugin and run its tests with hython"),
+    ("cov", "Measure the Rust and C++ coverage of the tests"),
+    ("bench", "Build the plugin and time its benchmarks"),
+    ("verify", "Build the plugin and check that it loads"),
+    ("size", "Build the plugin and break down its size"),
+    ("bindgen", "Copy the generated headers into the HDK plugin"),
+    ("rust-bindings", "Generate Rust FFI for HDK headers"),
+    ("migrate", "Upgrade the project to the current conventions"),
+    ("prune", "Remove stale build state"),
+    ("hda", "Expand or collapse digital assets"),
+    ("lint", "Analyze the C++ sources with clang-tidy"),
+    ("fmt-cpp", "Format the C++ sources with clang-format"),
+    ("ide", "Generate VS Code, CLion or clangd configurations"),
+    ("shell", "Start a shell with the plugin environment"),
+    ("watch", "Rebuild the plugin on changes"),
+    ("doctor", "Check the build environment"),
+    ("env", "Print the resolved build environment"),
+    ("config", "Print the effective configuration"),
+    ("completions", "Print a shell completion script"),
+];
+
+/// The versions of the Houdini installations found in the default installation locations, both
+/// as '<major>.<minor>' and in full.
+pub fn houdini_versions() -> Vec<String> {
+    let mut versions: Vec<_> = houdini::installations()
+        .into_iter()
+        .flat_map(|(version, _)| [version.short(), version.to_string()])
+        .collect();
+    versions.sort();
+    versions.dedup();
+    debug!("Completing the Houdini versions {:?}", versions);
+    versions
+}
+
+/// Print the completion script for the shell named by the first argument.
+pub fn generate<'a>(app: Command<'a>, versions: &'a [String], args: &[String]) -> Result<()> {
+    let shell = match args.first() {
+        Some(name) => Shell::from_str(name, true).map_err(|_| {
+            anyhow!(
+                "Unsupported shell '{}'. Use one of 'bash', 'elvish', 'fish', 'powershell' or 'zsh'.",
+                name
+            )
+        })?,
+        None => bail!("Missing shell. Usage: cargo hdk completions <bash|elvish|fish|powershell|zsh>"),
+    };
+
+    let app = if versions.is_empty() {
+        app
+    } else {
+        app.mut_arg("houdini-version", |arg| {
+            arg.possible_values(versions.iter().map(String::as_str))
+        })
+    };
+    // The options of each command are the same as for a plain build.
+    let args: Vec<Arg> = app
+        .get_arguments()
+        .filter(|arg| !matches!(arg.get_id(), "help" | "version"))
+        .cloned()
+        .collect();
+    let shells: Vec<_> = Shell::value_variants()
+        .iter()
+        .filter_map(|shell| shell.to_possible_value())
+        .collect();
+    let mut app = app.subcommands(COMMANDS.iter().map(|&(name, about)| {
+        let command = Command::new(name).about(about);
+        if name == "completions" {
+            command.arg(Arg::new("SHELL").possible_values(shells.clone()))
+        } else {
+            command.args(args.clone())
+        }
+    }));
+
+    clap_complete::generate(shell, &mut app, "cargo-hdk", &mut io::stdout());
+    Ok(())
+}
@@ -0,0 +1,894 @@
+//! Configuration of cargo-hdk in the `[package.metadata.hdk]` table of the `Cargo.toml`, the
+//! `hdk.toml` file at the workspace root and the user configuration file.
+//!
+//! Keys are named after the corresponding command line options with dashes replaced by
+//! underscores, e.g.
+//!
+//! ```toml
+//! [package.metadata.hdk]
+//! hdk_path = "plugin"
+//! generator = "Ninja"
+//! cmake = "-DMY_OPTION=ON"
+//! deps = ["hdkrs", "mycrate-sys"]
+//! install_dir = "dist"
+//! houdini_version = ">=19.5, <21"
+//! ```
+//!
+//! Settings for a single platform can be given in `windows`, `macos` and `linux` tables, e.g.
+//! `[package.metadata.hdk.windows]`, which override the other settings of the same file on that
+//! platform.
+//!
+//! Paths may start with `~` for the home directory and refer to environment variables as `$VAR`
+//! or `${VAR}`. A relative `install_dir`, `hfs`, `test_dir`, `bench_baseline`, Rust bindings
+//! output, digital asset or snapshot and benchmark file is resolved against the directory
+//! containing the file it is set in, i.e. the crate root for the package metadata.
+//!
+//! The `hdk.toml` file accepts the same keys at the top level and provides shared settings for
+//! all packages in the workspace, which are overridden by the package metadata. Machine specific
+//! settings can be given in the same way in `~/.config/cargo-hdk/config.toml`, which has the
+//! lowest priority. Options given on the command line take precedence over all of them.
+
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use cargo_metadata::camino::Utf8PathBuf;
+use cargo_metadata::semver::VersionReq;
+use cargo_metadata::{Metadata, Package};
+use log::*;
+use serde::{Deserialize, Serialize};
+
+use crate::CompilerCache;
+
+/// A setting given either as a single string or a list of strings, like the CMake variables set
+/// by a cargo feature or the commands of a hook.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(untagged, expecting = "expected a string or a list of strings")]
+pub enum OneOrMany {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl OneOrMany {
+    pub fn as_slice(&self) -> &[String] {
+        match self {
+            OneOrMany::One(value) => std::slice::from_ref(value),
+            OneOrMany::Many(values) => values,
+        }
+    }
+}
+
+/// A test comparing the geometry cooked by a node with a checked-in golden file.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Snapshot {
+    /// Scene file containing the node network.
+    pub hip: Option<PathBuf>,
+    /// Python script run after loading the scene, e.g. to build the node network.
+    pub script: Option<PathBuf>,
+    /// Path of the SOP node whose geometry is compared, e.g. '/obj/geo1/OUT'.
+    pub node: String,
+    /// File with the expected geometry in a format Houdini can read, e.g. '.bgeo.sc' or '.obj'.
+    pub golden: PathBuf,
+    /// Largest absolute difference allowed between floating point attribute values.
+    pub tolerance: Option<f64>,
+    /// Tolerances of specific attributes, overriding 'tolerance'.
+    #[serde(default)]
+    pub tolerances: BTreeMap<String, f64>,
+}
+
+/// A headless render of a ROP checking that the plugin renders, run by 'cargo hdk test --render'.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Render {
+    /// Scene file containing the node network.
+    pub hip: Option<PathBuf>,
+    /// Python script run after loading the scene, e.g. to build the node network.
+    pub script: Option<PathBuf>,
+    /// Path of the render node, e.g. '/out/karma1' or '/out/mantra1'.
+    pub rop: String,
+    /// Frame rendered. Defaults to the current frame of the scene.
+    pub frame: Option<f64>,
+    /// Resolution overriding that of the camera, to keep the render fast.
+    pub resolution: Option<[u32; 2]>,
+    /// Image the render is compared with. Without it, the render only has to complete.
+    pub reference: Option<PathBuf>,
+    /// Largest difference of a channel value allowed between the downscaled images.
+    pub tolerance: Option<f64>,
+    /// Width in pixels both images are downscaled to before comparing them, so noise and
+    /// small differences between renderer versions are averaged out.
+    pub compare_width: Option<u32>,
+}
+
+/// A benchmark timing the cooks of a node.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Bench {
+    /// Scene file containing the node network.
+    pub hip: Option<PathBuf>,
+    /// Python script run after loading the scene, e.g. to build the node network.
+    pub script: Option<PathBuf>,
+    /// Path of the node whose cooks are timed, e.g. '/obj/geo1/my_plugin1'.
+    pub node: String,
+    /// Number of timed cooks.
+    pub iterations: Option<u32>,
+    /// Slowdown of the mean cook time relative to the baseline in percent that fails the run.
+    pub threshold: Option<f64>,
+}
+
+/// Rust FFI generated from HDK headers by 'cargo hdk rust-bindings'.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct RustBindings {
+    /// HDK headers relative to '$HFS/toolkit/include', e.g. 'GU/GU_Detail.h'.
+    pub headers: Vec<String>,
+    /// Rust module the bindings are written to. Defaults to 'src/hdk_sys.rs' in the crate.
+    pub output: Option<PathBuf>,
+    /// Patterns of the types, functions and variables to generate bindings for.
+    #[serde(default)]
+    pub allowlist: Vec<String>,
+    /// Patterns of the items to leave out.
+    #[serde(default)]
+    pub blocklist: Vec<String>,
+    /// Patterns of the types generated as opaque blobs, e.g. complex templates.
+    #[serde(default)]
+    pub opaque: Vec<String>,
+    /// Additional arguments for bindgen.
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+/// Settings of the clang-tidy run of 'cargo hdk lint'.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Lint {
+    /// clang-tidy checks, e.g. ['-*', 'bugprone-*', 'performance-*']. Defaults to the checks of a
+    /// '.clang-tidy' file, or to a set suited for HDK plugins without one.
+    #[serde(default)]
+    pub checks: Vec<String>,
+    /// Regular expression of the headers whose diagnostics are reported. Defaults to the headers
+    /// in the HDK plugin directory.
+    pub header_filter: Option<String>,
+    /// Additional arguments for clang-tidy.
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+/// Settings of the code signing with '--codesign' and notarization with '--notarize' on macOS.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Codesign {
+    /// Signing identity, e.g. 'Developer ID Application: Name (TEAMID)', or '-' for ad hoc
+    /// signing. Overridden by '--codesign=<IDENTITY>'.
+    pub identity: Option<String>,
+    /// Entitlements property list embedded in the signature.
+    pub entitlements: Option<PathBuf>,
+    /// Keychain profile with the notary service credentials, stored with
+    /// 'xcrun notarytool store-credentials'.
+    pub keychain_profile: Option<String>,
+    /// Additional arguments for codesign.
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+/// Newest symbol versions of the C and C++ runtime the plugin may require on Linux, e.g. those of
+/// the oldest distribution of a render farm. Versions that aren't set default to the newest ones
+/// required by the Houdini libraries.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SymbolVersions {
+    /// glibc version, e.g. '2.28' for Rocky Linux 8.
+    pub glibc: Option<String>,
+    /// libstdc++ version, e.g. '3.4.25' for Rocky Linux 8.
+    pub glibcxx: Option<String>,
+    /// C++ ABI version of libstdc++, e.g. '1.3.11' for Rocky Linux 8.
+    pub cxxabi: Option<String>,
+}
+
+/// How the plugin libraries installed with '--cmake-install' are named and placed, so the same
+/// project installs conventionally named plugins on every platform.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PluginNaming {
+    /// Remove the 'lib' prefix CMake gives libraries on Linux and macOS, e.g. install
+    /// 'libSOP_Twist.so' as 'SOP_Twist.so'. Defaults to true.
+    pub strip_lib_prefix: Option<bool>,
+    /// Subdirectory of the 'dso' directory the plugin libraries are moved into, e.g. 'fs' for
+    /// file system plugins.
+    pub dso_subdir: Option<PathBuf>,
+}
+
+/// The Windows HDK and SDK used when cross compiling a Windows plugin on Linux or macOS.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CrossWindows {
+    /// Copy of a Windows Houdini installation, with at least its 'toolkit' and 'custom'
+    /// directories.
+    pub hfs: Option<PathBuf>,
+    /// MSVC C runtime and Windows SDK created with 'xwin splat'. Defaults to the one downloaded
+    /// by 'cargo xwin'.
+    pub sdk: Option<PathBuf>,
+}
+
+/// A digital asset of the project, kept in source control as an expanded directory and converted
+/// to and from the binary file with 'cargo hdk hda'.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Hda {
+    /// The binary asset library, e.g. 'otls/sop_twist.hda'.
+    pub file: PathBuf,
+    /// The directory the asset library is expanded into, e.g. 'hda/sop_twist'.
+    pub dir: PathBuf,
+}
+
+/// Name of the workspace configuration file.
+pub const WORKSPACE_CONFIG: &str = "hdk.toml";
+
+/// Path to the user configuration file.
+///
+/// This is '$XDG_CONFIG_HOME/cargo-hdk/config.toml' (by default '~/.config') on Linux and macOS,
+/// and '%APPDATA%\cargo-hdk\config.toml' on Windows.
+pub fn user_config_path() -> Option<PathBuf> {
+    let config_dir = if cfg!(windows) {
+        std::env::var_os("APPDATA").map(PathBuf::from)
+    } else {
+        std::env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+    }?;
+    Some(config_dir.join("cargo-hdk").join("config.toml"))
+}
+
+/// Where a layer of the configuration was read from.
+#[derive(Clone, Debug)]
+pub enum Source {
+    /// The `[package.metadata.hdk]` table in the given manifest.
+    Package(Utf8PathBuf),
+    /// The workspace configuration file.
+    Workspace(PathBuf),
+    /// The user configuration file.
+    User(PathBuf),
+    /// The profile selected with '--hdk-profile'.
+    Profile(String),
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Source::Package(path) => write!(f, "package metadata in {}", path),
+            Source::Workspace(path) => write!(f, "workspace config {}", path.display()),
+            Source::User(path) => write!(f, "user config {}", path.display()),
+            Source::Profile(name) => write!(f, "profile '{}'", name),
+        }
+    }
+}
+
+/// Settings read from the `[package.metadata.hdk]` table or a configuration file.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Config {
+    pub hdk_path: Option<PathBuf>,
+    pub build_dir: Option<String>,
+    pub cmake: Option<String>,
+    pub generator: Option<String>,
+    pub cache: Option<CompilerCache>,
+    pub deps: Option<Vec<String>>,
+    pub deps_of: Option<Vec<String>>,
+    pub out_dir_file_prefix: Option<String>,
+    pub out_dir_cmake: Option<PathBuf>,
+    pub rust_targets_cmake: Option<PathBuf>,
+    pub artifacts_cmake: Option<PathBuf>,
+    /// C compiler of the HDK plugin, or 'auto'.
+    pub cc: Option<String>,
+    /// C++ compiler of the HDK plugin, or 'auto'.
+    pub cxx: Option<String>,
+    /// Docker or Podman image the build runs in.
+    pub container: Option<String>,
+    /// Default install prefix for '--cmake-install', relative to the crate root. Use
+    /// `Config::install_dir` for the expanded path.
+    pub install_dir: Option<PathBuf>,
+    /// The directory `install_dir` is relative to, with the key and the file it was read from.
+    #[serde(skip)]
+    install_dir_origin: Option<(PathBuf, String)>,
+    /// Houdini installation used if 'HFS' is not set.
+    pub hfs: Option<PathBuf>,
+    /// Directory of the hython test scripts run by 'cargo hdk test'.
+    pub test_dir: Option<PathBuf>,
+    /// Houdini versions the plugin can be built against.
+    pub houdini_version: Option<VersionReq>,
+    /// CMake build types for custom cargo profiles.
+    pub build_types: BTreeMap<String, String>,
+    /// CMake variables set for each cargo feature.
+    pub features: BTreeMap<String, OneOrMany>,
+    /// Commands run before the build.
+    pub pre_build: Option<OneOrMany>,
+    /// Commands run after the HDK plugin is built.
+    pub post_build: Option<OneOrMany>,
+    /// Commands run after the HDK plugin is installed with '--cmake-install'.
+    pub post_install: Option<OneOrMany>,
+    /// Hscript commands sent to a running Houdini session after a build with '--reload'.
+    pub reload: Option<OneOrMany>,
+    /// Nodes recooked in a running Houdini session after a build with '--reload'.
+    pub reload_nodes: Option<Vec<String>>,
+    /// Environment variables set for the build.
+    pub env: BTreeMap<String, String>,
+    /// Additional arguments for the cargo build.
+    pub cargo_args: Option<Vec<String>>,
+    /// Golden geometry tests run by 'cargo hdk test'.
+    pub snapshots: BTreeMap<String, Snapshot>,
+    /// Headless renders run by 'cargo hdk test --render'.
+    pub renders: BTreeMap<String, Render>,
+    /// Cook time benchmarks run by 'cargo hdk bench'.
+    pub benches: BTreeMap<String, Bench>,
+    /// File with the benchmark results compared against by 'cargo hdk bench'.
+    pub bench_baseline: Option<PathBuf>,
+    /// HDK headers to generate Rust FFI for with 'cargo hdk rust-bindings'.
+    pub rust_bindings: Option<RustBindings>,
+    /// clang-tidy settings of 'cargo hdk lint'.
+    pub lint: Option<Lint>,
+    /// Code signing settings of '--codesign' and '--notarize'.
+    pub codesign: Option<Codesign>,
+    /// Symbol versions the plugin libraries are checked against on Linux.
+    pub symbol_versions: Option<SymbolVersions>,
+    /// Symbols the plugin libraries export on purpose besides the entry points Houdini calls, as
+    /// glob patterns.
+    pub exports: Option<Vec<String>>,
+    /// Names and locations of the installed plugin libraries.
+    pub plugin_naming: Option<PluginNaming>,
+    /// Windows HDK and SDK used when cross compiling for Windows.
+    pub cross_windows: Option<CrossWindows>,
+    /// Digital assets expanded and collapsed by 'cargo hdk hda'.
+    pub hdas: BTreeMap<String, Hda>,
+    /// Named sets of settings selected with '--hdk-profile'.
+    pub profiles: BTreeMap<String, Config>,
+    /// Settings used only on Windows.
+    pub windows: Option<Box<Config>>,
+    /// Settings used only on macOS.
+    pub macos: Option<Box<Config>>,
+    /// Settings used only on Linux.
+    pub linux: Option<Box<Config>>,
+}
+
+impl Config {
+    /// Merge the given layers of the configuration, ordered from the highest to the lowest
+    /// priority.
+    pub fn from_layers(layers: &[(Source, Config)]) -> Config {
+        layers
+            .iter()
+            .map(|(_, config)| config.clone())
+            .reduce(Config::merge)
+            .unwrap_or_default()
+    }
+
+    /// The settings of the profile with the given name.
+    pub fn profile(&self, name: &str) -> Result<Config> {
+        match self.profiles.get(name) {
+            Some(profile) => Ok(Config {
+                profiles: BTreeMap::new(),
+                ..profile.clone()
+            }),
+            None if self.profiles.is_empty() => {
+                bail!("Profile '{}' not found. No profiles are configured.", name)
+            }
+            None => bail!(
+                "Profile '{}' not found. Available profiles are: {}",
+                name,
+                self.profiles
+                    .keys()
+                    .map(String::as_str)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        }
+    }
+
+    /// Load each layer of the configuration of the given package separately, ordered from the
+    /// highest to the lowest priority: the package metadata, the workspace configuration and
+    /// the user configuration, unless `user` is false.
+    pub fn load_layers(
+        metadata: &Metadata,
+        package: &Package,
+        user: bool,
+    ) -> Result<Vec<(Source, Config)>> {
+        let mut layers = vec![(
+            Source::Package(package.manifest_path.clone()),
+            Config::from_package(package)?,
+        )];
+        let workspace_config = metadata.workspace_root.join(WORKSPACE_CONFIG);
+        if let Some(workspace) = Config::from_file(workspace_config.as_std_path())? {
+            layers.push((Source::Workspace(workspace_config.into()), workspace));
+        }
+        if user {
+            if let Some(path) = user_config_path() {
+                if let Some(user) = Config::from_file(&path)? {
+                    layers.push((Source::User(path), user));
+                }
+            }
+        }
+        Ok(layers)
+    }
+
+    /// Read the configuration from the given TOML file if it exists.
+    ///
+    /// Paths are expanded and resolved against the directory containing the file.
+    pub fn from_file(path: &Path) -> Result<Option<Config>> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => {
+                return Err(err).with_context(|| format!("Failed to read {}", path.display()))
+            }
+        };
+        debug!("Reading configuration from {}", path.display());
+        let mut config = Config::deserialize_checked(
+            &mut toml::Deserializer::new(&contents),
+            "",
+            &path.display(),
+        )?;
+        config = config.for_current_platform();
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        config.resolve_paths(dir, "", &path.display())?;
+        Ok(Some(config))
+    }
+
+    /// Combine this configuration with a lower priority one, using the values of `base` for all
+    /// settings missing here.
+    pub fn merge(self, base: Config) -> Config {
+        let mut build_types = base.build_types;
+        build_types.extend(self.build_types);
+        let mut features = base.features;
+        features.extend(self.features);
+        let mut env = base.env;
+        env.extend(self.env);
+        let mut snapshots = base.snapshots;
+        snapshots.extend(self.snapshots);
+        let mut renders = base.renders;
+        renders.extend(self.renders);
+        let mut benches = base.benches;
+        benches.extend(self.benches);
+        let mut hdas = base.hdas;
+        hdas.extend(self.hdas);
+        let mut profiles = base.profiles;
+        profiles.extend(self.profiles);
+        Config {
+            hdk_path: self.hdk_path.or(base.hdk_path),
+            build_dir: self.build_dir.or(base.build_dir),
+            cmake: self.cmake.or(base.cmake),
+            generator: self.generator.or(base.generator),
+            cache: self.cache.or(base.cache),
+            deps: self.deps.or(base.deps),
+            deps_of: self.deps_of.or(base.deps_of),
+            out_dir_file_prefix: self.out_dir_file_prefix.or(base.out_dir_file_prefix),
+            out_dir_cmake: self.out_dir_cmake.or(base.out_dir_cmake),
+            rust_targets_cmake: self.rust_targets_cmake.or(base.rust_targets_cmake),
+            artifacts_cmake: self.artifacts_cmake.or(base.artifacts_cmake),
+            cc: self.cc.or(base.cc),
+            cxx: self.cxx.or(base.cxx),
+            container: self.container.or(base.container),
+            install_dir_origin: if self.install_dir.is_some() {
+                self.install_dir_origin
+            } else {
+                base.install_dir_origin
+            },
+            install_dir: self.install_dir.or(base.install_dir),
+            hfs: self.hfs.or(base.hfs),
+            test_dir: self.test_dir.or(base.test_dir),
+            houdini_version: self.houdini_version.or(base.houdini_version),
+            build_types,
+            features,
+            pre_build: self.pre_build.or(base.pre_build),
+            post_build: self.post_build.or(base.post_build),
+            post_install: self.post_install.or(base.post_install),
+            reload: self.reload.or(base.reload),
+            reload_nodes: self.reload_nodes.or(base.reload_nodes),
+            env,
+            cargo_args: self.cargo_args.or(base.cargo_args),
+            snapshots,
+            renders,
+            benches,
+            bench_baseline: self.bench_baseline.or(base.bench_baseline),
+            rust_bindings: self.rust_bindings.or(base.rust_bindings),
+            lint: self.lint.or(base.lint),
+            codesign: self.codesign.or(base.codesign),
+            symbol_versions: self.symbol_versions.or(base.symbol_versions),
+            plugin_naming: self.plugin_naming.or(base.plugin_naming),
+            exports: self.exports.or(base.exports),
+            cross_windows: self.cross_windows.or(base.cross_windows),
+            hdas,
+            profiles,
+            windows: self.windows.or(base.windows),
+            macos: self.macos.or(base.macos),
+            linux: self.linux.or(base.linux),
+        }
+    }
+
+    /// Read the configuration from the metadata of the given package.
+    pub fn from_package(package: &Package) -> Result<Config> {
+        match package.metadata.get("hdk") {
+            Some(hdk) => {
+                let prefix = "package.metadata.hdk.";
+                let mut config = Config::deserialize_checked(hdk, prefix, &package.manifest_path)?
+                    .for_current_platform();
+                let dir = package
+                    .manifest_path
+                    .parent()
+                    .context("Failed to find manifest directory")?;
+                config.resolve_paths(dir.as_std_path(), prefix, &package.manifest_path)?;
+                Ok(config)
+            }
+            None => Ok(Config::default()),
+        }
+    }
+
+    /// Apply the settings for the current platform over the other settings, also within profiles.
+    fn for_current_platform(mut self) -> Config {
+        let windows = self.windows.take();
+        let macos = self.macos.take();
+        let linux = self.linux.take();
+        let platform = if cfg!(windows) {
+            windows
+        } else if cfg!(target_os = "macos") {
+            macos
+        } else if cfg!(target_os = "linux") {
+            linux
+        } else {
+            None
+        };
+        let mut config = match platform {
+            Some(platform) => platform.for_current_platform().merge(self),
+            None => self,
+        };
+        config.profiles = std::mem::take(&mut config.profiles)
+            .into_iter()
+            .map(|(name, profile)| (name, profile.for_current_platform()))
+            .collect();
+        config
+    }
+
+    /// Expand '~' and environment variables in all paths, and resolve the Houdini installation and
+    /// the test files against `dir`. The install directory is only expanded and resolved when it
+    /// is used, see `Config::install_dir`.
+    ///
+    /// Other paths are relative to the crate root or the build directory like the corresponding
+    /// options, so they are only expanded here.
+    fn resolve_paths(&mut self, dir: &Path, prefix: &str, source: &dyn fmt::Display) -> Result<()> {
+        let expand = |key: &str, value: &str| {
+            expand_path(value)
+                .with_context(|| format!("Invalid value for '{}{}' in {}", prefix, key, source))
+        };
+        // Placeholders in the build directory need a Unicode path, unlike the other paths.
+        if let Some(path) = &self.build_dir {
+            self.build_dir = Some(expand("build_dir", path)?.into_string().map_err(|path| {
+                anyhow!(
+                    "'{}build_dir' in {} expands to {:?}, which is not a Unicode path",
+                    prefix,
+                    source,
+                    path
+                )
+            })?);
+        }
+        macro_rules! expand {
+            ($($field:ident),*) => {
+                $(
+                    if let Some(path) = &self.$field {
+                        let path = expand(stringify!($field), &path.to_string_lossy())?;
+                        self.$field = Some(path.into());
+                    }
+                )*
+            };
+        }
+        expand!(hdk_path, out_dir_cmake, rust_targets_cmake, artifacts_cmake);
+        macro_rules! resolve {
+            ($($field:ident),*) => {
+                $(
+                    if let Some(path) = &self.$field {
+                        let path = expand(stringify!($field), &path.to_string_lossy())?;
+                        self.$field = Some(dir.join(path));
+                    }
+                )*
+            };
+        }
+        resolve!(hfs, test_dir, bench_baseline);
+        // The install directory may refer to the variables of the Houdini installation, like
+        // 'HOUDINI_MAJOR_RELEASE', which are only set once it is found.
+        if self.install_dir.is_some() {
+            let key = format!("'{}install_dir' in {}", prefix, source);
+            self.install_dir_origin = Some((dir.to_path_buf(), key));
+        }
+        for (name, snapshot) in &mut self.snapshots {
+            let key = |field: &str| format!("snapshots.{}.{}", name, field);
+            for (field, path) in [("hip", &mut snapshot.hip), ("script", &mut snapshot.script)] {
+                if let Some(value) = path {
+                    *path = Some(dir.join(expand(&key(field), &value.to_string_lossy())?));
+                }
+            }
+            snapshot.golden = dir.join(expand(&key("golden"), &snapshot.golden.to_string_lossy())?);
+        }
+        for (name, render) in &mut self.renders {
+            let key = |field: &str| format!("renders.{}.{}", name, field);
+            for (field, path) in [
+                ("hip", &mut render.hip),
+                ("script", &mut render.script),
+                ("reference", &mut render.reference),
+            ] {
+                if let Some(value) = path {
+                    *path = Some(dir.join(expand(&key(field), &value.to_string_lossy())?));
+                }
+            }
+        }
+        for (name, bench) in &mut self.benches {
+            let key = |field: &str| format!("benches.{}.{}", name, field);
+            for (field, path) in [("hip", &mut bench.hip), ("script", &mut bench.script)] {
+                if let Some(value) = path {
+                    *path = Some(dir.join(expand(&key(field), &value.to_string_lossy())?));
+                }
+            }
+        }
+        for (name, hda) in &mut self.hdas {
+            let key = |field: &str| format!("hdas.{}.{}", name, field);
+            hda.file = dir.join(expand(&key("file"), &hda.file.to_string_lossy())?);
+            hda.dir = dir.join(expand(&key("dir"), &hda.dir.to_string_lossy())?);
+        }
+        if let Some(bindings) = &mut self.rust_bindings {
+            if let Some(output) = &bindings.output {
+                let output = expand("rust_bindings.output", &output.to_string_lossy())?;
+                bindings.output = Some(dir.join(output));
+            }
+        }
+        if let Some(codesign) = &mut self.codesign {
+            if let Some(entitlements) = &codesign.entitlements {
+                let entitlements =
+                    expand("codesign.entitlements", &entitlements.to_string_lossy())?;
+                codesign.entitlements = Some(dir.join(entitlements));
+            }
+        }
+        if let Some(cross) = &mut self.cross_windows {
+            for (field, path) in [("hfs", &mut cross.hfs), ("sdk", &mut cross.sdk)] {
+                if let Some(value) = path {
+                    let key = format!("cross_windows.{}", field);
+                    *path = Some(dir.join(expand(&key, &value.to_string_lossy())?));
+                }
+            }
+        }
+        for (name, profile) in &mut self.profiles {
+            let prefix = format!("{}profiles.{}.", prefix, name);
+            profile.resolve_paths(dir, &prefix, source)?;
+        }
+        Ok(())
+    }
+
+    /// The expanded install directory, resolved against the directory of the file it is set in.
+    pub fn install_dir(&self) -> Result<Option<PathBuf>> {
+        let path = match &self.install_dir {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+        let (dir, key) = match &self.install_dir_origin {
+            Some((dir, key)) => (dir.as_path(), key.as_str()),
+            None => (Path::new(""), "'install_dir'"),
+        };
+        let expanded = expand_path(&path.to_string_lossy())
+            .with_context(|| format!("Invalid value for {}", key))?;
+        Ok(Some(dir.join(expanded)))
+    }
+
+    /// Deserialize the configuration, naming the key of an invalid value in the error and warning
+    /// about unknown keys, which are likely typos.
+    ///
+    /// Keys are reported with the given prefix and `source` names the file they were read from.
+    fn deserialize_checked<'de, D>(
+        deserializer: D,
+        prefix: &str,
+        source: &dyn fmt::Display,
+    ) -> Result<Config>
+    where
+        D: serde::Deserializer<'de>,
+        D::Error: fmt::Display,
+    {
+        let mut unknown = Vec::new();
+        let mut track_unknown = |path: serde_ignored::Path| unknown.push(path.to_string());
+        let deserializer = serde_ignored::Deserializer::new(deserializer, &mut track_unknown);
+        let config: Config = serde_path_to_error::deserialize(deserializer).map_err(|err| {
+            if err.path().iter().next().is_none() {
+                anyhow!("Invalid configuration in {}: {}", source, err.inner())
+            } else {
+                anyhow!(
+                    "Invalid value for '{}{}' in {}: {}",
+                    prefix,
+                    err.path(),
+                    source,
+                    err.inner()
+                )
+            }
+        })?;
+        for key in unknown {
+            crate::print_warning(format_args!(
+                "unknown key '{}{}' in {} is ignored",
+                prefix, key, source
+            ));
+        }
+        Ok(config)
+    }
+}
+
+/// Expand a leading '~' to the home directory and '$VAR' or '${VAR}' to the value of the
+/// environment variable 'VAR'. A literal '$' is written as '$$'.
+///
+/// The home directory and the variables may be paths that aren't valid Unicode.
+pub fn expand_path(value: &str) -> Result<OsString> {
+    let home = || {
+        std::env::var_os(if cfg!(windows) { "USERPROFILE" } else { "HOME" })
+            .context("Failed to expand '~': the home directory is not set")
+    };
+    let mut expanded = OsString::new();
+    let mut rest = value;
+    if rest == "~" || rest.starts_with("~/") || rest.starts_with("~\\") {
+        expanded.push(&home()?);
+        rest = &rest[1..];
+    }
+    while let Some(pos) = rest.find('$') {
+        expanded.push(&rest[..pos]);
+        rest = &rest[pos + 1..];
+        let (name, tail) = if let Some(braced) = rest.strip_prefix('{') {
+            let end = braced
+                .find('}')
+                .with_context(|| format!("Missing '}}' in '{}'", value))?;
+            (&braced[..end], &braced[end + 1..])
+        } else if let Some(tail) = rest.strip_prefix('$') {
+            expanded.push("$");
+            rest = tail;
+            continue;
+        } else {
+            let end = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            rest.split_at(end)
+        };
+        if name.is_empty() {
+            bail!("Missing environment variable name after '$' in '{}'", value);
+        }
+        let var = std::env::var_os(name)
+            .with_context(|| format!("Environment variable '{}' is not set", name))?;
+        expanded.push(&var);
+        rest = tail;
+    }
+    expanded.push(rest);
+    Ok(expanded)
+}
+
+/// Format a key, quoting it unless it is a bare key.
+fn toml_key(key: &str) -> String {
+    if !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        key.to_string()
+    } else {
+        toml::Value::String(key.to_string()).to_string()
+    }
+}
+
+/// Format a value on a single line, using inline tables for nested tables.
+fn inline(value: &toml::Value) -> String {
+    match value {
+        toml::Value::Table(table) => {
+            let entries: Vec<_> = table
+                .iter()
+                .map(|(key, value)| format!("{} = {}", toml_key(key), inline(value)))
+                .collect();
+            format!("{{ {} }}", entries.join(", "))
+        }
+        toml::Value::Array(values) => {
+            let values: Vec<_> = values.iter().map(inline).collect();
+            format!("[{}]", values.join(", "))
+        }
+        value => value.to_string(),
+    }
+}
+
+/// Print the effective configuration as TOML, annotating each value with where it came from.
+///
+/// `override_source` names the source of settings that don't come from a configuration layer,
+/// like command line options, and is checked first.
+pub fn print(
+    effective: &Config,
+    layers: &[(Source, Config)],
+    override_source: impl Fn(&str) -> Option<String>,
+) -> Result<()> {
+    let to_table = |config: &Config| -> Result<toml::value::Table> {
+        match toml::Value::try_from(config).context("Failed to serialize the configuration")? {
+            toml::Value::Table(table) => Ok(table),
+            _ => unreachable!("Config is serialized as a table"),
+        }
+    };
+    let effective = to_table(effective)?;
+    let layers = layers
+        .iter()
+        .map(|(source, config)| Ok((source, to_table(config)?)))
+        .collect::<Result<Vec<_>>>()?;
+
+    // Find the first layer containing the value at the given path.
+    let layer_source = |key: &str, entry: Option<&str>| {
+        layers
+            .iter()
+            .find(|(_, table)| {
+                let value = table.get(key);
+                match entry {
+                    Some(entry) => value.and_then(|v| v.get(entry)).is_some(),
+                    None => value.is_some(),
+                }
+            })
+            .map(|(source, _)| source.to_string())
+    };
+
+    let mut tables = Vec::new();
+    for (key, value) in &effective {
+        if let toml::Value::Table(table) = value {
+            if !table.is_empty() {
+                tables.push((key, table));
+            }
+            continue;
+        }
+        let source = override_source(key)
+            .or_else(|| layer_source(key, None))
+            .unwrap_or_else(|| "default".to_string());
+        println!("{} = {} # {}", key, inline(value), source);
+    }
+    for (key, table) in tables {
+        println!();
+        println!("[{}]", key);
+        for (entry, value) in table {
+            let source = layer_source(key, Some(entry)).unwrap_or_default();
+            println!("{} = {} # {}", toml_key(entry), inline(value), source);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expand_path_home() {
+        let home = std::env::var_os(if cfg!(windows) { "USERPROFILE" } else { "HOME" });
+        if let Some(home) = home {
+            let mut expected = home.clone();
+            expected.push("/plugins");
+            assert_eq!(expand_path("~").unwrap(), home);
+            assert_eq!(expand_path("~/plugins").unwrap(), expected);
+        }
+        // Only a leading '~' naming the home directory is expanded.
+        assert_eq!(expand_path("~user/x").unwrap(), "~user/x");
+        assert_eq!(expand_path("a/~/b").unwrap(), "a/~/b");
+    }
+
+    #[test]
+    fn expand_path_variables() {
+        std::env::set_var("CARGO_HDK_TEST_EXPAND", "/opt/hdk");
+        assert_eq!(
+            expand_path("$CARGO_HDK_TEST_EXPAND/dso").unwrap(),
+            "/opt/hdk/dso"
+        );
+        assert_eq!(
+            expand_path("${CARGO_HDK_TEST_EXPAND}_19.5").unwrap(),
+            "/opt/hdk_19.5"
+        );
+        assert_eq!(
+            expand_path("$CARGO_HDK_TEST_EXPAND_19.5")
+                .unwrap_err()
+                .to_string(),
+            "Environment variable 'CARGO_HDK_TEST_EXPAND_19' is not set"
+        );
+        assert_eq!(expand_path("plain/path").unwrap(), "plain/path");
+    }
+
+    #[test]
+    fn expand_path_dollar() {
+        assert_eq!(expand_path("a$$b").unwrap(), "a$b");
+        assert_eq!(expand_path("$$$$").unwrap(), "$$");
+    }
+
+    #[test]
+    fn expand_path_errors() {
+        assert!(expand_path("${CARGO_HDK_TEST_EXPAND").is_err());
+        assert!(expand_path("a/$/b").is_err());
+        assert!(expand_path("a/${}/b").is_err());
+        assert!(expand_path("$CARGO_HDK_TEST_EXPAND_UNSET").is_err());
+    }
+}
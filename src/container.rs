@@ -0,0 +1,198 @@
+//! Builds inside a Docker or Podman container with '--container <IMAGE>'.
+//!
+//! The image provides Houdini, Rust, CMake, a compiler and cargo-hdk itself, so builds don't
+//! depend on what is installed on the machine running them. cargo-hdk runs itself in a new
+//! container of the image with the same arguments, with the workspace and the cargo target
+//! directory mounted at the same paths, so paths in the build output, the CMake cache and the
+//! compilation database are the same inside and outside of the container. The cargo home and the
+//! caches of sccache and ccache are mounted as well, so downloads and compiled code are reused
+//! between builds.
+
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{Context, Result};
+use log::*;
+
+use crate::tools;
+
+/// Environment variable marking a cargo-hdk process running in the build container.
+const IN_CONTAINER_ENV: &str = "CARGO_HDK_IN_CONTAINER";
+
+/// Environment variable selecting the container engine, 'docker' or 'podman'.
+const ENGINE_ENV: &str = "CARGO_HDK_CONTAINER_ENGINE";
+
+/// Where the cargo home is mounted in the container.
+const CARGO_HOME: &str = "/cargo";
+
+/// Returns true if this process runs in the build container, where '--container' is ignored.
+pub fn in_container() -> bool {
+    env::var_os(IN_CONTAINER_ENV).is_some()
+}
+
+/// The container engine given by 'CARGO_HDK_CONTAINER_ENGINE', or otherwise Docker or Podman,
+/// whichever is found first.
+fn engine() -> Result<PathBuf> {
+    if let Ok(engine) = env::var(ENGINE_ENV) {
+        return tools::find_program(&engine)
+            .with_context(|| format!("Couldn't find '{}' given by '{}'", engine, ENGINE_ENV));
+    }
+    tools::find_program("docker")
+        .or_else(|| tools::find_program("podman"))
+        .context("Couldn't find 'docker' or 'podman' on the 'PATH' for '--container'.")
+}
+
+/// The cargo home of this machine.
+fn cargo_home() -> Option<PathBuf> {
+    env::var_os("CARGO_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".cargo")))
+}
+
+/// The cache directories of the compiler caches on this machine, with the variables selecting
+/// them.
+fn compiler_caches() -> Vec<(&'static str, PathBuf)> {
+    let cache_dir = env::var_os("XDG_CACHE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".cache")));
+    let mut caches = Vec::new();
+    for (var, name) in [("SCCACHE_DIR", "sccache"), ("CCACHE_DIR", "ccache")] {
+        let dir = env::var_os(var)
+            .map(PathBuf::from)
+            .or_else(|| cache_dir.as_ref().map(|dir| dir.join(name)));
+        if let Some(dir) = dir.filter(|dir| dir.is_dir()) {
+            caches.push((var, dir));
+        }
+    }
+    caches
+}
+
+/// The arguments of this process without '--container', to run cargo-hdk with in the container.
+fn forwarded_args() -> Vec<OsString> {
+    let mut args = Vec::new();
+    let mut skip_value = false;
+    for arg in env::args_os().skip(1) {
+        if skip_value {
+            skip_value = false;
+        } else if arg == "--container" {
+            skip_value = true;
+        } else if !arg.to_string_lossy().starts_with("--container=") {
+            args.push(arg);
+        }
+    }
+    args
+}
+
+/// The user and group ids of this process, which the files created in the container should
+/// belong to.
+fn user_ids() -> Option<String> {
+    let id = |flag: &str| {
+        let output = Command::new("id").arg(flag).output().ok()?;
+        let id = String::from_utf8(output.stdout).ok()?;
+        Some(id.trim().to_string())
+    };
+    Some(format!("{}:{}", id("-u")?, id("-g")?))
+}
+
+/// Run cargo-hdk with the arguments of this process in a container of `image`, mounting
+/// `workspace_root` and `target_dir`.
+pub fn run(image: &str, workspace_root: &Path, target_dir: &Path) -> Result<()> {
+    if cfg!(windows) {
+        bail!("'--container' is only supported on Linux and macOS, since the workspace is mounted at the same path in the container.");
+    }
+    let engine = engine()?;
+    let podman = engine
+        .file_stem()
+        .is_some_and(|name| name.to_string_lossy().contains("podman"));
+    let current_dir = env::current_dir().context("Failed to determine the current directory")?;
+
+    let mut cmd = Command::new(&engine);
+    cmd.args(["run", "--rm", "--init"]);
+    {
+        use std::io::IsTerminal;
+        if std::io::stdin().is_terminal() {
+            cmd.arg("--interactive");
+        }
+        if std::io::stdout().is_terminal() {
+            cmd.arg("--tty");
+        }
+    }
+    // Files written to the mounted directories belong to the user running the build. Rootless
+    // Podman maps the user of the container to the user running it instead.
+    if podman {
+        cmd.arg("--userns=keep-id");
+    } else if cfg!(target_os = "linux") {
+        match user_ids() {
+            Some(ids) => {
+                cmd.arg("--user").arg(ids);
+            }
+            None => warn!("Failed to determine the user id; files created in the container will belong to its user."),
+        }
+    }
+
+    // Mounting a missing directory would create it owned by root.
+    fs::create_dir_all(target_dir)
+        .with_context(|| format!("Failed to create {}", target_dir.display()))?;
+    let mut mounts = vec![
+        (workspace_root.to_path_buf(), workspace_root.to_path_buf()),
+        (target_dir.to_path_buf(), target_dir.to_path_buf()),
+    ];
+    if !current_dir.starts_with(workspace_root) {
+        mounts.push((current_dir.clone(), current_dir.clone()));
+    }
+    // Like cross, the cargo home is shared so the registry and git checkouts are downloaded once.
+    match cargo_home().filter(|dir| dir.is_dir()) {
+        Some(home) => {
+            mounts.push((home, PathBuf::from(CARGO_HOME)));
+            cmd.arg("--env").arg(format!("CARGO_HOME={}", CARGO_HOME));
+        }
+        None => debug!("No cargo home found to mount into the container"),
+    }
+    for (var, dir) in compiler_caches() {
+        let mount = Path::new("/cache").join(dir.file_name().unwrap_or_default());
+        cmd.arg("--env").arg(format!("{}={}", var, mount.display()));
+        mounts.push((dir, mount));
+    }
+    mounts.sort();
+    mounts.dedup();
+    for (source, target) in &mounts {
+        // Directories within the workspace, like the target directory, are mounted with it.
+        let nested = |(other, other_target): &(PathBuf, PathBuf)| {
+            other != source && other == other_target && source.starts_with(other)
+        };
+        if source == target && mounts.iter().any(nested) {
+            continue;
+        }
+        let mut volume = OsString::from(source);
+        volume.push(":");
+        volume.push(target);
+        cmd.arg("--volume").arg(volume);
+    }
+
+    cmd.arg("--workdir").arg(&current_dir);
+    cmd.arg("--env").arg(format!("{}=1", IN_CONTAINER_ENV));
+    // Options given in the environment apply in the container as well.
+    for (var, _) in env::vars_os() {
+        let var = var.to_string_lossy();
+        if (var.starts_with("CARGO_HDK_") && var != "CARGO_HDK_CONTAINER" && var != ENGINE_ENV)
+            || matches!(&*var, "CARGO_TARGET_DIR" | "CARGO_TERM_COLOR" | "RUST_LOG")
+        {
+            cmd.arg("--env").arg(&*var);
+        }
+    }
+    cmd.arg(image).arg("cargo").args(forwarded_args());
+
+    info!("Building in a container of {}.", image);
+    debug!("Running {:?}", cmd);
+    let status = cmd
+        .status()
+        .with_context(|| format!("Failed to run {}", engine.display()))?;
+    if !status.success() {
+        bail!("The build in a container of {} failed", image);
+    }
+    Ok(())
+}
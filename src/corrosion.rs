@@ -0,0 +1,106 @@
+//! Generation of a CMake superproject that builds the crate using
+//! [Corrosion](https://github.com/corrosion-rs/corrosion) together with the HDK plugin.
+
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use cargo_metadata::camino::Utf8Path;
+use cargo_metadata::Package;
+use log::*;
+
+use crate::cmake::{quote_path, write_file};
+
+/// Corrosion version fetched when it is not installed.
+const CORROSION_TAG: &str = "v0.5.0";
+
+/// Arguments that are handled by CMake itself instead of being passed on to cargo.
+fn is_profile_arg(arg: &str) -> bool {
+    arg == "--release"
+}
+
+/// Write the superproject `CMakeLists.txt` into `dir`.
+///
+/// The superproject imports the crate with `corrosion_import_crate` and adds the HDK plugin
+/// directory as a subdirectory, so the plugin can link the crate directly using
+/// `target_link_libraries(<plugin> PRIVATE <crate name>)`. The file is only rewritten if its
+/// contents change to avoid needlessly reconfiguring CMake.
+///
+/// In offline mode Corrosion is never fetched, so configuring fails with a clear message if it
+/// is not installed.
+pub fn write_superproject(
+    dir: &Path,
+    package: &Package,
+    hdk_dir: &Path,
+    build_args: &[String],
+    offline: bool,
+) -> Result<()> {
+    // The path is written into the CMake script, which must be valid UTF-8.
+    let hdk_dir = Utf8Path::from_path(hdk_dir).with_context(|| {
+        format!(
+            "The HDK plugin directory {} is not a Unicode path, which '--corrosion' requires",
+            hdk_dir.display()
+        )
+    })?;
+    let offline_arg = if offline && !build_args.iter().any(|arg| arg == "--offline") {
+        Some("--offline".to_string())
+    } else {
+        None
+    };
+    let flags: Vec<String> = build_args
+        .iter()
+        .filter(|arg| !is_profile_arg(arg))
+        .chain(&offline_arg)
+        .map(|arg| format!("\"{}\"", arg.replace('"', "\\\"")))
+        .collect();
+    let flags = if flags.is_empty() {
+        String::new()
+    } else {
+        format!("\n    FLAGS {}", flags.join(" "))
+    };
+
+    let fetch = if offline {
+        "    message(FATAL_ERROR \"Corrosion is not installed and can't be fetched in offline mode. \
+         Please install Corrosion or build without '--offline'.\")"
+            .to_string()
+    } else {
+        format!(
+            "    include(FetchContent)
+    FetchContent_Declare(Corrosion
+        GIT_REPOSITORY https://github.com/corrosion-rs/corrosion.git
+        GIT_TAG {tag})
+    FetchContent_MakeAvailable(Corrosion)",
+            tag = CORROSION_TAG
+        )
+    };
+
+    let contents = format!(
+        "# Generated by cargo-hdk. Do not edit.
+cmake_minimum_required(VERSION 3.15)
+project({name}_hdk LANGUAGES C CXX)
+
+find_package(Corrosion QUIET)
+if(NOT Corrosion_FOUND)
+{fetch}
+endif()
+
+corrosion_import_crate(
+    MANIFEST_PATH {manifest}
+    CRATES {name}{flags})
+
+add_subdirectory({hdk} hdk)
+",
+        name = package.name,
+        fetch = fetch,
+        manifest = quote_path(&package.manifest_path),
+        flags = flags,
+        hdk = quote_path(hdk_dir),
+    );
+
+    let path = dir.join("CMakeLists.txt");
+    if std::fs::read_to_string(&path).ok().as_deref() == Some(contents.as_str()) {
+        debug!("Corrosion superproject is up to date: {}", path.display());
+        return Ok(());
+    }
+    debug!("Writing Corrosion superproject: {}", path.display());
+    write_file(&path, &contents)
+}
@@ -0,0 +1,253 @@
+//! Combined Rust and C++ code coverage of the tests with `cargo hdk cov`.
+//!
+//! Both halves of the plugin are built with LLVM source based coverage: the Rust code with
+//! '-C instrument-coverage' and the C++ code with clang's '-fprofile-instr-generate
+//! -fcoverage-mapping'. The hython tests then write raw profiles, which are merged with
+//! 'llvm-profdata' and turned into an lcov file and an HTML report with 'llvm-cov'. Since the
+//! instrumented code of both languages ends up in the same plugin library, the report follows
+//! the calls from the HDK operators into the Rust code.
+//!
+//! The LLVM tools of the Rust toolchain ('rustup component add llvm-tools-preview') are preferred
+//! since they read the profiles written by rustc. Clang must not be newer than the LLVM of rustc
+//! for its profiles to be readable.
+
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use anyhow::{Context, Result};
+use log::*;
+
+use crate::cmake;
+use crate::tools;
+
+/// Compiler flags instrumenting C and C++ code with clang.
+pub const CLANG_FLAGS: &[&str] = &["-fprofile-instr-generate", "-fcoverage-mapping"];
+
+/// Linker flag adding the profiling runtime of clang.
+const CLANG_LINKER_FLAG: &str = "-fprofile-instr-generate";
+
+/// Sources left out of the report: dependencies, the standard library and the HDK headers.
+const IGNORED_SOURCES: &str =
+    r"(\.cargo[/\\]registry|[/\\]rustc[/\\]|[/\\]rustlib[/\\]|toolkit[/\\]include)";
+
+/// Add '-C instrument-coverage' to the flags of rustc.
+pub fn configure_rust() {
+    let mut flags = env::var("RUSTFLAGS").unwrap_or_default();
+    if !flags
+        .split_whitespace()
+        .any(|flag| flag == "instrument-coverage")
+    {
+        flags.push_str(" -C instrument-coverage");
+    }
+    env::set_var("RUSTFLAGS", flags.trim());
+}
+
+/// Make sure the C++ code is built with clang, selecting it for a new build directory if no other
+/// compiler is requested.
+///
+/// Returns false if another compiler is used, in which case only the Rust code can be covered.
+pub fn select_clang(build_dir: &Path, cmake_args: &mut Vec<String>) -> bool {
+    let requested = cmake_args
+        .iter()
+        .find_map(|arg| arg.strip_prefix("-DCMAKE_CXX_COMPILER="))
+        .map(String::from)
+        .or_else(|| cmake::cache_value(build_dir, "CMAKE_CXX_COMPILER"))
+        .or_else(|| env::var("CXX").ok());
+    if let Some(compiler) = requested {
+        let is_clang = matches!(Path::new(&compiler).file_name(),
+            Some(name) if name.to_string_lossy().contains("clang"));
+        if !is_clang {
+            warn!(
+                "Only the Rust code is instrumented for coverage, since the C++ compiler {} isn't clang.",
+                compiler
+            );
+        }
+        return is_clang;
+    }
+    match (tools::find_program("clang"), tools::find_program("clang++")) {
+        (Some(cc), Some(cxx)) => {
+            cmake_args.push(format!("-DCMAKE_C_COMPILER={}", cc.display()));
+            cmake_args.push(format!("-DCMAKE_CXX_COMPILER={}", cxx.display()));
+            true
+        }
+        _ => {
+            warn!("Only the Rust code is instrumented for coverage, since clang wasn't found on the 'PATH'.");
+            false
+        }
+    }
+}
+
+/// Add the CMake arguments instrumenting the C code and adding the profiling runtime to the link
+/// of the plugin to `cmake_args`. The C++ flags are set along with the other C++ flags.
+pub fn cmake_args(build_dir: &Path, cmake_args: &mut Vec<String>) {
+    cmake::merge_flags_arg(
+        build_dir,
+        "CMAKE_C_FLAGS",
+        "CFLAGS",
+        CLANG_FLAGS,
+        CLANG_FLAGS,
+        cmake_args,
+    );
+    for var in ["CMAKE_SHARED_LINKER_FLAGS", "CMAKE_MODULE_LINKER_FLAGS"] {
+        cmake::merge_flags_arg(
+            build_dir,
+            var,
+            "LDFLAGS",
+            &[CLANG_LINKER_FLAG],
+            &[CLANG_LINKER_FLAG],
+            cmake_args,
+        );
+    }
+}
+
+/// The directory of the raw profiles, emptied for a new run, and the environment making the
+/// instrumented code write its profile there.
+pub fn profile_env(coverage_dir: &Path) -> Result<(PathBuf, (&'static str, OsString))> {
+    let dir = coverage_dir.join("profraw");
+    if dir.exists() {
+        fs::remove_dir_all(&dir).with_context(|| format!("Failed to remove {}", dir.display()))?;
+    }
+    fs::create_dir_all(&dir)
+        .with_context(|| format!("Failed to create directory {}", dir.display()))?;
+    // One file per process and instrumented library.
+    let pattern = dir.join("%p-%m.profraw");
+    Ok((dir, ("LLVM_PROFILE_FILE", pattern.into_os_string())))
+}
+
+/// Find an LLVM tool, preferring the one installed with the Rust toolchain.
+fn find_llvm_tool(name: &str, toolchain: Option<&str>) -> Result<PathBuf> {
+    let rustc = |args: &[&str]| {
+        let mut cmd = match toolchain {
+            Some(toolchain) => {
+                let mut cmd = Command::new("rustup");
+                cmd.args(["run", toolchain, "rustc"]);
+                cmd
+            }
+            None => Command::new("rustc"),
+        };
+        let output = cmd.args(args).output().ok()?;
+        Some(String::from_utf8_lossy(&output.stdout).into_owned())
+    };
+    let version = rustc(&["-vV"]).unwrap_or_default();
+    let host = version.lines().find_map(|line| line.strip_prefix("host: "));
+    if let (Some(host), Some(sysroot)) = (host, rustc(&["--print", "sysroot"])) {
+        let path = Path::new(sysroot.trim())
+            .join("lib")
+            .join("rustlib")
+            .join(host)
+            .join("bin")
+            .join(format!("{}{}", name, env::consts::EXE_SUFFIX));
+        if path.is_file() {
+            return Ok(path);
+        }
+    }
+    tools::find_program(name).with_context(|| {
+        format!(
+            "Couldn't find '{}'. Install it with 'rustup component add llvm-tools-preview'.",
+            name
+        )
+    })
+}
+
+/// Merge the raw profiles and write the lcov file and the HTML report for the given instrumented
+/// libraries to `coverage_dir`, printing a summary.
+pub fn report(
+    profile_dir: &Path,
+    libraries: &[PathBuf],
+    coverage_dir: &Path,
+    toolchain: Option<&str>,
+) -> Result<()> {
+    let profiles: Vec<_> = fs::read_dir(profile_dir)
+        .with_context(|| format!("Failed to read {}", profile_dir.display()))?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| matches!(path.extension(), Some(ext) if ext == "profraw"))
+        .collect();
+    if profiles.is_empty() {
+        bail!(
+            "No coverage profiles were written to {}. Check that the tests load the plugin.",
+            profile_dir.display()
+        );
+    }
+    let (first, rest) = match libraries.split_first() {
+        Some(split) => split,
+        None => bail!("No instrumented libraries found to report the coverage of."),
+    };
+    let profdata_tool = find_llvm_tool("llvm-profdata", toolchain)?;
+    let cov_tool = find_llvm_tool("llvm-cov", toolchain)?;
+
+    let profdata = coverage_dir.join("coverage.profdata");
+    info!("Merging {} coverage profiles.", profiles.len());
+    let status = Command::new(&profdata_tool)
+        .args(["merge", "-sparse"])
+        .args(&profiles)
+        .arg("-o")
+        .arg(&profdata)
+        .status()
+        .with_context(|| format!("Failed to run {}", profdata_tool.display()))?;
+    if !status.success() {
+        bail!(
+            "Failed to merge the coverage profiles with {}. Its LLVM version must not be older than the ones of rustc and clang. Install the tools of rustc with 'rustup component add llvm-tools-preview'.",
+            profdata_tool.display()
+        );
+    }
+
+    let llvm_cov = |command: &str| {
+        let mut cmd = Command::new(&cov_tool);
+        let mut instr_profile = OsString::from("-instr-profile=");
+        instr_profile.push(&profdata);
+        cmd.arg(command)
+            .arg(instr_profile)
+            .arg(format!("-ignore-filename-regex={}", IGNORED_SOURCES))
+            .arg(first);
+        for library in rest {
+            cmd.arg("-object").arg(library);
+        }
+        cmd
+    };
+
+    let lcov = coverage_dir.join("lcov.info");
+    let output = llvm_cov("export")
+        .arg("-format=lcov")
+        .stderr(Stdio::inherit())
+        .output()
+        .with_context(|| format!("Failed to run {}", cov_tool.display()))?;
+    if !output.status.success() {
+        bail!("Failed to export the coverage to {}", lcov.display());
+    }
+    fs::write(&lcov, &output.stdout)
+        .with_context(|| format!("Failed to write {}", lcov.display()))?;
+
+    let html = coverage_dir.join("html");
+    let mut output_dir = OsString::from("-output-dir=");
+    output_dir.push(&html);
+    let status = llvm_cov("show")
+        .args(["-format=html", "-show-instantiations=false"])
+        .arg(output_dir)
+        .status()
+        .with_context(|| format!("Failed to run {}", cov_tool.display()))?;
+    if !status.success() {
+        bail!(
+            "Failed to write the HTML coverage report to {}",
+            html.display()
+        );
+    }
+
+    println!();
+    let status = llvm_cov("report")
+        .status()
+        .with_context(|| format!("Failed to run {}", cov_tool.display()))?;
+    if !status.success() {
+        warn!("Failed to print the coverage summary.");
+    }
+    println!();
+    println!(
+        "Wrote {} and {}",
+        lcov.display(),
+        html.join("index.html").display()
+    );
+    Ok(())
+}
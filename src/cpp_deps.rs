@@ -0,0 +1,181 @@
+//! Provisioning of additional C++ dependencies for the HDK plugin using vcpkg or Conan.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{Context, Result};
+use log::*;
+
+use crate::cmake;
+use crate::tools::find_program;
+
+/// Install the C++ dependencies declared in the hdk directory, if any, and return the arguments
+/// needed by the CMake configure step to find them.
+///
+/// Dependencies are declared either with a vcpkg manifest (`vcpkg.json`) or a Conan recipe
+/// (`conanfile.txt` or `conanfile.py`). In offline mode, packages must already be available
+/// locally (in the Conan cache or the vcpkg asset cache).
+pub fn provision(
+    hdk_dir: &Path,
+    build_dir: &Path,
+    build_type: &str,
+    cmake_args: &[String],
+    offline: bool,
+) -> Result<Vec<String>> {
+    let has_toolchain = cmake_args
+        .iter()
+        .any(|arg| arg.starts_with("-DCMAKE_TOOLCHAIN_FILE"));
+
+    if hdk_dir.join("vcpkg.json").exists() {
+        vcpkg(hdk_dir, build_dir, has_toolchain, offline)
+    } else if let Some(conanfile) = ["conanfile.py", "conanfile.txt"]
+        .iter()
+        .map(|name| hdk_dir.join(name))
+        .find(|path| path.exists())
+    {
+        conan(&conanfile, build_dir, build_type, has_toolchain, offline)
+    } else {
+        Ok(Vec::new())
+    }
+}
+
+/// Find the root of the vcpkg installation.
+fn vcpkg_root() -> Option<PathBuf> {
+    if let Some(root) = std::env::var_os("VCPKG_ROOT") {
+        return Some(PathBuf::from(root));
+    }
+    find_program("vcpkg").and_then(|exe| exe.parent().map(Path::to_path_buf))
+}
+
+fn vcpkg(
+    hdk_dir: &Path,
+    build_dir: &Path,
+    has_toolchain: bool,
+    offline: bool,
+) -> Result<Vec<String>> {
+    info!("Installing C++ dependencies using vcpkg.");
+
+    let root = vcpkg_root().context(
+        "Found 'vcpkg.json' but no vcpkg installation. Please set 'VCPKG_ROOT' or add vcpkg to the 'PATH'.",
+    )?;
+    let exe = root.join(if cfg!(windows) { "vcpkg.exe" } else { "vcpkg" });
+    let installed_dir = build_dir.join("vcpkg_installed");
+
+    let mut cmd = Command::new(&exe);
+    cmd.current_dir(build_dir)
+        .arg("install")
+        .arg(format!("--x-manifest-root={}", hdk_dir.display()))
+        .arg(format!("--x-install-root={}", installed_dir.display()));
+    if offline {
+        // Only use sources from the configured asset cache instead of their original URLs.
+        let sources = match std::env::var("X_VCPKG_ASSET_SOURCES") {
+            Ok(sources) if !sources.is_empty() => format!("{};x-block-origin", sources),
+            _ => "x-block-origin".to_string(),
+        };
+        cmd.env("X_VCPKG_ASSET_SOURCES", sources);
+    }
+
+    let log = build_dir.join("logs").join("vcpkg.log");
+    let status = cmake::run_logged(&mut cmd, &log).context("Failed to run vcpkg")?;
+    if !status.success() {
+        if offline {
+            bail!(
+                "vcpkg install failed in offline mode. Some packages may need to be downloaded. \
+                 See the full log at {}",
+                log.display()
+            );
+        }
+        bail!(
+            "vcpkg install failed. See the full log at {}",
+            log.display()
+        );
+    }
+
+    let toolchain = root
+        .join("scripts")
+        .join("buildsystems")
+        .join("vcpkg.cmake");
+    let mut args = vec![
+        format!("-DVCPKG_INSTALLED_DIR={}", installed_dir.display()),
+        // Dependencies are already installed above.
+        "-DVCPKG_MANIFEST_INSTALL=OFF".to_string(),
+    ];
+    if has_toolchain {
+        warn!("A CMake toolchain file was given explicitly; not using the vcpkg toolchain.");
+    } else {
+        args.push(format!("-DCMAKE_TOOLCHAIN_FILE={}", toolchain.display()));
+    }
+    Ok(args)
+}
+
+fn conan(
+    conanfile: &Path,
+    build_dir: &Path,
+    build_type: &str,
+    has_toolchain: bool,
+    offline: bool,
+) -> Result<Vec<String>> {
+    info!("Installing C++ dependencies using Conan.");
+
+    let exe = find_program("conan").context(
+        "Found a Conan recipe but no 'conan' executable. Please install Conan and add it to the 'PATH'.",
+    )?;
+    let output_dir = build_dir.join("conan");
+
+    let mut cmd = Command::new(exe);
+    cmd.current_dir(build_dir)
+        .arg("install")
+        .arg(conanfile)
+        .arg("--output-folder")
+        .arg(&output_dir)
+        .arg("--build=missing")
+        .arg("-s")
+        .arg(format!("build_type={}", build_type));
+    if offline {
+        // Resolve packages from the local cache only.
+        cmd.arg("--no-remote");
+    }
+
+    let log = build_dir.join("logs").join("conan.log");
+    let status = cmake::run_logged(&mut cmd, &log).context("Failed to run conan")?;
+    if !status.success() {
+        if offline {
+            bail!(
+                "conan install failed in offline mode. Some packages may be missing from the \
+                 local Conan cache. See the full log at {}",
+                log.display()
+            );
+        }
+        bail!(
+            "conan install failed. See the full log at {}",
+            log.display()
+        );
+    }
+
+    let mut args = vec![format!("-DCMAKE_PREFIX_PATH={}", output_dir.display())];
+
+    // The toolchain location depends on the layout used by the recipe.
+    let toolchain = vec![
+        output_dir.join("conan_toolchain.cmake"),
+        output_dir
+            .join("build")
+            .join(build_type)
+            .join("generators")
+            .join("conan_toolchain.cmake"),
+        output_dir
+            .join("build")
+            .join("generators")
+            .join("conan_toolchain.cmake"),
+    ]
+    .into_iter()
+    .find(|path| path.exists());
+
+    match toolchain {
+        Some(_) if has_toolchain => {
+            warn!("A CMake toolchain file was given explicitly; not using the Conan toolchain.")
+        }
+        Some(toolchain) => args.push(format!("-DCMAKE_TOOLCHAIN_FILE={}", toolchain.display())),
+        None => debug!("No Conan toolchain file found in {}", output_dir.display()),
+    }
+    Ok(args)
+}
@@ -0,0 +1,233 @@
+//! Cross compilation of Windows plugins on Linux and macOS.
+//!
+//! Building for a '*-pc-windows-msvc' target on another platform uses the LLVM tools in place of
+//! MSVC: 'clang-cl' compiles the C++ code, 'lld-link' links both the Rust and the C++ code and
+//! 'llvm-lib' creates static libraries. The MSVC C runtime and Windows SDK headers and libraries
+//! come from a directory created with 'xwin splat', and the HDK headers and import libraries from
+//! a copy of a Windows Houdini installation, both given in the 'cross_windows' table. CMake is
+//! configured with a generated toolchain file, and cargo and the 'cc' crate through environment
+//! variables.
+
+use std::env;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use log::*;
+
+use crate::cmake;
+use crate::config::CrossWindows;
+use crate::tools;
+
+/// Name of the generated CMake toolchain file in the build directory.
+const TOOLCHAIN_FILE: &str = "windows-toolchain.cmake";
+
+/// Header directories of the C runtime and the Windows SDK in an 'xwin splat' directory.
+const INCLUDE_DIRS: &[&str] = &[
+    "crt/include",
+    "sdk/include/ucrt",
+    "sdk/include/um",
+    "sdk/include/shared",
+    "sdk/include/winrt",
+];
+
+/// Library directories of the C runtime and the Windows SDK in an 'xwin splat' directory, below
+/// which the libraries of each architecture are.
+const LIB_DIRS: &[&str] = &["crt/lib", "sdk/lib/um", "sdk/lib/ucrt"];
+
+/// A build of a Windows plugin on another platform.
+pub struct WindowsCross {
+    target: String,
+    /// The Windows Houdini installation providing the HDK.
+    hfs: PathBuf,
+    /// The directory with the C runtime and the Windows SDK created by 'xwin splat'.
+    sdk: PathBuf,
+    /// The architecture as named by xwin and CMake.
+    arch: (&'static str, &'static str),
+    clang_cl: PathBuf,
+    lld_link: PathBuf,
+    llvm_lib: PathBuf,
+}
+
+/// The directory 'cargo xwin' splats the SDK into, used if 'cross_windows.sdk' isn't set.
+fn cargo_xwin_dir() -> Option<PathBuf> {
+    let cache = env::var_os("XDG_CACHE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))?;
+    Some(cache.join("cargo-xwin").join("xwin"))
+}
+
+fn find_llvm_tool(name: &str) -> Result<PathBuf> {
+    tools::find_program(name).with_context(|| {
+        format!(
+            "Couldn't find '{}' on the 'PATH', which cross compiling for Windows needs. Install LLVM, and if its tools have a version suffix, link '{}' to the one of the installed version.",
+            name, name
+        )
+    })
+}
+
+impl WindowsCross {
+    /// The cross build for the given target if it is a Windows MSVC target and this isn't
+    /// Windows, or `None` otherwise.
+    pub fn new(
+        target: Option<&str>,
+        config: Option<&CrossWindows>,
+    ) -> Result<Option<WindowsCross>> {
+        let target = match target {
+            Some(target) if target.ends_with("-pc-windows-msvc") && !cfg!(windows) => target,
+            _ => return Ok(None),
+        };
+        let arch = match target.split('-').next() {
+            Some("x86_64") => ("x86_64", "AMD64"),
+            Some("aarch64") => ("aarch64", "ARM64"),
+            Some("i686") => ("x86", "X86"),
+            _ => bail!("Cross compiling for '{}' is not supported", target),
+        };
+        let hfs = config
+            .and_then(|config| config.hfs.clone())
+            .context("Cross compiling for Windows needs the HDK of a Windows Houdini installation. Copy its 'toolkit' and 'custom' directories and set 'cross_windows.hfs' in [package.metadata.hdk] to the copy.")?;
+        if !hfs.join("toolkit").join("include").is_dir() {
+            bail!(
+                "{} given by 'cross_windows.hfs' doesn't contain the HDK headers in 'toolkit/include'",
+                hfs.display()
+            );
+        }
+        let sdk = config
+            .and_then(|config| config.sdk.clone())
+            .or_else(|| cargo_xwin_dir().filter(|dir| dir.is_dir()))
+            .context("Cross compiling for Windows needs the MSVC C runtime and the Windows SDK. Download them with 'xwin --accept-license splat --output <DIR>' and set 'cross_windows.sdk' in [package.metadata.hdk] to the directory.")?;
+        if !sdk.join(INCLUDE_DIRS[0]).is_dir() {
+            bail!(
+                "{} isn't a directory created with 'xwin splat'",
+                sdk.display()
+            );
+        }
+        Ok(Some(WindowsCross {
+            target: target.to_string(),
+            hfs,
+            sdk,
+            arch,
+            clang_cl: find_llvm_tool("clang-cl")?,
+            lld_link: find_llvm_tool("lld-link")?,
+            llvm_lib: find_llvm_tool("llvm-lib")?,
+        }))
+    }
+
+    /// The Windows Houdini installation the plugin is built against.
+    pub fn hfs(&self) -> &Path {
+        &self.hfs
+    }
+
+    fn include_dirs(&self) -> Vec<PathBuf> {
+        INCLUDE_DIRS.iter().map(|dir| self.sdk.join(dir)).collect()
+    }
+
+    fn lib_dirs(&self) -> Vec<PathBuf> {
+        LIB_DIRS
+            .iter()
+            .map(|dir| self.sdk.join(dir).join(self.arch.0))
+            .collect()
+    }
+
+    /// Link the Rust code with 'lld-link' and compile the C and C++ code of build scripts with
+    /// 'clang-cl', unless other tools are set already.
+    ///
+    /// Like in a Visual Studio developer command prompt, the headers and libraries of the C
+    /// runtime and the Windows SDK are found through the 'INCLUDE' and 'LIB' variables, which
+    /// 'clang-cl' and 'lld-link' read on all platforms.
+    pub fn configure_rust(&self) {
+        fn set_default(var: &str, value: &OsStr) {
+            if env::var_os(var).is_none() {
+                debug!("Setting {}={}", var, value.to_string_lossy());
+                env::set_var(var, value);
+            }
+        }
+        let triple = self.target.replace('-', "_");
+        set_default(
+            &format!("CARGO_TARGET_{}_LINKER", triple.to_uppercase()),
+            self.lld_link.as_os_str(),
+        );
+        set_default(&format!("CC_{}", triple), self.clang_cl.as_os_str());
+        set_default(&format!("CXX_{}", triple), self.clang_cl.as_os_str());
+        set_default(&format!("AR_{}", triple), self.llvm_lib.as_os_str());
+        for (var, dirs) in [("INCLUDE", self.include_dirs()), ("LIB", self.lib_dirs())] {
+            let paths: Vec<_> = dirs.iter().map(|dir| dir.display().to_string()).collect();
+            set_default(var, OsStr::new(&paths.join(";")));
+        }
+    }
+
+    /// Write the CMake toolchain file for the cross build into `build_dir` and return the
+    /// arguments selecting it, unless `cmake_args` select a toolchain file already.
+    pub fn cmake_args(&self, build_dir: &Path, cmake_args: &[String]) -> Result<Vec<String>> {
+        if cmake_args
+            .iter()
+            .any(|arg| arg.starts_with("-DCMAKE_TOOLCHAIN_FILE"))
+        {
+            warn!("A CMake toolchain file was given explicitly; it must set up the cross compilation for Windows.");
+            return Ok(Vec::new());
+        }
+        // CMake expects forward slashes and quoted paths may contain spaces.
+        let path = |path: &Path| path.display().to_string().replace('\\', "/");
+        let compile_flags: Vec<_> = self
+            .include_dirs()
+            .iter()
+            .map(|dir| format!("/imsvc \\\"{}\\\"", path(dir)))
+            .collect();
+        let link_flags: Vec<_> = self
+            .lib_dirs()
+            .iter()
+            .map(|dir| format!("/libpath:\\\"{}\\\"", path(dir)))
+            .collect();
+        let mut lines = vec![
+            "# Cross compilation for Windows, generated by cargo-hdk.".to_string(),
+            "set(CMAKE_SYSTEM_NAME Windows)".to_string(),
+            format!("set(CMAKE_SYSTEM_PROCESSOR {})", self.arch.1),
+        ];
+        for lang in ["C", "CXX"] {
+            lines.push(format!(
+                "set(CMAKE_{}_COMPILER \"{}\")",
+                lang,
+                path(&self.clang_cl)
+            ));
+            lines.push(format!(
+                "set(CMAKE_{}_COMPILER_TARGET {})",
+                lang, self.target
+            ));
+            lines.push(format!(
+                "set(CMAKE_{}_FLAGS_INIT \"{}\")",
+                lang,
+                compile_flags.join(" ")
+            ));
+        }
+        lines.push(format!("set(CMAKE_LINKER \"{}\")", path(&self.lld_link)));
+        lines.push(format!("set(CMAKE_AR \"{}\")", path(&self.llvm_lib)));
+        // Resources and manifests are optional for plugins, so these tools may be missing.
+        let mut link_flags = link_flags.join(" ");
+        match tools::find_program("llvm-rc") {
+            Some(rc) => lines.push(format!("set(CMAKE_RC_COMPILER \"{}\")", path(&rc))),
+            None => debug!("No llvm-rc found for compiling Windows resources"),
+        }
+        match tools::find_program("llvm-mt") {
+            Some(mt) => lines.push(format!("set(CMAKE_MT \"{}\")", path(&mt))),
+            None => link_flags.push_str(" /manifest:no"),
+        }
+        for kind in ["EXE", "SHARED", "MODULE"] {
+            lines.push(format!(
+                "set(CMAKE_{}_LINKER_FLAGS_INIT \"{}\")",
+                kind, link_flags
+            ));
+        }
+        // Programs run during the build are those of this platform.
+        lines.push("set(CMAKE_FIND_ROOT_PATH_MODE_PROGRAM NEVER)".to_string());
+        lines.push(String::new());
+
+        let toolchain = build_dir.join(TOOLCHAIN_FILE);
+        cmake::write_file(&toolchain, &lines.join("\n"))?;
+        debug!("Wrote the Windows toolchain file {}", toolchain.display());
+        Ok(vec![format!(
+            "-DCMAKE_TOOLCHAIN_FILE={}",
+            toolchain.display()
+        )])
+    }
+}
@@ -0,0 +1,202 @@
+//! Launching Houdini with the freshly built plugin under a debugger using `cargo hdk debug`.
+//!
+//! GDB and LLDB are started with a command file, written next to the build, which maps the paths
+//! of the Rust standard library in the debug info to the sources of the 'rust-src' component,
+//! optionally stops when the plugin is loaded, and then runs the application. The 'rust-gdb' and
+//! 'rust-lldb' wrappers are preferred if available, since they add pretty printers for Rust types.
+//! On Windows, the application is opened in the Visual Studio debugger with 'devenv /DebugExe'.
+
+use std::ffi::OsString;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use log::*;
+
+use crate::tools;
+use crate::verify;
+
+/// Debuggers supported by `cargo hdk debug`.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Debugger {
+    Gdb,
+    Lldb,
+    /// The Visual Studio debugger.
+    Devenv,
+}
+
+impl Debugger {
+    /// The usual debugger of the platform: Visual Studio on Windows, LLDB on macOS and GDB
+    /// elsewhere, unless only LLDB is installed.
+    pub fn for_platform() -> Debugger {
+        if cfg!(windows) {
+            Debugger::Devenv
+        } else if cfg!(target_os = "macos") || tools::find_program("gdb").is_none() {
+            Debugger::Lldb
+        } else {
+            Debugger::Gdb
+        }
+    }
+
+    /// The debugger executable, preferring the wrappers with Rust pretty printers.
+    fn program(self) -> Result<PathBuf> {
+        let candidates: &[&str] = match self {
+            Debugger::Gdb => &["rust-gdb", "gdb"],
+            Debugger::Lldb => &["rust-lldb", "lldb"],
+            Debugger::Devenv => &["devenv"],
+        };
+        candidates
+            .iter()
+            .find_map(|name| tools::find_program(name))
+            .with_context(|| match self {
+                Debugger::Devenv => "Couldn't find 'devenv' on the 'PATH'. Run cargo-hdk from a Visual Studio developer command prompt.".to_string(),
+                _ => format!("Couldn't find '{}' on the 'PATH'.", candidates[1]),
+            })
+    }
+}
+
+/// The directory of the Rust standard library sources referred to by the debug info, and the
+/// local sources installed by the 'rust-src' component.
+pub fn rust_source_map() -> Option<(String, PathBuf)> {
+    let output = Command::new("rustc").arg("-vV").output().ok()?;
+    let version = String::from_utf8_lossy(&output.stdout);
+    let hash = version
+        .lines()
+        .find_map(|line| line.strip_prefix("commit-hash: "))?
+        .trim()
+        .to_string();
+    let output = Command::new("rustc")
+        .args(["--print", "sysroot"])
+        .output()
+        .ok()?;
+    let sysroot = PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+    let src = sysroot.join("lib").join("rustlib").join("src").join("rust");
+    if !src.is_dir() {
+        info!("Install the 'rust-src' rustup component to step through the Rust standard library.");
+        return None;
+    }
+    Some((format!("/rustc/{}", hash), src))
+}
+
+/// Escape the characters of the given text that have a meaning in a regular expression.
+pub fn escape_regex(name: &str) -> String {
+    name.chars().fold(String::new(), |mut escaped, c| {
+        if "\\^$.|?*+()[]{}".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+        escaped
+    })
+}
+
+/// The debugger commands setting up the session and running the application.
+fn commands(debugger: Debugger, plugins: &[PathBuf], break_on_load: bool) -> Result<String> {
+    let source_map = rust_source_map();
+    let plugin_names: Vec<_> = plugins
+        .iter()
+        .filter_map(|plugin| plugin.file_name())
+        .map(|name| name.to_string_lossy().into_owned())
+        .collect();
+    let mut commands = String::new();
+    match debugger {
+        Debugger::Gdb => {
+            writeln!(commands, "set breakpoint pending on")?;
+            if let Some((from, to)) = &source_map {
+                writeln!(commands, "set substitute-path {} {}", from, to.display())?;
+            }
+            if break_on_load {
+                for name in &plugin_names {
+                    writeln!(commands, "catch load {}", escape_regex(name))?;
+                }
+            }
+            writeln!(commands, "run")?;
+        }
+        Debugger::Lldb => {
+            if let Some((from, to)) = &source_map {
+                writeln!(
+                    commands,
+                    "settings set target.source-map {} '{}'",
+                    from,
+                    to.display()
+                )?;
+            }
+            if break_on_load {
+                // Stops when Houdini registers the contents of the plugin right after loading it.
+                let functions = verify::REGISTER_FUNCTIONS.join("|");
+                for name in &plugin_names {
+                    writeln!(
+                        commands,
+                        "breakpoint set --func-regex '^({})$' --shlib '{}'",
+                        functions, name
+                    )?;
+                }
+            }
+            writeln!(commands, "run")?;
+        }
+        Debugger::Devenv => {}
+    }
+    Ok(commands)
+}
+
+/// Launch the given program with its arguments under the debugger and wait for it to exit.
+///
+/// The command file of the debugger is written to `dir`. With `break_on_load`, the debugger stops
+/// when one of the given plugin libraries is loaded.
+pub fn run(
+    debugger: Debugger,
+    program: &Path,
+    args: &[String],
+    envs: &[(&str, OsString)],
+    dir: &Path,
+    plugins: &[PathBuf],
+    break_on_load: bool,
+) -> Result<()> {
+    let debugger_program = debugger.program()?;
+    let mut cmd = Command::new(&debugger_program);
+    match debugger {
+        Debugger::Gdb | Debugger::Lldb => {
+            if break_on_load && plugins.is_empty() {
+                warn!("No plugin libraries found in the build directory to break on.");
+            }
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create directory {}", dir.display()))?;
+            let path = dir.join(match debugger {
+                Debugger::Gdb => "gdb_commands",
+                _ => "lldb_commands",
+            });
+            fs::write(&path, commands(debugger, plugins, break_on_load)?)
+                .with_context(|| format!("Failed to write debugger commands {}", path.display()))?;
+            if debugger == Debugger::Gdb {
+                cmd.arg("-x").arg(&path).arg("--args").arg(program);
+            } else {
+                cmd.arg("-s").arg(&path).arg("--").arg(program);
+            }
+        }
+        Debugger::Devenv => {
+            if break_on_load {
+                warn!("'--break-on-load' is not supported by the Visual Studio debugger.");
+            }
+            cmd.arg("/DebugExe").arg(program);
+        }
+    }
+    cmd.args(args)
+        .envs(envs.iter().map(|(key, value)| (key, value)));
+    for (key, value) in envs {
+        debug!("Setting {}={:?} for the debugger", key, value);
+    }
+    info!(
+        "Launching {} under {}.",
+        program.display(),
+        debugger_program.display()
+    );
+    let status = cmd
+        .status()
+        .with_context(|| format!("Failed to run {}", debugger_program.display()))?;
+    // The debugger exits with the status of its last command, which is not a failure of the
+    // plugin.
+    debug!("The debugger exited with {}", status);
+    Ok(())
+}
@@ -0,0 +1,239 @@
+//! Selection of the dependencies whose build outputs are exported to CMake.
+
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{Context, Result};
+use cargo_metadata::semver::VersionReq;
+use cargo_metadata::{DependencyKind, Metadata, Node, Package, PackageId, Resolve};
+use log::*;
+
+/// A dependency selector given to '--deps' in the form `name` or `name@version`.
+///
+/// The name may be `all` to select every package, or a glob pattern using `*` and `?`.
+struct Spec<'a> {
+    name: &'a str,
+    version: Option<VersionReq>,
+}
+
+impl<'a> Spec<'a> {
+    /// Returns true if this spec can match more than one package name.
+    fn is_pattern(&self) -> bool {
+        self.name == "all" || self.name.contains(['*', '?'])
+    }
+
+    fn matches_name(&self, name: &str) -> bool {
+        self.name == "all" || glob_match(self.name, name)
+    }
+
+    fn parse(spec: &'a str) -> Result<Self> {
+        match spec.split_once('@') {
+            Some((name, version)) => Ok(Spec {
+                name,
+                // Like cargo package specs, a partial version like '1.2' matches any '1.2.x'.
+                version: Some(
+                    VersionReq::parse(&format!("={}", version))
+                        .with_context(|| format!("Invalid version in dependency '{}'", spec))?,
+                ),
+            }),
+            None => Ok(Spec {
+                name: spec,
+                version: None,
+            }),
+        }
+    }
+}
+
+/// Match `name` against a glob `pattern` where `*` matches any sequence of characters and `?`
+/// matches any single character.
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    // Position in the pattern after the last '*' and the name position it was matched at.
+    let mut star: Option<(usize, usize)> = None;
+    let (mut p, mut n) = (0, 0);
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p + 1, n));
+            p += 1;
+        } else if let Some((star_p, star_n)) = star {
+            // Let the last '*' consume one more character.
+            p = star_p;
+            n = star_n + 1;
+            star = Some((star_p, star_n + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Find all packages in the dependency graph matching the given selector, warning if there are
+/// none unless `report_missing` is false.
+fn find_packages<'m>(
+    metadata: &'m Metadata,
+    resolve: &'m Resolve,
+    spec_str: &str,
+    report_missing: bool,
+) -> Result<Vec<&'m Package>> {
+    let spec = Spec::parse(spec_str)?;
+    let matches: Vec<_> = resolve
+        .nodes
+        .iter()
+        .map(|node| &metadata[&node.id])
+        .filter(|pkg| spec.matches_name(&pkg.name))
+        .filter(|pkg| spec.version.iter().all(|req| req.matches(&pkg.version)))
+        .collect();
+
+    match matches.as_slice() {
+        [] if report_missing => warn!(
+            "Dependency '{}' not found in the dependency graph",
+            spec_str
+        ),
+        [] | [_] => {}
+        _ if spec.is_pattern() => {}
+        _ => warn!(
+            "Multiple versions of '{}' found ({}); the last one built will be exported. \
+             Use '{}@<version>' to select one.",
+            spec.name,
+            matches
+                .iter()
+                .map(|pkg| pkg.version.to_string())
+                .collect::<Vec<_>>()
+                .join(", "),
+            spec.name
+        ),
+    }
+    Ok(matches)
+}
+
+fn dependency_graph(metadata: &Metadata) -> Result<&Resolve> {
+    metadata
+        .resolve
+        .as_ref()
+        .context("Cargo metadata is missing the dependency graph")
+}
+
+/// Resolve the given dependency selectors to the exact ids of the packages in the dependency
+/// graph, mapped to the name used for their exported files.
+///
+/// Selectors matching no package are reported unless `report_missing` is false, like for the
+/// default selector, which most crates don't use.
+pub fn resolve(
+    metadata: &Metadata,
+    specs: &[String],
+    report_missing: bool,
+) -> Result<HashMap<PackageId, String>> {
+    let resolve = dependency_graph(metadata)?;
+    let mut ids = HashMap::new();
+    for spec in specs {
+        for pkg in find_packages(metadata, resolve, spec, report_missing)? {
+            debug!("Exporting dependency {}", pkg.id);
+            ids.insert(pkg.id.clone(), pkg.name.clone());
+        }
+    }
+    Ok(ids)
+}
+
+/// Resolve the packages matching the given selectors along with all of their transitive
+/// dependencies, mapped to the name used for their exported files.
+pub fn resolve_transitive(
+    metadata: &Metadata,
+    roots: &[String],
+) -> Result<HashMap<PackageId, String>> {
+    let resolve = dependency_graph(metadata)?;
+    let nodes: HashMap<&PackageId, &Node> =
+        resolve.nodes.iter().map(|node| (&node.id, node)).collect();
+
+    let mut ids = HashMap::new();
+    let mut stack = Vec::new();
+    for root in roots {
+        stack.extend(
+            find_packages(metadata, resolve, root, true)?
+                .into_iter()
+                .map(|pkg| &pkg.id),
+        );
+    }
+    while let Some(id) = stack.pop() {
+        if ids.contains_key(id) {
+            continue;
+        }
+        debug!("Exporting dependency {}", id);
+        ids.insert(id.clone(), metadata[id].name.clone());
+        if let Some(node) = nodes.get(id) {
+            stack.extend(node.dependencies.iter());
+        }
+    }
+    Ok(ids)
+}
+
+/// The number of packages built for the package `root`: the package itself and its dependencies
+/// other than dev-dependencies, or 0 if the dependency graph is missing.
+pub fn crate_count(metadata: &Metadata, root: &PackageId) -> u64 {
+    let resolve = match metadata.resolve.as_ref() {
+        Some(resolve) => resolve,
+        None => return 0,
+    };
+    let nodes: HashMap<&PackageId, &Node> =
+        resolve.nodes.iter().map(|node| (&node.id, node)).collect();
+
+    let mut seen = HashSet::new();
+    let mut stack = vec![root];
+    while let Some(id) = stack.pop() {
+        if !seen.insert(id) {
+            continue;
+        }
+        if let Some(node) = nodes.get(id) {
+            stack.extend(
+                node.deps
+                    .iter()
+                    .filter(|dep| {
+                        dep.dep_kinds.is_empty()
+                            || dep
+                                .dep_kinds
+                                .iter()
+                                .any(|info| info.kind != DependencyKind::Development)
+                    })
+                    .map(|dep| &dep.pkg),
+            );
+        }
+    }
+    seen.len() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glob_match_literal() {
+        assert!(glob_match("hdkrs", "hdkrs"));
+        assert!(!glob_match("hdkrs", "hdkrs-sys"));
+        assert!(!glob_match("hdkrs", "hdk"));
+        assert!(glob_match("", ""));
+        assert!(!glob_match("", "hdkrs"));
+    }
+
+    #[test]
+    fn glob_match_question_mark() {
+        assert!(glob_match("hdk?s", "hdkrs"));
+        assert!(!glob_match("hdk?s", "hdks"));
+        assert!(!glob_match("?", ""));
+    }
+
+    #[test]
+    fn glob_match_star() {
+        assert!(glob_match("*", ""));
+        assert!(glob_match("*", "hdkrs"));
+        assert!(glob_match("hdk*", "hdk"));
+        assert!(glob_match("hdk*", "hdkrs-sys"));
+        assert!(glob_match("*-sys", "hdkrs-sys"));
+        assert!(!glob_match("*-sys", "hdkrs-sys-macros"));
+        assert!(glob_match("h*k*s", "hdkrs"));
+        assert!(glob_match("a*b", "aabab"));
+        assert!(!glob_match("a*b", "aaba"));
+        assert!(glob_match("**", "hdkrs"));
+    }
+}
@@ -0,0 +1,280 @@
+//! Runtime DLL dependencies of the plugin libraries on Windows.
+//!
+//! The DLLs imported by a plugin are resolved when Houdini loads it, from the Houdini 'bin'
+//! directory, the system directories, the directory of the plugin and the 'PATH'. DLLs of other
+//! dependencies, like those of vcpkg packages, are often only found through the 'PATH' of the
+//! development environment, so the plugin fails to load everywhere else. After a build, the import
+//! tables of the plugin libraries are scanned for DLLs that neither Houdini nor Windows provide,
+//! which are reported, copied next to the plugin or fail the build depending on '--runtime-dlls'.
+
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use log::*;
+
+use crate::binary::{read_u16, read_u32};
+use crate::cmake;
+
+/// Handling of the DLLs a plugin depends on that Houdini doesn't provide, set by '--runtime-dlls'.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RuntimeDlls {
+    /// Warn about them.
+    Warn,
+    /// Copy them next to the plugin libraries, and with '--cmake-install' next to the installed
+    /// plugin libraries.
+    Copy,
+    /// Fail the build with a list of them.
+    Deny,
+}
+
+/// Where a DLL the plugin depends on was found.
+enum Location {
+    /// Next to the plugin library, where Houdini finds it.
+    Beside(PathBuf),
+    /// On the 'PATH' or next to the Rust libraries, which Houdini doesn't search.
+    Elsewhere(PathBuf),
+    /// Nowhere.
+    Missing,
+}
+
+/// A DLL imported by a plugin library, directly or through another DLL, that neither Houdini nor
+/// Windows provide.
+struct Dependency {
+    name: String,
+    imported_by: PathBuf,
+    location: Location,
+}
+
+impl Dependency {
+    fn describe(&self) -> String {
+        match &self.location {
+            Location::Beside(path) | Location::Elsewhere(path) => format!(
+                "{} (imported by {}) found at {}",
+                self.name,
+                self.imported_by.display(),
+                path.display()
+            ),
+            Location::Missing => format!(
+                "{} (imported by {}) not found",
+                self.name,
+                self.imported_by.display()
+            ),
+        }
+    }
+}
+
+/// The names of the DLLs imported by the given PE image, including delay-loaded ones, or `None`
+/// if it isn't a valid PE image.
+fn parse_imports(data: &[u8]) -> Option<Vec<String>> {
+    if data.get(0..2)? != b"MZ" {
+        return None;
+    }
+    let pe = read_u32(data, 0x3c)? as usize;
+    if data.get(pe..pe + 4)? != b"PE\0\0" {
+        return None;
+    }
+    let coff = pe + 4;
+    let section_count = read_u16(data, coff + 2)? as usize;
+    let optional = coff + 20;
+    let section_table = optional + read_u16(data, coff + 16)? as usize;
+    // The data directories follow the fields of the optional header, which are wider in PE32+.
+    let (directory_count, directories) = match read_u16(data, optional)? {
+        0x10b => (read_u32(data, optional + 92)?, optional + 96),
+        0x20b => (read_u32(data, optional + 108)?, optional + 112),
+        _ => return None,
+    };
+
+    // Map a relative virtual address to the file offset through the section containing it.
+    let offset_of = |rva: u32| -> Option<usize> {
+        (0..section_count).find_map(|index| {
+            let header = section_table + index * 40;
+            let virtual_size = read_u32(data, header + 8)?;
+            let address = read_u32(data, header + 12)?;
+            let raw_size = read_u32(data, header + 16)?;
+            let raw_offset = read_u32(data, header + 20)?;
+            let size = virtual_size.max(raw_size);
+            let delta = rva.checked_sub(address)?;
+            (delta < size).then(|| (raw_offset + delta) as usize)
+        })
+    };
+    let name_at = |rva: u32| -> Option<String> {
+        let start = offset_of(rva)?;
+        let length = data.get(start..)?.iter().position(|&b| b == 0)?;
+        Some(String::from_utf8_lossy(&data[start..start + length]).into_owned())
+    };
+
+    let mut names = Vec::new();
+    // The import directory, and the delay-load import directory with names at a different offset.
+    for (directory, descriptor_size, name_offset) in [(1, 20, 12), (13, 32, 4)] {
+        if directory >= directory_count {
+            continue;
+        }
+        let rva = read_u32(data, directories + directory as usize * 8)?;
+        if rva == 0 {
+            continue;
+        }
+        let mut descriptor = offset_of(rva)?;
+        loop {
+            let name = read_u32(data, descriptor + name_offset)?;
+            if name == 0 {
+                break;
+            }
+            names.push(name_at(name)?);
+            descriptor += descriptor_size;
+        }
+    }
+    Some(names)
+}
+
+/// The names of the DLLs imported by the given DLL or executable.
+fn imports(path: &Path) -> Result<Vec<String>> {
+    let data = fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    parse_imports(&data).with_context(|| format!("{} is not a valid DLL", path.display()))
+}
+
+/// Returns true if the DLL is part of Windows: an API set or a DLL in the system directory.
+fn is_system(name: &str, system_dir: &Path) -> bool {
+    let lower = name.to_ascii_lowercase();
+    lower.starts_with("api-ms-win-")
+        || lower.starts_with("ext-ms-")
+        || system_dir.join(name).is_file()
+}
+
+/// The DLLs the given plugin library depends on that neither Houdini nor Windows provide,
+/// including those imported by such DLLs. They are looked for next to the plugin, then in
+/// `search_dirs`.
+fn dependencies(plugin: &Path, hfs: &Path, search_dirs: &[PathBuf]) -> Result<Vec<Dependency>> {
+    let system_dir = env::var_os("SystemRoot")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(r"C:\Windows"))
+        .join("System32");
+    let houdini_dir = hfs.join("bin");
+    let plugin_dir = plugin.parent().unwrap_or_else(|| Path::new("."));
+
+    let mut seen = HashSet::new();
+    let mut dependencies = Vec::new();
+    let mut queue = vec![plugin.to_path_buf()];
+    while let Some(library) = queue.pop() {
+        for name in imports(&library)? {
+            // DLL names are case insensitive.
+            if !seen.insert(name.to_ascii_lowercase()) {
+                continue;
+            }
+            if houdini_dir.join(&name).is_file() || is_system(&name, &system_dir) {
+                continue;
+            }
+            let beside = plugin_dir.join(&name);
+            let location = if beside.is_file() {
+                Location::Beside(beside)
+            } else {
+                match search_dirs
+                    .iter()
+                    .map(|dir| dir.join(&name))
+                    .find(|path| path.is_file())
+                {
+                    Some(path) => Location::Elsewhere(path),
+                    None => Location::Missing,
+                }
+            };
+            if let Location::Beside(path) | Location::Elsewhere(path) = &location {
+                queue.push(path.clone());
+            }
+            dependencies.push(Dependency {
+                name,
+                imported_by: library.clone(),
+                location,
+            });
+        }
+    }
+    Ok(dependencies)
+}
+
+/// Check the DLLs the given plugin libraries depend on, looking for those Houdini doesn't provide
+/// next to the plugins, then in `search_dirs`, and handle those found elsewhere or missing
+/// according to `mode`.
+///
+/// Returns the plugins with the DLLs next to them they depend on, including those copied there.
+pub fn check(
+    plugins: &[PathBuf],
+    hfs: &Path,
+    search_dirs: &[PathBuf],
+    mode: RuntimeDlls,
+) -> Result<Vec<(PathBuf, PathBuf)>> {
+    let mut beside = Vec::new();
+    let mut unresolved = Vec::new();
+    for plugin in plugins {
+        for dependency in dependencies(plugin, hfs, search_dirs)? {
+            match &dependency.location {
+                Location::Beside(path) => beside.push((plugin.clone(), path.clone())),
+                Location::Elsewhere(path) if mode == RuntimeDlls::Copy => {
+                    let target = plugin
+                        .parent()
+                        .unwrap_or_else(|| Path::new("."))
+                        .join(&dependency.name);
+                    fs::copy(path, &target).with_context(|| {
+                        format!("Failed to copy {} to {}", path.display(), target.display())
+                    })?;
+                    info!("Copied {} next to {}", path.display(), plugin.display());
+                    beside.push((plugin.clone(), target));
+                }
+                _ => unresolved.push(dependency),
+            }
+        }
+    }
+    if unresolved.is_empty() {
+        return Ok(beside);
+    }
+
+    let list: Vec<_> = unresolved
+        .iter()
+        .map(|dependency| format!("  {}", dependency.describe()))
+        .collect();
+    let mut message = format!(
+        "The HDK plugin depends on DLLs that Houdini doesn't provide, so it won't load outside of this environment:\n{}",
+        list.join("\n")
+    );
+    let found = unresolved
+        .iter()
+        .any(|dependency| matches!(dependency.location, Location::Elsewhere(_)));
+    if found && mode != RuntimeDlls::Copy {
+        message.push_str("\nUse '--runtime-dlls=copy' to copy the DLLs found next to the plugin.");
+    }
+    if mode == RuntimeDlls::Deny {
+        bail!("{}", message);
+    }
+    crate::print_warning(message);
+    Ok(beside)
+}
+
+/// Copy the DLLs next to the plugin libraries returned by [`check`] next to the installed copies
+/// of the libraries recorded in the install manifests of `build_dir`, unless they are installed
+/// already.
+///
+/// Returns the paths of the copied DLLs.
+pub fn install(dlls: &[(PathBuf, PathBuf)], build_dir: &Path) -> Result<Vec<PathBuf>> {
+    let installed = cmake::installed_files(build_dir);
+    let mut copied = Vec::new();
+    for (plugin, dll) in dlls {
+        let name = plugin.file_name();
+        let dirs = installed
+            .iter()
+            .filter(|path| path.file_name() == name)
+            .filter_map(|path| path.parent());
+        for dir in dirs {
+            let target = dir.join(dll.file_name().unwrap_or_default());
+            if installed.contains(&target) || copied.contains(&target) {
+                continue;
+            }
+            debug!("Copying {} to {}", dll.display(), target.display());
+            fs::copy(dll, &target).with_context(|| {
+                format!("Failed to copy {} to {}", dll.display(), target.display())
+            })?;
+            copied.push(target);
+        }
+    }
+    Ok(copied)
+}
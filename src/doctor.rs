@@ -0,0 +1,529 @@
+//! Diagnostics of the build environment with `cargo hdk doctor`.
+//!
+//! Each check prints whether it passed, along with a hint on how to fix it otherwise. Failed
+//! checks would make the build fail, while warnings point at problems that only show up later,
+//! like a compiler that differs from the one Houdini was built with, or a missing license for
+//! launching Houdini.
+
+use std::env;
+use std::fmt;
+use std::fs;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+
+use anyhow::Result;
+use cargo_metadata::semver;
+use log::*;
+
+use crate::compilers;
+use crate::config::Config;
+use crate::houdini::{self, Version};
+use crate::launch::{self, App};
+use crate::tools;
+
+/// Oldest CMake version supported, as required by the Corrosion superproject.
+const MIN_CMAKE: (u32, u32) = (3, 15);
+
+/// Oldest Ninja version that acts as a jobserver client for '--jobserver'.
+const MIN_NINJA_JOBSERVER: (u32, u32) = (1, 13);
+
+/// Default port of the license server ('sesinetd').
+const LICENSE_PORT: u16 = 1715;
+
+/// Time to wait for the license server to accept a connection.
+const LICENSE_TIMEOUT: Duration = Duration::from_secs(3);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Status {
+    Ok,
+    Warn,
+    Fail,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(match self {
+            Status::Ok => "ok",
+            Status::Warn => "warn",
+            Status::Fail => "FAIL",
+        })
+    }
+}
+
+/// The results of the checks, printed as they are added.
+#[derive(Default)]
+struct Report {
+    warnings: usize,
+    failures: usize,
+    passed: usize,
+}
+
+impl Report {
+    fn add(&mut self, status: Status, message: String, hint: Option<String>) {
+        match status {
+            Status::Ok => self.passed += 1,
+            Status::Warn => self.warnings += 1,
+            Status::Fail => self.failures += 1,
+        }
+        println!("[{:<4}] {}", status, message);
+        if let Some(hint) = hint {
+            for line in hint.lines() {
+                println!("       {}", line);
+            }
+        }
+    }
+
+    fn ok(&mut self, message: String) {
+        self.add(Status::Ok, message, None);
+    }
+
+    fn warn(&mut self, message: String, hint: impl Into<String>) {
+        self.add(Status::Warn, message, Some(hint.into()));
+    }
+
+    fn fail(&mut self, message: String, hint: impl Into<String>) {
+        self.add(Status::Fail, message, Some(hint.into()));
+    }
+}
+
+/// The first line of the output of the given program, which usually contains its version.
+fn tool_version(program: &Path, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+    debug!(
+        "{} {:?} exited with {}",
+        program.display(),
+        args,
+        output.status
+    );
+    // MSVC prints its version to stderr.
+    let text = [output.stdout, output.stderr].concat();
+    let text = String::from_utf8_lossy(&text);
+    text.lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .map(String::from)
+}
+
+/// Parse the first version number like '3.26.4' or '11.2.0-1ubuntu1' in the given text.
+fn parse_version(text: &str) -> Option<Vec<u32>> {
+    text.split_whitespace().find_map(|word| {
+        let numeric: String = word
+            .chars()
+            .take_while(|c| c.is_ascii_digit() || *c == '.')
+            .collect();
+        if !numeric.contains('.') {
+            return None;
+        }
+        numeric
+            .split('.')
+            .filter(|part| !part.is_empty())
+            .map(|part| part.parse().ok())
+            .collect()
+    })
+}
+
+/// The `(major, minor)` part of a parsed version.
+fn major_minor(version: &[u32]) -> (u32, u32) {
+    (
+        version.first().copied().unwrap_or(0),
+        version.get(1).copied().unwrap_or(0),
+    )
+}
+
+/// Check the Houdini installation and its version, returning the version if it was found.
+fn check_houdini(report: &mut Report, hfs: Option<&str>, config: &Config) -> Option<Version> {
+    let source = if env::var_os("HFS").is_some() {
+        "the HFS environment variable"
+    } else if config.hfs.is_some() {
+        "the configuration"
+    } else {
+        "a default install location"
+    };
+    let hfs = match hfs {
+        Some(hfs) => Path::new(hfs),
+        None => {
+            report.fail(
+                "No Houdini installation found".to_string(),
+                "Source 'houdini_setup' from the Houdini installation directory, or set 'HFS' or\n\
+                 'hfs' in the configuration to the installation path.",
+            );
+            return None;
+        }
+    };
+    if !hfs.is_dir() {
+        report.fail(
+            format!(
+                "The Houdini installation {} from {} doesn't exist",
+                hfs.display(),
+                source
+            ),
+            "Set 'HFS' to an existing Houdini installation.",
+        );
+        return None;
+    }
+    let version = match houdini::version(hfs) {
+        Ok(version) => version,
+        Err(err) => {
+            report.fail(
+                format!("Failed to determine the version of Houdini at {}: {:#}", hfs.display(), err),
+                "Check that 'HFS' points at the root of a Houdini installation, which contains the\n\
+                 'toolkit' directory.",
+            );
+            return None;
+        }
+    };
+    report.ok(format!(
+        "Houdini {} at {} (from {})",
+        version,
+        hfs.display(),
+        source
+    ));
+
+    if let Some(requirement) = &config.houdini_version {
+        let semver = semver::Version::new(
+            version.major.into(),
+            version.minor.into(),
+            version.build.into(),
+        );
+        if requirement.matches(&semver) {
+            report.ok(format!("Houdini {} matches '{}'", version, requirement));
+        } else {
+            report.fail(
+                format!(
+                    "Houdini {} doesn't match the required version '{}'",
+                    version, requirement
+                ),
+                "Point 'HFS' at a matching Houdini installation, or update 'houdini_version' in\n\
+                 the configuration.",
+            );
+        }
+    }
+
+    let hdk_config = hfs
+        .join("toolkit")
+        .join("cmake")
+        .join("HoudiniConfig.cmake");
+    if hdk_config.is_file() {
+        report.ok("The HDK CMake configuration is installed".to_string());
+    } else {
+        report.fail(
+            format!("{} is missing", hdk_config.display()),
+            "Reinstall Houdini with the 'Houdini Development Kit' component selected.",
+        );
+    }
+    Some(version)
+}
+
+/// Returns true if both paths refer to the same file, resolving symbolic links and relative
+/// paths where possible.
+pub fn same_path(a: &Path, b: &Path) -> bool {
+    let canonical = |path: &Path| fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    canonical(a) == canonical(b)
+}
+
+/// Check that the Houdini variables of the environment belong to the installation in `hfs`.
+fn check_environment(report: &mut Report, hfs: &str) {
+    let expected = match launch::houdini_env(hfs) {
+        Ok(envs) => envs,
+        Err(err) => {
+            report.warn(
+                format!("Failed to determine the Houdini environment: {:#}", err),
+                "Check the 'PATH' environment variable.",
+            );
+            return;
+        }
+    };
+    let mut consistent = true;
+    for (key, value) in &expected {
+        if *key == "HFS" || *key == "PATH" {
+            continue;
+        }
+        let actual = match env::var_os(key) {
+            Some(actual) => actual,
+            None => continue,
+        };
+        let matches = if key.starts_with("HOUDINI_") {
+            actual == *value
+        } else {
+            same_path(Path::new(&actual), Path::new(value))
+        };
+        if !matches {
+            consistent = false;
+            report.warn(
+                format!(
+                    "{} is {:?}, which doesn't belong to the Houdini installation at {}",
+                    key, actual, hfs
+                ),
+                "The environment was likely set up for another Houdini installation. Source\n\
+                 'houdini_setup' from the installation in 'HFS' again, or start a new shell.",
+            );
+        }
+    }
+
+    let hython = App::Hython.path(hfs);
+    if let Some(found) = tools::find_program("hython") {
+        if let Some(bin) = found.parent() {
+            if !same_path(bin, hython.parent().unwrap_or(bin)) {
+                consistent = false;
+                report.warn(
+                    format!(
+                        "hython on the 'PATH' is {}, which is not part of {}",
+                        found.display(),
+                        hfs
+                    ),
+                    "Houdini applications started from the shell use another installation than\n\
+                     the build. Remove the other installation from the 'PATH'.",
+                );
+            }
+        }
+    }
+    if consistent {
+        report.ok("The Houdini environment variables match HFS".to_string());
+    }
+}
+
+/// The C++ compiler CMake uses, following the 'cxx' configuration key unless it is 'auto' and
+/// otherwise the 'CXX' environment variable.
+fn find_cxx(configured: Option<&str>) -> Option<PathBuf> {
+    if let Some(cxx) = configured.filter(|cxx| *cxx != compilers::AUTO) {
+        return if Path::new(cxx).is_absolute() {
+            Some(PathBuf::from(cxx))
+        } else {
+            tools::find_program(cxx)
+        };
+    }
+    if let Some(cxx) = env::var_os("CXX").filter(|cxx| !cxx.is_empty()) {
+        let cxx = PathBuf::from(cxx);
+        return if cxx.is_absolute() {
+            Some(cxx)
+        } else {
+            tools::find_program(&cxx.to_string_lossy())
+        };
+    }
+    let candidates: &[&str] = if cfg!(windows) {
+        &["cl"]
+    } else {
+        &["c++", "g++", "clang++"]
+    };
+    candidates.iter().find_map(|name| tools::find_program(name))
+}
+
+/// Check that a C++ compiler is available and matches the one Houdini was built with.
+fn check_compiler(report: &mut Report, houdini: Option<&Version>, configured: Option<&str>) {
+    let cxx = match find_cxx(configured) {
+        Some(cxx) => cxx,
+        None if cfg!(windows) => {
+            report.warn(
+                "The MSVC compiler 'cl' is not on the 'PATH'".to_string(),
+                "Visual Studio generators find the compiler themselves. To use Ninja or Make, run\n\
+                 cargo-hdk from a Visual Studio developer command prompt.",
+            );
+            return;
+        }
+        None => {
+            report.fail(
+                "No C++ compiler found".to_string(),
+                "Install GCC or Clang, or set 'CXX' to the compiler executable.",
+            );
+            return;
+        }
+    };
+    // MSVC prints its version when run without arguments.
+    let is_msvc = matches!(cxx.file_stem(), Some(stem) if stem.eq_ignore_ascii_case("cl"));
+    let args: &[&str] = if is_msvc { &[] } else { &["--version"] };
+    let description = match tool_version(&cxx, args) {
+        Some(description) => description,
+        None => {
+            report.fail(
+                format!("Failed to run the C++ compiler {}", cxx.display()),
+                "Check that the compiler is installed correctly, or set 'CXX' to another compiler.",
+            );
+            return;
+        }
+    };
+    let version = parse_version(&description).unwrap_or_default();
+    let found = format!("C++ compiler {} ({})", cxx.display(), description);
+
+    let houdini = match houdini {
+        Some(houdini) => houdini,
+        None => return report.ok(found),
+    };
+    let (gcc_majors, vs_versions) = houdini::compilers(houdini);
+    let (major, minor) = major_minor(&version);
+    if is_msvc {
+        if matches!(houdini::visual_studio(major, minor), Some(vs) if vs_versions.contains(&vs)) {
+            report.ok(found);
+        } else {
+            report.warn(
+                found,
+                format!(
+                    "Houdini {} is built with Visual Studio {}. Plugins built with another version\n\
+                     may fail to link or load. Use a matching developer command prompt or\n\
+                     Visual Studio generator.",
+                    houdini,
+                    vs_versions.join(" or ")
+                ),
+            );
+        }
+    } else if cfg!(target_os = "linux") && !description.to_lowercase().contains("clang") {
+        if gcc_majors.contains(&major) {
+            report.ok(found);
+        } else {
+            report.warn(
+                found,
+                format!(
+                    "Houdini {} is built with GCC {}. Plugins built with another major version\n\
+                     may need a newer libstdc++ than Houdini ships with or miss C++ features the\n\
+                     HDK uses. Install a matching GCC and select it with '--cxx auto' or 'CXX',\n\
+                     e.g. 'CXX=g++-{}'.",
+                    houdini,
+                    gcc_majors
+                        .iter()
+                        .map(|major| major.to_string())
+                        .collect::<Vec<_>>()
+                        .join(" or "),
+                    gcc_majors.last().copied().unwrap_or_default()
+                ),
+            );
+        }
+    } else {
+        report.ok(found);
+    }
+}
+
+/// Check the CMake and Ninja versions.
+fn check_build_tools(report: &mut Report) {
+    match tools::find_program("cmake") {
+        Some(cmake) => {
+            let description = tool_version(&cmake, &["--version"]).unwrap_or_default();
+            let version = parse_version(&description).unwrap_or_default();
+            let found = format!("{} at {}", description, cmake.display());
+            if major_minor(&version) >= MIN_CMAKE {
+                report.ok(found);
+            } else {
+                report.fail(
+                    found,
+                    format!(
+                        "CMake {}.{} or newer is required. Install a newer CMake, e.g. with\n\
+                         'pip install cmake'.",
+                        MIN_CMAKE.0, MIN_CMAKE.1
+                    ),
+                );
+            }
+        }
+        None => report.fail(
+            "CMake not found on the 'PATH'".to_string(),
+            "Install CMake from https://cmake.org/download/ or your package manager.",
+        ),
+    }
+
+    match tools::find_program("ninja") {
+        Some(ninja) => {
+            let description = tool_version(&ninja, &["--version"]).unwrap_or_default();
+            let version = parse_version(&description).unwrap_or_default();
+            let found = format!("Ninja {} at {}", description, ninja.display());
+            if major_minor(&version) >= MIN_NINJA_JOBSERVER {
+                report.ok(found);
+            } else {
+                report.warn(
+                    found,
+                    format!(
+                        "Ninja {}.{} or newer is needed to share the jobserver with '--jobserver'.",
+                        MIN_NINJA_JOBSERVER.0, MIN_NINJA_JOBSERVER.1
+                    ),
+                );
+            }
+        }
+        None => report.warn(
+            "Ninja not found on the 'PATH'".to_string(),
+            "The platform default CMake generator is used instead. Install Ninja for faster\n\
+             incremental builds of the HDK plugin.",
+        ),
+    }
+}
+
+/// The license servers configured with 'SESI_LMHOST' or in the license preferences of the user.
+fn license_servers() -> Vec<String> {
+    let servers = env::var("SESI_LMHOST").ok().or_else(|| {
+        let home = env::var_os(if cfg!(windows) { "USERPROFILE" } else { "HOME" })?;
+        let prefs = fs::read_to_string(Path::new(&home).join(".sesi_licenses.pref")).ok()?;
+        prefs.lines().find_map(|line| {
+            line.trim()
+                .strip_prefix("serverhost=")
+                .map(|value| value.trim().to_string())
+        })
+    });
+    servers
+        .unwrap_or_default()
+        .split([';', ',', ' '])
+        .filter(|server| !server.is_empty() && *server != "+")
+        .map(String::from)
+        .collect()
+}
+
+/// Check that the license servers accept connections.
+fn check_license(report: &mut Report) {
+    let servers = license_servers();
+    if servers.is_empty() {
+        report.warn(
+            "No license server configured".to_string(),
+            "Building the plugin doesn't need a license, but 'cargo hdk run', 'test' and 'bench'\n\
+             do. Set 'SESI_LMHOST' or run 'hserver -S <server>' unless Houdini is licensed\n\
+             through a SideFX account or a local license.",
+        );
+        return;
+    }
+    for server in servers {
+        let address = if server.contains(':') {
+            server.clone()
+        } else {
+            format!("{}:{}", server, LICENSE_PORT)
+        };
+        let reachable = address
+            .to_socket_addrs()
+            .map(|mut addrs| {
+                addrs.any(|addr| TcpStream::connect_timeout(&addr, LICENSE_TIMEOUT).is_ok())
+            })
+            .unwrap_or(false);
+        if reachable {
+            report.ok(format!("License server {} is reachable", address));
+        } else {
+            report.warn(
+                format!("License server {} is not reachable", address),
+                "Check the server name, the network connection and firewalls. Houdini applications\n\
+                 and hython can't run without a license.",
+            );
+        }
+    }
+}
+
+/// Check everything the build needs and print the results with hints for fixing problems.
+///
+/// Returns an error if any of the checks failed.
+pub fn run(hfs: Option<&str>, config: &Config) -> Result<()> {
+    let mut report = Report::default();
+    println!();
+    let version = check_houdini(&mut report, hfs, config);
+    if let (Some(hfs), Some(_)) = (hfs, &version) {
+        check_environment(&mut report, hfs);
+    }
+    check_compiler(&mut report, version.as_ref(), config.cxx.as_deref());
+    check_build_tools(&mut report);
+    check_license(&mut report);
+
+    println!();
+    println!(
+        "{} passed; {} warnings; {} failed",
+        report.passed, report.warnings, report.failures
+    );
+    if report.failures > 0 {
+        bail!(
+            "{} check{} failed",
+            report.failures,
+            if report.failures == 1 { "" } else { "s" }
+        );
+    }
+    Ok(())
+}
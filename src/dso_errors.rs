@@ -0,0 +1,220 @@
+//! Errors of Houdini loading the plugin in `cargo hdk run` and `cargo hdk test`.
+//!
+//! Houdini only prints why a DSO failed to load with 'HOUDINI_DSO_ERROR' set, buried in the rest
+//! of its output, and carries on without the plugin. The launched applications get
+//! 'HOUDINI_DSO_ERROR=2', and the lines of their output naming one of the plugin libraries are
+//! interpreted as missing symbols, missing libraries, runtime or Houdini versions the plugin
+//! wasn't built for, or the wrong architecture, which are reported after the session or with the
+//! failed test.
+
+use std::collections::BTreeSet;
+use std::env;
+use std::ffi::OsString;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+use std::process::{Command, ExitStatus, Stdio};
+
+use anyhow::{Context, Result};
+
+use crate::verify;
+
+/// Environment variable making Houdini print why DSOs fail to load.
+const ERROR_ENV: &str = "HOUDINI_DSO_ERROR";
+
+/// The environment making Houdini print DSO errors, unless 'HOUDINI_DSO_ERROR' is set already.
+pub fn env() -> Option<(&'static str, OsString)> {
+    if env::var_os(ERROR_ENV).is_some() {
+        None
+    } else {
+        Some((ERROR_ENV, "2".into()))
+    }
+}
+
+/// Why a plugin library failed to load.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Problem {
+    /// A symbol the plugin uses is defined by none of the loaded libraries.
+    MissingSymbol(String),
+    /// A library the plugin links wasn't found.
+    MissingLibrary(String),
+    /// The C or C++ runtime lacks a symbol version the plugin requires, like 'GLIBCXX_3.4.30'.
+    RuntimeVersion(String),
+    /// The plugin was built for another Houdini version.
+    HoudiniVersion,
+    /// The plugin was built for another architecture.
+    Architecture,
+    /// Any other error.
+    Other,
+}
+
+/// A plugin library Houdini failed to load, with the line of its output reporting it.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct LoadError {
+    pub plugin: String,
+    pub problem: Problem,
+    pub message: String,
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.problem {
+            Problem::MissingSymbol(symbol) => write!(
+                f,
+                "{}: undefined symbol {}. Link the library defining it, or export it from the Rust code with '#[no_mangle]'.",
+                self.plugin,
+                verify::demangle(symbol)
+            ),
+            Problem::MissingLibrary(library) => write!(
+                f,
+                "{}: the library {} it links wasn't found. Install it next to the plugin or in a directory on the loader path.",
+                self.plugin, library
+            ),
+            Problem::RuntimeVersion(version) => write!(
+                f,
+                "{}: the C++ runtime of Houdini lacks {}. Build with a compiler matching Houdini, e.g. with '--cxx auto'.",
+                self.plugin, version
+            ),
+            Problem::HoudiniVersion => write!(
+                f,
+                "{}: built for another Houdini version. Rebuild it against this Houdini installation: {}",
+                self.plugin, self.message
+            ),
+            Problem::Architecture => write!(
+                f,
+                "{}: built for another architecture than Houdini: {}",
+                self.plugin, self.message
+            ),
+            Problem::Other => write!(f, "{}: {}", self.plugin, self.message),
+        }
+    }
+}
+
+/// The word following `marker` in `line`, without quotes.
+fn word_after<'a>(line: &'a str, marker: &str) -> Option<&'a str> {
+    let start = line.find(marker)? + marker.len();
+    line[start..]
+        .split_whitespace()
+        .next()
+        .map(|word| word.trim_matches(|c| matches!(c, '\'' | '`' | '"' | ',' | ')')))
+}
+
+/// Interpret a line of Houdini's output reporting that a DSO failed to load.
+fn problem(line: &str) -> Option<Problem> {
+    let lower = line.to_lowercase();
+    let problem = if let Some(symbol) =
+        word_after(line, "undefined symbol:").or_else(|| word_after(line, "Symbol not found:"))
+    {
+        // Mach-O symbols have a leading underscore.
+        let symbol = symbol
+            .strip_prefix("__Z")
+            .map_or(symbol.to_string(), |rest| format!("_Z{}", rest));
+        Problem::MissingSymbol(symbol)
+    } else if let Some(library) = word_after(line, "Library not loaded:") {
+        Problem::MissingLibrary(library.rsplit('/').next().unwrap_or(library).to_string())
+    } else if lower.contains("cannot open shared object file") {
+        // '<library>: cannot open shared object file: No such file or directory'
+        let library = line
+            .split(": cannot open shared object file")
+            .next()?
+            .rsplit(": ")
+            .next()?;
+        Problem::MissingLibrary(library.to_string())
+    } else if let Some(version) =
+        word_after(line, "version").filter(|_| lower.contains("not found"))
+    {
+        Problem::RuntimeVersion(version.to_string())
+    } else if lower.contains("wrong elf class")
+        || lower.contains("incompatible architecture")
+        || lower.contains("not a valid win32 application")
+        || lower.contains("error 193")
+    {
+        Problem::Architecture
+    } else if lower.contains("version")
+        && (lower.contains("compiled") || lower.contains("built") || lower.contains("expected"))
+    {
+        Problem::HoudiniVersion
+    } else if lower.contains("error") || lower.contains("fail") || lower.contains("unable") {
+        Problem::Other
+    } else {
+        return None;
+    };
+    Some(problem)
+}
+
+/// The load errors of the given plugin libraries in the output of Houdini.
+pub fn scan(output: &str, plugins: &[PathBuf]) -> Vec<LoadError> {
+    let names: Vec<String> = plugins
+        .iter()
+        .filter_map(|plugin| plugin.file_name())
+        .map(|name| name.to_string_lossy().into_owned())
+        .collect();
+    let mut errors = BTreeSet::new();
+    let lines: Vec<&str> = output.lines().collect();
+    for (index, line) in lines.iter().enumerate() {
+        let plugin = match names.iter().find(|name| line.contains(name.as_str())) {
+            Some(plugin) => plugin,
+            None => continue,
+        };
+        // The reason of a failed 'dlopen' on macOS follows on the next lines.
+        let message = lines[index..lines.len().min(index + 3)].join(" ");
+        if let Some(problem) =
+            problem(line).or_else(|| problem(&message).filter(|p| *p != Problem::Other))
+        {
+            errors.insert(LoadError {
+                plugin: plugin.clone(),
+                problem,
+                message: line.trim().to_string(),
+            });
+        }
+    }
+    errors.into_iter().collect()
+}
+
+/// Run `cmd` with its error output passed through, returning its exit status and the lines of the
+/// output naming one of the plugin libraries along with the two lines following each.
+pub fn run_captured(cmd: &mut Command, plugins: &[PathBuf]) -> Result<(ExitStatus, String)> {
+    let names: Vec<String> = plugins
+        .iter()
+        .filter_map(|plugin| plugin.file_name())
+        .map(|name| name.to_string_lossy().into_owned())
+        .collect();
+    let mut child = cmd.stderr(Stdio::piped()).spawn()?;
+    let mut stderr = child
+        .stderr
+        .take()
+        .context("Failed to capture the error output")?;
+    let mut captured = String::new();
+    let mut following = 0;
+    let mut line = Vec::new();
+    let mut buffer = [0; 4096];
+    loop {
+        let read = match stderr.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err.into()),
+        };
+        // Passed through as it arrives, so prompts of interactive sessions show up.
+        let mut out = io::stderr();
+        out.write_all(&buffer[..read])?;
+        out.flush()?;
+        for &byte in &buffer[..read] {
+            if byte != b'\n' {
+                line.push(byte);
+                continue;
+            }
+            let text = String::from_utf8_lossy(&line);
+            if names.iter().any(|name| text.contains(name.as_str())) {
+                following = 3;
+            }
+            if following > 0 {
+                following -= 1;
+                captured.push_str(&text);
+                captured.push('\n');
+            }
+            line.clear();
+        }
+    }
+    Ok((child.wait()?, captured))
+}
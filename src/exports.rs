@@ -0,0 +1,213 @@
+//! Audit and trimming of the symbols exported by the plugin libraries.
+//!
+//! Houdini loads all plugins into one process, so symbols a plugin exports besides the entry
+//! points Houdini calls can collide with those of Houdini or of other plugins. E.g. two plugins
+//! with Rust code both export the symbols of their Rust standard libraries, and one of them may
+//! end up calling into the other's. After a build on Linux and macOS, the symbols exported by the
+//! plugin libraries are listed with 'nm', and those that are neither entry points nor listed in
+//! the 'exports' configuration are reported.
+//!
+//! '--trim-exports' compiles the C++ code with '-fvisibility=hidden' and links the plugin with a
+//! list of the symbols to export, a version script on Linux and an exported symbols list on
+//! macOS, which also hides the symbols of the Rust code. DLLs on Windows only export symbols
+//! marked with '__declspec(dllexport)', so they are neither checked nor trimmed.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::Result;
+use log::*;
+
+use crate::cmake;
+use crate::deps;
+use crate::tools;
+use crate::verify;
+
+/// Compiler flags hiding the symbols of the C++ code that aren't exported explicitly.
+pub const VISIBILITY_FLAGS: &[&str] = &["-fvisibility=hidden", "-fvisibility-inlines-hidden"];
+
+/// Patterns of the entry points Houdini looks up in plugins, like 'HoudiniDSOVersion' and
+/// 'newSopOperator'.
+const ENTRY_POINTS: &[&str] = &["Houdini*", "new*", "CMDextendLibrary"];
+
+/// Symbols the linker defines in every ELF library.
+const LINKER_SYMBOLS: &[&str] = &["_init", "_fini", "_edata", "_end", "__bss_start"];
+
+/// Number of symbols listed for each kind of unexpected export.
+const MAX_SYMBOLS: usize = 5;
+
+/// Returns true if `symbol` is exported on purpose.
+fn is_intended(symbol: &str, exports: &[String]) -> bool {
+    LINKER_SYMBOLS.contains(&symbol)
+        || ENTRY_POINTS
+            .iter()
+            .copied()
+            .chain(exports.iter().map(String::as_str))
+            .any(|pattern| deps::glob_match(pattern, symbol))
+}
+
+/// Returns true if `symbol` belongs to Rust code, including the standard library.
+fn is_rust(symbol: &str) -> bool {
+    // Legacy mangled names end with a hash like '17h0123456789abcdefE'.
+    let legacy_hash = symbol
+        .strip_suffix('E')
+        .and_then(|rest| rest.get(rest.len().saturating_sub(19)..))
+        .is_some_and(|hash| {
+            hash.starts_with("17h") && hash[3..].chars().all(|c| c.is_ascii_hexdigit())
+        });
+    symbol.starts_with("_R")
+        || (symbol.starts_with("_ZN") && legacy_hash)
+        || symbol.contains("rust_")
+}
+
+/// The symbols exported by the given library according to 'nm'.
+pub fn exported_symbols(library: &Path) -> Option<Vec<String>> {
+    let nm = tools::find_program("nm")?;
+    let mut cmd = Command::new(nm);
+    if cfg!(target_os = "macos") {
+        cmd.args(["-g", "-U"]);
+    } else {
+        cmd.args(["-D", "--defined-only"]);
+    }
+    let output = cmd.arg(library).output().ok()?;
+    if !output.status.success() {
+        debug!(
+            "nm failed on {}: {}",
+            library.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+        return None;
+    }
+    let symbols = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        // Version definitions are listed as absolute symbols.
+        .filter(|line| line.split_whitespace().nth(1) != Some("A"))
+        .filter_map(|line| line.split_whitespace().last())
+        // Versioned symbols are listed as 'name@@VERSION'.
+        .map(|symbol| symbol.split('@').next().unwrap_or(symbol))
+        // Mach-O symbols have a leading underscore.
+        .map(|symbol| {
+            if cfg!(target_os = "macos") {
+                symbol.strip_prefix('_').unwrap_or(symbol)
+            } else {
+                symbol
+            }
+        })
+        .map(String::from)
+        .collect();
+    Some(symbols)
+}
+
+/// The symbols exported by the given plugin library that aren't entry points or listed in
+/// `exports`, or `None` if they can't be listed.
+pub fn unexpected_exports(plugin: &Path, exports: &[String]) -> Option<Vec<String>> {
+    let symbols = match exported_symbols(plugin) {
+        Some(symbols) => symbols,
+        None => {
+            debug!(
+                "Failed to list the symbols exported by {}",
+                plugin.display()
+            );
+            return None;
+        }
+    };
+    Some(
+        symbols
+            .into_iter()
+            .filter(|symbol| !is_intended(symbol, exports))
+            .collect(),
+    )
+}
+
+/// Warn about symbols exported by the given plugin libraries that aren't entry points or listed
+/// in `exports`.
+pub fn check(plugins: &[PathBuf], exports: &[String]) {
+    for plugin in plugins {
+        let unexpected = match unexpected_exports(plugin, exports) {
+            Some(unexpected) if !unexpected.is_empty() => unexpected,
+            _ => continue,
+        };
+        let (rust, other): (Vec<_>, Vec<_>) = unexpected
+            .iter()
+            .map(String::as_str)
+            .partition(|symbol| is_rust(symbol));
+        let (cpp, c): (Vec<_>, Vec<_>) = other
+            .into_iter()
+            .partition(|symbol| symbol.starts_with("_Z"));
+        let mut lines = Vec::new();
+        for (kind, symbols) in [("Rust", rust), ("C++", cpp), ("C", c)] {
+            if symbols.is_empty() {
+                continue;
+            }
+            let listed = symbols[..symbols.len().min(MAX_SYMBOLS)].join(", ");
+            let mut line = format!(
+                "  {} {}: {}",
+                symbols.len(),
+                kind,
+                verify::demangle(&listed)
+            );
+            if symbols.len() > MAX_SYMBOLS {
+                line.push_str(&format!(" and {} more", symbols.len() - MAX_SYMBOLS));
+            }
+            lines.push(line);
+        }
+        warn!(
+            "{} exports {} symbols besides the entry points Houdini calls, which can collide with those of Houdini or other plugins in the same process:\n{}\nBuild with '--trim-exports' to export only the entry points, or list the symbols exported on purpose in 'exports'.",
+            plugin.display(),
+            unexpected.len(),
+            lines.join("\n")
+        );
+    }
+}
+
+/// The path of the list of symbols to export in `build_dir`, and the linker flag passing it to
+/// the linker.
+fn export_list(build_dir: &Path) -> (PathBuf, String) {
+    if cfg!(target_os = "macos") {
+        let path = build_dir.join("exports.txt");
+        let flag = format!("-Wl,-exported_symbols_list,{}", path.display());
+        (path, flag)
+    } else {
+        let path = build_dir.join("exports.map");
+        let flag = format!("-Wl,--version-script={}", path.display());
+        (path, flag)
+    }
+}
+
+/// Write the list of symbols to export, the entry points and `exports`, to `path`.
+fn write_export_list(path: &Path, exports: &[String]) -> Result<()> {
+    let patterns = ENTRY_POINTS
+        .iter()
+        .copied()
+        .chain(exports.iter().map(String::as_str));
+    let contents = if cfg!(target_os = "macos") {
+        patterns.map(|pattern| format!("_{}\n", pattern)).collect()
+    } else {
+        let globals: String = patterns
+            .map(|pattern| format!("    {};\n", pattern))
+            .collect();
+        format!("{{\n  global:\n{}  local:\n    *;\n}};\n", globals)
+    };
+    cmake::write_file(path, &contents)
+}
+
+/// Add the linker flag exporting only the entry points and `exports` to the linker flags of the
+/// plugin if `trim` is true, or remove it otherwise.
+pub fn cmake_args(
+    build_dir: &Path,
+    trim: bool,
+    exports: &[String],
+    cmake_args: &mut Vec<String>,
+) -> Result<()> {
+    let (path, flag) = export_list(build_dir);
+    let add = if trim {
+        write_export_list(&path, exports)?;
+        vec![flag.as_str()]
+    } else {
+        Vec::new()
+    };
+    for var in ["CMAKE_SHARED_LINKER_FLAGS", "CMAKE_MODULE_LINKER_FLAGS"] {
+        cmake::merge_flags_arg(build_dir, var, "LDFLAGS", &[&flag], &add, cmake_args);
+    }
+    Ok(())
+}
@@ -0,0 +1,116 @@
+//! Mapping of the cargo features enabled for the build to CMake cache definitions.
+//!
+//! Features are mapped to CMake variables in the package metadata:
+//!
+//! ```toml
+//! [package.metadata.hdk.features]
+//! gpu = "PLUGIN_GPU"
+//! simd = ["PLUGIN_SIMD", "USE_AVX2"]
+//! ```
+//!
+//! Each variable is set to `ON` if the feature is enabled and `OFF` otherwise, so toggling a
+//! feature also updates the CMake cache of an existing build directory.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use cargo_metadata::Package;
+use log::*;
+
+use crate::config::OneOrMany;
+
+/// Collect all values of the given option in a list of command line arguments, given either as
+/// `<name> <value>` or `<name>=<value>`, or as `<name><value>` for short options like `-F`.
+fn arg_values<'a>(args: &'a [String], names: &[&str]) -> Vec<&'a str> {
+    let mut values = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if names.contains(&arg.as_str()) {
+            values.extend(iter.next().map(String::as_str));
+        } else if let Some(value) = names.iter().find_map(|name| {
+            let rest = arg.strip_prefix(name)?;
+            if name.starts_with("--") {
+                rest.strip_prefix('=')
+            } else {
+                Some(rest.strip_prefix('=').unwrap_or(rest))
+            }
+        }) {
+            values.push(value);
+        }
+    }
+    values
+}
+
+/// Determine the features of `package` enabled by the given cargo arguments, including features
+/// enabled by default or by other enabled features.
+fn enabled(args: &[String], package: &Package) -> BTreeSet<String> {
+    let mut stack: Vec<String> = Vec::new();
+    if args.iter().any(|arg| arg == "--all-features") {
+        stack.extend(package.features.keys().cloned());
+    }
+    if !args.iter().any(|arg| arg == "--no-default-features") {
+        stack.push("default".to_string());
+    }
+    for value in arg_values(args, &["--features", "-F"]) {
+        // Features may be separated by commas or spaces and prefixed with the package name.
+        stack.extend(
+            value
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|f| !f.is_empty())
+                .map(|f| {
+                    f.strip_prefix(package.name.as_str())
+                        .and_then(|f| f.strip_prefix('/'))
+                        .unwrap_or(f)
+                        .to_string()
+                }),
+        );
+    }
+
+    let mut enabled = BTreeSet::new();
+    while let Some(feature) = stack.pop() {
+        if let Some(implied) = package.features.get(&feature) {
+            // Only plain features of the same package can enable other features.
+            stack.extend(
+                implied
+                    .iter()
+                    .filter(|f| !f.starts_with("dep:") && !f.contains('/'))
+                    .filter(|f| !enabled.contains(*f))
+                    .cloned(),
+            );
+            enabled.insert(feature);
+        }
+    }
+    enabled
+}
+
+/// Build the CMake definitions for the features mapped in the package metadata.
+pub fn cmake_args(
+    args: &[String],
+    package: &Package,
+    mapping: &BTreeMap<String, OneOrMany>,
+) -> Vec<String> {
+    if mapping.is_empty() {
+        return Vec::new();
+    }
+
+    let enabled = enabled(args, package);
+    debug!("Enabled cargo features: {:?}", enabled);
+
+    let mut cmake_args = Vec::new();
+    for (feature, vars) in mapping {
+        if !package.features.contains_key(feature) {
+            warn!(
+                "Feature '{}' in 'package.metadata.hdk.features' is not a feature of '{}'",
+                feature, package.name
+            );
+        }
+        let value = if enabled.contains(feature) {
+            "ON"
+        } else {
+            "OFF"
+        };
+        for var in vars.as_slice() {
+            cmake_args.push(format!("-D{}={}", var, value));
+        }
+    }
+    cmake_args
+}
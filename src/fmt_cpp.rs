@@ -0,0 +1,181 @@
+//! Formatting the C++ sources of the plugin with clang-format using `cargo hdk fmt-cpp`.
+//!
+//! The sources are formatted with the '.clang-format' file of the project, found in the HDK plugin
+//! directory or one of its parents, and otherwise with a default following the style of the HDK
+//! headers and examples. Build directories and the headers copied by `cargo hdk bindgen` are left
+//! alone. With '--check', nothing is written and the needed changes are printed as a diff instead.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{Context, Result};
+use log::*;
+
+use crate::bindgen;
+use crate::migrate;
+use crate::tools;
+
+/// Style used without a '.clang-format' file, resembling the HDK sources: four space indents,
+/// braces on their own lines and the return type of function definitions on a separate line.
+const DEFAULT_STYLE: &str = "{BasedOnStyle: LLVM, IndentWidth: 4, ColumnLimit: 100, \
+    BreakBeforeBraces: Allman, AccessModifierOffset: -4, PointerAlignment: Right, \
+    AlwaysBreakAfterDefinitionReturnType: All, SortIncludes: false}";
+
+/// Extensions of the C and C++ sources and headers.
+const EXTENSIONS: &[&str] = &["c", "cc", "cpp", "cxx", "h", "hh", "hpp", "hxx", "inl"];
+
+/// Options of `cargo hdk fmt-cpp`.
+struct FmtArgs {
+    check: bool,
+    paths: Vec<PathBuf>,
+}
+
+impl FmtArgs {
+    fn parse(args: &[String]) -> Result<Self> {
+        let mut check = false;
+        let mut paths = Vec::new();
+        let cwd = env::current_dir()?;
+        for arg in args {
+            if arg == "--check" {
+                check = true;
+            } else if arg.starts_with('-') {
+                bail!(
+                    "Unexpected argument '{}'. Use 'cargo hdk fmt-cpp [--check] [<PATH>...]'",
+                    arg
+                );
+            } else {
+                paths.push(cwd.join(arg));
+            }
+        }
+        Ok(FmtArgs { check, paths })
+    }
+}
+
+/// Returns true if the file is a C or C++ source or header.
+fn is_source(path: &Path) -> bool {
+    matches!(path.extension().and_then(|ext| ext.to_str()), Some(ext) if EXTENSIONS.contains(&ext))
+}
+
+/// The sources in the given directory and its subdirectories, skipping hidden directories,
+/// CMake build directories and the directory of the generated headers.
+fn find_sources(dir: &Path, generated_dir: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        if dir.join("CMakeCache.txt").exists() || dir == generated_dir {
+            continue;
+        }
+        let entries = match dir.read_dir() {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            let path = entry.path();
+            if path.is_dir() {
+                if !entry.file_name().to_string_lossy().starts_with('.') {
+                    dirs.push(path);
+                }
+            } else if is_source(&path) {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    files
+}
+
+/// Returns true if a '.clang-format' file applies to the sources in the given directory.
+fn has_clang_format_file(dir: &Path) -> bool {
+    let dir = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+    dir.ancestors()
+        .any(|dir| dir.join(".clang-format").is_file() || dir.join("_clang-format").is_file())
+}
+
+/// Format the C++ sources in `hdk_dir`, or those given in `args`, with clang-format.
+///
+/// `args` are the arguments following 'fmt-cpp'. With '--check', an error is returned if any
+/// file isn't formatted instead.
+pub fn run(hdk_dir: &Path, args: &[String]) -> Result<()> {
+    let args = FmtArgs::parse(args)?;
+    let generated_dir = hdk_dir.join(bindgen::GENERATED_DIR);
+    let files = if args.paths.is_empty() {
+        find_sources(hdk_dir, &generated_dir)
+    } else {
+        let mut files = Vec::new();
+        for path in &args.paths {
+            if path.is_dir() {
+                files.extend(find_sources(path, &generated_dir));
+            } else if path.is_file() {
+                files.push(path.clone());
+            } else {
+                bail!("{} doesn't exist", path.display());
+            }
+        }
+        files
+    };
+    if files.is_empty() {
+        println!("No C++ sources found in {}", hdk_dir.display());
+        return Ok(());
+    }
+    let clang_format = tools::find_program("clang-format").context(
+        "Couldn't find 'clang-format' on the 'PATH'. Install it with LLVM, e.g. from the 'clang-format' package.",
+    )?;
+    let style = if has_clang_format_file(hdk_dir) {
+        "file"
+    } else {
+        DEFAULT_STYLE
+    };
+
+    let mut unformatted = Vec::new();
+    for file in &files {
+        let original = fs::read_to_string(file)
+            .with_context(|| format!("Failed to read {}", file.display()))?;
+        debug!("Running {} on {}", clang_format.display(), file.display());
+        let output = Command::new(&clang_format)
+            .arg(format!("--style={}", style))
+            .arg(file)
+            .output()
+            .with_context(|| format!("Failed to run {}", clang_format.display()))?;
+        if !output.status.success() {
+            bail!(
+                "clang-format failed to format {}: {}",
+                file.display(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        let formatted = String::from_utf8(output.stdout)
+            .with_context(|| format!("clang-format wrote invalid UTF-8 for {}", file.display()))?;
+        if formatted == original {
+            continue;
+        }
+        if args.check {
+            migrate::print_diff(&file.display().to_string(), &original, &formatted);
+            println!();
+        } else {
+            fs::write(file, &formatted)
+                .with_context(|| format!("Failed to write {}", file.display()))?;
+            println!("Formatted {}", file.display());
+        }
+        unformatted.push(file);
+    }
+
+    let checked = format!(
+        "{} file{}",
+        files.len(),
+        if files.len() == 1 { "" } else { "s" }
+    );
+    if unformatted.is_empty() {
+        println!("Checked {}, all formatted.", checked);
+    } else if args.check {
+        bail!(
+            "{} of {} need formatting. Run 'cargo hdk fmt-cpp' to format them.",
+            unformatted.len(),
+            checked
+        );
+    } else {
+        println!("Formatted {} of {}.", unformatted.len(), checked);
+    }
+    Ok(())
+}
@@ -0,0 +1,149 @@
+//! Converting digital assets between binary files and expanded directories with
+//! `cargo hdk hda expand|collapse`.
+//!
+//! Expanded assets are plain text files that diff and merge well in source control, while
+//! Houdini and installed plugins use the binary asset libraries. Both directions run 'hotl' of
+//! the Houdini installation the plugin is built with, so the asset format matches that version.
+//! The assets of the project are declared in the 'hdas' configuration, which maps each asset
+//! library to its expanded directory.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{Context, Result};
+use log::*;
+
+use crate::config::Hda;
+use crate::doctor::same_path;
+use crate::launch;
+
+/// The directions of the conversion.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Action {
+    /// Binary asset library to expanded directory.
+    Expand,
+    /// Expanded directory to binary asset library.
+    Collapse,
+}
+
+impl Action {
+    fn name(self) -> &'static str {
+        match self {
+            Action::Expand => "expand",
+            Action::Collapse => "collapse",
+        }
+    }
+}
+
+/// The assets to convert: those given on the command line, looked up in the configuration unless
+/// both paths are given, or all configured assets.
+fn select(action: Action, args: &[String], hdas: &BTreeMap<String, Hda>) -> Result<Vec<Hda>> {
+    let cwd = env::current_dir()?;
+    let (source, target) = match args {
+        [] => {
+            if hdas.is_empty() {
+                bail!(
+                    "No digital assets configured. Declare them in 'hdas' in '[package.metadata.hdk]', or give the paths to convert."
+                );
+            }
+            return Ok(hdas.values().cloned().collect());
+        }
+        [source] => (cwd.join(source), None),
+        [source, target] => (cwd.join(source), Some(cwd.join(target))),
+        _ => bail!(
+            "Expected at most a source and a target path, but got {:?}",
+            args
+        ),
+    };
+    let hda = match (action, target) {
+        (Action::Expand, Some(dir)) => Hda { file: source, dir },
+        (Action::Collapse, Some(file)) => Hda { file, dir: source },
+        (_, None) => {
+            let configured = hdas.values().find(|hda| match action {
+                Action::Expand => same_path(&hda.file, &source),
+                Action::Collapse => same_path(&hda.dir, &source),
+            });
+            match configured {
+                Some(hda) => hda.clone(),
+                None => bail!(
+                    "{} is not a configured digital asset. Declare it in 'hdas' in '[package.metadata.hdk]', or give the {} as well.",
+                    source.display(),
+                    match action {
+                        Action::Expand => "directory to expand it into",
+                        Action::Collapse => "asset library to collapse it into",
+                    }
+                ),
+            }
+        }
+    };
+    Ok(vec![hda])
+}
+
+/// Convert the given asset with 'hotl'.
+fn convert(hotl: &Path, action: Action, hda: &Hda, envs: &[(&str, OsString)]) -> Result<()> {
+    let (flag, source, target) = match action {
+        Action::Expand => ("-t", &hda.file, &hda.dir),
+        Action::Collapse => ("-l", &hda.dir, &hda.file),
+    };
+    if !source.exists() {
+        bail!("{} doesn't exist", source.display());
+    }
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create directory {}", parent.display()))?;
+    }
+    let mut cmd = Command::new(hotl);
+    cmd.arg(flag)
+        .arg(&hda.dir)
+        .arg(&hda.file)
+        .envs(envs.iter().map(|(key, value)| (key, value)));
+    debug!("Running {:?}", cmd);
+    let output = cmd
+        .output()
+        .with_context(|| format!("Failed to run {}", hotl.display()))?;
+    if !output.status.success() {
+        bail!(
+            "hotl failed to {} {}: {}",
+            action.name(),
+            source.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    let done = match action {
+        Action::Expand => "Expanded",
+        Action::Collapse => "Collapsed",
+    };
+    println!("{} {} into {}", done, source.display(), target.display());
+    Ok(())
+}
+
+/// Expand or collapse digital assets with 'hotl' of the Houdini installation in `hfs`.
+///
+/// `args` are the arguments following 'hda': 'expand [<FILE> [<DIR>]]' or
+/// 'collapse [<DIR> [<FILE>]]'.
+pub fn run(hfs: &str, hdas: &BTreeMap<String, Hda>, args: &[String]) -> Result<()> {
+    let action = match args.first().map(String::as_str) {
+        Some("expand") => Action::Expand,
+        Some("collapse") => Action::Collapse,
+        _ => bail!("Expected 'cargo hdk hda expand [<FILE> [<DIR>]]' or 'cargo hdk hda collapse [<DIR> [<FILE>]]'"),
+    };
+    let selected = select(action, &args[1..], hdas)?;
+    let hotl = PathBuf::from(hfs)
+        .join("bin")
+        .join(format!("hotl{}", env::consts::EXE_SUFFIX));
+    if !hotl.is_file() {
+        bail!(
+            "Couldn't find 'hotl' in the Houdini installation at {}",
+            hfs
+        );
+    }
+    let envs = launch::houdini_env(hfs)?;
+    for hda in &selected {
+        convert(&hotl, action, hda, &envs)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,47 @@
+//! User commands run at fixed points of the build, configured in `[package.metadata.hdk]`:
+//!
+//! ```toml
+//! [package.metadata.hdk]
+//! pre_build = "python3 tools/gen_version_header.py"
+//! post_build = ["strip-plugin.sh", "echo built $CARGO_HDK_RUST_ARTIFACTS"]
+//! post_install = "curl -X POST https://farm.example.com/plugin-updated"
+//! ```
+//!
+//! Each command is run by the system shell from the crate root with the build environment, so it
+//! can refer to 'HFS' and the 'CARGO_HDK_*' variables describing the build.
+
+use std::ffi::OsString;
+use std::path::Path;
+use std::process::Command;
+
+use anyhow::{Context, Result};
+use log::*;
+
+/// Builds a command running the given command line with the system shell.
+fn shell_command(command: &str) -> Command {
+    if cfg!(windows) {
+        let mut cmd = Command::new("cmd");
+        cmd.arg("/C").arg(command);
+        cmd
+    } else {
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg(command);
+        cmd
+    }
+}
+
+/// Run the commands of the hook with the given name in order, stopping at the first failure.
+pub fn run(name: &str, commands: &[String], dir: &Path, envs: &[(&str, OsString)]) -> Result<()> {
+    for command in commands {
+        info!("Running {} hook: {}", name, command);
+        let status = shell_command(command)
+            .current_dir(dir)
+            .envs(envs.iter().map(|(key, value)| (key, value)))
+            .status()
+            .with_context(|| format!("Failed to run {} hook '{}'", name, command))?;
+        if !status.success() {
+            bail!("The {} hook '{}' failed with {}", name, command, status);
+        }
+    }
+    Ok(())
+}
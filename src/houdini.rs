@@ -0,0 +1,333 @@
+//! Information about the Houdini installation used to build the plugin.
+
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{Context, Result};
+use log::*;
+
+use crate::cmake;
+use crate::macos;
+
+/// Version of a Houdini installation.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Version {
+    pub major: u32,
+    pub minor: u32,
+    pub build: u32,
+}
+
+impl Version {
+    /// The `<major>.<minor>` version used to name Houdini directories (e.g. `19.5`).
+    pub fn short(&self) -> String {
+        format!("{}.{}", self.major, self.minor)
+    }
+
+    /// Returns true if this version starts with the given, possibly partial, version (e.g. `19.5`
+    /// matches `19.5.303`).
+    pub fn matches(&self, version: &str) -> bool {
+        let parts: Vec<_> = version.trim().split('.').collect();
+        parts.len() <= 3
+            && parts
+                .iter()
+                .zip([self.major, self.minor, self.build])
+                .all(|(part, number)| part.parse::<u32>().ok() == Some(number))
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.build)
+    }
+}
+
+impl std::str::FromStr for Version {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parts = s.trim().split('.').map(|p| p.parse::<u32>());
+        let mut next = || -> Result<u32> {
+            parts
+                .next()
+                .unwrap_or(Ok(0))
+                .with_context(|| format!("Invalid Houdini version: {}", s))
+        };
+        Ok(Version {
+            major: next()?,
+            minor: next()?,
+            build: next()?,
+        })
+    }
+}
+
+/// Determine the version of the Houdini installation at `hfs` from the HDK headers.
+pub fn version(hfs: &Path) -> Result<Version> {
+    let header = hfs
+        .join("toolkit")
+        .join("include")
+        .join("SYS")
+        .join("SYS_Version.h");
+    let contents = fs::read_to_string(&header).with_context(|| {
+        format!(
+            "Failed to read Houdini version header: {}",
+            header.display()
+        )
+    })?;
+    contents
+        .lines()
+        .find_map(|line| {
+            let rest = line.trim().strip_prefix("#define")?.trim();
+            let value = rest.strip_prefix("SYS_VERSION_FULL ")?;
+            Some(value.trim().trim_matches('"').to_string())
+        })
+        .with_context(|| format!("No SYS_VERSION_FULL found in {}", header.display()))?
+        .parse()
+}
+
+/// Convert a compiler flag printed by 'hcustom -c' to a clang flag if it affects parsing the
+/// headers. MSVC style flags are translated, since the headers are parsed by clang for bindgen and
+/// clangd.
+fn clang_flag(flag: &str) -> Option<String> {
+    let flag = flag.replace("\\\"", "\"");
+    let flag = match flag.strip_prefix('/') {
+        Some(rest) if cfg!(windows) => match rest.strip_prefix("std:") {
+            Some(std) => format!("-std={}", std),
+            None => format!("-{}", rest),
+        },
+        _ => flag,
+    };
+    let keep = ["-D", "-U", "-I", "-std="]
+        .iter()
+        .any(|prefix| flag.starts_with(prefix));
+    keep.then_some(flag)
+}
+
+/// Definitions selecting the libstdc++ ABI.
+pub const CXX11_ABI_FLAGS: &[&str] = &["-D_GLIBCXX_USE_CXX11_ABI=0", "-D_GLIBCXX_USE_CXX11_ABI=1"];
+
+/// The libstdc++ ABI Houdini was built with on Linux, from the compile definitions of its CMake
+/// package or, if they don't say, from its version: Houdini 19.0 and newer use the C++11 ABI.
+pub fn cxx11_abi(hfs: &Path, version: &Version) -> u32 {
+    let cmake_dir = hfs.join("toolkit").join("cmake");
+    let defined = fs::read_dir(&cmake_dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| fs::read_to_string(entry.ok()?.path()).ok())
+        .find_map(|contents| {
+            let (_, rest) = contents.split_once("_GLIBCXX_USE_CXX11_ABI=")?;
+            rest.chars().next()?.to_digit(10)
+        });
+    defined.unwrap_or(if version.major >= 19 { 1 } else { 0 })
+}
+
+/// The directory of the Houdini libraries the plugin links: 'dsolib' on Linux, the 'Libraries' of
+/// the framework on macOS, and the import libraries in 'custom/houdini/dsolib' on Windows, also
+/// when cross compiling for it.
+pub fn library_dir(hfs: &Path, windows: bool) -> PathBuf {
+    if windows {
+        hfs.join("custom").join("houdini").join("dsolib")
+    } else if cfg!(target_os = "macos") {
+        macos::houdini_libraries(hfs)
+    } else {
+        hfs.join("dsolib")
+    }
+}
+
+/// CMake arguments setting 'HOUDINI_INCLUDE_DIR', 'HOUDINI_LIB_DIR' and 'HOUDINI_CMAKE_DIR' to
+/// the HDK headers, the Houdini libraries and the CMake package of the installation at `hfs`,
+/// leaving out the variables set in `cmake_args`.
+///
+/// The headers and libraries are in different places on each platform, in particular inside the
+/// framework bundle on macOS, so these let a 'CMakeLists.txt' refer to them without checking the
+/// platform.
+pub fn cmake_args(hfs: &Path, windows: bool, cmake_args: &[String]) -> Vec<String> {
+    let toolkit = hfs.join("toolkit");
+    let dirs = vec![
+        ("HOUDINI_INCLUDE_DIR", toolkit.join("include")),
+        ("HOUDINI_LIB_DIR", library_dir(hfs, windows)),
+        ("HOUDINI_CMAKE_DIR", toolkit.join("cmake")),
+    ];
+    let vars = dirs
+        .into_iter()
+        // CMake takes backslashes in values for escapes.
+        .map(|(var, dir)| (var, Some(dir.display().to_string().replace('\\', "/"))))
+        .collect();
+    cmake::default_var_args(vars, cmake_args)
+}
+
+/// The flags the HDK headers are parsed with, from 'hcustom -c' or, if that fails, the defines
+/// the HDK requires on each platform.
+///
+/// On Linux, '_GLIBCXX_USE_CXX11_ABI' is always defined to match Houdini, since the layout of
+/// standard library types in the HDK headers depends on it.
+pub fn hdk_flags(hfs: &Path, version: &Version) -> Vec<String> {
+    let include = hfs.join("toolkit").join("include");
+    let hcustom = hfs
+        .join("bin")
+        .join(format!("hcustom{}", std::env::consts::EXE_SUFFIX));
+    let output = Command::new(&hcustom).arg("-c").output();
+    let mut flags: Vec<_> = match output {
+        Ok(output) if output.status.success() => {
+            let printed = String::from_utf8_lossy(&output.stdout);
+            debug!("HDK compiler flags: {}", printed.trim());
+            let mut tokens = printed.split_whitespace();
+            let mut flags = Vec::new();
+            while let Some(token) = tokens.next() {
+                // The HDK headers are passed to gcc and clang as system headers.
+                if token == "-isystem" {
+                    if let Some(dir) = tokens.next() {
+                        flags.push(format!("-I{}", dir));
+                    }
+                } else if let Some(dir) = token.strip_prefix("-isystem") {
+                    flags.push(format!("-I{}", dir));
+                } else {
+                    flags.extend(clang_flag(token));
+                }
+            }
+            flags
+        }
+        _ => {
+            warn!(
+                "Failed to get the HDK compiler flags from {}. Using the default HDK defines.",
+                hcustom.display()
+            );
+            let platform: &[&str] = if cfg!(windows) {
+                &["-DWIN32", "-DNOMINMAX", "-D_USE_MATH_DEFINES"]
+            } else if cfg!(target_os = "macos") {
+                &["-DMBSD", "-D_GNU_SOURCE"]
+            } else {
+                &["-DLINUX", "-D_GNU_SOURCE"]
+            };
+            let common = [
+                "-DAMD64",
+                "-DSIZEOF_VOID_P=8",
+                "-DSESI_LITTLE_ENDIAN",
+                "-DENABLE_THREADS",
+                "-DUSE_PTHREADS",
+                "-D_REENTRANT",
+                "-D_FILE_OFFSET_BITS=64",
+                "-DMAKING_DSO",
+                "-std=c++17",
+            ];
+            std::iter::once(format!("-DVERSION=\"{}\"", version))
+                .chain(platform.iter().chain(&common).map(|flag| flag.to_string()))
+                .collect()
+        }
+    };
+    if cfg!(target_os = "linux")
+        && !flags
+            .iter()
+            .any(|flag| flag.starts_with("-D_GLIBCXX_USE_CXX11_ABI="))
+    {
+        flags.push(format!(
+            "-D_GLIBCXX_USE_CXX11_ABI={}",
+            cxx11_abi(hfs, version)
+        ));
+    }
+    let include_flag = format!("-I{}", include.display());
+    if !flags.contains(&include_flag) {
+        flags.push(include_flag);
+    }
+    flags
+}
+
+/// Compilers used to build the Houdini release, which plugins should be built with too.
+///
+/// These are the GCC major versions of the Linux builds and the Visual Studio versions of the
+/// Windows build.
+pub fn compilers(version: &Version) -> (&'static [u32], &'static [&'static str]) {
+    match (version.major, version.minor) {
+        (major, _) if major < 18 => (&[6], &["2017"]),
+        (18, 0) => (&[6], &["2017"]),
+        (18, _) => (&[6, 9], &["2019"]),
+        (19, 0) => (&[9], &["2019"]),
+        (19, _) => (&[9, 11], &["2019"]),
+        (20, 0) => (&[9, 11], &["2022"]),
+        _ => (&[11], &["2022"]),
+    }
+}
+
+/// The Visual Studio version shipping the MSVC compiler with the given version, e.g. '19.29'.
+pub fn visual_studio(major: u32, minor: u32) -> Option<&'static str> {
+    match (major, minor) {
+        (19, 10..=19) => Some("2017"),
+        (19, 20..=29) => Some("2019"),
+        (19, 30..=49) => Some("2022"),
+        _ => None,
+    }
+}
+
+/// The directories in `dir` whose names start with `prefix`.
+fn subdirs(dir: &Path, prefix: &str) -> Vec<PathBuf> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_name().to_string_lossy().starts_with(prefix))
+        .map(|entry| entry.path())
+        .filter(|path| path.is_dir())
+        .collect()
+}
+
+/// The Houdini installations in the default installation locations of the platform, sorted by
+/// version.
+pub fn installations() -> Vec<(Version, PathBuf)> {
+    let candidates = if cfg!(target_os = "windows") {
+        let program_files = std::env::var_os("ProgramFiles")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from(r"C:\Program Files"));
+        subdirs(&program_files.join("Side Effects Software"), "Houdini ")
+    } else if cfg!(target_os = "macos") {
+        subdirs(Path::new("/Applications/Houdini"), "Houdini")
+            .into_iter()
+            .map(|dir| {
+                dir.join("Frameworks")
+                    .join("Houdini.framework")
+                    .join("Versions")
+                    .join("Current")
+                    .join("Resources")
+            })
+            .collect()
+    } else {
+        subdirs(Path::new("/opt"), "hfs")
+    };
+    let mut installations: Vec<_> = candidates
+        .into_iter()
+        .filter_map(|hfs| Some((version(&hfs).ok()?, hfs)))
+        .collect();
+    installations.sort();
+    installations
+}
+
+/// The user preference directory Houdini searches for plugins and other configuration.
+///
+/// This respects `HOUDINI_USER_PREF_DIR` if set, otherwise it returns the platform specific
+/// default location.
+pub fn user_pref_dir(version: &Version) -> Option<PathBuf> {
+    if let Ok(dir) = std::env::var("HOUDINI_USER_PREF_DIR") {
+        return Some(PathBuf::from(dir.replace("__HVER__", &version.short())));
+    }
+
+    let home = std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .map(PathBuf::from)?;
+
+    if cfg!(target_os = "windows") {
+        Some(
+            home.join("Documents")
+                .join(format!("houdini{}", version.short())),
+        )
+    } else if cfg!(target_os = "macos") {
+        Some(
+            home.join("Library")
+                .join("Preferences")
+                .join("houdini")
+                .join(version.short()),
+        )
+    } else {
+        Some(home.join(format!("houdini{}", version.short())))
+    }
+}
@@ -0,0 +1,463 @@
+//! IDE configurations for building, running and debugging the plugin generated by
+//! `cargo hdk ide vscode|clion|clangd`.
+//!
+//! The build tasks run cargo-hdk with the options `cargo hdk ide` was given, and the launch
+//! configurations start Houdini or hython with the plugin environment of the selected build, or
+//! attach to a running Houdini, so both the Rust and the C++ code can be debugged from the IDE.
+//! The CMake integration of the IDE is pointed at the build directory of cargo-hdk, so it reuses
+//! the configured build instead of creating its own.
+//!
+//! Existing files are updated in place: entries generated before are replaced by name and all
+//! other entries are kept.
+//!
+//! For other editors, the '.clangd' file in the HDK plugin directory gives clangd the HDK include
+//! paths and defines of the Houdini installation, so the C++ code can be edited even before the
+//! build directory is configured, and the compilation database of the build once it is.
+
+use std::ffi::OsString;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use cargo_metadata::camino::Utf8Path;
+use clap::ValueEnum;
+use log::*;
+use serde_json::{json, Map, Value};
+
+use crate::bindgen;
+use crate::debugger::{self, Debugger};
+use crate::houdini;
+use crate::launch::App;
+
+/// IDEs supported by `cargo hdk ide`.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Ide {
+    /// Visual Studio Code with the C/C++ and CMake Tools extensions.
+    Vscode,
+    Clion,
+    /// The clangd language server, used by many editors.
+    Clangd,
+}
+
+/// The name of the task or target building the plugin.
+const BUILD_NAME: &str = "cargo hdk build";
+
+/// The build being set up in the IDE.
+pub struct Project<'a> {
+    pub name: &'a str,
+    pub hfs: &'a str,
+    pub manifest_dir: &'a Utf8Path,
+    pub hdk_dir: &'a Utf8Path,
+    pub build_dir: &'a Utf8Path,
+    pub build_type: &'a str,
+    /// The CMake generator of the build directory, or `None` for the platform default.
+    pub generator: Option<String>,
+    /// The cargo-hdk arguments of the build, without the 'ide' command.
+    pub args: Vec<String>,
+    /// The environment loading the plugin from the build directory.
+    pub envs: Vec<(String, OsString)>,
+    pub debugger: Debugger,
+}
+
+impl Project<'_> {
+    /// The arguments of cargo running the given cargo-hdk command on this build.
+    fn cargo_args(&self, command: Option<&str>) -> Vec<String> {
+        // A '+<toolchain>' argument has to stay in front of the command.
+        let toolchain = self.args.first().filter(|arg| arg.starts_with('+'));
+        let rest = &self.args[toolchain.is_some() as usize..];
+        std::iter::once("hdk")
+            .chain(toolchain.map(String::as_str))
+            .chain(command)
+            .chain(rest.iter().map(String::as_str))
+            .map(String::from)
+            .collect()
+    }
+}
+
+/// The cargo-hdk arguments to reproduce a build in the IDE: the given command line arguments
+/// without the 'hdk' subcommand name passed in by cargo and the 'ide <IDE>' command.
+pub fn build_args(args: impl Iterator<Item = String>, ide: &str) -> Vec<String> {
+    let mut args: Vec<_> = args.collect();
+    if args.first().map(String::as_str) == Some("hdk") {
+        args.remove(0);
+    }
+    if let Some(pos) = args
+        .windows(2)
+        .position(|pair| pair[0] == "ide" && pair[1] == ide)
+    {
+        args.drain(pos..pos + 2);
+    }
+    args
+}
+
+/// Write the file if its contents changed.
+fn write_file(path: &Utf8Path, contents: &str) -> Result<()> {
+    if fs::read_to_string(path).ok().as_deref() == Some(contents) {
+        info!("{} is up to date.", path);
+        return Ok(());
+    }
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create directory {}", dir))?;
+    }
+    fs::write(path, contents).with_context(|| format!("Failed to write {}", path))?;
+    println!("Wrote {}", path);
+    Ok(())
+}
+
+/// Merge the generated JSON object into the file at `path`.
+///
+/// The entries of the arrays named in `arrays` are identified by the given key, so generated
+/// entries replace earlier ones with the same name. Other values are overwritten. Files that
+/// can't be parsed, e.g. because they contain comments, are left alone.
+fn merge_json(path: &Utf8Path, generated: Value, arrays: &[(&str, &str)]) -> Result<()> {
+    let mut merged = match fs::read_to_string(path) {
+        Ok(contents) => match serde_json::from_str::<Map<String, Value>>(&contents) {
+            Ok(existing) => existing,
+            Err(err) => {
+                warn!(
+                    "Skipping {}, since it couldn't be parsed: {}. Remove its comments or the file to have it updated.",
+                    path, err
+                );
+                return Ok(());
+            }
+        },
+        Err(_) => Map::new(),
+    };
+    let generated = match generated {
+        Value::Object(generated) => generated,
+        _ => unreachable!("IDE configurations are JSON objects"),
+    };
+    for (key, value) in generated {
+        let id = arrays.iter().find(|(array, _)| *array == key).map(|x| x.1);
+        match (id, value, merged.get_mut(&key)) {
+            (Some(id), Value::Array(entries), Some(Value::Array(existing))) => {
+                existing.retain(|entry| !entries.iter().any(|new| new.get(id) == entry.get(id)));
+                existing.extend(entries);
+            }
+            (_, value, _) => {
+                merged.insert(key, value);
+            }
+        }
+    }
+    let contents = serde_json::to_string_pretty(&merged)
+        .with_context(|| format!("Failed to serialize {}", path))?;
+    write_file(path, &format!("{}\n", contents))
+}
+
+/// The path relative to the given directory with a variable naming that directory, if it is
+/// inside it.
+fn relative(path: &Utf8Path, dir: &Utf8Path, var: &str) -> String {
+    match path.strip_prefix(dir) {
+        Ok(rest) if rest.as_str().is_empty() => var.to_string(),
+        Ok(rest) => format!("{}/{}", var, rest.as_str().replace('\\', "/")),
+        Err(_) => path.to_string(),
+    }
+}
+
+/// A VS Code debug configuration for the C/C++ extension.
+fn vscode_debug_config(project: &Project, name: &str, request: &str, app: App) -> Value {
+    let mut config = json!({
+        "name": name,
+        "type": if project.debugger == Debugger::Devenv { "cppvsdbg" } else { "cppdbg" },
+        "request": request,
+        "program": app.path(project.hfs),
+    });
+    match project.debugger {
+        Debugger::Gdb => {
+            config["MIMode"] = json!("gdb");
+            config["setupCommands"] = json!([{
+                "description": "Enable pretty printing",
+                "text": "-enable-pretty-printing",
+                "ignoreFailures": true,
+            }]);
+        }
+        Debugger::Lldb => config["MIMode"] = json!("lldb"),
+        Debugger::Devenv => {}
+    }
+    if let Some((from, to)) = debugger::rust_source_map() {
+        config["sourceFileMap"] = json!({ from: to });
+    }
+    if request == "attach" {
+        config["processId"] = json!("${command:pickProcess}");
+    } else {
+        let environment: Vec<_> = project
+            .envs
+            .iter()
+            .map(|(name, value)| json!({ "name": name, "value": value.to_string_lossy() }))
+            .collect();
+        config["args"] = json!([]);
+        config["cwd"] = json!("${workspaceFolder}");
+        config["environment"] = json!(environment);
+        config["preLaunchTask"] = json!(BUILD_NAME);
+    }
+    config
+}
+
+/// Write the tasks, launch configurations and CMake Tools settings into '.vscode' in the crate
+/// root.
+fn vscode(project: &Project) -> Result<()> {
+    let dir = project.manifest_dir.join(".vscode");
+    let problem_matcher = if cfg!(windows) {
+        json!(["$rustc", "$msCompile"])
+    } else {
+        json!(["$rustc", "$gcc"])
+    };
+    let task = |label: &str, command: Option<&str>, group: Option<Value>| {
+        let mut task = json!({
+            "label": label,
+            "type": "process",
+            "command": "cargo",
+            "args": project.cargo_args(command),
+            "options": { "cwd": "${workspaceFolder}" },
+            "problemMatcher": problem_matcher,
+        });
+        if let Some(group) = group {
+            task["group"] = group;
+        }
+        task
+    };
+    let tasks = json!({
+        "version": "2.0.0",
+        "tasks": [
+            task(BUILD_NAME, None, Some(json!({ "kind": "build", "isDefault": true }))),
+            task("cargo hdk check", Some("check"), None),
+            task("cargo hdk test", Some("test"), Some(json!({ "kind": "test", "isDefault": true }))),
+        ],
+    });
+    merge_json(&dir.join("tasks.json"), tasks, &[("tasks", "label")])?;
+
+    let launch = json!({
+        "version": "0.2.0",
+        "configurations": [
+            vscode_debug_config(project, "Houdini (cargo hdk)", "launch", App::Houdini),
+            vscode_debug_config(project, "hython (cargo hdk)", "launch", App::Hython),
+            vscode_debug_config(project, "Attach to Houdini (cargo hdk)", "attach", App::Houdini),
+        ],
+    });
+    merge_json(
+        &dir.join("launch.json"),
+        launch,
+        &[("configurations", "name")],
+    )?;
+
+    let workspace = "${workspaceFolder}";
+    let mut settings = json!({
+        "cmake.sourceDirectory": relative(project.hdk_dir, project.manifest_dir, workspace),
+        "cmake.buildDirectory": relative(project.build_dir, project.manifest_dir, workspace),
+        "cmake.configureOnOpen": false,
+        "cmake.environment": { "HFS": project.hfs },
+        "cmake.configureSettings": { "CMAKE_BUILD_TYPE": project.build_type },
+        "C_Cpp.default.configurationProvider": "ms-vscode.cmake-tools",
+    });
+    if let Some(generator) = &project.generator {
+        settings["cmake.generator"] = json!(generator);
+    }
+    merge_json(&dir.join("settings.json"), settings, &[])
+}
+
+/// Escape the characters with a meaning in XML attribute values.
+fn escape_xml(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Join arguments into a command line, quoting those containing spaces.
+fn command_line(args: &[String]) -> String {
+    let quoted: Vec<_> = args
+        .iter()
+        .map(|arg| {
+            if arg.contains(char::is_whitespace) {
+                format!("\"{}\"", arg)
+            } else {
+                arg.clone()
+            }
+        })
+        .collect();
+    quoted.join(" ")
+}
+
+/// A CLion run configuration launching the application with the plugin environment after
+/// building it with cargo-hdk.
+fn clion_run_config(project: &Project, name: &str, app: App) -> Result<String> {
+    let mut xml = String::new();
+    writeln!(
+        xml,
+        r#"<component name="ProjectRunConfigurationManager">
+  <configuration default="false" name="{}" type="CLionExternalRunConfiguration" factoryName="Application" REDIRECT_INPUT="false" ELEVATE="false" USE_EXTERNAL_CONSOLE="false" PASS_PARENT_ENVS_2="true" PROJECT_NAME="{}" TARGET_NAME="{}" CONFIG_NAME="{}" RUN_PATH="{}" WORKING_DIR="file://$PROJECT_DIR$">
+    <envs>"#,
+        escape_xml(name),
+        escape_xml(project.name),
+        BUILD_NAME,
+        BUILD_NAME,
+        escape_xml(&app.path(project.hfs).to_string_lossy()),
+    )?;
+    for (key, value) in &project.envs {
+        writeln!(
+            xml,
+            r#"      <env name="{}" value="{}" />"#,
+            key,
+            escape_xml(&value.to_string_lossy())
+        )?;
+    }
+    writeln!(
+        xml,
+        r#"    </envs>
+    <method v="2">
+      <option name="CLION.EXTERNAL.BUILD" enabled="true" />
+    </method>
+  </configuration>
+</component>"#
+    )?;
+    Ok(xml)
+}
+
+/// Write the external tools running cargo-hdk, a custom build target using them and run
+/// configurations into '.idea' in the crate root, and a CMake preset using the build directory
+/// into the HDK plugin directory.
+fn clion(project: &Project) -> Result<()> {
+    let dir = project.manifest_dir.join(".idea");
+    let mut tools = String::from("<toolSet name=\"cargo-hdk\">\n");
+    for (name, command) in [(BUILD_NAME, None), ("cargo hdk clean", Some("--clean"))] {
+        writeln!(
+            tools,
+            r#"  <tool name="{}" showInMainMenu="false" showInEditor="false" showInProject="false" showInSearchPopup="false" disabled="false" useConsole="true" showConsoleOnStdOut="false" showConsoleOnStdErr="false" synchronizeAfterRun="true">
+    <exec>
+      <option name="COMMAND" value="cargo" />
+      <option name="PARAMETERS" value="{}" />
+      <option name="WORKING_DIRECTORY" value="$ProjectFileDir$" />
+    </exec>
+  </tool>"#,
+            name,
+            escape_xml(&command_line(&project.cargo_args(command)))
+        )?;
+    }
+    tools.push_str("</toolSet>\n");
+    write_file(&dir.join("tools").join("cargo-hdk.xml"), &tools)?;
+
+    let targets_path = dir.join("customTargets.xml");
+    let targets = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<project version="4">
+  <component name="CLionExternalBuildManager">
+    <target id="cargo-hdk" name="{name}" defaultType="TOOL">
+      <configuration id="cargo-hdk" name="{name}">
+        <build type="TOOL">
+          <tool actionId="Tool_cargo-hdk_{name}" />
+        </build>
+        <clean type="TOOL">
+          <tool actionId="Tool_cargo-hdk_cargo hdk clean" />
+        </clean>
+      </configuration>
+    </target>
+  </component>
+</project>
+"#,
+        name = BUILD_NAME
+    );
+    match fs::read_to_string(&targets_path) {
+        Ok(existing) if !existing.contains(r#"<target id="cargo-hdk""#) => warn!(
+            "Skipping {}, since it has other build targets. Add a custom build target running the '{}' tool instead.",
+            targets_path, BUILD_NAME
+        ),
+        _ => write_file(&targets_path, &targets)?,
+    }
+
+    let run_configs = dir.join("runConfigurations");
+    for (file, name, app) in [
+        ("cargo_hdk_houdini.xml", "Houdini (cargo hdk)", App::Houdini),
+        ("cargo_hdk_hython.xml", "hython (cargo hdk)", App::Hython),
+    ] {
+        write_file(
+            &run_configs.join(file),
+            &clion_run_config(project, name, app)?,
+        )?;
+    }
+
+    let mut preset = json!({
+        "name": "cargo-hdk",
+        "displayName": format!("cargo hdk ({})", project.build_type),
+        "binaryDir": relative(project.build_dir, project.hdk_dir, "${sourceDir}"),
+        "cacheVariables": { "CMAKE_BUILD_TYPE": project.build_type },
+        "environment": { "HFS": project.hfs },
+    });
+    if let Some(generator) = &project.generator {
+        preset["generator"] = json!(generator);
+    }
+    let presets = json!({ "version": 3, "configurePresets": [preset] });
+    merge_json(
+        &project.hdk_dir.join("CMakeUserPresets.json"),
+        presets,
+        &[("configurePresets", "name")],
+    )
+}
+
+/// The lines delimiting the part of '.clangd' written by cargo-hdk.
+const CLANGD_BEGIN: &str = "# Begin of the HDK settings written by 'cargo hdk ide clangd'.";
+const CLANGD_END: &str = "# End of the HDK settings written by 'cargo hdk ide clangd'.";
+
+/// Write the HDK compile flags and the compilation database of the build directory to '.clangd'
+/// in the HDK plugin directory.
+///
+/// The settings are kept in a separate YAML document of the file, so other settings are kept
+/// when they are updated.
+fn clangd(project: &Project) -> Result<()> {
+    let hfs = Path::new(project.hfs);
+    let version = houdini::version(hfs)?;
+    let mut flags = houdini::hdk_flags(hfs, &version);
+    let generated = project.hdk_dir.join(bindgen::GENERATED_DIR);
+    if generated.is_dir() {
+        flags.push(format!("-I{}", generated));
+    }
+
+    // Single quoted YAML strings only escape single quotes.
+    let quote = |value: &str| format!("'{}'", value.replace('\'', "''"));
+    let mut settings = String::new();
+    writeln!(settings, "{}", CLANGD_BEGIN)?;
+    writeln!(settings, "# Houdini {} at {}", version, project.hfs)?;
+    writeln!(settings, "CompileFlags:")?;
+    writeln!(
+        settings,
+        "  CompilationDatabase: {}",
+        quote(project.build_dir.as_str())
+    )?;
+    writeln!(settings, "  Add:")?;
+    for flag in &flags {
+        writeln!(settings, "    - {}", quote(flag))?;
+    }
+    writeln!(settings, "{}", CLANGD_END)?;
+
+    let path = project.hdk_dir.join(".clangd");
+    let existing = fs::read_to_string(&path).unwrap_or_default();
+    let contents = match (existing.find(CLANGD_BEGIN), existing.find(CLANGD_END)) {
+        (Some(begin), Some(end)) if begin < end => {
+            let end = end + CLANGD_END.len();
+            let rest = existing[end..]
+                .strip_prefix('\n')
+                .unwrap_or(&existing[end..]);
+            format!("{}{}{}", &existing[..begin], settings, rest)
+        }
+        _ if existing.trim().is_empty() => settings,
+        _ => format!("{}\n---\n{}", existing.trim_end(), settings),
+    };
+    write_file(&path, &contents)
+}
+
+/// Generate the configuration of the given IDE for the project.
+pub fn run(ide: Ide, project: &Project) -> Result<()> {
+    match ide {
+        Ide::Vscode => {
+            vscode(project)?;
+            info!("Attach to a running Houdini with the 'Attach to Houdini (cargo hdk)' configuration.");
+        }
+        Ide::Clion => {
+            clion(project)?;
+            info!("Attach to a running Houdini with Run | Attach to Process.");
+        }
+        Ide::Clangd => clangd(project)?,
+    }
+    Ok(())
+}
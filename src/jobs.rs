@@ -0,0 +1,36 @@
+//! A GNU make jobserver shared between the cargo build and the CMake build.
+//!
+//! Cargo, Make and Ninja (since 1.13) all act as jobserver clients when they find a jobserver in
+//! the `MAKEFLAGS` environment variable, so passing the same jobserver to each of them bounds
+//! the total number of concurrent jobs across both build systems.
+
+use std::num::NonZeroU32;
+
+use anyhow::{Context, Result};
+use jobserver::Client;
+use log::*;
+
+/// Get the jobserver to share between the builds.
+///
+/// A jobserver inherited from a parent make process is reused. Otherwise a new one is created
+/// with the given number of jobs, or the available parallelism if none is given.
+///
+/// This must be called before any files are opened, since an inherited jobserver is passed
+/// down as file descriptors.
+pub fn jobserver(jobs: Option<NonZeroU32>) -> Result<Client> {
+    // SAFETY: Called at startup before any file descriptors are opened by this process, so the
+    // descriptors named in the environment (if any) refer to the inherited jobserver.
+    if let Some(client) = unsafe { Client::from_env() } {
+        info!("Using the jobserver of the parent process.");
+        return Ok(client);
+    }
+
+    let limit = match jobs {
+        Some(jobs) => jobs.get() as usize,
+        None => std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1),
+    };
+    debug!("Creating a jobserver with {} jobs", limit);
+    Client::new(limit).context("Failed to create a jobserver")
+}
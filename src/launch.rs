@@ -0,0 +1,200 @@
+//! Launching Houdini with the freshly built plugin using `cargo hdk run`, and shells with the
+//! same environment using `cargo hdk shell`.
+//!
+//! Houdini finds the plugin through its search path variables, which are pointed at the build
+//! directory, so nothing needs to be installed. The default search paths are kept by ending each
+//! variable with '&'. If Houdini fails to load the plugin, the reason is reported after the session.
+
+use std::env;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use log::*;
+
+use crate::dso_errors;
+use crate::houdini;
+
+/// Houdini applications that can be launched by `cargo hdk run`.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum App {
+    Houdini,
+    Houdinicore,
+    Hython,
+}
+
+impl App {
+    fn program(self) -> &'static str {
+        match self {
+            App::Houdini => "houdini",
+            App::Houdinicore => "houdinicore",
+            App::Hython => "hython",
+        }
+    }
+
+    /// Path to the executable of the application in the Houdini installation in `hfs`.
+    pub fn path(self, hfs: &str) -> PathBuf {
+        Path::new(hfs)
+            .join("bin")
+            .join(format!("{}{}", self.program(), env::consts::EXE_SUFFIX))
+    }
+}
+
+/// Extensions of the scene files and scripts that can be opened by the launched application.
+const SCENE_EXTENSIONS: &[&str] = &["hip", "hipnc", "hiplc", "py"];
+
+/// The position of the first argument naming a scene file or script.
+///
+/// This and all following arguments are passed to the launched application instead of cargo.
+pub fn app_args_start(args: &[String]) -> Option<usize> {
+    args.iter().position(|arg| {
+        matches!(
+            Path::new(arg).extension().and_then(|ext| ext.to_str()),
+            Some(ext) if SCENE_EXTENSIONS.contains(&ext)
+        )
+    })
+}
+
+/// Prepend the given directories to a Houdini search path variable.
+fn search_path(name: &str, dirs: &[PathBuf]) -> OsString {
+    let mut value = OsString::new();
+    for dir in dirs {
+        // Houdini accepts ';' as the separator on all platforms.
+        value.push(dir);
+        value.push(";");
+    }
+    value.push(env::var_os(name).unwrap_or_else(|| "&".into()));
+    value
+}
+
+/// The environment that makes Houdini load the plugin from the build directory.
+///
+/// The plugin is loaded from the 'dso' subdirectory of its CMake binary directory, as set up by
+/// the 'cargo hdk new' templates, or from the binary directory itself. Digital assets are loaded
+/// from 'otls' directories in the crate root and the HDK plugin directory.
+pub fn plugin_env(
+    binary_dir: &Path,
+    manifest_dir: &Path,
+    hdk_dir: &Path,
+) -> Vec<(&'static str, OsString)> {
+    // The plugin may not be built yet, e.g. when starting a shell, so both are searched.
+    let dso_dirs = [binary_dir.join("dso"), binary_dir.to_path_buf()];
+    let mut envs = vec![(
+        "HOUDINI_DSO_PATH",
+        search_path("HOUDINI_DSO_PATH", &dso_dirs),
+    )];
+
+    let mut otls_dirs: Vec<_> = vec![manifest_dir.join("otls"), hdk_dir.join("otls")]
+        .into_iter()
+        .filter(|dir| dir.is_dir())
+        .collect();
+    otls_dirs.dedup();
+    if !otls_dirs.is_empty() {
+        envs.push((
+            "HOUDINI_OTLSCAN_PATH",
+            search_path("HOUDINI_OTLSCAN_PATH", &otls_dirs),
+        ));
+    }
+    envs
+}
+
+/// The environment set up by the 'houdini_setup' script of the Houdini installation in `hfs`.
+pub fn houdini_env(hfs: &str) -> Result<Vec<(&'static str, OsString)>> {
+    let hfs = Path::new(hfs);
+    let hh = hfs.join("houdini");
+    let hb = hfs.join("bin");
+    let hsb = hh.join("sbin");
+
+    let mut paths = vec![hb.clone(), hsb.clone()];
+    if let Some(path) = env::var_os("PATH") {
+        paths.extend(env::split_paths(&path));
+    }
+    let mut envs = vec![
+        ("HFS", hfs.as_os_str().to_owned()),
+        ("HH", hh.clone().into_os_string()),
+        ("HHC", hh.join("config").into_os_string()),
+        ("HT", hfs.join("toolkit").into_os_string()),
+        ("HDSO", hfs.join("dsolib").into_os_string()),
+        ("HB", hb.into_os_string()),
+        ("HSB", hsb.into_os_string()),
+        (
+            "PATH",
+            env::join_paths(paths).context("Failed to add the Houdini binaries to 'PATH'")?,
+        ),
+    ];
+    match houdini::version(hfs) {
+        Ok(version) => envs.extend(vec![
+            ("HOUDINI_MAJOR_RELEASE", version.major.to_string().into()),
+            ("HOUDINI_MINOR_RELEASE", version.minor.to_string().into()),
+            ("HOUDINI_BUILD_VERSION", version.build.to_string().into()),
+        ]),
+        Err(err) => warn!("Failed to determine the Houdini version: {:#}", err),
+    }
+    Ok(envs)
+}
+
+/// Spawn the shell of the user with the given environment and wait for it to exit.
+pub fn shell(envs: &[(&str, OsString)]) -> Result<()> {
+    let shell = if cfg!(windows) {
+        env::var_os("COMSPEC").unwrap_or_else(|| "cmd.exe".into())
+    } else {
+        env::var_os("SHELL").unwrap_or_else(|| "/bin/sh".into())
+    };
+    for (key, value) in envs {
+        debug!("Setting {}={:?} for the shell", key, value);
+    }
+    println!(
+        "Starting {} with the Houdini and plugin environment. Exit the shell to return.",
+        Path::new(&shell).display()
+    );
+    let status = Command::new(&shell)
+        .envs(envs.iter().map(|(key, value)| (key, value)))
+        // Lets prompts and scripts tell that they are run in a cargo-hdk shell.
+        .env("CARGO_HDK_SHELL", "1")
+        .status()
+        .with_context(|| format!("Failed to start the shell {}", Path::new(&shell).display()))?;
+    // The status of an interactive shell is that of the last command, which is not a failure of
+    // cargo-hdk.
+    debug!("The shell exited with {}", status);
+    Ok(())
+}
+
+/// Launch the given application of the Houdini installation in `hfs` and wait for it to exit.
+///
+/// Fails if the application reports that one of the given plugin libraries failed to load.
+pub fn run(
+    app: App,
+    hfs: &str,
+    args: &[String],
+    envs: &[(&str, OsString)],
+    plugins: &[PathBuf],
+) -> Result<()> {
+    let program = app.path(hfs);
+    for (key, value) in envs {
+        debug!("Setting {}={:?} for {}", key, value, app.program());
+    }
+    info!("Launching {}.", program.display());
+    let (status, output) = dso_errors::run_captured(
+        Command::new(&program)
+            .args(args)
+            .envs(envs.iter().map(|(key, value)| (key, value)))
+            .envs(dso_errors::env()),
+        plugins,
+    )
+    .with_context(|| format!("Failed to run {}", program.display()))?;
+    let errors = dso_errors::scan(&output, plugins);
+    if !errors.is_empty() {
+        let errors: Vec<_> = errors.iter().map(|error| format!("  {}", error)).collect();
+        bail!(
+            "{} failed to load the plugin:\n{}",
+            app.program(),
+            errors.join("\n")
+        );
+    }
+    if !status.success() {
+        bail!("{} exited with {}", app.program(), status);
+    }
+    Ok(())
+}
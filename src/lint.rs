@@ -0,0 +1,266 @@
+//! Static analysis of the C++ plugin sources with clang-tidy using `cargo hdk lint`.
+//!
+//! The sources are analyzed with the compilation database CMake writes to the build directory,
+//! so clang-tidy sees the same HDK include paths and defines as the compiler. Only the sources in
+//! the HDK plugin directory are checked, and diagnostics in headers are reported for the headers
+//! of the plugin, not for those of the HDK or other dependencies.
+
+use std::collections::{BTreeMap, HashSet};
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use anyhow::{Context, Result};
+use log::*;
+use serde::Deserialize;
+
+use crate::config::Lint;
+use crate::debugger::escape_regex;
+use crate::tools;
+
+/// Checks used without a '.clang-tidy' file or configured checks: likely bugs and performance
+/// problems, leaving out style checks that conflict with the conventions of the HDK.
+const DEFAULT_CHECKS: &str = "-*,bugprone-*,clang-analyzer-*,performance-*,\
+    modernize-use-override,modernize-use-nullptr,misc-redundant-expression,\
+    -bugprone-easily-swappable-parameters,-bugprone-narrowing-conversions";
+
+/// Options of `cargo hdk lint`.
+pub struct LintArgs<'a> {
+    /// Checks given on the command line, overriding the configured ones.
+    pub checks: Option<&'a str>,
+    /// Apply the suggested fixes.
+    pub fix: bool,
+    /// Fail if there are any warnings.
+    pub deny_warnings: bool,
+    /// Number of clang-tidy processes run in parallel.
+    pub jobs: usize,
+}
+
+/// An entry of 'compile_commands.json'.
+#[derive(Deserialize)]
+struct CompileCommand {
+    directory: PathBuf,
+    file: PathBuf,
+}
+
+/// A diagnostic reported by clang-tidy along with the notes and source excerpts following it.
+struct Diagnostic {
+    error: bool,
+    check: Option<String>,
+    text: String,
+}
+
+/// The sources in `hdk_dir` compiled according to the compilation database, leaving out those
+/// generated in the build directory.
+fn sources(database: &Path, hdk_dir: &Path, build_dir: &Path) -> Result<Vec<PathBuf>> {
+    let contents = fs::read_to_string(database)
+        .with_context(|| format!("Failed to read {}", database.display()))?;
+    let commands: Vec<CompileCommand> = serde_json::from_str(&contents)
+        .with_context(|| format!("Failed to parse {}", database.display()))?;
+    let hdk_dir = fs::canonicalize(hdk_dir).unwrap_or_else(|_| hdk_dir.to_path_buf());
+    let build_dir = fs::canonicalize(build_dir).unwrap_or_else(|_| build_dir.to_path_buf());
+    let mut files: Vec<_> = commands
+        .into_iter()
+        .map(|command| command.directory.join(command.file))
+        .map(|file| fs::canonicalize(&file).unwrap_or(file))
+        .filter(|file| file.starts_with(&hdk_dir) && !file.starts_with(&build_dir))
+        .collect();
+    files.sort();
+    files.dedup();
+    Ok(files)
+}
+
+/// Returns true if a '.clang-tidy' file applies to the sources in the given directory.
+fn has_clang_tidy_file(dir: &Path) -> bool {
+    let dir = fs::canonicalize(dir).unwrap_or_else(|_| dir.to_path_buf());
+    dir.ancestors().any(|dir| dir.join(".clang-tidy").is_file())
+}
+
+/// Split the output of clang-tidy into its diagnostics.
+///
+/// Each diagnostic starts with a line like 'file:line:col: warning: message [check]' and
+/// includes the notes and source excerpts up to the next one.
+fn parse_diagnostics(output: &str) -> Vec<Diagnostic> {
+    let mut diagnostics: Vec<Diagnostic> = Vec::new();
+    for line in output.lines() {
+        let severity = [": warning: ", ": error: "]
+            .iter()
+            .find(|severity| line.contains(*severity));
+        match severity {
+            Some(severity) => {
+                let check = line
+                    .strip_suffix(']')
+                    .and_then(|line| line.rsplit_once(" ["))
+                    .map(|(_, check)| check.to_string());
+                diagnostics.push(Diagnostic {
+                    error: *severity == ": error: ",
+                    check,
+                    text: format!("{}\n", line),
+                });
+            }
+            None => {
+                if let Some(diagnostic) = diagnostics.last_mut() {
+                    diagnostic.text.push_str(line);
+                    diagnostic.text.push('\n');
+                }
+            }
+        }
+    }
+    diagnostics
+}
+
+/// Run clang-tidy over the plugin sources compiled in `build_dir`, printing the diagnostics and a
+/// summary of the warnings by check.
+///
+/// Returns an error if there are any errors or, with `deny_warnings`, any warnings.
+pub fn run(build_dir: &Path, hdk_dir: &Path, config: Option<&Lint>, args: &LintArgs) -> Result<()> {
+    let database = build_dir.join("compile_commands.json");
+    if !database.is_file() {
+        bail!(
+            "No compilation database found at {}. 'cargo hdk lint' needs a Ninja or Makefile CMake generator, since Visual Studio generators don't write one.",
+            database.display()
+        );
+    }
+    let files = sources(&database, hdk_dir, build_dir)?;
+    if files.is_empty() {
+        bail!(
+            "No C++ sources of {} found in {}",
+            hdk_dir.display(),
+            database.display()
+        );
+    }
+    let clang_tidy = tools::find_program("clang-tidy").context(
+        "Couldn't find 'clang-tidy' on the 'PATH'. Install it with LLVM, e.g. from the 'clang-tidy' or 'clang-tools' package.",
+    )?;
+
+    let configured = config.filter(|lint| !lint.checks.is_empty());
+    let checks = match (args.checks, configured) {
+        (Some(checks), _) => Some(checks.to_string()),
+        (None, Some(lint)) => Some(lint.checks.join(",")),
+        (None, None) if has_clang_tidy_file(hdk_dir) => None,
+        (None, None) => Some(DEFAULT_CHECKS.to_string()),
+    };
+    let header_filter = match config.and_then(|lint| lint.header_filter.clone()) {
+        Some(filter) => filter,
+        None => {
+            let dir = fs::canonicalize(hdk_dir).unwrap_or_else(|_| hdk_dir.to_path_buf());
+            format!("^{}", escape_regex(&dir.to_string_lossy()))
+        }
+    };
+
+    let mut tidy_args: Vec<OsString> = vec![
+        "-p".into(),
+        build_dir.into(),
+        "--quiet".into(),
+        format!("--header-filter={}", header_filter).into(),
+    ];
+    tidy_args.extend(checks.map(|checks| format!("--checks={}", checks).into()));
+    if args.fix {
+        tidy_args.push("--fix".into());
+    }
+    tidy_args.extend(
+        config
+            .iter()
+            .flat_map(|lint| &lint.args)
+            .map(OsString::from),
+    );
+
+    // Fixes of the same header by parallel runs would conflict.
+    let jobs = if args.fix { 1 } else { args.jobs.max(1) };
+    println!(
+        "Checking {} file{} with {}",
+        files.len(),
+        if files.len() == 1 { "" } else { "s" },
+        clang_tidy.display()
+    );
+    let next = AtomicUsize::new(0);
+    let outputs: Mutex<Vec<Option<io::Result<Output>>>> =
+        Mutex::new(files.iter().map(|_| None).collect());
+    thread::scope(|scope| {
+        for _ in 0..jobs.min(files.len()) {
+            scope.spawn(|| {
+                let mut index = next.fetch_add(1, Ordering::Relaxed);
+                while let Some(file) = files.get(index) {
+                    debug!("Running {} on {}", clang_tidy.display(), file.display());
+                    let output = Command::new(&clang_tidy)
+                        .args(&tidy_args)
+                        .arg(file)
+                        .output();
+                    outputs.lock().unwrap()[index] = Some(output);
+                    index = next.fetch_add(1, Ordering::Relaxed);
+                }
+            });
+        }
+    });
+
+    // Diagnostics in headers are reported once for every source including them.
+    let mut seen = HashSet::new();
+    let mut warnings = 0;
+    let mut errors = 0;
+    let mut by_check: BTreeMap<String, usize> = BTreeMap::new();
+    let outputs = outputs.into_inner().unwrap();
+    for (file, output) in files.iter().zip(outputs) {
+        let output = output
+            .expect("clang-tidy wasn't run on every file")
+            .with_context(|| format!("Failed to run {}", clang_tidy.display()))?;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let diagnostics = parse_diagnostics(&stdout);
+        let failed = !output.status.success() && !diagnostics.iter().any(|d| d.error);
+        for diagnostic in diagnostics {
+            if !seen.insert(diagnostic.text.clone()) {
+                continue;
+            }
+            print!("{}", diagnostic.text);
+            if diagnostic.error {
+                errors += 1;
+            } else {
+                warnings += 1;
+            }
+            if let Some(check) = diagnostic.check {
+                *by_check.entry(check).or_default() += 1;
+            }
+        }
+        if failed {
+            // E.g. an invalid check or argument, reported on stderr.
+            errors += 1;
+            println!(
+                "{}: error: clang-tidy failed ({}):\n{}",
+                file.display(),
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+    }
+
+    println!();
+    println!(
+        "lint result: {} warning{} and {} error{} in {} file{}",
+        warnings,
+        if warnings == 1 { "" } else { "s" },
+        errors,
+        if errors == 1 { "" } else { "s" },
+        files.len(),
+        if files.len() == 1 { "" } else { "s" },
+    );
+    let mut counts: Vec<_> = by_check.into_iter().collect();
+    counts.sort_by(|(a, x), (b, y)| y.cmp(x).then_with(|| a.cmp(b)));
+    for (check, count) in &counts {
+        println!("{:>6}  {}", count, check);
+    }
+
+    if errors > 0 {
+        bail!("clang-tidy found {} errors", errors);
+    }
+    if args.deny_warnings && warnings > 0 {
+        bail!(
+            "clang-tidy found {} warnings, which are denied by '--deny-warnings'",
+            warnings
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,178 @@
+//! Handling of long and network paths on Windows.
+//!
+//! Deep cargo target directories combined with the trees CMake and the compilers create in the
+//! build directory easily exceed the 260 character limit (MAX_PATH) of many Windows APIs. Files
+//! written and removed by cargo-hdk itself use the extended-length '\\?\' form of long paths,
+//! which lifts the limit, while paths passed to CMake and other tools use the plain form they
+//! understand. Since not all tools can cope with long or network (UNC) paths, failed builds are
+//! checked for the errors they cause, to explain them.
+
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::path::{Component, Path, PathBuf, Prefix};
+use std::process::Command;
+
+use log::*;
+
+/// The longest path supported by Windows APIs without long path support.
+const MAX_PATH: usize = 260;
+
+/// Length reserved for the paths created in the build directory, like
+/// 'CMakeFiles/<target>.dir/<source path>.obj'.
+const BUILD_TREE_DEPTH: usize = 100;
+
+/// Messages of tools failing on long or network paths, with an explanation and whether the
+/// path is too long, rather than on a network share.
+const PATH_ERRORS: &[(&str, &str, bool)] = &[
+    (
+        "exceeds the OS max path limit",
+        "MSBuild can't handle paths longer than 260 characters.",
+        true,
+    ),
+    (
+        "The specified path, file name, or both are too long",
+        "A tool failed on a path longer than 260 characters.",
+        true,
+    ),
+    (
+        "The maximum full path to an object file is 250 characters",
+        "The object files of the build exceed the 250 character path limit of CMake on Windows.",
+        true,
+    ),
+    (
+        "Filename too long",
+        "A tool failed on a path longer than 260 characters.",
+        true,
+    ),
+    (
+        "UNC paths are not supported",
+        "The Windows command prompt can't run commands in a network (UNC) directory.",
+        false,
+    ),
+];
+
+/// The extended-length form of the given path on Windows, like '\\?\C:\...' or
+/// '\\?\UNC\server\share\...', if it is too long for Windows APIs without long path support.
+///
+/// Relative paths are made absolute, and '.' and '..' components are resolved, since
+/// extended-length paths are used as they are. Other paths are returned unchanged.
+pub fn extended(path: &Path) -> PathBuf {
+    // Directories are limited to 248 characters, leaving room for an 8.3 file name.
+    if !cfg!(windows) || path.as_os_str().len() < MAX_PATH - 12 {
+        return path.to_path_buf();
+    }
+    let absolute = match env::current_dir() {
+        Ok(dir) if !path.is_absolute() => dir.join(path),
+        _ => path.to_path_buf(),
+    };
+    let mut components = absolute.components();
+    let mut extended = match components.next() {
+        Some(Component::Prefix(prefix)) => match prefix.kind() {
+            Prefix::Disk(letter) => OsString::from(format!(r"\\?\{}:", letter as char)),
+            Prefix::UNC(server, share) => {
+                let mut unc = OsString::from(r"\\?\UNC\");
+                unc.push(server);
+                unc.push(r"\");
+                unc.push(share);
+                unc
+            }
+            // Already in extended-length or device form.
+            _ => return absolute,
+        },
+        _ => return absolute,
+    };
+    let mut parts: Vec<&OsStr> = Vec::new();
+    for component in components {
+        match component {
+            Component::Normal(part) => parts.push(part),
+            Component::ParentDir => {
+                parts.pop();
+            }
+            _ => {}
+        }
+    }
+    for part in parts {
+        extended.push(r"\");
+        extended.push(part);
+    }
+    PathBuf::from(extended)
+}
+
+/// The plain form of an extended-length path on Windows, like 'C:\...' for '\\?\C:\...' and
+/// '\\server\share\...' for '\\?\UNC\server\share\...', for passing it to CMake and other tools
+/// that don't understand the extended-length form. Other paths are returned unchanged.
+pub fn simplified(path: &str) -> String {
+    if !cfg!(windows) {
+        return path.to_string();
+    }
+    if let Some(unc) = path.strip_prefix(r"\\?\UNC\") {
+        return format!(r"\\{}", unc);
+    }
+    match path.strip_prefix(r"\\?\") {
+        Some(rest) if rest.as_bytes().get(1) == Some(&b':') => rest.to_string(),
+        _ => path.to_string(),
+    }
+}
+
+/// Returns true if long paths are enabled for applications that support them, according to the
+/// 'LongPathsEnabled' registry value.
+fn long_paths_enabled() -> bool {
+    let output = Command::new("reg")
+        .args([
+            "query",
+            r"HKLM\SYSTEM\CurrentControlSet\Control\FileSystem",
+            "/v",
+            "LongPathsEnabled",
+        ])
+        .output();
+    match output {
+        Ok(output) => String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .any(|line| line.contains("LongPathsEnabled") && line.trim_end().ends_with("0x1")),
+        Err(_) => false,
+    }
+}
+
+/// Warn about a build directory on Windows that leaves too little room for the paths of the
+/// build tree, or is on a network share.
+pub fn check_build_dir(build_dir: &Path) {
+    if !cfg!(windows) {
+        return;
+    }
+    let path = build_dir.to_string_lossy();
+    if path.starts_with(r"\\") && !path.starts_with(r"\\?\") || path.starts_with(r"\\?\UNC\") {
+        warn!(
+            "The build directory {} is on a network share. Visual Studio generators and the command prompt don't support network (UNC) paths, so map the share to a drive letter or use a local '--build-dir'.",
+            build_dir.display()
+        );
+    }
+    if path.len() + BUILD_TREE_DEPTH > MAX_PATH {
+        warn!(
+            "The build directory {} has {} characters, leaving little room for the files CMake and the compiler create in it before reaching the 260 character path limit of Windows.{} Use a shorter '--build-dir', e.g. 'C:\\b\\{{package}}\\{{profile}}', if the build fails.",
+            build_dir.display(),
+            path.len(),
+            if long_paths_enabled() {
+                " Long paths are enabled, but MSVC and MSBuild don't support them."
+            } else {
+                ""
+            }
+        );
+    }
+}
+
+/// An explanation of a failure caused by a long or network path, if the given log contains the
+/// error of a tool that can't cope with one.
+pub fn diagnose(log: &Path) -> Option<String> {
+    let contents = fs::read(log).ok()?;
+    let contents = String::from_utf8_lossy(&contents);
+    let (_, explanation, too_long) = PATH_ERRORS
+        .iter()
+        .find(|(message, _, _)| contents.contains(message))?;
+    let hint = if *too_long {
+        "Use a shorter '--build-dir', e.g. 'C:\\b\\{package}\\{profile}', and move the crate closer to the drive root if that isn't enough."
+    } else {
+        "Map the network share to a drive letter, or use a '--build-dir' on a local drive."
+    };
+    Some(format!("{} {}", explanation, hint))
+}
@@ -0,0 +1,260 @@
+//! Deployment target and runtime paths of macOS builds.
+//!
+//! The plugin is built for the oldest macOS version the Houdini libraries support, read from their
+//! load commands, so it loads wherever Houdini does and the linker doesn't warn about objects
+//! built for newer versions. The plugin libraries get an '@rpath' install name and runtime paths
+//! to their own directory and the libraries of the Houdini framework, both in the build directory
+//! and when installed, so they load regardless of where they are copied.
+//!
+//! The plugin is also built for an architecture of the Houdini libraries, since Houdini can't
+//! load a plugin built for another one. This matters when an Intel build of Houdini runs under
+//! Rosetta on Apple silicon, or the Rust toolchain is one for the other architecture.
+
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::Result;
+use log::*;
+
+use crate::cmake;
+use crate::tools;
+
+/// Environment variable selecting the deployment target of rustc and CMake.
+const DEPLOYMENT_TARGET_ENV: &str = "MACOSX_DEPLOYMENT_TARGET";
+
+/// The 'Libraries' directory of the Houdini framework, next to the 'Resources' directory in 'HFS'.
+pub fn houdini_libraries(hfs: &Path) -> PathBuf {
+    hfs.parent().unwrap_or(hfs).join("Libraries")
+}
+
+/// Architectures of macOS as named by Rust targets, and by CMake and 'lipo'.
+const ARCHITECTURES: &[(&str, &str)] = &[("aarch64", "arm64"), ("x86_64", "x86_64")];
+
+/// The architecture of a Rust target for macOS as named by CMake and 'lipo', or `None` for other
+/// targets.
+fn target_architecture(target: &str) -> Option<&'static str> {
+    let arch = target.strip_suffix("-apple-darwin")?;
+    ARCHITECTURES
+        .iter()
+        .find(|(rust, _)| *rust == arch)
+        .map(|(_, arch)| *arch)
+}
+
+/// The architectures of the given Mach-O file reported by 'lipo', if it can be run.
+pub fn architectures(path: &Path) -> Option<Vec<String>> {
+    let output = Command::new("lipo").arg("-archs").arg(path).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(
+        String::from_utf8_lossy(&output.stdout)
+            .split_whitespace()
+            .map(String::from)
+            .collect(),
+    )
+}
+
+/// Run 'rustup' with the given arguments for `toolchain`, returning the output if it succeeds.
+fn rustup(args: &[&str], toolchain: Option<&str>) -> Option<String> {
+    let mut cmd = Command::new(tools::find_program("rustup")?);
+    cmd.args(args);
+    if let Some(toolchain) = toolchain {
+        cmd.args(["--toolchain", toolchain]);
+    }
+    match cmd.output() {
+        Ok(output) if output.status.success() => {
+            Some(String::from_utf8_lossy(&output.stdout).into_owned())
+        }
+        _ => None,
+    }
+}
+
+/// The given targets whose standard library isn't installed, if the toolchain is managed by
+/// rustup.
+pub fn missing_targets<'a>(toolchain: Option<&str>, targets: &[&'a str]) -> Vec<&'a str> {
+    let installed = match rustup(&["target", "list", "--installed"], toolchain) {
+        Some(installed) => installed,
+        None => return Vec::new(),
+    };
+    targets
+        .iter()
+        .filter(|target| !installed.lines().any(|line| line.trim() == **target))
+        .copied()
+        .collect()
+}
+
+/// The host target of the Rust toolchain, which cargo builds for by default.
+fn rust_host(toolchain: Option<&str>) -> Option<String> {
+    let mut cmd = match toolchain {
+        Some(toolchain) => {
+            let mut cmd = Command::new("rustup");
+            cmd.args(["run", toolchain, "rustc"]);
+            cmd
+        }
+        None => Command::new("rustc"),
+    };
+    let output = cmd.arg("-vV").output().ok()?;
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .find_map(|line| line.strip_prefix("host: "))
+        .map(String::from)
+}
+
+/// Select the architecture the plugin is built for, as named by CMake, among those of the
+/// Houdini libraries in `hfs`.
+///
+/// A `target` given for the Rust build must be one of them. Otherwise the host architecture of
+/// the Rust toolchain is used if Houdini supports it, or else the Rust build is switched to the
+/// target of the architecture of Houdini with 'CARGO_BUILD_TARGET'. Returns `None` if the
+/// architectures of Houdini are unknown or the target isn't a macOS one.
+pub fn select_architecture(
+    hfs: &Path,
+    target: Option<&str>,
+    toolchain: Option<&str>,
+) -> Result<Option<&'static str>> {
+    let library = houdini_libraries(hfs).join("libHoudiniUT.dylib");
+    let houdini = match architectures(&library) {
+        Some(archs) => archs,
+        None => {
+            debug!(
+                "Failed to determine the architectures of {}",
+                library.display()
+            );
+            return Ok(None);
+        }
+    };
+    let supported = |arch: &str| houdini.iter().any(|a| a == arch);
+    if let Some(target) = target {
+        let arch = match target_architecture(target) {
+            Some(arch) => arch,
+            None => return Ok(None),
+        };
+        if !supported(arch) {
+            bail!(
+                "The Houdini installation at {} is built for {}, so it can't load a plugin built for '{}'. Build for the architecture of Houdini, or use a build of Houdini for {}.",
+                hfs.display(),
+                houdini.join(" and "),
+                target,
+                arch
+            );
+        }
+        return Ok(Some(arch));
+    }
+
+    let host = rust_host(toolchain);
+    let host_arch = host.as_deref().and_then(target_architecture);
+    if let Some(arch) = host_arch.filter(|arch| supported(arch)) {
+        debug!("Building for {}, which Houdini supports", arch);
+        return Ok(Some(arch));
+    }
+    // E.g. an Intel build of Houdini, which runs under Rosetta on Apple silicon.
+    let (rust_arch, arch) = match ARCHITECTURES.iter().find(|(_, arch)| supported(arch)) {
+        Some(&arch) => arch,
+        None => return Ok(None),
+    };
+    let target = format!("{}-apple-darwin", rust_arch);
+    if !missing_targets(toolchain, &[&target]).is_empty() {
+        bail!(
+            "The Houdini installation at {} is built for {} only, but the Rust toolchain builds for {}, e.g. since Houdini runs under Rosetta. Install the standard library of {} with 'rustup target add {}' to build the plugin for this Houdini.",
+            hfs.display(),
+            arch,
+            host_arch.unwrap_or("another architecture"),
+            arch,
+            target
+        );
+    }
+    info!(
+        "Building for {} since the Houdini installation at {} is built for it.",
+        target,
+        hfs.display()
+    );
+    env::set_var("CARGO_BUILD_TARGET", &target);
+    Ok(Some(arch))
+}
+
+/// Parse a macOS version like '10.15' or '11.0' into comparable numbers.
+fn parse_version(version: &str) -> Option<Vec<u32>> {
+    version.split('.').map(|part| part.parse().ok()).collect()
+}
+
+/// The oldest macOS version supported by the given library according to 'otool -l', which is the
+/// lowest one of all its architectures.
+fn minimum_version(library: &Path) -> Option<String> {
+    let output = Command::new("otool").arg("-l").arg(library).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut command = "";
+    let mut versions = Vec::new();
+    for line in stdout.lines() {
+        let mut fields = line.split_whitespace();
+        match (fields.next(), fields.next()) {
+            (Some("cmd"), Some(cmd)) => command = cmd,
+            (Some("minos"), Some(version)) if command == "LC_BUILD_VERSION" => {
+                versions.push(version)
+            }
+            (Some("version"), Some(version)) if command == "LC_VERSION_MIN_MACOSX" => {
+                versions.push(version)
+            }
+            _ => {}
+        }
+    }
+    versions
+        .into_iter()
+        .filter_map(|version| Some((parse_version(version)?, version)))
+        .min()
+        .map(|(_, version)| version.to_string())
+}
+
+/// The deployment target of the build: 'MACOSX_DEPLOYMENT_TARGET' if set, or otherwise the
+/// oldest macOS version supported by the Houdini libraries.
+pub fn deployment_target(hfs: &Path) -> Option<String> {
+    if let Ok(target) = env::var(DEPLOYMENT_TARGET_ENV) {
+        return Some(target);
+    }
+    let library = houdini_libraries(hfs).join("libHoudiniUT.dylib");
+    let target = minimum_version(&library);
+    if target.is_none() {
+        warn!(
+            "Failed to determine the macOS version {} is built for. Set '{}' to build for the same version.",
+            library.display(),
+            DEPLOYMENT_TARGET_ENV
+        );
+    }
+    target
+}
+
+/// Build the Rust code for the given deployment target, unless one is set already.
+pub fn configure_rust(deployment_target: &str) {
+    if env::var_os(DEPLOYMENT_TARGET_ENV).is_none() {
+        debug!("Setting {}={}", DEPLOYMENT_TARGET_ENV, deployment_target);
+        env::set_var(DEPLOYMENT_TARGET_ENV, deployment_target);
+    }
+}
+
+/// CMake arguments setting the deployment target, the architecture, the install names and the
+/// runtime paths of the plugin libraries, leaving out the variables set in `cmake_args`.
+pub fn cmake_args(
+    hfs: &Path,
+    deployment_target: Option<&str>,
+    architecture: Option<&str>,
+    cmake_args: &[String],
+) -> Vec<String> {
+    // The libraries of dependencies are found next to the plugin, and those of Houdini in the
+    // framework even for processes other than Houdini itself.
+    let rpath = format!("@loader_path;{}", houdini_libraries(hfs).display());
+    let vars = vec![
+        (
+            "CMAKE_OSX_DEPLOYMENT_TARGET",
+            deployment_target.map(String::from),
+        ),
+        ("CMAKE_OSX_ARCHITECTURES", architecture.map(String::from)),
+        ("CMAKE_MACOSX_RPATH", Some("ON".to_string())),
+        ("CMAKE_INSTALL_NAME_DIR", Some("@rpath".to_string())),
+        ("CMAKE_BUILD_RPATH", Some(rpath.clone())),
+        ("CMAKE_INSTALL_RPATH", Some(rpath)),
+    ];
+    cmake::default_var_args(vars, cmake_args)
+}
@@ -1,49 +1,732 @@
 #[macro_use]
 extern crate anyhow;
 
+use std::collections::HashMap;
+use std::ffi::OsString;
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
 use std::{env, fs};
 
 use anyhow::{Context, Result};
 
-use clap::{AppSettings, Parser};
+use clap::{AppSettings, ArgMatches, Parser, ValueEnum, ValueSource};
 use log::*;
 
-use cargo_metadata::{camino::Utf8PathBuf, Message, MetadataCommand, Package};
+use cargo_metadata::semver;
+use cargo_metadata::{
+    camino::{Utf8Path, Utf8PathBuf},
+    Artifact, Message, Metadata, MetadataCommand, Package, PackageId,
+};
+
+mod bench;
+mod binary;
+mod bindgen;
+mod build_env;
+mod clean;
+mod cmake;
+mod codesign;
+mod collisions;
+mod compilers;
+mod completions;
+mod config;
+mod container;
+mod corrosion;
+mod coverage;
+mod cpp_deps;
+mod cross;
+mod debugger;
+mod deps;
+mod dlls;
+mod doctor;
+mod dso_errors;
+mod exports;
+mod features;
+mod fmt_cpp;
+mod hda;
+mod hooks;
+mod houdini;
+mod ide;
+mod jobs;
+mod launch;
+mod lint;
+mod long_paths;
+mod macos;
+mod migrate;
+mod msys;
+mod naming;
+mod progress;
+mod prune;
+mod reload;
+mod report;
+mod rust_bindings;
+mod sanitizer;
+mod scaffold;
+mod size;
+mod static_crt;
+mod symbol_versions;
+mod symbols;
+mod testing;
+mod tools;
+mod universal;
+mod verify;
+mod watch;
+mod wsl;
 
 const ABOUT: &str = "
 cargo-hdk is a cargo subcommand to compile and install a Houdini plugin written in Rust and C++.";
 
+use config::Config;
+
+/// Compiler caches supported by '--cache'.
+#[derive(ValueEnum, serde::Deserialize, serde::Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CompilerCache {
+    Sccache,
+    Ccache,
+}
+
+impl CompilerCache {
+    fn program(self) -> &'static str {
+        match self {
+            CompilerCache::Sccache => "sccache",
+            CompilerCache::Ccache => "ccache",
+        }
+    }
+}
+
 #[derive(Parser, Debug)]
-#[clap(author, about = ABOUT, name = "cargo-hdk")]
+#[clap(
+    author,
+    about = ABOUT,
+    name = "cargo-hdk",
+    override_usage = "cargo hdk [OPTIONS] [BUILD ARGS]...\n    cargo hdk [OPTIONS] -- [BUILD ARGS]..."
+)]
 struct Opt {
     #[clap(flatten)]
     verbose: clap_verbosity_flag::Verbosity,
 
     /// Arguments for the 'cargo build' step. These are ignored when the '--hdk-only' flag is used.
+    ///
+    /// If the first argument is 'check', only validate the build: 'cargo check' is run instead of
+    /// 'cargo build', and CMake is configured without compiling the HDK plugin. This quickly
+    /// checks that Houdini is found, the 'OUT_DIR' files are exported and the CMake project
+    /// configures, e.g. in a pre-commit hook.
+    ///
+    /// If the first argument is 'new', generate a new plugin crate instead:
+    /// 'cargo hdk new <PATH> [--template sop|rop|dop|chop|vop] [--name <NAME>]' creates a crate
+    /// with a Rust library and an HDK plugin for the given kind of operator that calls into it.
+    /// The template may also be a git repository URL (with an optional '--branch <BRANCH>') or a
+    /// local directory containing a custom template.
+    ///
+    /// If the first argument is 'run', build the plugin and launch Houdini with it loaded from the
+    /// build directory (see '--app'). A scene file ('.hip', '.hipnc' or '.hiplc') or script
+    /// ('.py') argument is passed to Houdini along with all arguments following it, as in
+    /// 'cargo hdk run --release scene.hip'.
+    ///
+    /// If the first argument is 'debug', build the plugin with debug info (using the
+    /// 'RelWithDebInfo' CMake build type for optimized profiles) and launch Houdini with it
+    /// under a debugger (see '--debugger' and '--break-on-load'). Arguments are passed to
+    /// Houdini as for 'cargo hdk run', e.g. 'cargo hdk debug --release scene.hip'.
+    ///
+    /// If the first argument is 'test', build the plugin and run the Python test scripts named
+    /// 'test_*.py' in 'hdk/tests' (or the configured 'test_dir') with hython, with the plugin
+    /// loaded from the build directory, along with the golden geometry tests declared in the
+    /// 'snapshots' configuration (see '--update-snapshots') and, with '--render', the headless
+    /// renders declared in the 'renders' configuration. Given '.py' files are run instead, as in
+    /// 'cargo hdk test --release hdk/tests/test_cook.py'.
+    ///
+    /// If the first argument is 'cov', build the Rust code and, with clang, the C++ code with
+    /// LLVM coverage instrumentation in a separate build directory, run the tests as for
+    /// 'cargo hdk test' and merge the coverage of both into an lcov file and an HTML report in
+    /// '<build dir>/coverage/report'. This needs 'llvm-profdata' and 'llvm-cov', e.g.
+    /// from 'rustup component add llvm-tools-preview'.
+    ///
+    /// If the first argument is 'bench', build the plugin in release mode (unless another profile
+    /// is given) and time the cooks of the benchmarks declared in the 'benches' configuration
+    /// with hython, comparing them with a baseline (see '--save-baseline').
+    ///
+    /// If the first argument is 'verify', build the plugin and load each plugin library found in
+    /// the build directory in a separate process with the Houdini libraries on the loader path.
+    /// This reports symbols that can't be resolved, with demangled names, and checks that the
+    /// plugin exports the DSO version tag of 'UT/UT_DSOVersion.h' and a function registering its
+    /// operators, like 'newSopOperator'.
+    ///
+    /// If the first argument is 'size', build the plugin and break down the size of each plugin
+    /// library found in the build directory: its sections, its symbols grouped by Rust crate, its
+    /// largest symbols and the code of each C++ translation unit. This uses 'nm' and 'size' from
+    /// LLVM or GNU binutils.
+    ///
+    /// If the first argument is 'bindgen', build the Rust code and copy the C and C++ headers
+    /// generated by cxx or cbindgen in the exported 'OUT_DIR's to 'include/generated' in the HDK
+    /// plugin directory, so the C++ code and IDEs see the current bindings at a stable path. A
+    /// crate with a 'cbindgen.toml' and no headers in its 'OUT_DIR' gets its header from the
+    /// cbindgen CLI. Only changed headers are written and stale ones are removed.
+    ///
+    /// If the first argument is 'rust-bindings', generate Rust FFI for the HDK headers listed in
+    /// the 'rust_bindings' configuration with the bindgen CLI, using the defines and include paths
+    /// of the Houdini installation, and write it to 'src/hdk_sys.rs' or the configured output.
+    ///
+    /// If the first argument is 'migrate', upgrade a project written for older conventions of
+    /// cargo-hdk: the HDK plugin's CMake files read the 'OUT_DIR's from the '--out-dir-cmake'
+    /// script instead of the per crate files and refer to the build directory as
+    /// '${CMAKE_BINARY_DIR}', and the configuration uses the current keys and 'cmake' syntax.
+    /// The changes are printed as a diff, and only printed with 'cargo hdk migrate --dry-run'.
+    ///
+    /// If the first argument is 'prune', remove stale build state: build directories configured
+    /// for a Houdini installation that no longer exists, the '--houdini-target-dir' directories
+    /// of versions that are no longer installed, 'OUT_DIR' and manifest directory files of crates
+    /// no longer in the 'Cargo.lock', and logs not written for a week (see
+    /// 'cargo hdk prune --log-age <DAYS>'). The reclaimed disk space is reported, and
+    /// 'cargo hdk prune --dry-run' only lists what would be removed.
+    ///
+    /// If the first argument is 'hda', convert the digital assets declared in the 'hdas'
+    /// configuration with 'hotl' of the Houdini installation: 'cargo hdk hda expand' expands
+    /// each asset library into its directory for source control and 'cargo hdk hda collapse'
+    /// turns the directories back into asset libraries. A single asset is converted by giving its
+    /// file or directory, along with the other path if it isn't configured, as in
+    /// 'cargo hdk hda expand otls/sop_twist.hda hda/sop_twist'.
+    ///
+    /// If the first argument is 'ide', write the configuration of the given IDE ('vscode' or
+    /// 'clion') into the crate: build tasks running cargo-hdk with the same options, launch
+    /// configurations debugging Houdini and hython with the plugin environment or attaching to a
+    /// running Houdini, and CMake settings using the build directory. The following arguments
+    /// select the build as for a build, e.g. 'cargo hdk ide vscode --release'. With 'clangd',
+    /// the HDK include paths and defines of the Houdini installation and the compilation
+    /// database of the build directory are written to '.clangd' in the HDK plugin directory.
+    ///
+    /// If the first argument is 'lint', check the Rust code and configure CMake as for
+    /// 'cargo hdk check', then analyze the C++ sources of the HDK plugin directory with
+    /// clang-tidy using the compilation database of the build directory, so the HDK include
+    /// paths and defines match the build. The checks are taken from '--tidy-checks', the 'lint'
+    /// configuration or a '.clang-tidy' file, with a default set for HDK plugins otherwise. The
+    /// warnings are summarized by check, and '--fix' applies the suggested fixes.
+    ///
+    /// If the first argument is 'fmt-cpp', format the C++ sources of the HDK plugin directory
+    /// with clang-format, using the '.clang-format' file of the project or a default following
+    /// the style of the HDK. Build directories and the headers copied by 'cargo hdk bindgen' are
+    /// left out, and given files or directories are formatted instead, as in
+    /// 'cargo hdk fmt-cpp hdk/src'. With 'cargo hdk fmt-cpp --check', the changes are only
+    /// printed as a diff, and the command fails if any file needs formatting.
+    ///
+    /// If the first argument is 'shell', start the shell of the user ('SHELL', or 'COMSPEC' on
+    /// Windows) with the environment of the Houdini installation and 'HOUDINI_DSO_PATH' pointing
+    /// at the build directory, so any Houdini application or debugger started from it loads the
+    /// plugin. The following arguments select the build directory as for a build, e.g.
+    /// 'cargo hdk shell --release'.
+    ///
+    /// If the first argument is 'watch', build and then rebuild whenever the Rust sources
+    /// ('src', 'Cargo.toml' and 'build.rs') or the HDK plugin directory change, until
+    /// interrupted. Changes to the HDK plugin only rerun the CMake build. The following arguments
+    /// are used for each build, e.g. 'cargo hdk watch --release'.
+    ///
+    /// If the first argument is 'doctor', check the build environment without building anything:
+    /// the Houdini installation and its version, the Houdini environment variables, the C++
+    /// compiler compared with the one Houdini was built with, CMake and Ninja, and the license
+    /// server. Each problem is printed with a hint on how to fix it.
+    ///
+    /// If the first argument is 'env', print the resolved build environment without building
+    /// anything: the Houdini installation and version, the profile, the build and target
+    /// directories, the CMake generator, the libraries built by cargo and the 'OUT_DIR' paths
+    /// exported by the last build (see '--format'). The following arguments select the build as
+    /// for a build, e.g. 'cargo hdk env --release'.
+    ///
+    /// If the first argument is 'completions', print a completion script for the given shell
+    /// ('bash', 'elvish', 'fish', 'powershell' or 'zsh') completing the options, the commands
+    /// above and the versions of the Houdini installations found in the default locations.
+    ///
+    /// If the first argument is 'config', print the effective configuration merged from the
+    /// configuration files, environment variables and command line options, along with where
+    /// each value came from, without building anything.
+    ///
+    /// Arguments after a '--' separator are passed to cargo verbatim, even if they look like
+    /// cargo-hdk options (e.g. 'cargo hdk --jobserver -- --features gpu -j 4').
     #[clap(name = "BUILD ARGS")]
     build_args: Vec<String>,
 
+    /// Arguments for the application launched by 'cargo hdk run', split off the build arguments.
+    #[clap(skip)]
+    app_args: Vec<String>,
+
+    /// Whether '--deps' has its default value, i.e. it isn't set on the command line, in the
+    /// environment or in the configuration.
+    #[clap(skip)]
+    default_deps: bool,
+
+    /// Path to the 'Cargo.toml' of the crate to build.
+    ///
+    /// This is passed on to cargo, so 'cargo hdk' can be run from anywhere. A '--manifest-path'
+    /// given in the cargo build arguments is used as well.
+    #[clap(long, value_name = "PATH", env = "CARGO_HDK_MANIFEST_PATH")]
+    manifest_path: Option<PathBuf>,
+
+    /// The workspace member whose HDK plugin is built.
+    ///
+    /// This is passed on to cargo as '--package'. A '-p' or '--package' given in the cargo build
+    /// arguments is used as well. Required in workspaces with multiple members unless the
+    /// current directory is inside one of them.
+    #[clap(long, value_name = "SPEC", env = "CARGO_HDK_PACKAGE")]
+    package: Option<String>,
+
+    /// The Rust toolchain used to run cargo, e.g. 'nightly-2024-06-01'.
+    ///
+    /// Cargo is run with 'rustup run <toolchain> cargo', so this requires rustup. A leading
+    /// '+<toolchain>' argument is used as well, as in 'cargo hdk +nightly --release'.
+    #[clap(long, value_name = "TOOLCHAIN", env = "CARGO_HDK_RUST_TOOLCHAIN")]
+    rust_toolchain: Option<String>,
+
     /// Skip the 'cargo build` step. Build only the HDK plugin.
-    #[clap(short = 'k', long)]
+    #[clap(short = 'k', long, env = "CARGO_HDK_HDK_ONLY")]
     hdk_only: bool,
 
+    /// Let CMake drive the cargo build using Corrosion.
+    ///
+    /// Instead of running 'cargo build' directly, a CMake superproject is generated in the build
+    /// directory that imports the crate with 'corrosion_import_crate' and adds the HDK plugin
+    /// directory with 'add_subdirectory'. The plugin can then link the crate with
+    /// 'target_link_libraries(<plugin> PRIVATE <crate name>)'. Corrosion is fetched at configure
+    /// time unless it is already installed.
+    ///
+    /// Since cargo is run by CMake, no 'OUT_DIR' or artifact files are generated in this mode.
+    #[clap(long, env = "CARGO_HDK_CORROSION")]
+    corrosion: bool,
+
     /// Remove artifacts created by the build process including the HDK plugin.
     ///
     /// To clean the HDK build only, use the '--hdk-only' flag in combination with this flag.
-    #[clap(long)]
+    /// Files generated for CMake outside of the build directory, e.g. with an absolute
+    /// '--out-dir-file-prefix', are removed as well.
+    #[clap(long, env = "CARGO_HDK_CLEAN")]
     clean: bool,
 
+    /// With '--clean', also remove the HDK build directories of all other cargo profiles.
+    ///
+    /// These are the 'build_*' directories in the HDK plugin directory, or the directories
+    /// matching '--build-dir' with any '{profile}'.
+    #[clap(long, requires = "clean", env = "CARGO_HDK_ALL_PROFILES")]
+    all_profiles: bool,
+
+    /// With '--clean', also remove the build directories of all other Houdini versions.
+    ///
+    /// These are the directories matching '--build-dir' with any '{houdini}' or
+    /// '{houdini_version}', along with the cargo target directories of '--houdini-target-dir'.
+    #[clap(long, requires = "clean", env = "CARGO_HDK_ALL_VERSIONS")]
+    all_versions: bool,
+
+    /// With '--clean', also remove the files installed by '--cmake-install' from each removed
+    /// build directory, as listed in its CMake install manifest.
+    #[clap(long, requires = "clean", env = "CARGO_HDK_CLEAN_INSTALLED")]
+    clean_installed: bool,
+
+    /// Number of parallel jobs for both the cargo build and the CMake build.
+    ///
+    /// This is passed to cargo as '--jobs' and to 'cmake --build' as '--parallel', so the two
+    /// steps share the same budget instead of each assuming they own the machine. A '--jobs'
+    /// or '-j' given in the cargo build arguments, or cargo's 'CARGO_BUILD_JOBS', is used for the
+    /// CMake build as well.
+    #[clap(short, long, value_name = "N", env = "CARGO_HDK_JOBS")]
+    jobs: Option<std::num::NonZeroU32>,
+
+    /// Share a GNU make jobserver between the cargo build and the CMake build.
+    ///
+    /// A jobserver with the number of '--jobs' (or the number of CPUs) is passed to cargo and to
+    /// the Make or Ninja build run by 'cmake --build', which keeps the total number of jobs
+    /// bounded across both build systems. If cargo-hdk is run by make with a jobserver, that
+    /// jobserver is used instead. Ninja supports jobservers since version 1.13.
+    #[clap(long, env = "CARGO_HDK_JOBSERVER")]
+    jobserver: bool,
+
+    /// The version of the Houdini installation to build with, e.g. '19.5' or '19.5.303'.
+    ///
+    /// The installation given by 'HFS' is used if it has this version. Otherwise the latest
+    /// matching installation is picked from the default installation locations: '/opt/hfs*' on
+    /// Linux, '/Applications/Houdini' on macOS and 'C:\Program Files\Side Effects Software' on
+    /// Windows.
+    #[clap(long, value_name = "VERSION", env = "CARGO_HDK_HOUDINI_VERSION")]
+    houdini_version: Option<String>,
+
+    /// Use a separate cargo target directory for each Houdini version.
+    ///
+    /// Build scripts like the one of 'hdkrs' depend on the Houdini installation, so switching
+    /// between Houdini versions would otherwise rebuild them from scratch every time, or mix
+    /// artifacts built for different versions. With this flag, cargo builds into
+    /// '<target dir>/houdini<version>' (e.g. 'target/houdini19.5.303').
+    #[clap(long, env = "CARGO_HDK_HOUDINI_TARGET_DIR")]
+    houdini_target_dir: bool,
+
+    /// Cache compiler outputs using the given tool.
+    ///
+    /// This sets 'RUSTC_WRAPPER' for the cargo build and 'CMAKE_C_COMPILER_LAUNCHER' and
+    /// 'CMAKE_CXX_COMPILER_LAUNCHER' for the CMake build. Since ccache can't cache Rust code,
+    /// only the C++ build is cached with ccache.
+    #[clap(long, value_enum, value_name = "TOOL", env = "CARGO_HDK_CACHE")]
+    cache: Option<CompilerCache>,
+
+    /// Optimize the plugin for size.
+    ///
+    /// This builds with the release profile (unless another profile is given) using
+    /// 'opt-level = "z"', 'panic = "abort"', fat LTO, a single codegen unit and stripped symbols,
+    /// and builds the HDK plugin with the 'MinSizeRel' CMake build type, stripping it on
+    /// '--cmake-install'. On a nightly toolchain, the standard library is also rebuilt with
+    /// '-Z build-std=std,panic_abort', which requires the 'rust-src' rustup component.
+    #[clap(long, env = "CARGO_HDK_MINIMAL_SIZE")]
+    minimal_size: bool,
+
+    /// Ignore the user configuration file.
+    ///
+    /// By default, machine specific settings are read from '~/.config/cargo-hdk/config.toml'
+    /// ('%APPDATA%\cargo-hdk\config.toml' on Windows). Use this flag for reproducible builds,
+    /// e.g. on CI.
+    #[clap(long, env = "CARGO_HDK_NO_USER_CONFIG")]
+    no_user_config: bool,
+
+    /// Apply a named set of settings from the configuration.
+    ///
+    /// Profiles are defined in '[package.metadata.hdk.profiles.<name>]' tables (or
+    /// '[profiles.<name>]' in 'hdk.toml') with the same keys as '[package.metadata.hdk]', and
+    /// override them. Besides options like 'cmake', 'generator' or 'houdini_version', profiles
+    /// can set 'env' variables and extra 'cargo_args' for the build.
+    #[clap(long, value_name = "NAME", env = "CARGO_HDK_HDK_PROFILE")]
+    hdk_profile: Option<String>,
+
+    /// Configure CMake while cargo builds the Rust code.
+    ///
+    /// The CMake configure step is run concurrently with the cargo build, and the HDK plugin is
+    /// built once both are done. This only works if the 'CMakeLists.txt' doesn't read the
+    /// generated 'OUT_DIR' or artifact files at configure time, since those are written after
+    /// the cargo build. The output of both steps may be interleaved.
+    #[clap(long, env = "CARGO_HDK_OVERLAP_CONFIGURE")]
+    overlap_configure: bool,
+
+    /// Build without accessing the network.
+    ///
+    /// This passes '--offline' to cargo, disconnects CMake 'FetchContent' so only already
+    /// downloaded sources are used, and prevents vcpkg and Conan from downloading packages.
+    /// Corrosion must be installed to use '--corrosion' in this mode. Offline mode is also
+    /// enabled by '--offline' or '--frozen' in the cargo build arguments and by
+    /// 'CARGO_NET_OFFLINE=true'.
+    #[clap(long, env = "CARGO_HDK_OFFLINE")]
+    offline: bool,
+
+    /// Build the Rust and the C++ code with the given sanitizer.
+    ///
+    /// The Rust code is instrumented with '-Zsanitizer' on a nightly toolchain (rustc has no
+    /// 'undefined' sanitizer), and the C++ code with '-fsanitize'. The instrumented build uses
+    /// separate build and cargo target directories named after the sanitizer (e.g. 'asan').
+    /// Houdini launched by 'cargo hdk run', 'debug', 'test' or 'bench' gets the sanitizer runtime
+    /// of the C++ compiler preloaded, and options like 'ASAN_OPTIONS' suitable for Houdini.
+    #[clap(long, value_enum, value_name = "SANITIZER", env = "CARGO_HDK_SANITIZE")]
+    sanitize: Option<sanitizer::Sanitizer>,
+
+    /// Build a universal macOS plugin running natively on Apple silicon and Intel Macs.
+    ///
+    /// The Rust code is built for 'aarch64-apple-darwin' and 'x86_64-apple-darwin' and the
+    /// libraries are merged with 'lipo', while the C++ code is built with
+    /// 'CMAKE_OSX_ARCHITECTURES=arm64;x86_64'. The build directory is named after the
+    /// 'universal-apple-darwin' target. This needs a universal build of Houdini.
+    #[clap(long, env = "CARGO_HDK_UNIVERSAL")]
+    universal: bool,
+
+    /// Build in a container of the given Docker or Podman image with Houdini installed.
+    ///
+    /// cargo-hdk runs 'cargo hdk' with the other arguments in a new container of the image, which
+    /// must provide Houdini, Rust, CMake, a C++ compiler and cargo-hdk. The workspace and the
+    /// cargo target directory are mounted at the same paths, and the cargo home and the sccache
+    /// and ccache directories are shared with the container. The engine is Docker or otherwise
+    /// Podman, or the one given by 'CARGO_HDK_CONTAINER_ENGINE'. Also set by 'container' in the
+    /// configuration.
+    #[clap(long, value_name = "IMAGE", env = "CARGO_HDK_CONTAINER")]
+    container: Option<String>,
+
+    /// The Houdini application launched by 'cargo hdk run' and 'cargo hdk debug'.
+    ///
+    /// The application is run from the Houdini installation with 'HOUDINI_DSO_PATH' pointing at
+    /// the plugin in the build directory and 'HOUDINI_OTLSCAN_PATH' at the 'otls' directories of
+    /// the crate, ahead of the default search paths.
+    #[clap(
+        long,
+        value_enum,
+        value_name = "APP",
+        default_value = "houdini",
+        env = "CARGO_HDK_APP"
+    )]
+    app: launch::App,
+
+    /// The debugger used by 'cargo hdk debug'.
+    ///
+    /// By default, this is the Visual Studio debugger on Windows, LLDB on macOS and GDB
+    /// elsewhere. The 'rust-gdb' and 'rust-lldb' wrappers are used if available.
+    #[clap(long, value_enum, value_name = "DEBUGGER", env = "CARGO_HDK_DEBUGGER")]
+    debugger: Option<debugger::Debugger>,
+
+    /// Stop in the debugger when the plugin is loaded by 'cargo hdk debug'.
+    #[clap(long, env = "CARGO_HDK_BREAK_ON_LOAD")]
+    break_on_load: bool,
+
+    /// Overwrite the golden files of snapshot tests with the geometry cooked by 'cargo hdk test'.
+    ///
+    /// This creates missing golden files, and updates existing ones after an intended change of
+    /// the cook results. Review the changes before checking them in.
+    #[clap(long, env = "CARGO_HDK_UPDATE_SNAPSHOTS")]
+    update_snapshots: bool,
+
+    /// Also run the render tests declared in the 'renders' configuration with 'cargo hdk test'.
+    ///
+    /// Each ROP is rendered headlessly with the plugin loaded, and the image is compared with its
+    /// reference, if any. With '--update-snapshots', the reference images are updated instead.
+    #[clap(long, env = "CARGO_HDK_RENDER")]
+    render: bool,
+
+    /// Save the results of 'cargo hdk bench' as the new baseline.
+    ///
+    /// The baseline is stored in 'hdk/benches/baseline.toml', or the 'bench_baseline' file of the
+    /// configuration, and should be saved on the reference machine, e.g. from the last release.
+    #[clap(long, env = "CARGO_HDK_SAVE_BASELINE")]
+    save_baseline: bool,
+
+    /// The clang-tidy checks run by 'cargo hdk lint', e.g. '-*,bugprone-*'.
+    ///
+    /// This overrides the 'checks' of the 'lint' configuration and a '.clang-tidy' file.
+    #[clap(long, value_name = "CHECKS", env = "CARGO_HDK_TIDY_CHECKS")]
+    tidy_checks: Option<String>,
+
+    /// Apply the fixes suggested by clang-tidy with 'cargo hdk lint'.
+    #[clap(long, env = "CARGO_HDK_FIX")]
+    fix: bool,
+
+    /// Fail 'cargo hdk lint' if clang-tidy reports any warnings.
+    #[clap(long, env = "CARGO_HDK_DENY_WARNINGS")]
+    deny_warnings: bool,
+
+    /// Update a running Houdini session listening for hscript commands on the given port after
+    /// a successful build.
+    ///
+    /// Open the port in Houdini with 'openport <PORT>'. The 'reload' commands of the
+    /// configuration are sent with 'hcommand', followed by a forced cook of each of the
+    /// 'reload_nodes'. This is most useful with 'cargo hdk watch', as in
+    /// 'cargo hdk watch --release --reload 9000'. A session that can't be reached is reported as
+    /// a warning without failing the build.
+    #[clap(long, value_name = "PORT", env = "CARGO_HDK_RELOAD")]
+    reload: Option<u16>,
+
+    /// The output format of 'cargo hdk env'.
+    ///
+    /// 'sh' and 'powershell' print lines setting environment variables, which can be evaluated by
+    /// the shell, as in 'eval "$(cargo hdk env)"'. 'json' prints a single JSON object.
+    #[clap(
+        long,
+        value_enum,
+        value_name = "FORMAT",
+        default_value = "sh",
+        env = "CARGO_HDK_FORMAT"
+    )]
+    format: build_env::Format,
+
     /// Pass arguments to CMake configuration.
     ///
     /// For instance to use Ninja as the cmake generator, use '--cmake "-G Ninja"'.
-    #[clap(short, long, default_value = "")]
+    #[clap(short, long, default_value = "", env = "CARGO_HDK_CMAKE_ARGS")]
     cmake: String,
 
-    /// Path to the HDK plugin relative to the root of the crate. This must be a Unicode path.
-    #[clap(short, long, default_value = "./hdk")]
-    hdk_path: Utf8PathBuf,
+    /// The CMake generator to use for the HDK plugin build.
+    ///
+    /// If no generator is given here or via '--cmake', Ninja is used when it is found on the
+    /// 'PATH', otherwise CMake picks its platform default. The generator is only selected
+    /// automatically for a fresh build directory, since CMake doesn't allow changing the
+    /// generator of an existing build.
+    #[clap(short = 'G', long, env = "CARGO_HDK_GENERATOR")]
+    generator: Option<String>,
+
+    /// Don't install C++ dependencies declared in the HDK plugin directory.
+    ///
+    /// By default, if the HDK plugin directory contains a vcpkg manifest ('vcpkg.json') or a
+    /// Conan recipe ('conanfile.txt' or 'conanfile.py'), the dependencies are installed into the
+    /// build directory before configuring CMake, and the corresponding toolchain file and prefix
+    /// path are passed to the CMake configure step.
+    #[clap(long, env = "CARGO_HDK_NO_CPP_DEPS")]
+    no_cpp_deps: bool,
+
+    /// Treat C++ compiler warnings as errors when building the HDK plugin.
+    ///
+    /// This appends '-Werror' ('/WX' on Windows) to the 'CMAKE_CXX_FLAGS' of the build directory.
+    /// Building without this flag removes it again.
+    #[clap(long, env = "CARGO_HDK_CPP_WARNINGS_AS_ERRORS")]
+    cpp_warnings_as_errors: bool,
+
+    /// The C compiler of the HDK plugin, passed to CMake as 'CMAKE_C_COMPILER'.
+    ///
+    /// A name is looked up on the 'PATH'. With 'auto', the installed compilers are searched for
+    /// one matching the Houdini installation, as described for '--cxx'.
+    #[clap(long, value_name = "COMPILER", env = "CARGO_HDK_CC")]
+    cc: Option<String>,
+
+    /// The C++ compiler of the HDK plugin, passed to CMake as 'CMAKE_CXX_COMPILER'.
+    ///
+    /// A name is looked up on the 'PATH'. With 'auto', the installed compilers are searched for
+    /// one matching the Houdini installation: on Linux a GCC with a major version Houdini is built
+    /// with, like 'g++-11' or the 'gcc-toolset-11' in '/opt/rh', and on macOS the clang of Xcode.
+    /// On Windows the Visual Studio toolset is selected by the generator instead. 'auto' for one of
+    /// '--cc' and '--cxx' selects both compilers unless the other is given. A compiler given in
+    /// '--cmake' takes precedence.
+    #[clap(long, value_name = "COMPILER", env = "CARGO_HDK_CXX")]
+    cxx: Option<String>,
+
+    /// Link the C++ runtime into the HDK plugin statically, for plugins that must load on
+    /// machines with other runtime versions than the build machine.
+    ///
+    /// On Windows this selects the static MSVC runtime with 'CMAKE_MSVC_RUNTIME_LIBRARY', which
+    /// needs the policy CMP0091 of CMake 3.15, and builds the Rust code with '+crt-static'. On
+    /// Linux this links with '-static-libstdc++ -static-libgcc'. The plugin then has its own copy
+    /// of the runtime, so memory and standard library objects must not be passed between the
+    /// plugin and Houdini where the two copies could disagree. Building without this flag
+    /// restores the shared runtime.
+    #[clap(long, env = "CARGO_HDK_STATIC_CRT")]
+    static_crt: bool,
+
+    /// Export only the entry points Houdini calls from the plugin libraries on Linux and macOS,
+    /// and the symbols listed in the 'exports' configuration.
+    ///
+    /// This compiles the C++ code with '-fvisibility=hidden -fvisibility-inlines-hidden' and links
+    /// the plugin with a version script on Linux or an exported symbols list on macOS, which also
+    /// hides the symbols of the Rust code, so they can't collide with those of Houdini or other
+    /// plugins. Building without this flag removes the flags again.
+    #[clap(long, env = "CARGO_HDK_TRIM_EXPORTS")]
+    trim_exports: bool,
+
+    /// Only warn if the C++ compiler selected by CMake doesn't match the one the Houdini
+    /// installation is built with, instead of failing before the build.
+    ///
+    /// On Windows, the MSVC toolset is compared with the Visual Studio version of Houdini, and on
+    /// Linux the GCC version, or the version of the libstdc++ used by clang, with the GCC versions
+    /// of Houdini.
+    #[clap(long, env = "CARGO_HDK_ALLOW_COMPILER_MISMATCH")]
+    allow_compiler_mismatch: bool,
+
+    /// Show the full compiler and linker command lines during the CMake build.
+    ///
+    /// This passes '--verbose' to 'cmake --build'. Specify twice to also pass '--debug-output' to
+    /// the CMake configure step, and three times to additionally pass '--trace-expand'. The level
+    /// can also be set with 'CARGO_HDK_CMAKE_VERBOSE=<LEVEL>'.
+    #[clap(long, parse(from_occurrences))]
+    cmake_verbose: u8,
+
+    /// Run 'cmake --install' after building the HDK plugin.
+    ///
+    /// This lets projects that define CMake 'install()' rules deploy the plugin using standard
+    /// CMake machinery. A relative prefix is relative to the root of the crate. If no prefix is
+    /// given, the Houdini user preference directory (e.g. '~/houdini19.5' on Linux, or
+    /// 'HOUDINI_USER_PREF_DIR' if set) is used. 'CARGO_HDK_CMAKE_INSTALL' sets the prefix, or
+    /// selects the default prefix if it is empty.
+    #[clap(
+        long,
+        value_name = "PREFIX",
+        min_values = 0,
+        max_values = 1,
+        require_equals = true,
+        value_parser
+    )]
+    cmake_install: Option<Option<PathBuf>>,
+
+    /// Directory the symbol files of the plugin libraries are written to by '--cmake-install',
+    /// relative to the root of the crate: the PDBs on Windows, and with '--split-debuginfo' the
+    /// '.debug' files on Linux and dSYM bundles on macOS.
+    ///
+    /// By default, each symbol file is installed next to its library.
+    #[clap(long, value_name = "DIR", env = "CARGO_HDK_SYMBOLS_DIR", value_parser)]
+    symbols_dir: Option<PathBuf>,
+
+    /// Strip the plugin libraries installed by '--cmake-install' of debug information and local
+    /// symbols.
+    ///
+    /// On Windows, where the debug information is in the PDBs, the PDBs aren't installed.
+    #[clap(long, env = "CARGO_HDK_STRIP")]
+    strip: bool,
+
+    /// Move the debug information of the plugin libraries installed by '--cmake-install' into
+    /// separate symbol files, and strip the libraries.
+    ///
+    /// On Linux the debug information is extracted with 'objcopy' into '<library>.debug', which
+    /// the stripped library refers to with a '.gnu_debuglink' section so debuggers find it, and on
+    /// macOS 'dsymutil' writes a '<library>.dSYM' bundle. On Windows the PDBs are installed as
+    /// usual. The symbol files are put next to the libraries, or into '--symbols-dir'. Release
+    /// builds use the 'RelWithDebInfo' CMake build type and cargo's 'debug' setting to have debug
+    /// information to split.
+    #[clap(long, env = "CARGO_HDK_SPLIT_DEBUGINFO")]
+    split_debuginfo: bool,
+
+    /// What to do on Windows with DLLs the plugin libraries import that Houdini doesn't provide.
+    ///
+    /// After the build, the import tables of the plugin libraries are scanned for DLLs that are
+    /// neither in '$HFS/bin' nor part of Windows. Those next to the plugin are found by Houdini,
+    /// while those found elsewhere on the 'PATH' (e.g. of vcpkg packages) or not at all make the
+    /// plugin fail to load outside of the build environment. 'warn' reports them, 'deny' fails
+    /// the build with a list of them, and 'copy' copies those found elsewhere next to the plugin
+    /// and, with '--cmake-install', installs the DLLs next to the plugin along with it.
+    #[clap(
+        long,
+        value_enum,
+        value_name = "MODE",
+        default_value = "warn",
+        env = "CARGO_HDK_RUNTIME_DLLS"
+    )]
+    runtime_dlls: dlls::RuntimeDlls,
+
+    /// Sign the plugin libraries on macOS after the build, with the given identity or the
+    /// 'codesign.identity' of the configuration.
+    ///
+    /// The libraries are signed with the hardened runtime and a secure timestamp, along with the
+    /// 'codesign.entitlements' if configured. With '--cmake-install', the installed libraries are
+    /// signed again, since installing may change them.
+    #[clap(
+        long,
+        value_name = "IDENTITY",
+        min_values = 0,
+        max_values = 1,
+        require_equals = true,
+        env = "CARGO_HDK_CODESIGN"
+    )]
+    codesign: Option<Option<String>>,
+
+    /// Sign the plugin libraries like '--codesign' and submit them for notarization by Apple,
+    /// waiting for the result.
+    ///
+    /// The credentials of the notary service are read from the keychain profile configured as
+    /// 'codesign.keychain_profile', stored with 'xcrun notarytool store-credentials'. With
+    /// '--cmake-install', the installed libraries are notarized instead.
+    #[clap(long, env = "CARGO_HDK_NOTARIZE")]
+    notarize: bool,
+
+    /// Path to the HDK plugin relative to the root of the crate.
+    #[clap(
+        short,
+        long,
+        default_value = "./hdk",
+        env = "CARGO_HDK_HDK_PATH",
+        value_parser
+    )]
+    hdk_path: PathBuf,
+
+    /// Directory where the HDK plugin is built, relative to the root of the crate.
+    ///
+    /// The following placeholders are replaced in the given path:
+    ///
+    /// '{package}' with the name of the package being built,
+    /// '{profile}' with the cargo profile directory name (e.g. 'debug', 'release' or the name of a
+    /// custom profile),
+    /// '{houdini}' with the '<major>.<minor>' version of the Houdini installation (e.g. '19.5'),
+    /// '{houdini_version}' with the full version of the Houdini installation (e.g. '19.5.303'),
+    /// '{target}' with the target triple given by '--target' or 'host' for native builds,
+    /// '{target_dir}' with the cargo target directory, which follows '--target-dir' and
+    /// 'CARGO_TARGET_DIR'.
+    ///
+    /// For instance, '--build-dir "{target_dir}/hdk/{houdini}/{profile}"' keeps the build out of
+    /// the source tree. By default the plugin is built in '<hdk path>/build_{profile}', or
+    /// '<hdk path>/build_{target}_{profile}' when cross compiling.
+    ///
+    /// When cross compiling with a build directory that doesn't contain '{target}', the target
+    /// triple is appended to the build directory, so that native and cross builds don't share
+    /// a CMake cache.
+    #[clap(long, env = "CARGO_HDK_BUILD_DIR")]
+    build_dir: Option<String>,
 
     /// Path prefix to the automatically generated files containing the Rust output directories
     /// ('OUT_DIR') of the crate being built as well as any additional dependencies specified by
@@ -60,269 +743,2813 @@ struct Opt {
     ///
     /// If multiple versions of the same dependency are found, the last one built is the one that
     /// will have an associated 'OUT_DIR' file.
-    #[clap(long, default_value = "rust/out_dir_")]
+    ///
+    /// The same placeholders as in '--build-dir' are replaced in the prefix, e.g.
+    /// 'rust/{package}/{profile}/out_dir_'.
+    #[clap(
+        long,
+        default_value = "rust/out_dir_",
+        env = "CARGO_HDK_OUT_DIR_FILE_PREFIX"
+    )]
     out_dir_file_prefix: String,
 
-    /// The list of dependency names for which to produce an 'OUT_DIR' file.
-    #[clap(long, default_value = "hdkrs")]
-    deps: Vec<String>,
-}
+    /// Also write the manifest directory of the crate being built and each dependency selected by
+    /// '--deps' or '--deps-of' to a file with the given path prefix.
+    ///
+    /// This is useful for dependencies that provide headers in their source tree, since those
+    /// don't necessarily have a build script and hence no 'OUT_DIR'. The files are saved in the
+    /// same way as 'OUT_DIR' files, by default as '<hdk build directory>/rust/manifest_dir_<crate>.txt'.
+    /// When '--out-dir-cmake' is used, the manifest directories are also available as
+    /// '<NAME>_MANIFEST_DIR' variables. Placeholders are replaced as in '--out-dir-file-prefix'.
+    #[clap(
+        long,
+        value_name = "PREFIX",
+        min_values = 0,
+        max_values = 1,
+        require_equals = true,
+        default_missing_value = "rust/manifest_dir_",
+        env = "CARGO_HDK_MANIFEST_DIR_FILE_PREFIX"
+    )]
+    manifest_dir_file_prefix: Option<String>,
 
-pub fn init_logging(level: Option<log::Level>) {
-    if let Some(level) = level {
-        let mut builder = env_logger::Builder::new();
-        builder.filter(None, level.to_level_filter());
-        builder.format_timestamp(None).format_module_path(false);
-        builder.init();
-    }
-}
+    /// Additionally write a single CMake script setting a '<NAME>_OUT_DIR' variable for each
+    /// exported 'OUT_DIR'.
+    ///
+    /// The path is relative to the hdk build directory unless it is absolute. The script can be
+    /// loaded from the 'CMakeLists.txt' with 'include(${CMAKE_BINARY_DIR}/rust/out_dirs.cmake)'.
+    ///
+    /// Dependency names are converted to upper case with non-alphanumeric characters replaced
+    /// by underscores, so the 'OUT_DIR' of 'hdkrs' is stored in 'HDKRS_OUT_DIR'.
+    #[clap(
+        long,
+        value_name = "PATH",
+        min_values = 0,
+        max_values = 1,
+        require_equals = true,
+        default_missing_value = "rust/out_dirs.cmake",
+        env = "CARGO_HDK_OUT_DIR_CMAKE",
+        value_parser
+    )]
+    out_dir_cmake: Option<PathBuf>,
 
-// Run the cargo build (or clean) command and return the output directories to cache for each
-// dependency (including the crate being compiled).
-fn cargo_build(opts: &Opt, package: &Package) -> Result<Vec<(String, Utf8PathBuf)>> {
-    info!("Building Rust code using cargo.");
+    /// Write a CMake script defining 'IMPORTED' library targets for the built Rust library.
+    ///
+    /// The path is relative to the hdk build directory unless it is absolute. After including the
+    /// script, the plugin can link the Rust library with
+    /// 'target_link_libraries(<plugin> PRIVATE <crate name>::rust)', which also links the
+    /// system libraries required by the Rust standard library on each platform.
+    ///
+    /// If the crate builds both a 'staticlib' and a 'cdylib', the shared library is available as
+    /// '<crate name>::rust_shared'.
+    #[clap(
+        long,
+        value_name = "PATH",
+        min_values = 0,
+        max_values = 1,
+        require_equals = true,
+        default_missing_value = "rust_targets.cmake",
+        env = "CARGO_HDK_RUST_TARGETS_CMAKE",
+        value_parser
+    )]
+    rust_targets_cmake: Option<PathBuf>,
 
-    let build_args = if opts.build_args.first().map(|x| x.as_str()) == Some("hdk") {
-        &opts.build_args[1..]
-    } else {
-        opts.build_args.as_slice()
-    };
+    /// Write a CMake script setting a variable with the exact path of every library file built
+    /// by cargo.
+    ///
+    /// The path is relative to the hdk build directory unless it is absolute. Variables are named
+    /// '<NAME>_<KIND>', where '<NAME>' is the upper case library name and '<KIND>' is one of
+    /// 'STATICLIB', 'CDYLIB', 'DYLIB', 'RLIB' or 'IMPLIB' (the import library of a Windows DLL).
+    /// For instance, the static library of 'mycrate' is stored in 'MYCRATE_STATICLIB'.
+    ///
+    /// This avoids guessing paths like 'target/<profile>/libmycrate.a', which differ between
+    /// profiles, targets and custom target directories.
+    #[clap(
+        long,
+        value_name = "PATH",
+        min_values = 0,
+        max_values = 1,
+        require_equals = true,
+        default_missing_value = "rust/artifacts.cmake",
+        env = "CARGO_HDK_ARTIFACTS_CMAKE",
+        value_parser
+    )]
+    artifacts_cmake: Option<PathBuf>,
 
-    if opts.clean {
-        let status = Command::new(env!("CARGO"))
-            .arg("clean")
-            .args(build_args)
-            .status()
-            .context("Cargo clean failed")?;
+    /// Write a JSON report of the artifacts of the build to the given path, or to stdout if it is
+    /// '-'.
+    ///
+    /// The report lists the plugin libraries with their split debug symbols, the libraries built
+    /// by cargo, the Houdini package files installed with '--cmake-install' and the 'OUT_DIR'
+    /// files and scripts exported for CMake, each with its size and SHA-256 hash, along with the
+    /// package version, profile and Houdini version, for packaging pipelines.
+    #[clap(long, value_name = "PATH", env = "CARGO_HDK_ARTIFACT_JSON")]
+    artifact_json: Option<PathBuf>,
 
-        if !status.success() {
-            return Err(anyhow!("Rust clean failed"));
-        }
-        Ok(Vec::new())
-    } else {
-        // First build the crate with the standard build args.
-        let out = Command::new(env!("CARGO"))
-            .args(&["build", "--message-format=json"])
-            .args(build_args)
-            .stderr(Stdio::inherit())
-            .stdout(Stdio::piped())
-            .output()
-            .context("Cargo build failed")?;
-
-        if !out.status.success() {
-            return Err(anyhow!("Rust build failed"));
-        }
-
-        let reader = std::io::BufReader::new(out.stdout.as_slice());
-        let mut out_dir_deps = Vec::new();
-        for message in Message::parse_stream(reader) {
-            if let Message::BuildScriptExecuted(script) = message.unwrap() {
-                trace!(
-                    "Checking if a build script package id {} is {}",
-                    &script.package_id.repr,
-                    &package.id
-                );
-                if script.package_id == package.id {
-                    out_dir_deps.push((package.name.clone(), script.out_dir.clone()));
-                    continue;
-                }
-                for dep in &opts.deps {
-                    trace!(
-                        "Checking if a build script package id {} contains {}",
-                        &script.package_id.repr,
-                        &dep
-                    );
-                    if script.package_id.repr.contains(dep) {
-                        out_dir_deps.push((dep.clone(), script.out_dir.clone()));
-                        continue;
-                    }
-                }
-            }
+    /// The list of dependency names for which to produce an 'OUT_DIR' file.
+    ///
+    /// Dependencies are matched exactly by name in the dependency graph of the crate. If
+    /// multiple versions of a dependency are used, a specific one can be selected with
+    /// 'name@version' (e.g. 'hdkrs@0.3').
+    ///
+    /// Use 'all' to produce an 'OUT_DIR' file for every dependency that runs a build script, or
+    /// a glob pattern like 'hdk*' to select dependencies by name.
+    #[clap(
+        long,
+        default_value = "hdkrs",
+        env = "CARGO_HDK_DEPS",
+        use_value_delimiter = true
+    )]
+    deps: Vec<String>,
+
+    /// Produce an 'OUT_DIR' file for the given crate and all of its transitive dependencies that
+    /// run a build script.
+    ///
+    /// This is useful when depending on a crate like 'hdkrs' indirectly through another crate.
+    /// Crates are selected in the same way as for '--deps'.
+    #[clap(
+        long,
+        value_name = "CRATE",
+        env = "CARGO_HDK_DEPS_OF",
+        use_value_delimiter = true
+    )]
+    deps_of: Vec<String>,
+}
+
+impl Opt {
+    /// Arguments for cargo without the 'hdk' subcommand name passed in by cargo and without the
+    /// 'check', 'run', 'test', 'bench' or 'shell' command, or 'ide' with the name of the IDE.
+    fn cargo_args(&self) -> &[String] {
+        let args = self.command_args();
+        if self.ide() {
+            // Also without the name of the IDE.
+            args.get(2..).unwrap_or_default()
+        } else if self.check()
+            || self.run()
+            || self.debug()
+            || self.test()
+            || self.cov()
+            || self.bench()
+            || self.shell()
+            || self.watch()
+            || self.env()
+            || self.verify()
+            || self.size()
+            || self.bindgen()
+            || self.rust_bindings()
+            || self.migrate()
+            || self.prune()
+            || self.hda()
+            || self.lint()
+            || self.fmt_cpp()
+        {
+            &args[1..]
+        } else {
+            args
         }
+    }
 
-        Ok(out_dir_deps)
+    /// Arguments without the 'hdk' subcommand name passed in by cargo and without a leading
+    /// '+<toolchain>' argument.
+    fn command_args(&self) -> &[String] {
+        let args = self.hdk_args();
+        if self.toolchain_arg().is_some() {
+            &args[1..]
+        } else {
+            args
+        }
     }
-}
 
-fn main() -> Result<()> {
-    use terminal_size::{terminal_size, Width};
-    let app = Opt::clap()
-        .set_term_width(if let Some((Width(w), _)) = terminal_size() {
-            w as usize
+    /// Arguments without the 'hdk' subcommand name passed in by cargo.
+    fn hdk_args(&self) -> &[String] {
+        if self.build_args.first().map(|x| x.as_str()) == Some("hdk") {
+            &self.build_args[1..]
         } else {
-            80
-        })
-        .setting(AppSettings::AllowLeadingHyphen);
+            self.build_args.as_slice()
+        }
+    }
 
-    let opts = Opt::from_clap(&app.get_matches());
-    init_logging(opts.verbose.log_level());
+    /// The toolchain given with a leading '+<toolchain>' argument.
+    fn toolchain_arg(&self) -> Option<&str> {
+        self.hdk_args()
+            .first()
+            .and_then(|arg| arg.strip_prefix('+'))
+            .filter(|toolchain| !toolchain.is_empty())
+    }
 
-    // Remember current working directory.
-    let orig_cur_dir = env::current_dir().context("Failed to get current directory")?;
-    info!("Looking for a parent directory containing the `Cargo.toml` manifest file.");
+    /// The Rust toolchain given either as a cargo-hdk option or a leading '+<toolchain>' argument.
+    fn rust_toolchain(&self) -> Option<&str> {
+        self.rust_toolchain
+            .as_deref()
+            .or_else(|| self.toolchain_arg())
+    }
 
-    let metadata = MetadataCommand::new().exec()?;
-    let package = metadata
-        .root_package()
-        .context("Failed to find crate root")?;
+    /// Returns true if the build should only be validated using 'cargo hdk check'.
+    fn check(&self) -> bool {
+        self.command_args().first().map(|x| x.as_str()) == Some("check")
+    }
 
-    info!("Looking for a Houdini installation.");
+    /// Returns true if Houdini should be launched after the build using 'cargo hdk run'.
+    fn run(&self) -> bool {
+        self.command_args().first().map(|x| x.as_str()) == Some("run")
+    }
 
-    let hfs = env::var("HFS").ok().or_else(|| {
-        // Try some typical installation paths:
-        for version in &["18.5", "18.0", "17.5", "17.0"] {
-            let hfs_path = format!("/opt/hfs{}", version);
-            info!("Using Houdini installation path {:?}", hfs_path);
-            if Path::new(&hfs_path).exists() {
-                return Some(hfs_path);
-            }
-        }
-        None
-    }).context("Couldn't find HFS. Please source 'houdini_setup' from houdini's installation directory or set the 'HFS' environment variable to the Houdini installation path.")?;
+    /// Returns true if Houdini should be launched under a debugger after the build using
+    /// 'cargo hdk debug'.
+    fn debug(&self) -> bool {
+        self.command_args().first().map(|x| x.as_str()) == Some("debug")
+    }
 
-    env::set_var("HFS", &hfs);
-    // Set the path variable to include hfs bin directory.
-    // This is needed in case hserver needs to verify the license during a build.
-    if let Some(path) = env::var_os("PATH") {
-        let mut paths = env::split_paths(&path).collect::<Vec<_>>();
-        paths.push(PathBuf::from(&hfs).join("bin"));
-        let new_path = env::join_paths(paths)?;
-        env::set_var("PATH", &new_path);
+    /// Returns true if the tests of the plugin should be run after the build using
+    /// 'cargo hdk test'.
+    fn test(&self) -> bool {
+        self.command_args().first().map(|x| x.as_str()) == Some("test")
     }
 
-    debug!("Determining build type.");
+    /// Returns true if the coverage of the tests should be measured using 'cargo hdk cov'.
+    fn cov(&self) -> bool {
+        self.command_args().first().map(|x| x.as_str()) == Some("cov")
+    }
 
-    let build_type = opts
-        .build_args
-        .iter()
-        .find(|&x| x == "--release")
-        .map(|_| "Release")
-        .unwrap_or_else(|| "Debug");
+    /// Returns true if the cook times of the plugin should be measured after the build using
+    /// 'cargo hdk bench'.
+    fn bench(&self) -> bool {
+        self.command_args().first().map(|x| x.as_str()) == Some("bench")
+    }
 
-    let build_dir = package
-        .manifest_path
-        .parent()
-        .context("Failed to find manifest directory")?
-        .join(&opts.hdk_path)
-        .join(&format!("build_{}", build_type.to_lowercase()));
+    /// Returns true if the plugin should be rebuilt on changes using 'cargo hdk watch'.
+    fn watch(&self) -> bool {
+        self.command_args().first().map(|x| x.as_str()) == Some("watch")
+    }
 
-    // Do the CMake clean
+    /// Returns true if the built plugin should be loaded and checked using 'cargo hdk verify'.
+    fn verify(&self) -> bool {
+        self.command_args().first().map(|x| x.as_str()) == Some("verify")
+    }
 
-    if opts.clean {
-        // Clean the build artifacts.
-        if let Err(e) = fs::remove_dir_all(&build_dir) {
-            warn!("Failed to remove {}: {}", build_dir, e);
-        }
+    /// Returns true if the size of the built plugin should be broken down using 'cargo hdk size'.
+    fn size(&self) -> bool {
+        self.command_args().first().map(|x| x.as_str()) == Some("size")
+    }
 
-        return Ok(());
-    } else {
-        debug!("Creating the build directory: {:?}.", build_dir);
+    /// Returns true if the generated headers should be copied into the HDK plugin directory using
+    /// 'cargo hdk bindgen'.
+    fn bindgen(&self) -> bool {
+        self.command_args().first().map(|x| x.as_str()) == Some("bindgen")
+    }
 
-        // Create build directory if it doesn't exist
-        match fs::create_dir(&build_dir) {
-            Err(err) if err.kind() != std::io::ErrorKind::AlreadyExists => {
-                bail!("Failed to create build directory: {:?}", &build_dir);
-            }
-            _ => {}
-        }
+    /// Returns true if Rust FFI should be generated from HDK headers using
+    /// 'cargo hdk rust-bindings'.
+    fn rust_bindings(&self) -> bool {
+        self.command_args().first().map(|x| x.as_str()) == Some("rust-bindings")
     }
 
-    // Do the Cargo build/clean
+    /// Returns true if the project should be upgraded to the current conventions using
+    /// 'cargo hdk migrate'.
+    fn migrate(&self) -> bool {
+        self.command_args().first().map(|x| x.as_str()) == Some("migrate")
+    }
 
-    // Cargo build with a custom target directory set to the cmake build directory.
-    if !opts.hdk_only {
-        // Cache the out_dir in a file so that the C++ code can be built without running cargo later.
-        let out_dir_deps = cargo_build(&opts, &package)?;
-        for (dep, out_dir) in out_dir_deps {
-            use std::io::Write;
-            let out_dir_path = build_dir.join(format!("{}{}.txt", &opts.out_dir_file_prefix, dep));
-            // Build directory structure for out_dir_path.
-            let out_dir_path_dir = out_dir_path
-                .parent()
-                .expect(&format!("Invalid 'OUT_DIR' path: {}", out_dir_path));
-            if !out_dir_path_dir.exists() {
-                fs::create_dir_all(out_dir_path_dir).expect(&format!(
-                    "Failed to create 'OUT_DIR' path directory: {}",
-                    out_dir_path_dir
-                ));
-            }
+    /// Returns true if stale build state should be removed using 'cargo hdk prune'.
+    fn prune(&self) -> bool {
+        self.command_args().first().map(|x| x.as_str()) == Some("prune")
+    }
 
-            let mut out_dir_file = fs::File::create(out_dir_path.clone()).context(format!(
-                "Failed to create the OUT_DIR file: {}",
-                out_dir_path
-            ))?;
-            write!(out_dir_file, "{}", out_dir)?;
-            // Close the file at the end of the scope.
-        }
+    /// Returns true if digital assets should be expanded or collapsed using 'cargo hdk hda'.
+    fn hda(&self) -> bool {
+        self.command_args().first().map(|x| x.as_str()) == Some("hda")
     }
 
-    if opts.clean {
-        return Ok(());
+    /// Returns true if the C++ sources should be analyzed with clang-tidy using 'cargo hdk lint'.
+    fn lint(&self) -> bool {
+        self.command_args().first().map(|x| x.as_str()) == Some("lint")
     }
 
-    // Do the CMake build
+    /// Returns true if the C++ sources should be formatted with clang-format using
+    /// 'cargo hdk fmt-cpp'.
+    fn fmt_cpp(&self) -> bool {
+        self.command_args().first().map(|x| x.as_str()) == Some("fmt-cpp")
+    }
 
-    env::set_current_dir(&build_dir)
-        .with_context(|| format!("Failed to set current directory: {:?}", &build_dir))?;
+    /// Returns true if the Rust code should only be checked without building the HDK plugin, as
+    /// for 'cargo hdk check' and 'cargo hdk lint'.
+    fn check_only(&self) -> bool {
+        self.check() || self.lint()
+    }
 
-    debug!("Parsing cmake args.");
+    /// Returns true if IDE configurations should be generated using 'cargo hdk ide'.
+    fn ide(&self) -> bool {
+        self.command_args().first().map(|x| x.as_str()) == Some("ide")
+    }
 
-    let mut cmake_args = Vec::new();
-    if !opts.cmake.is_empty() {
-        // Strip square brackets if they are there for backwards compatibility.
-        let arg_str = if opts.cmake.starts_with('[') && opts.cmake.ends_with(']') {
-            &opts.cmake[1..opts.cmake.len() - 1]
-        } else {
-            &opts.cmake[..]
-        };
+    /// Returns true if a shell with the Houdini environment should be started using
+    /// 'cargo hdk shell'.
+    fn shell(&self) -> bool {
+        self.command_args().first().map(|x| x.as_str()) == Some("shell")
+    }
 
-        // Quick and dirty quoation sensitive parser
-        let mut cur_arg = String::new();
-        let mut in_quoted_text = None;
-        for c in arg_str.chars() {
-            if let Some(quotation_char) = in_quoted_text {
-                if c == quotation_char {
-                    // End quoted text
-                    in_quoted_text = None;
-                    continue; // Consume the qutation mark
-                }
-            } else if c == '\"' || c == '\'' {
-                in_quoted_text = Some(c);
-                continue;
-            } else if c.is_whitespace() {
-                if !cmake_args.is_empty() {
-                    let finished_arg = std::mem::replace(&mut cur_arg, String::new());
-                    cmake_args.push(finished_arg);
-                }
-                continue;
+    /// Move the arguments starting at the scene file or script of 'cargo hdk run', or the test
+    /// scripts of 'cargo hdk test', out of the build arguments.
+    fn split_app_args(&mut self) {
+        if self.run() || self.debug() || self.test() || self.cov() {
+            if let Some(start) = launch::app_args_start(&self.build_args) {
+                self.app_args = self.build_args.split_off(start);
             }
-            cur_arg.push(c);
-        }
-        // Push any remainder arg to cmake_args.
-        if !cur_arg.is_empty() {
-            cmake_args.push(cur_arg);
         }
     }
 
-    info!("Configuring CMake.");
-
-    Command::new("cmake")
-        .arg("..")
-        .args(&cmake_args)
-        .arg(&format!("-DCMAKE_BUILD_TYPE={}", build_type))
-        .status()
-        .context("Failed to configure CMake.")?;
-
-    info!("Building the C/C++ HDK plugin.");
+    /// Read the options from the environment variables clap can't handle: the level of
+    /// '--cmake-verbose', which clap only counts on the command line, and the prefix of
+    /// '--cmake-install', which is empty for the default prefix.
+    ///
+    /// The options requiring '--cmake-install' are checked here, since it may be set this way.
+    fn read_env(&mut self) -> Result<()> {
+        if self.cmake_verbose == 0 {
+            if let Some(value) = env::var_os("CARGO_HDK_CMAKE_VERBOSE") {
+                self.cmake_verbose = match value.to_string_lossy().trim() {
+                    "" | "false" => 0,
+                    "true" => 1,
+                    level => level.parse().map_err(|_| {
+                        anyhow!(
+                            "Invalid value '{}' of CARGO_HDK_CMAKE_VERBOSE: expected a level from 0 to 3, 'true' or 'false'",
+                            level
+                        )
+                    })?,
+                };
+            }
+        }
+        if self.cmake_install.is_none() {
+            if let Some(prefix) = env::var_os("CARGO_HDK_CMAKE_INSTALL") {
+                self.cmake_install = Some((!prefix.is_empty()).then(|| PathBuf::from(prefix)));
+            }
+        }
+        for (option, set) in [
+            ("--strip", self.strip),
+            ("--split-debuginfo", self.split_debuginfo),
+        ] {
+            if set && self.cmake_install.is_none() {
+                bail!("The argument '{}' requires '--cmake-install'", option);
+            }
+        }
+        Ok(())
+    }
+
+    /// Convert the POSIX paths of an MSYS2 or Cygwin shell in the options, the cargo arguments and
+    /// the arguments of the launched application to native Windows paths.
+    fn normalize_paths(&mut self) {
+        let paths = [
+            &mut self.manifest_path,
+            &mut self.symbols_dir,
+            &mut self.out_dir_cmake,
+            &mut self.rust_targets_cmake,
+            &mut self.artifacts_cmake,
+            &mut self.artifact_json,
+        ];
+        for path in IntoIterator::into_iter(paths).flatten() {
+            msys::normalize_path(path);
+        }
+        if let Some(Some(prefix)) = &mut self.cmake_install {
+            msys::normalize_path(prefix);
+        }
+        msys::normalize_path(&mut self.hdk_path);
+        msys::normalize_args(&mut self.build_args);
+        for arg in &mut self.app_args {
+            if let Some(native) = msys::native_path(arg) {
+                *arg = native;
+            }
+        }
+    }
+
+    /// Returns true if a new plugin crate should be generated using 'cargo hdk new'.
+    fn new_project(&self) -> bool {
+        self.command_args().first().map(|x| x.as_str()) == Some("new")
+    }
+
+    /// Returns true if a shell completion script should be printed using 'cargo hdk completions'.
+    fn completions(&self) -> bool {
+        self.command_args().first().map(|x| x.as_str()) == Some("completions")
+    }
+
+    /// Returns true if the build environment should be checked using 'cargo hdk doctor'.
+    fn doctor(&self) -> bool {
+        self.command_args().first().map(|x| x.as_str()) == Some("doctor")
+    }
+
+    /// Returns true if the resolved build environment should be printed using 'cargo hdk env'.
+    fn env(&self) -> bool {
+        self.command_args().first().map(|x| x.as_str()) == Some("env")
+    }
+
+    /// Returns true if the effective configuration should be printed using 'cargo hdk config'.
+    fn show_config(&self) -> bool {
+        self.command_args().first().map(|x| x.as_str()) == Some("config")
+    }
+
+    /// The manifest path given either as a cargo-hdk option or in the cargo arguments.
+    fn manifest_path(&self) -> Option<PathBuf> {
+        self.manifest_path
+            .clone()
+            .or_else(|| find_arg_value(self.cargo_args(), "--manifest-path").map(PathBuf::from))
+    }
+
+    /// The package given either as a cargo-hdk option or in the cargo arguments.
+    fn package(&self) -> Option<&str> {
+        if let Some(package) = &self.package {
+            return Some(package);
+        }
+        let args = self.cargo_args();
+        find_arg_value(args, "--package")
+            .or_else(|| find_arg_value(args, "-p"))
+            .or_else(|| {
+                // Cargo also accepts the short form without a separator (e.g. '-pfoo').
+                args.iter()
+                    .find_map(|arg| arg.strip_prefix("-p").filter(|rest| !rest.is_empty()))
+            })
+    }
+
+    /// Use the given configuration for all options that were not given on the command line, and
+    /// append the configured cargo arguments.
+    fn apply_config(&mut self, config: &Config, matches: &ArgMatches) {
+        self.default_deps = matches.value_source("deps") == Some(ValueSource::DefaultValue)
+            && config.deps.is_none();
+        macro_rules! apply {
+            ($($field:ident),*) => {
+                $(
+                    // Arguments are identified by their long name.
+                    let id = stringify!($field).replace('_', "-");
+                    if !matches!(
+                        matches.value_source(&id),
+                        Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+                    ) {
+                        if let Some(value) = &config.$field {
+                            self.$field = value.clone().into();
+                        }
+                    }
+                )*
+            };
+        }
+        apply!(
+            hdk_path,
+            build_dir,
+            cmake,
+            generator,
+            cache,
+            deps,
+            deps_of,
+            out_dir_file_prefix,
+            out_dir_cmake,
+            rust_targets_cmake,
+            artifacts_cmake,
+            cc,
+            cxx,
+            container
+        );
+        if let Some(cargo_args) = &config.cargo_args {
+            self.build_args.extend(cargo_args.iter().cloned());
+        }
+    }
+
+    /// The configuration with the options resolved from the command line, environment variables
+    /// and configuration files.
+    fn effective_config(&self, config: &Config) -> Config {
+        let mut config = config.clone();
+        macro_rules! effective {
+            ($($field:ident),*) => {
+                $( config.$field = self.$field.clone().into(); )*
+            };
+        }
+        effective!(
+            hdk_path,
+            build_dir,
+            cmake,
+            generator,
+            cache,
+            deps,
+            deps_of,
+            out_dir_file_prefix,
+            out_dir_cmake,
+            rust_targets_cmake,
+            artifacts_cmake,
+            cc,
+            cxx,
+            container
+        );
+        if let Some(hfs) = env::var_os("HFS") {
+            config.hfs = Some(hfs.into());
+        }
+        // The selected profile is already merged in.
+        config.profiles.clear();
+        config
+    }
+
+    /// Additional cargo arguments implied by cargo-hdk options.
+    fn extra_cargo_args(&self) -> Vec<&std::ffi::OsStr> {
+        let mut args = Vec::new();
+        if let Some(path) = &self.manifest_path {
+            args.push("--manifest-path".as_ref());
+            args.push(path.as_os_str());
+        }
+        if let Some(package) = &self.package {
+            args.push("--package".as_ref());
+            args.push(package.as_ref());
+        }
+        if self.offline {
+            args.push("--offline".as_ref());
+        }
+        if (self.minimal_size || self.bench()) && !has_profile_arg(self.cargo_args()) {
+            args.push("--release".as_ref());
+        }
+        args
+    }
+
+    /// The number of parallel jobs given either as a cargo-hdk option, in the cargo arguments or
+    /// with cargo's 'CARGO_BUILD_JOBS'.
+    fn jobs(&self) -> Option<std::num::NonZeroU32> {
+        self.jobs.or_else(|| {
+            let args = self.cargo_args();
+            find_arg_value(args, "--jobs")
+                .or_else(|| find_arg_value(args, "-j"))
+                .or_else(|| {
+                    // Cargo also accepts the short form without a separator (e.g. '-j8').
+                    args.iter()
+                        .find_map(|arg| arg.strip_prefix("-j").filter(|rest| !rest.is_empty()))
+                })
+                .and_then(|jobs| jobs.parse().ok())
+                .or_else(|| env::var("CARGO_BUILD_JOBS").ok()?.parse().ok())
+        })
+    }
+
+    /// Returns true if the network must not be accessed, following the cargo-hdk option as well
+    /// as the cargo arguments and configuration.
+    fn offline(&self) -> bool {
+        self.offline
+            || self
+                .cargo_args()
+                .iter()
+                .any(|arg| arg == "--offline" || arg == "--frozen")
+            || env::var("CARGO_NET_OFFLINE").ok().as_deref() == Some("true")
+    }
+}
+
+pub fn init_logging(level: Option<log::Level>) {
+    if let Some(level) = level {
+        let mut builder = env_logger::Builder::new();
+        builder.filter(None, level.to_level_filter());
+        builder.format_timestamp(None).format_module_path(false);
+        builder.init();
+    }
+}
+
+/// Print a warning the user should act on, like cargo's 'warning: unused manifest key', which is
+/// shown at the default verbosity, unlike the logged warnings. With '-v' it is logged along with
+/// the other messages instead, and '--quiet' hides it.
+pub fn print_warning(message: impl std::fmt::Display) {
+    use colored::Colorize;
+    use std::io::IsTerminal;
+    if log_enabled!(Level::Warn) {
+        warn!("{}", message);
+    } else if log::max_level() != LevelFilter::Off {
+        let label = if std::io::stderr().is_terminal() {
+            "warning:".yellow().bold().to_string()
+        } else {
+            "warning:".to_string()
+        };
+        progress::suspend(|| eprintln!("{} {}", label, message));
+    }
+}
 
-    Command::new("cmake")
-        .arg("--build")
-        .arg(".")
+/// Outputs of the cargo build step needed to generate files for CMake.
+#[derive(Debug, Default)]
+struct CargoOutput {
+    /// Output directories ('OUT_DIR') of the crate being built and the requested dependencies.
+    out_dirs: Vec<(String, Utf8PathBuf)>,
+    /// Artifacts compiled by cargo in the order they were reported.
+    artifacts: Vec<Artifact>,
+}
+
+impl CargoOutput {
+    /// Returns the files produced for the library target of the given package.
+    fn lib_filenames(&self, package: &Package) -> Vec<Utf8PathBuf> {
+        self.artifacts
+            .iter()
+            .filter(|a| is_library(a, package))
+            .flat_map(|a| a.filenames.iter().cloned())
+            .collect()
+    }
+
+    /// Replace the libraries of the given package with universal libraries in `target_dir`,
+    /// merged with the libraries of the same name in `others`, built for the other architectures.
+    fn merge_universal(
+        &mut self,
+        others: &[CargoOutput],
+        package: &Package,
+        target_dir: &Utf8Path,
+        profile: &str,
+    ) -> Result<()> {
+        let other_libs: Vec<_> = others
+            .iter()
+            .flat_map(|output| output.lib_filenames(package))
+            .collect();
+        for artifact in self.artifacts.iter_mut() {
+            if !is_library(artifact, package) {
+                continue;
+            }
+            for filename in &mut artifact.filenames {
+                let name = filename.file_name().unwrap_or_default().to_string();
+                let mut libs = vec![filename.as_path()];
+                libs.extend(
+                    other_libs
+                        .iter()
+                        .filter(|lib| lib.file_name() == Some(name.as_str()))
+                        .map(|lib| lib.as_path()),
+                );
+                if libs.len() != universal::TARGETS.len() {
+                    bail!(
+                        "{} wasn't built for all targets of the universal build",
+                        name
+                    );
+                }
+                let merged = universal::merged_path(target_dir, profile, &name);
+                universal::merge(&libs, &merged)?;
+                *filename = merged;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns true if the artifact is a library of the given package the HDK plugin can link.
+fn is_library(artifact: &Artifact, package: &Package) -> bool {
+    artifact.package_id == package.id
+        && artifact
+            .target
+            .kind
+            .iter()
+            .any(|k| k == "staticlib" || k == "cdylib")
+}
+
+// Create a cargo command using the requested Rust toolchain, if any.
+fn cargo_command(opts: &Opt) -> Result<Command> {
+    match opts.rust_toolchain() {
+        Some(toolchain) => {
+            let rustup = tools::find_program("rustup").with_context(|| {
+                format!(
+                    "Rust toolchain '{}' was requested but rustup was not found on the 'PATH'.",
+                    toolchain
+                )
+            })?;
+            debug!("Running cargo with toolchain {}", toolchain);
+            let mut cmd = Command::new(rustup);
+            cmd.args(["run", toolchain, "cargo"]);
+            Ok(cmd)
+        }
+        None => Ok(Command::new(env!("CARGO"))),
+    }
+}
+
+// Run the cargo clean command.
+fn cargo_clean(opts: &Opt) -> Result<()> {
+    info!("Cleaning Rust code using cargo.");
+
+    let status = cargo_command(opts)?
+        .arg("clean")
+        .args(opts.extra_cargo_args())
+        .args(opts.cargo_args())
         .status()
-        .context("Failed to build HDK plugin.")?;
+        .context("Cargo clean failed")?;
+
+    if !status.success() {
+        return Err(anyhow!("Rust clean failed"));
+    }
+    Ok(())
+}
+
+// Run the cargo build command and return the output directories to cache for each dependency
+// (including the crate being compiled) along with the compiled artifacts.
+//
+// The progress bar counts the crates compiled out of the given number of crates.
+fn cargo_build(
+    opts: &Opt,
+    package: &Package,
+    deps: &HashMap<PackageId, String>,
+    jobserver: Option<&jobserver::Client>,
+    target: Option<&str>,
+    crates: u64,
+) -> Result<CargoOutput> {
+    let (command, phase) = if opts.check_only() {
+        info!("Checking Rust code using cargo.");
+        ("check", progress::Phase::bar("Checking", crates))
+    } else {
+        info!("Building Rust code using cargo.");
+        ("build", progress::Phase::bar("Compiling", crates))
+    };
+
+    let build_args = opts.cargo_args();
+
+    // First build the crate with the standard build args.
+    let message_format = if use_color(build_args) {
+        "--message-format=json-diagnostic-rendered-ansi"
+    } else {
+        "--message-format=json"
+    };
+    let mut cmd = cargo_command(opts)?;
+    if let Some(jobserver) = jobserver {
+        jobserver.configure_make(&mut cmd);
+    }
+    if phase.is_shown() {
+        // Cargo's own status lines and progress bar would be interleaved with ours.
+        cmd.arg("--quiet").env("CARGO_TERM_PROGRESS_WHEN", "never");
+    }
+    let mut child = cmd
+        .args([command, message_format])
+        .args(opts.extra_cargo_args())
+        // The jobserver already limits the number of jobs.
+        .args(
+            opts.jobs
+                .filter(|_| jobserver.is_none())
+                .map(|jobs| format!("--jobs={}", jobs)),
+        )
+        .args(build_args)
+        .args(target.map(|target| format!("--target={}", target)))
+        .stderr(Stdio::inherit())
+        .stdout(Stdio::piped())
+        .spawn()
+        .context("Cargo build failed")?;
+
+    // Process messages as they arrive instead of waiting for the build to finish.
+    let stdout = child.stdout.take().expect("Cargo stdout is piped");
+    let reader = std::io::BufReader::new(stdout);
+    let mut output = CargoOutput::default();
+    for message in Message::parse_stream(reader) {
+        match message.context("Failed to read cargo output")? {
+            Message::CompilerArtifact(artifact) => {
+                if !artifact.target.kind.iter().any(|k| k == "custom-build") {
+                    phase.step(&artifact.target.name);
+                    info!(
+                        "{} {} ({})",
+                        if artifact.fresh { "Fresh" } else { "Compiled" },
+                        &artifact.target.name,
+                        artifact.target.kind.join(", ")
+                    );
+                }
+                output.artifacts.push(artifact);
+            }
+            Message::CompilerMessage(msg) => {
+                // Cargo doesn't print diagnostics itself when emitting JSON messages.
+                if let Some(rendered) = &msg.message.rendered {
+                    phase.suspend(|| eprint!("{}", rendered));
+                }
+            }
+            Message::TextLine(line) => phase.suspend(|| println!("{}", line)),
+            Message::BuildFinished(finished) => {
+                debug!("Cargo build finished (success: {})", finished.success);
+            }
+            Message::BuildScriptExecuted(script) => {
+                trace!(
+                    "Checking if a build script package id {} is {}",
+                    &script.package_id.repr,
+                    &package.id
+                );
+                if script.package_id == package.id {
+                    output
+                        .out_dirs
+                        .push((package.name.clone(), script.out_dir.clone()));
+                    continue;
+                }
+                if let Some(dep) = deps.get(&script.package_id) {
+                    trace!("Found build script output of {}", &script.package_id.repr);
+                    output.out_dirs.push((dep.clone(), script.out_dir.clone()));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let status = child.wait().context("Cargo build failed")?;
+    if !status.success() {
+        phase.abandon();
+        return Err(anyhow!("Rust build failed"));
+    }
+    phase.finish();
+
+    Ok(output)
+}
+
+// Determine whether compiler diagnostics should be colored, following the '--color' cargo flag
+// and the 'CARGO_TERM_COLOR' environment variable like cargo does.
+fn use_color(build_args: &[String]) -> bool {
+    use std::io::IsTerminal;
+    let choice = find_arg_value(build_args, "--color")
+        .map(String::from)
+        .or_else(|| env::var("CARGO_TERM_COLOR").ok());
+    match choice.as_deref() {
+        Some("always") => true,
+        Some("never") => false,
+        _ => std::io::stderr().is_terminal(),
+    }
+}
+
+// Additional arguments passed to the CMake configure step for the given '--cmake-verbose' count.
+fn cmake_configure_verbosity_args(level: u8) -> &'static [&'static str] {
+    match level {
+        0 | 1 => &[],
+        2 => &["--debug-output"],
+        _ => &["--debug-output", "--trace-expand"],
+    }
+}
 
-    env::set_current_dir(&orig_cur_dir)
-        .with_context(|| format!("Failed to reset current directory: {:?}", &orig_cur_dir))?;
+// Select the workspace member to build.
+//
+// If no package is requested explicitly, the root package is used. In a virtual workspace, the
+// member containing the current directory is used, or the only member if there is just one.
+fn select_package<'m>(metadata: &'m Metadata, requested: Option<&str>) -> Result<&'m Package> {
+    let members: Vec<&Package> = metadata
+        .workspace_members
+        .iter()
+        .map(|id| &metadata[id])
+        .collect();
+    let member_names = || {
+        members
+            .iter()
+            .map(|pkg| pkg.name.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+
+    if let Some(requested) = requested {
+        let name = requested.split('@').next().unwrap_or(requested);
+        return members
+            .iter()
+            .copied()
+            .find(|pkg| pkg.name == name)
+            .with_context(|| {
+                format!(
+                    "Package '{}' is not a member of the workspace. Available members: {}",
+                    requested,
+                    member_names()
+                )
+            });
+    }
+
+    if let Some(root) = metadata.root_package() {
+        return Ok(root);
+    }
+
+    if let [member] = members.as_slice() {
+        return Ok(member);
+    }
+
+    // Like cargo, use the member whose directory contains the current directory.
+    let cur_dir = env::current_dir().context("Failed to get current directory")?;
+    members
+        .iter()
+        .copied()
+        .find(|pkg| {
+            pkg.manifest_path
+                .parent()
+                .into_iter()
+                .any(|dir| cur_dir.starts_with(dir))
+        })
+        .with_context(|| {
+            format!(
+                "Multiple packages found in the workspace; use '--package' to select one of: {}",
+                member_names()
+            )
+        })
+}
+
+// Check that the MSVC toolset CMake selected for the build directory matches the Visual Studio
+// version the Houdini installation at `hfs` is built with.
+//
+// Objects built with another toolset link against Houdini's libraries, but differences in the
+// layout of standard library types crash at runtime, far from the cause. Other compilers aren't
+// checked here.
+fn check_msvc_toolset(build_dir: &Path, hfs: &str, allow_mismatch: bool) -> Result<()> {
+    let compiler = match cmake::cxx_compiler(build_dir) {
+        Some(compiler) => compiler,
+        None => return Ok(()),
+    };
+    let version = match compiler.msvc_version() {
+        Some(version) => version,
+        None => return Ok(()),
+    };
+    let houdini = match houdini::version(Path::new(hfs)) {
+        Ok(houdini) => houdini,
+        Err(_) => return Ok(()),
+    };
+    let (_, vs_versions) = houdini::compilers(&houdini);
+    let mut parts = version
+        .split('.')
+        .map(|part| part.parse::<u32>().unwrap_or(0));
+    let (major, minor) = (parts.next().unwrap_or(0), parts.next().unwrap_or(0));
+    let vs = houdini::visual_studio(major, minor);
+    if matches!(vs, Some(vs) if vs_versions.contains(&vs)) {
+        debug!("The MSVC toolset {} matches Houdini {}", version, houdini);
+        return Ok(());
+    }
+    let toolsets: Vec<_> = vs_versions
+        .iter()
+        .map(|vs| match *vs {
+            "2017" => "v141",
+            "2019" => "v142",
+            _ => "v143",
+        })
+        .collect();
+    let message = format!(
+        "The MSVC toolset {} ({}) selected by CMake doesn't match Houdini {}, which is built with Visual Studio {}. Plugins built with another toolset may crash from ABI differences.",
+        version,
+        match vs {
+            Some(vs) => format!("Visual Studio {}", vs),
+            None => "an unknown Visual Studio version".to_string(),
+        },
+        houdini,
+        vs_versions.join(" or ")
+    );
+    if allow_mismatch {
+        warn!("{}", message);
+        return Ok(());
+    }
+    bail!(
+        "{}\nSelect a matching toolset, e.g. with '--cmake \"-T {}\"' for a Visual Studio generator or from a matching developer command prompt, and reconfigure with '--clean'. Pass '--allow-compiler-mismatch' to build anyway.",
+        message,
+        toolsets.join(" or -T ")
+    );
+}
+
+// Check on Linux that the C++ compiler CMake selected for the build directory is a GCC version
+// Houdini is built with or, for clang, uses the libstdc++ of one.
+//
+// Plugins built with a newer GCC need a newer libstdc++ than Houdini and the machines it runs on
+// may have, and fail to load with undefined symbols, while older ones may lack C++ features the
+// HDK headers use.
+fn check_gcc_version(build_dir: &Path, hfs: &str, allow_mismatch: bool) -> Result<()> {
+    if !cfg!(target_os = "linux") {
+        return Ok(());
+    }
+    let compiler = match cmake::cxx_compiler(build_dir) {
+        Some(compiler) => compiler,
+        None => return Ok(()),
+    };
+    let major = match compiler.gcc_major() {
+        Some(major) => major,
+        None => return Ok(()),
+    };
+    let houdini = match houdini::version(Path::new(hfs)) {
+        Ok(houdini) => houdini,
+        Err(_) => return Ok(()),
+    };
+    let (gcc_majors, _) = houdini::compilers(&houdini);
+    if gcc_majors.contains(&major) {
+        debug!("GCC {} matches Houdini {}", major, houdini);
+        return Ok(());
+    }
+    let expected = gcc_majors.last().copied().unwrap_or_default();
+    let gcc_versions = gcc_majors
+        .iter()
+        .map(|major| major.to_string())
+        .collect::<Vec<_>>()
+        .join(" or ");
+    let (selected, hint) = if compiler.id == "Clang" {
+        (
+            format!("Clang {} uses the libstdc++ of GCC {}, but", compiler.version, major),
+            format!(
+                "Install GCC {} and select its libstdc++ with '--gcc-install-dir=/usr/lib/gcc/<triple>/{}' in 'CXXFLAGS'",
+                expected, expected
+            ),
+        )
+    } else {
+        (
+            format!("The C++ compiler selected by CMake is GCC {}, but", compiler.version),
+            format!(
+                "Install GCC {} and select it with 'CXX=g++-{}' or '--cmake \"-DCMAKE_CXX_COMPILER=g++-{}\"'",
+                expected, expected, expected
+            ),
+        )
+    };
+    let message = format!(
+        "{} Houdini {} is built with GCC {}. Plugins built with another GCC version may fail to load with undefined symbols from a newer libstdc++, or miss C++ features the HDK uses.",
+        selected, houdini, gcc_versions
+    );
+    if allow_mismatch {
+        warn!("{}", message);
+        return Ok(());
+    }
+    bail!(
+        "{}\n{}, and reconfigure with '--clean'. Pass '--allow-compiler-mismatch' to build anyway.",
+        message,
+        hint
+    );
+}
+
+// Warn if the library of the given package can't be linked by the HDK plugin.
+//
+// Only 'staticlib' and 'cdylib' libraries can be linked from C++, and a missing crate type
+// otherwise only surfaces as a confusing link failure in the CMake build.
+fn check_crate_types(package: &Package) {
+    const LIB_KINDS: &[&str] = &["lib", "rlib", "dylib", "cdylib", "staticlib", "proc-macro"];
+    let lib = package
+        .targets
+        .iter()
+        .find(|t| t.kind.iter().any(|k| LIB_KINDS.contains(&k.as_str())));
+    let lib = match lib {
+        Some(lib) => lib,
+        None => {
+            print_warning(format_args!(
+                "package '{}' has no library target for the HDK plugin to link. \
+                 Add a '[lib]' section with 'crate-type = [\"staticlib\"]' to {}.",
+                package.name, package.manifest_path
+            ));
+            return;
+        }
+    };
+
+    let has = |kind: &str| lib.crate_types.iter().any(|k| k == kind);
+    match (has("staticlib"), has("cdylib")) {
+        (true, true) => debug!(
+            "Library '{}' is built both as a static and a shared library; \
+             the static library is preferred for linking",
+            lib.name
+        ),
+        (true, false) => debug!("Library '{}' is built as a static library", lib.name),
+        (false, true) => debug!("Library '{}' is built as a shared library", lib.name),
+        (false, false) => print_warning(format_args!(
+            "library '{}' is built as '{}', which can't be linked by the HDK plugin. \
+             Add 'crate-type = [\"staticlib\"]' (or '\"cdylib\"') to the '[lib]' section of {}.",
+            lib.name,
+            lib.crate_types.join("', '"),
+            package.manifest_path
+        )),
+    }
+}
+
+// Print the effective configuration for 'cargo hdk config'.
+fn print_config(
+    opts: &Opt,
+    config: &Config,
+    layers: &[(config::Source, Config)],
+    matches: &ArgMatches,
+) -> Result<()> {
+    let app = Opt::clap();
+    config::print(&opts.effective_config(config), layers, |key| {
+        if key == "hfs" && env::var_os("HFS").is_some() {
+            return Some("environment variable HFS".to_string());
+        }
+        // Default values are reported as such unless a configuration layer sets the value.
+        let id = key.replace('_', "-");
+        let arg = app.get_arguments().find(|arg| arg.get_id() == id)?;
+        match matches.value_source(&id)? {
+            ValueSource::CommandLine => Some(format!("command line option --{}", id)),
+            ValueSource::EnvVariable => Some(format!(
+                "environment variable {}",
+                arg.get_env()?.to_string_lossy()
+            )),
+            _ => None,
+        }
+    })
+}
+
+// Returns true if the given cargo arguments select a profile.
+fn has_profile_arg(args: &[String]) -> bool {
+    find_arg_value(args, "--profile").is_some() || args.iter().any(|arg| arg == "--release")
+}
+
+// Configure cargo to optimize the given profile for size using environment variables, so the
+// settings also apply when cargo is run by Corrosion.
+fn configure_minimal_size(opts: &Opt, profile: &str, target: Option<&str>) -> Result<()> {
+    let prefix = format!(
+        "CARGO_PROFILE_{}_",
+        profile.to_uppercase().replace('-', "_")
+    );
+    for (key, value) in &[
+        ("OPT_LEVEL", "z"),
+        ("PANIC", "abort"),
+        ("LTO", "fat"),
+        ("CODEGEN_UNITS", "1"),
+        ("STRIP", "symbols"),
+    ] {
+        env::set_var(format!("{}{}", prefix, key), value);
+    }
+
+    let mut rustc = match opts.rust_toolchain() {
+        Some(toolchain) => {
+            let mut cmd = Command::new("rustup");
+            cmd.args(["run", toolchain, "rustc"]);
+            cmd
+        }
+        None => Command::new("rustc"),
+    };
+    let output = rustc
+        .arg("-vV")
+        .output()
+        .context("Failed to determine the rustc version")?;
+    let version = String::from_utf8_lossy(&output.stdout);
+    if !version.contains("-nightly") {
+        info!(
+            "Not rebuilding the standard library for size since it requires a nightly toolchain."
+        );
+        return Ok(());
+    }
+    env::set_var("CARGO_UNSTABLE_BUILD_STD", "std,panic_abort");
+    // Building the standard library requires an explicit target.
+    if target.is_none() {
+        let host = version
+            .lines()
+            .find_map(|line| line.strip_prefix("host: "))
+            .context("Failed to determine the host target from rustc")?;
+        env::set_var("CARGO_BUILD_TARGET", host);
+    }
+    Ok(())
+}
+
+// The target selected by '--target' in the given cargo arguments or 'CARGO_BUILD_TARGET'.
+fn cargo_build_target(args: &[String]) -> Option<String> {
+    find_arg_value(args, "--target")
+        .map(String::from)
+        .or_else(|| env::var("CARGO_BUILD_TARGET").ok())
+}
+
+// The cargo profile selected by the given cargo arguments.
+fn cargo_profile(args: &[String]) -> &str {
+    if let Some(profile) = find_arg_value(args, "--profile") {
+        profile
+    } else if args.iter().any(|arg| arg == "--release") {
+        "release"
+    } else {
+        "dev"
+    }
+}
+
+// The name of the directory cargo uses for the artifacts of the given profile.
+fn profile_dir_name(profile: &str) -> &str {
+    match profile {
+        "dev" | "test" => "debug",
+        "bench" => "release",
+        custom => custom,
+    }
+}
+
+// Determine the CMake build type for the given cargo profile.
+//
+// Custom profiles can be mapped to a build type in the package metadata:
+//
+// [package.metadata.hdk.build_types]
+// release-lto = "Release"
+// dev-opt = "RelWithDebInfo"
+fn cmake_build_type(profile: &str, config: &Config) -> String {
+    if let Some(build_type) = config.build_types.get(profile) {
+        return build_type.clone();
+    }
+    match profile {
+        "dev" | "test" => "Debug".to_string(),
+        "release" | "bench" => "Release".to_string(),
+        custom => {
+            info!(
+                "Building the HDK plugin for the custom '{}' profile as Release. \
+                 Set 'package.metadata.hdk.build_types.{}' in 'Cargo.toml' to use another build type.",
+                custom, custom
+            );
+            "Release".to_string()
+        }
+    }
+}
+
+// Find the value of the given option in a list of command line arguments, given either as
+// '<name> <value>' or '<name>=<value>'.
+fn find_arg_value<'a>(args: &'a [String], name: &str) -> Option<&'a str> {
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg == name {
+            return iter.next().map(String::as_str);
+        }
+        if let Some(value) = arg
+            .strip_prefix(name)
+            .and_then(|rest| rest.strip_prefix('='))
+        {
+            return Some(value);
+        }
+    }
+    None
+}
+
+// Determine the target directory cargo builds into.
+//
+// Like cargo, a '--target-dir' argument takes precedence over the 'CARGO_TARGET_DIR' environment
+// variable, and both are relative to the current directory. Otherwise the target directory
+// reported by 'cargo metadata' is used, which also accounts for the 'build.target-dir' config.
+fn cargo_target_dir(args: &[String], metadata: &Metadata) -> Result<Utf8PathBuf> {
+    let dir = match find_arg_value(args, "--target-dir")
+        .map(String::from)
+        .or_else(|| env::var("CARGO_TARGET_DIR").ok())
+    {
+        Some(dir) => dir,
+        None => return Ok(metadata.target_directory.clone()),
+    };
+    let cur_dir = env::current_dir().context("Failed to get current directory")?;
+    Utf8PathBuf::from_path_buf(cur_dir.join(dir))
+        .map_err(|dir| anyhow!("Cargo target directory is not a Unicode path: {:?}", dir))
+}
+
+/// The given path as a Unicode path, for writing it into a configuration or script that needs
+/// one, like those of IDEs.
+fn unicode_path<'a>(path: &'a Path, purpose: &str) -> Result<&'a Utf8Path> {
+    Utf8Path::from_path(path).with_context(|| {
+        format!(
+            "{} is not a Unicode path, which {} requires",
+            path.display(),
+            purpose
+        )
+    })
+}
+
+/// Find the Houdini installation, optionally of the given version.
+///
+/// The installation given by 'HFS' or the configuration is used if it has the requested version,
+/// otherwise the latest matching installation in the default installation locations is used.
+fn find_hfs(config: &Config, version: Option<&str>) -> Option<String> {
+    let has_version = |hfs: &str| match version {
+        Some(version) => matches!(houdini::version(Path::new(hfs)), Ok(v) if v.matches(version)),
+        None => true,
+    };
+    if let Ok(hfs) = env::var("HFS") {
+        if has_version(&hfs) {
+            return Some(hfs);
+        }
+        info!(
+            "Ignoring HFS={:?}, which is not Houdini {}",
+            hfs,
+            version.unwrap_or_default()
+        );
+    }
+    if let Some(hfs) = &config.hfs {
+        let hfs = hfs.to_string_lossy().into_owned();
+        // A configuration written in an MSYS2 or Cygwin shell may use its paths.
+        let hfs = msys::shell()
+            .and_then(|_| msys::native_path(&hfs))
+            .unwrap_or(hfs);
+        if has_version(&hfs) {
+            info!(
+                "Using Houdini installation path {:?} from the configuration",
+                hfs
+            );
+            return Some(hfs);
+        }
+    }
+    // Try the typical installation paths, latest version first.
+    let (_, hfs) = houdini::installations()
+        .into_iter()
+        .rev()
+        .find(|(v, _)| match version {
+            Some(version) => v.matches(version),
+            None => true,
+        })?;
+    let hfs = hfs.to_string_lossy().into_owned();
+    info!("Using Houdini installation path {:?}", hfs);
+    Some(hfs)
+}
+
+// Replace each '{name}' in the given template with the corresponding value.
+fn expand_placeholders(template: &str, values: &[(&str, &str)]) -> String {
+    values
+        .iter()
+        .fold(template.to_string(), |acc, (name, value)| {
+            acc.replace(&format!("{{{}}}", name), value)
+        })
+}
+
+fn main() -> Result<()> {
+    // 'cargo hdk verify' loads each plugin in a child process running cargo-hdk.
+    if let Some(path) = env::var_os(verify::LOAD_ENV) {
+        return verify::load(Path::new(&path));
+    }
+
+    use terminal_size::{terminal_size, Width};
+    let app = Opt::clap()
+        .set_term_width(if let Some((Width(w), _)) = terminal_size() {
+            w as usize
+        } else {
+            80
+        })
+        .setting(AppSettings::AllowLeadingHyphen);
+
+    let matches = app.get_matches();
+    let mut opts = Opt::from_clap(&matches);
+    opts.split_app_args();
+    opts.read_env()?;
+    init_logging(opts.verbose.log_level());
+    // Log messages would be interleaved with the progress bars.
+    progress::init(opts.verbose.log_level() >= Some(Level::Warn));
+
+    // Native tools don't understand the paths of MSYS2, Cygwin and Git Bash shells on Windows.
+    if let Some(shell) = msys::shell() {
+        debug!(
+            "Running in {}, converting POSIX paths to Windows paths.",
+            shell
+        );
+        msys::normalize_env();
+        opts.normalize_paths();
+    }
+
+    // Take over an inherited jobserver before any files are opened.
+    let jobserver = if opts.jobserver {
+        Some(jobs::jobserver(opts.jobs())?)
+    } else {
+        None
+    };
+
+    if opts.new_project() {
+        return scaffold::new_project(&opts.command_args()[1..]);
+    }
+
+    if opts.completions() {
+        let versions = completions::houdini_versions();
+        return completions::generate(Opt::clap(), &versions, &opts.command_args()[1..]);
+    }
+
+    if opts.clean
+        && (opts.check()
+            || opts.run()
+            || opts.debug()
+            || opts.test()
+            || opts.cov()
+            || opts.bench()
+            || opts.shell()
+            || opts.watch()
+            || opts.doctor()
+            || opts.env()
+            || opts.verify()
+            || opts.size()
+            || opts.bindgen()
+            || opts.rust_bindings()
+            || opts.migrate()
+            || opts.prune()
+            || opts.ide()
+            || opts.hda()
+            || opts.lint()
+            || opts.fmt_cpp())
+    {
+        bail!(
+            "The '--clean' flag can't be used with 'cargo hdk {}'",
+            opts.command_args()[0]
+        );
+    }
+
+    info!("Looking for a parent directory containing the `Cargo.toml` manifest file.");
+
+    let mut metadata_cmd = MetadataCommand::new();
+    if let Some(manifest_path) = opts.manifest_path() {
+        metadata_cmd.manifest_path(manifest_path);
+    }
+    if opts.offline() {
+        info!("Running in offline mode.");
+        metadata_cmd.other_options(vec!["--offline".to_string()]);
+    }
+
+    if opts.doctor() {
+        // The environment can be checked outside of a crate as well, just without its settings.
+        let config = metadata_cmd
+            .exec()
+            .ok()
+            .and_then(|metadata| {
+                let package = select_package(&metadata, opts.package()).ok()?;
+                let layers = Config::load_layers(&metadata, package, !opts.no_user_config).ok()?;
+                Some(Config::from_layers(&layers))
+            })
+            .unwrap_or_default();
+        let hfs = find_hfs(&config, opts.houdini_version.as_deref());
+        return doctor::run(hfs.as_deref(), &config);
+    }
+
+    let resolving = progress::Phase::spinner("Resolving");
+    resolving.set_message("cargo metadata");
+    let metadata = metadata_cmd.exec();
+    if metadata.is_ok() {
+        resolving.finish();
+    } else {
+        resolving.abandon();
+    }
+    let metadata = metadata?;
+    let package = select_package(&metadata, opts.package())?;
+    debug!("Building the HDK plugin of {}", package.id);
+    if !opts.hdk_only && !opts.show_config() && !opts.migrate() {
+        check_crate_types(package);
+    }
+
+    let mut config_layers = Config::load_layers(&metadata, package, !opts.no_user_config)?;
+    if let Some(name) = &opts.hdk_profile {
+        let profile = Config::from_layers(&config_layers).profile(name)?;
+        config_layers.insert(0, (config::Source::Profile(name.clone()), profile));
+    }
+    let config = Config::from_layers(&config_layers);
+    opts.apply_config(&config, &matches);
+
+    if opts.show_config() {
+        return print_config(&opts, &config, &config_layers, &matches);
+    }
+
+    if opts.migrate() {
+        let manifest_dir = package
+            .manifest_path
+            .parent()
+            .context("Failed to find manifest directory")?;
+        let dry_run = opts.cargo_args().iter().any(|arg| arg == "--dry-run");
+        return migrate::run(
+            package.manifest_path.as_std_path(),
+            metadata
+                .workspace_root
+                .join(config::WORKSPACE_CONFIG)
+                .as_std_path(),
+            &manifest_dir.as_std_path().join(&opts.hdk_path),
+            opts.out_dir_cmake.is_some(),
+            dry_run,
+        );
+    }
+
+    if opts.fmt_cpp() {
+        let manifest_dir = package
+            .manifest_path
+            .parent()
+            .context("Failed to find manifest directory")?;
+        return fmt_cpp::run(
+            &manifest_dir.as_std_path().join(&opts.hdk_path),
+            &opts.command_args()[1..],
+        );
+    }
+
+    if opts.watch() {
+        let manifest_dir = package
+            .manifest_path
+            .parent()
+            .context("Failed to find manifest directory")?;
+        let target_dir = cargo_target_dir(opts.cargo_args(), &metadata)?;
+        return watch::run(
+            manifest_dir.as_std_path(),
+            &manifest_dir.as_std_path().join(&opts.hdk_path),
+            target_dir.as_std_path(),
+        );
+    }
+
+    if let Some(image) = &opts.container {
+        if !container::in_container() {
+            let target_dir = cargo_target_dir(opts.cargo_args(), &metadata)?;
+            return container::run(
+                image,
+                metadata.workspace_root.as_std_path(),
+                target_dir.as_std_path(),
+            );
+        }
+    }
+
+    for (key, value) in &config.env {
+        debug!("Setting {}={} from the configuration", key, value);
+        env::set_var(key, value);
+    }
+
+    let compiler_cache = match opts.cache {
+        Some(cache) => {
+            let program = tools::find_program(cache.program()).with_context(|| {
+                format!(
+                    "Couldn't find '{}' on the 'PATH' for '--cache'.",
+                    cache.program()
+                )
+            })?;
+            debug!("Caching compiler outputs using {}", program.display());
+            if cache == CompilerCache::Sccache {
+                env::set_var("RUSTC_WRAPPER", &program);
+            } else {
+                info!("Rust code is not cached since ccache doesn't support rustc.");
+            }
+            Some(program)
+        }
+        None => None,
+    };
+
+    // Windows plugins built elsewhere use the HDK of a Windows installation from the configuration.
+    let windows_cross = cross::WindowsCross::new(
+        cargo_build_target(&opts.build_args).as_deref(),
+        config.cross_windows.as_ref(),
+    )?;
+    if windows_cross.is_some()
+        && (opts.run()
+            || opts.debug()
+            || opts.test()
+            || opts.cov()
+            || opts.bench()
+            || opts.verify()
+            || opts.shell()
+            || opts.hda()
+            || opts.reload.is_some())
+    {
+        bail!("A Windows plugin cross compiled on this platform can't be loaded into Houdini here. Only build it, e.g. with 'cargo hdk' or 'cargo hdk --cmake-install'.");
+    }
+
+    info!("Looking for a Houdini installation.");
+
+    let found_hfs = match &windows_cross {
+        Some(cross) => Some(cross.hfs().to_string_lossy().into_owned()),
+        None => find_hfs(&config, opts.houdini_version.as_deref()),
+    };
+    let hfs = match (found_hfs, &opts.houdini_version) {
+        (Some(hfs), _) => hfs,
+        (None, Some(version)) => bail!("Couldn't find Houdini {}. Set the 'HFS' environment variable to its installation path.", version),
+        (None, None) => bail!("Couldn't find HFS. Please source 'houdini_setup' from houdini's installation directory or set the 'HFS' environment variable to the Houdini installation path."),
+    };
+
+    // A Windows installation of Houdini seen from WSL is built for on the Windows side.
+    if windows_cross.is_none() && wsl::is_wsl() && wsl::is_windows_houdini(Path::new(&hfs)) {
+        return wsl::run(Path::new(&hfs), metadata.workspace_root.as_std_path());
+    }
+
+    env::set_var("HFS", &hfs);
+    // Like 'houdini_setup', so the configuration can refer to the version, e.g. in 'install_dir'.
+    if let Ok(version) = houdini::version(Path::new(&hfs)) {
+        env::set_var("HOUDINI_MAJOR_RELEASE", version.major.to_string());
+        env::set_var("HOUDINI_MINOR_RELEASE", version.minor.to_string());
+        env::set_var("HOUDINI_BUILD_VERSION", version.build.to_string());
+    }
+    // Set the path variable to include hfs bin directory.
+    // This is needed in case hserver needs to verify the license during a build.
+    if let Some(path) = env::var_os("PATH") {
+        let mut paths = env::split_paths(&path).collect::<Vec<_>>();
+        paths.push(PathBuf::from(&hfs).join("bin"));
+        let new_path = env::join_paths(paths)?;
+        env::set_var("PATH", &new_path);
+    }
+
+    if let Some(requirement) = &config.houdini_version {
+        let version = houdini::version(Path::new(&hfs))?;
+        let semver = semver::Version::new(
+            version.major.into(),
+            version.minor.into(),
+            version.build.into(),
+        );
+        if !requirement.matches(&semver) {
+            bail!(
+                "Houdini {} found at {} doesn't match the required version '{}' in 'package.metadata.hdk.houdini_version'.",
+                version,
+                hfs,
+                requirement
+            );
+        }
+    }
+
+    if opts.hda() {
+        return hda::run(&hfs, &config.hdas, &opts.command_args()[1..]);
+    }
+
+    debug!("Determining build type.");
+
+    let cargo_profile =
+        if (opts.minimal_size || opts.bench()) && !has_profile_arg(opts.cargo_args()) {
+            "release"
+        } else {
+            cargo_profile(opts.cargo_args())
+        };
+    let build_type = if opts.minimal_size {
+        "MinSizeRel".to_string()
+    } else {
+        cmake_build_type(cargo_profile, &config)
+    };
+    // Debugging optimized code needs debug info in both the Rust and the C++ build.
+    let build_type = if (opts.debug() && (build_type == "Release" || build_type == "MinSizeRel"))
+        || (opts.split_debuginfo && build_type == "Release")
+    {
+        "RelWithDebInfo".to_string()
+    } else {
+        build_type
+    };
+    if opts.split_debuginfo && !symbols::has_debug_info(&build_type) {
+        warn!(
+            "The {} build has no debug information to split into symbol files. Use the 'RelWithDebInfo' build type for the profile in 'build_types'.",
+            build_type
+        );
+    }
+    if opts.debug() || opts.split_debuginfo {
+        let profile_var = cargo_profile.to_uppercase().replace('-', "_");
+        env::set_var(format!("CARGO_PROFILE_{}_DEBUG", profile_var), "true");
+    }
+    let build_type = build_type.as_str();
+    debug!(
+        "Using CMake build type {} for cargo profile {}",
+        build_type, cargo_profile
+    );
+
+    // Let build scripts use the Houdini installation found here instead of discovering it again.
+    env::set_var("CARGO_HDK_HFS", &hfs);
+    match houdini::version(Path::new(&hfs)) {
+        Ok(version) => env::set_var("CARGO_HDK_HOUDINI_BUILD_VERSION", version.to_string()),
+        Err(err) => warn!("Failed to determine the Houdini version: {:#}", err),
+    }
+    env::set_var("CARGO_HDK_BUILD_TYPE", build_type);
+
+    // CMake and the build tools don't understand extended-length paths like '\\?\C:\...'.
+    let manifest_dir = &Utf8PathBuf::from(long_paths::simplified(
+        package
+            .manifest_path
+            .parent()
+            .context("Failed to find manifest directory")?
+            .as_str(),
+    ));
+    let hdk_dir = manifest_dir.as_std_path().join(&opts.hdk_path);
+    let profile = profile_dir_name(cargo_profile);
+    let mut target_dir = Utf8PathBuf::from(long_paths::simplified(
+        cargo_target_dir(opts.cargo_args(), &metadata)?.as_str(),
+    ));
+    let base_target_dir = target_dir.clone();
+    if opts.houdini_target_dir {
+        if find_arg_value(opts.cargo_args(), "--target-dir").is_some() {
+            bail!("'--houdini-target-dir' can't be used with '--target-dir' in the cargo arguments. Please set 'CARGO_TARGET_DIR' instead.");
+        }
+        // Build scripts depending on the Houdini installation are rebuilt for each version.
+        let version = houdini::version(Path::new(&hfs))?;
+        target_dir = target_dir.join(format!("houdini{}", version));
+        env::set_var("CARGO_TARGET_DIR", &target_dir);
+    }
+    // Instrumented code is built separately, so it doesn't replace the regular build.
+    let instrumented_dir = match (opts.cov(), opts.sanitize) {
+        (true, Some(sanitizer)) => Some(format!("coverage-{}", sanitizer.short_name())),
+        (true, None) => Some("coverage".to_string()),
+        (false, Some(sanitizer)) => Some(sanitizer.short_name().to_string()),
+        (false, None) => None,
+    };
+    if let Some(dir) = &instrumented_dir {
+        if find_arg_value(opts.cargo_args(), "--target-dir").is_none() {
+            target_dir = target_dir.join(dir);
+            env::set_var("CARGO_TARGET_DIR", &target_dir);
+        }
+    }
+    if opts.cov() {
+        coverage::configure_rust();
+    }
+    debug!("Using cargo target directory {}", target_dir);
+    // The Rust and C++ code must be built for an architecture of the Houdini libraries.
+    let architecture = if cfg!(target_os = "macos") && !opts.universal && windows_cross.is_none() {
+        macos::select_architecture(
+            Path::new(&hfs),
+            cargo_build_target(&opts.build_args).as_deref(),
+            opts.rust_toolchain(),
+        )?
+    } else {
+        None
+    };
+    let target = if opts.universal {
+        if !cfg!(target_os = "macos") {
+            bail!("'--universal' builds are only supported on macOS");
+        }
+        if find_arg_value(&opts.build_args, "--target").is_some() {
+            bail!("'--universal' can't be used with '--target' in the cargo arguments, since it builds for both macOS targets.");
+        }
+        universal::check_targets(opts.rust_toolchain())?;
+        universal::check_houdini(Path::new(&hfs));
+        Some(universal::TARGET.to_string())
+    } else {
+        cargo_build_target(&opts.build_args)
+    };
+
+    // Placeholders in the build directory and the file prefixes.
+    let templates = [
+        opts.build_dir.as_deref(),
+        Some(opts.out_dir_file_prefix.as_str()),
+        opts.manifest_dir_file_prefix.as_deref(),
+    ];
+    let houdini_version = if templates
+        .iter()
+        .flatten()
+        .any(|t| t.contains("{houdini}") || t.contains("{houdini_version}"))
+    {
+        Some(houdini::version(Path::new(&hfs))?)
+    } else {
+        None
+    };
+    let houdini_short = houdini_version
+        .as_ref()
+        .map(|v| v.short())
+        .unwrap_or_default();
+    let houdini_full = houdini_version
+        .as_ref()
+        .map(|v| v.to_string())
+        .unwrap_or_default();
+    let placeholders = [
+        ("package", package.name.as_str()),
+        ("profile", profile),
+        ("houdini", &houdini_short),
+        ("houdini_version", &houdini_full),
+        ("target", target.as_deref().unwrap_or("host")),
+        ("target_dir", target_dir.as_str()),
+    ];
+    let out_dir_file_prefix = expand_placeholders(&opts.out_dir_file_prefix, &placeholders);
+    let manifest_dir_file_prefix = opts
+        .manifest_dir_file_prefix
+        .as_deref()
+        .map(|prefix| expand_placeholders(prefix, &placeholders));
+
+    let build_dir_for = |placeholders: &[(&str, &str)]| {
+        let value = |name| {
+            placeholders
+                .iter()
+                .find(|(key, _)| *key == name)
+                .map_or("", |(_, value)| *value)
+        };
+        match &opts.build_dir {
+            Some(template) => {
+                let dir = manifest_dir
+                    .as_std_path()
+                    .join(expand_placeholders(template, placeholders));
+                match &target {
+                    Some(target) if !template.contains("{target}") => dir.join(target),
+                    _ => dir,
+                }
+            }
+            None => match &target {
+                Some(target) => hdk_dir.join(format!("build_{}_{}", target, value("profile"))),
+                None => hdk_dir.join(format!("build_{}", value("profile"))),
+            },
+        }
+    };
+    let build_dir = build_dir_for(&placeholders);
+    let build_dir = match &instrumented_dir {
+        Some(dir) => build_dir.join(dir),
+        None => build_dir,
+    };
+
+    // With Corrosion, the HDK plugin is built in a subdirectory of the superproject.
+    let plugin_binary_dir = if opts.corrosion {
+        build_dir.join("hdk")
+    } else {
+        build_dir.clone()
+    };
+
+    if opts.shell() {
+        let mut envs = launch::houdini_env(&hfs)?;
+        envs.extend(launch::plugin_env(
+            &plugin_binary_dir,
+            manifest_dir.as_std_path(),
+            &hdk_dir,
+        ));
+        return launch::shell(&envs);
+    }
+
+    if opts.ide() {
+        let name = opts
+            .command_args()
+            .get(1)
+            .context("Missing the IDE. Use 'cargo hdk ide vscode', 'clion' or 'clangd'.")?;
+        let ide = ide::Ide::from_str(name, true).map_err(|_| {
+            anyhow!(
+                "Unknown IDE '{}'. Use 'cargo hdk ide vscode', 'clion' or 'clangd'.",
+                name
+            )
+        })?;
+        let mut envs: Vec<_> = config
+            .env
+            .iter()
+            .map(|(key, value)| (key.clone(), OsString::from(value)))
+            .collect();
+        envs.extend(
+            launch::plugin_env(&plugin_binary_dir, manifest_dir.as_std_path(), &hdk_dir)
+                .into_iter()
+                .chain(
+                    opts.sanitize
+                        .map(|sanitizer| sanitizer.runtime_env(build_dir.as_path()))
+                        .unwrap_or_default(),
+                )
+                .map(|(key, value)| (key.to_string(), value)),
+        );
+        let project = ide::Project {
+            name: &package.name,
+            hfs: &hfs,
+            manifest_dir,
+            hdk_dir: unicode_path(&hdk_dir, "'cargo hdk ide'")?,
+            build_dir: unicode_path(&build_dir, "'cargo hdk ide'")?,
+            build_type,
+            generator: build_env::generator(
+                opts.generator.as_deref(),
+                &cmake::parse_args(&opts.cmake),
+                &build_dir,
+            ),
+            args: ide::build_args(env::args().skip(1), name),
+            envs,
+            debugger: opts
+                .debugger
+                .unwrap_or_else(debugger::Debugger::for_platform),
+        };
+        return ide::run(ide, &project);
+    }
+
+    if opts.env() {
+        let mut deps = deps::resolve(&metadata, &opts.deps, !opts.default_deps)?;
+        deps.extend(deps::resolve_transitive(&metadata, &opts.deps_of)?);
+        let mut out_dirs = std::collections::BTreeMap::new();
+        for dep in std::iter::once(&package.name).chain(deps.values()) {
+            let path = build_dir.join(format!("{}{}.txt", out_dir_file_prefix, dep));
+            if let Ok(out_dir) = fs::read_to_string(&path) {
+                out_dirs.insert(dep.clone(), Utf8PathBuf::from(out_dir.trim()));
+            }
+        }
+        let mut lib_dir = target_dir.clone();
+        if let Some(target) = &target {
+            lib_dir.push(target);
+        }
+        lib_dir.push(profile);
+        let build_env = build_env::BuildEnv {
+            hfs: hfs.clone(),
+            houdini_version: houdini::version(Path::new(&hfs))
+                .ok()
+                .map(|v| v.to_string()),
+            profile: cargo_profile.to_string(),
+            build_type: build_type.to_string(),
+            manifest_dir: manifest_dir.to_path_buf(),
+            target_dir: target_dir.clone(),
+            build_dir: unicode_path(&build_dir, "'cargo hdk env'")?.to_path_buf(),
+            generator: build_env::generator(
+                opts.generator.as_deref(),
+                &cmake::parse_args(&opts.cmake),
+                &build_dir,
+            ),
+            artifacts: build_env::lib_artifacts(package, &lib_dir, target.as_deref()),
+            out_dirs,
+        };
+        return build_env.print(opts.format);
+    }
+
+    if opts.prune() {
+        // All build directories of the package, whatever their profile, Houdini version or target.
+        let wildcards: Vec<_> = placeholders
+            .iter()
+            .map(|&(name, value)| match name {
+                "profile" | "houdini" | "houdini_version" | "target" => (name, "*"),
+                _ => (name, value),
+            })
+            .collect();
+        let build_dirs = clean::build_dirs(&build_dir_for(&wildcards));
+        let prefixes: Vec<_> = std::iter::once(&opts.out_dir_file_prefix)
+            .chain(&opts.manifest_dir_file_prefix)
+            .map(|prefix| expand_placeholders(prefix, &wildcards))
+            .collect();
+        let packages = metadata
+            .packages
+            .iter()
+            .map(|package| package.name.as_str())
+            .collect();
+        let mut installed: Vec<_> = houdini::installations()
+            .into_iter()
+            .map(|(version, _)| version)
+            .collect();
+        installed.extend(
+            std::iter::once(Path::new(&hfs))
+                .chain(config.hfs.as_deref())
+                .filter_map(|hfs| houdini::version(hfs).ok()),
+        );
+        return prune::run(
+            &build_dirs,
+            base_target_dir.as_std_path(),
+            &prefixes,
+            &packages,
+            &installed,
+            &opts.command_args()[1..],
+        );
+    }
+
+    if opts.rust_bindings() {
+        let bindings = config.rust_bindings.as_ref().context(
+            "No Rust bindings configured. Add a 'rust_bindings' table with the HDK 'headers' to '[package.metadata.hdk]'.",
+        )?;
+        return rust_bindings::run(
+            &hfs,
+            bindings,
+            manifest_dir.as_std_path(),
+            build_dir.as_path(),
+        );
+    }
+
+    // Find the tests before building, so missing tests are reported right away.
+    let snapshot_driver = build_dir.join("snapshot.py");
+    let render_driver = build_dir.join("render.py");
+    let tests = if !opts.test() && !opts.cov() {
+        Vec::new()
+    } else if opts.app_args.is_empty() {
+        let dir = match &config.test_dir {
+            Some(dir) => dir.clone(),
+            None => hdk_dir.join("tests"),
+        };
+        let mut tests = testing::discover(&dir, manifest_dir.as_std_path())?;
+        tests.extend(testing::snapshots(
+            &config.snapshots,
+            snapshot_driver.as_path(),
+            opts.update_snapshots,
+        )?);
+        if opts.render {
+            if config.renders.is_empty() {
+                bail!("No render tests found. Declare 'renders' in '[package.metadata.hdk]'.");
+            }
+            tests.extend(testing::renders(
+                &config.renders,
+                render_driver.as_path(),
+                build_dir.join("renders").as_path(),
+                opts.update_snapshots,
+            )?);
+        }
+        if tests.is_empty() {
+            bail!(
+                "No tests found. Add Python test scripts named 'test_*.py' to {} or declare 'snapshots' in '[package.metadata.hdk]'.",
+                dir.display()
+            );
+        }
+        tests
+    } else {
+        let cwd = env::current_dir()?;
+        opts.app_args
+            .iter()
+            .map(|test| testing::Test::script(cwd.join(test), manifest_dir.as_std_path()))
+            .collect()
+    };
+
+    if opts.bench() {
+        if config.benches.is_empty() {
+            bail!("No benchmarks found. Declare 'benches' in '[package.metadata.hdk]'.");
+        }
+        bench::validate(&config.benches)?;
+    }
+
+    if opts.minimal_size {
+        configure_minimal_size(&opts, cargo_profile, target.as_deref())?;
+    }
+    let deployment_target = if cfg!(target_os = "macos") && windows_cross.is_none() {
+        macos::deployment_target(Path::new(&hfs))
+    } else {
+        None
+    };
+    if let Some(deployment_target) = &deployment_target {
+        macos::configure_rust(deployment_target);
+    }
+    // The layout of standard library types in the HDK headers depends on the libstdc++ ABI.
+    let cxx11_abi_flag = if cfg!(target_os = "linux") && windows_cross.is_none() {
+        houdini::version(Path::new(&hfs))
+            .ok()
+            .map(|version| houdini::cxx11_abi(Path::new(&hfs), &version))
+            .and_then(|abi| houdini::CXX11_ABI_FLAGS.get(abi as usize).copied())
+    } else {
+        None
+    };
+    if let Some(flag) = cxx11_abi_flag {
+        // C++ code compiled by build scripts, e.g. with the 'cc' crate, must use it too.
+        let flags: Vec<_> = env::var("CXXFLAGS")
+            .unwrap_or_default()
+            .split_whitespace()
+            .filter(|flag| !houdini::CXX11_ABI_FLAGS.contains(flag))
+            .chain(std::iter::once(flag))
+            .map(String::from)
+            .collect();
+        env::set_var("CXXFLAGS", flags.join(" "));
+    }
+    if let Some(sanitizer) = opts.sanitize {
+        sanitizer.configure_rust(opts.rust_toolchain(), target.as_deref())?;
+    }
+    if let Some(cross) = &windows_cross {
+        cross.configure_rust();
+    }
+    if opts.static_crt {
+        let msvc = cfg!(windows) || windows_cross.is_some();
+        static_crt::warn_abi(msvc);
+        if msvc {
+            static_crt::configure_rust();
+        }
+    }
+
+    // Do the CMake clean
+
+    if opts.clean {
+        // Other build directories are found by replacing their placeholders with wildcards.
+        let mut build_dirs = vec![build_dir.clone()];
+        if opts.all_profiles || opts.all_versions {
+            let wildcards: Vec<_> = placeholders
+                .iter()
+                .map(|&(name, value)| {
+                    let any = match name {
+                        "profile" => opts.all_profiles,
+                        "houdini" | "houdini_version" => opts.all_versions,
+                        _ => false,
+                    };
+                    (name, if any { "*" } else { value })
+                })
+                .collect();
+            let pattern = build_dir_for(&wildcards);
+            debug!(
+                "Looking for build directories matching {}",
+                pattern.display()
+            );
+            build_dirs.extend(
+                clean::build_dirs(&pattern)
+                    .into_iter()
+                    .filter(|dir| *dir != build_dir),
+            );
+        }
+
+        // Clean the build artifacts.
+        for dir in &build_dirs {
+            if opts.clean_installed {
+                clean::remove_installed(dir)?;
+            }
+            clean::remove_dir(dir);
+        }
+        let scripts: Vec<_> = [
+            &opts.out_dir_cmake,
+            &opts.rust_targets_cmake,
+            &opts.artifacts_cmake,
+        ]
+        .iter()
+        .filter_map(|script| script.as_deref())
+        .collect();
+        let prefixes: Vec<_> = std::iter::once(out_dir_file_prefix.as_str())
+            .chain(manifest_dir_file_prefix.as_deref())
+            .collect();
+        clean::remove_generated(&build_dir, &prefixes, &scripts);
+
+        // Cargo removes its own artifacts from the target directory given in the build args or
+        // the environment, so this stays consistent with the directory used for the build.
+        if !opts.hdk_only && !opts.corrosion {
+            cargo_clean(&opts)?;
+            if opts.all_versions && opts.houdini_target_dir {
+                for dir in clean::houdini_target_dirs(base_target_dir.as_std_path()) {
+                    clean::remove_dir(&dir);
+                }
+            }
+        }
+
+        return Ok(());
+    } else {
+        debug!("Creating the build directory: {:?}.", build_dir);
+
+        // Create build directory if it doesn't exist
+        match fs::create_dir_all(&build_dir) {
+            Err(err) if err.kind() != std::io::ErrorKind::AlreadyExists => {
+                bail!("Failed to create build directory: {:?}", &build_dir);
+            }
+            _ => {}
+        }
+    }
+
+    // Hook commands get the environment of the build along with a description of the build.
+    let mut hook_env = vec![
+        ("CARGO_HDK_PROFILE", OsString::from(cargo_profile)),
+        (
+            "CARGO_HDK_MANIFEST_DIR",
+            manifest_dir.as_os_str().to_owned(),
+        ),
+        (
+            "CARGO_HDK_CMAKE_BINARY_DIR",
+            build_dir.as_os_str().to_owned(),
+        ),
+        ("CARGO_HDK_TARGET_DIR", target_dir.as_os_str().to_owned()),
+    ];
+
+    if let Some(commands) = &config.pre_build {
+        hooks::run(
+            "pre_build",
+            commands.as_slice(),
+            manifest_dir.as_std_path(),
+            &hook_env,
+        )?;
+    }
+
+    // Do the Cargo build
+
+    let log_dir = build_dir.join("logs");
+
+    let build_rust = || -> Result<Vec<Utf8PathBuf>> {
+        // Cache the out_dir in a file so that the C++ code can be built without running cargo later.
+        let mut deps = deps::resolve(&metadata, &opts.deps, !opts.default_deps)?;
+        deps.extend(deps::resolve_transitive(&metadata, &opts.deps_of)?);
+        let crates = deps::crate_count(&metadata, &package.id);
+        let output = if opts.universal {
+            let mut outputs = Vec::new();
+            for target in universal::TARGETS {
+                outputs.push(cargo_build(
+                    &opts,
+                    package,
+                    &deps,
+                    jobserver.as_ref(),
+                    Some(target),
+                    crates,
+                )?);
+            }
+            let mut output = outputs.remove(0);
+            if !opts.check_only() {
+                output.merge_universal(&outputs, package, &target_dir, profile)?;
+            }
+            output
+        } else {
+            cargo_build(&opts, package, &deps, jobserver.as_ref(), None, crates)?
+        };
+
+        // Manifest directories are useful for dependencies providing headers in their source tree.
+        let mut manifest_dirs = Vec::new();
+        if manifest_dir_file_prefix.is_some() {
+            for (id, dep) in std::iter::once((&package.id, &package.name)).chain(&deps) {
+                if let Some(dir) = metadata[id].manifest_path.parent() {
+                    manifest_dirs.push((dep.clone(), dir.to_path_buf()));
+                }
+            }
+        }
+
+        if let Some(script_path) = &opts.out_dir_cmake {
+            cmake::write_out_dirs_script(
+                &build_dir.join(script_path),
+                &target_dir,
+                &output.out_dirs,
+                &manifest_dirs,
+            )?;
+        }
+        // 'cargo check' doesn't produce libraries, so keep the scripts from the last build.
+        if !opts.check_only() {
+            if let Some(script_path) = &opts.rust_targets_cmake {
+                cmake::write_rust_targets_script(
+                    &build_dir.join(script_path),
+                    &package.name,
+                    &output.lib_filenames(package),
+                )?;
+            }
+            if let Some(script_path) = &opts.artifacts_cmake {
+                cmake::write_artifacts_script(&build_dir.join(script_path), &output.artifacts)?;
+            }
+        }
+        for (dep, out_dir) in &output.out_dirs {
+            let path = build_dir.join(format!("{}{}.txt", out_dir_file_prefix, dep));
+            cmake::write_file(&path, out_dir.as_str())?;
+        }
+        for (dep, manifest_dir) in &manifest_dirs {
+            if let Some(prefix) = &manifest_dir_file_prefix {
+                let path = build_dir.join(format!("{}{}.txt", prefix, dep));
+                cmake::write_file(&path, manifest_dir.as_str())?;
+            }
+        }
+        Ok(output.lib_filenames(package))
+    };
+
+    // Do the CMake configuration
+
+    let configure = || -> Result<()> {
+        debug!("Parsing cmake args.");
+
+        let mut cmake_args = cmake::parse_args(&opts.cmake);
+        if let Some(generator) =
+            cmake::select_generator(opts.generator.as_deref(), &cmake_args, build_dir.as_path())
+        {
+            cmake_args.push("-G".to_string());
+            cmake_args.push(generator);
+        }
+
+        if opts.universal {
+            cmake_args.extend(universal::cmake_args());
+        }
+        let houdini_args = houdini::cmake_args(
+            Path::new(&hfs),
+            cfg!(windows) || windows_cross.is_some(),
+            &cmake_args,
+        );
+        cmake_args.extend(houdini_args);
+        if cfg!(target_os = "macos") && windows_cross.is_none() {
+            let macos_args = macos::cmake_args(
+                Path::new(&hfs),
+                deployment_target.as_deref(),
+                architecture,
+                &cmake_args,
+            );
+            cmake_args.extend(macos_args);
+        }
+        if cfg!(target_os = "linux") && windows_cross.is_none() {
+            // The plugin finds the Houdini libraries without 'LD_LIBRARY_PATH', e.g. when loaded
+            // by Python outside of a Houdini environment, also once installed.
+            let rpath = format!("$ORIGIN;{}", Path::new(&hfs).join("dsolib").display());
+            let rpath_args = cmake::default_var_args(
+                vec![
+                    ("CMAKE_BUILD_RPATH", Some(rpath.clone())),
+                    ("CMAKE_INSTALL_RPATH", Some(rpath)),
+                ],
+                &cmake_args,
+            );
+            cmake_args.extend(rpath_args);
+        }
+        if let Some(cross) = &windows_cross {
+            if opts.cc.is_some() || opts.cxx.is_some() {
+                warn!("'--cc' and '--cxx' are ignored when cross compiling for Windows, which uses clang-cl.");
+            }
+            cmake_args.extend(cross.cmake_args(&build_dir, &cmake_args)?);
+        } else {
+            let compiler_args = compilers::cmake_args(
+                opts.cc.as_deref(),
+                opts.cxx.as_deref(),
+                Path::new(&hfs),
+                &cmake_args,
+            )?;
+            cmake_args.extend(compiler_args);
+        }
+
+        // clang-cl takes the same flags as MSVC when cross compiling for Windows.
+        let msvc = cfg!(windows) || windows_cross.is_some();
+        let werror = if msvc { "/WX" } else { "-Werror" };
+        let mut cxx_flags = if opts.cpp_warnings_as_errors {
+            vec![werror]
+        } else {
+            Vec::new()
+        };
+        let instrument_cpp =
+            opts.cov() && coverage::select_clang(build_dir.as_path(), &mut cmake_args);
+        if let Some(sanitizer) = opts.sanitize {
+            cxx_flags.extend(sanitizer.compiler_flags());
+            sanitizer.cmake_args(build_dir.as_path(), &mut cmake_args)?;
+        }
+        if instrument_cpp {
+            cxx_flags.extend(coverage::CLANG_FLAGS);
+            coverage::cmake_args(build_dir.as_path(), &mut cmake_args);
+        }
+        if opts.trim_exports && !msvc {
+            cxx_flags.extend(exports::VISIBILITY_FLAGS);
+        }
+        // Match the libstdc++ ABI of the Houdini libraries.
+        cxx_flags.extend(cxx11_abi_flag);
+        // PDBs let crash dumps of Houdini sessions with the plugin be symbolized.
+        let debug_info = msvc && symbols::has_debug_info(build_type);
+        if debug_info {
+            cxx_flags.push(symbols::msvc_debug_info_flag(compiler_cache.is_some()));
+        }
+        if msvc {
+            cmake_args.extend(symbols::msvc_linker_args(build_dir.as_path(), debug_info));
+        }
+        let remove: Vec<_> = std::iter::once(werror)
+            .chain(coverage::CLANG_FLAGS.iter().copied())
+            .chain(symbols::MSVC_DEBUG_INFO_FLAGS.iter().copied())
+            .chain(houdini::CXX11_ABI_FLAGS.iter().copied())
+            .chain(exports::VISIBILITY_FLAGS.iter().copied())
+            .chain(
+                opts.sanitize
+                    .map_or(&[][..], |s| s.compiler_flags())
+                    .iter()
+                    .copied(),
+            )
+            .collect();
+        // Add to the flags given with '--cmake', if any, rather than overriding them.
+        cmake::merge_flags_arg(
+            build_dir.as_path(),
+            "CMAKE_CXX_FLAGS",
+            "CXXFLAGS",
+            &remove,
+            &cxx_flags,
+            &mut cmake_args,
+        );
+        static_crt::cmake_args(build_dir.as_path(), opts.static_crt, msvc, &mut cmake_args);
+        if !msvc {
+            exports::cmake_args(
+                build_dir.as_path(),
+                opts.trim_exports,
+                config.exports.as_deref().unwrap_or_default(),
+                &mut cmake_args,
+            )?;
+        } else if opts.trim_exports {
+            warn!("'--trim-exports' is ignored for Windows, where DLLs only export symbols marked with '__declspec(dllexport)'.");
+        }
+
+        cmake_args.extend(features::cmake_args(
+            opts.cargo_args(),
+            package,
+            &config.features,
+        ));
+
+        if let Some(program) = &compiler_cache {
+            for lang in &["C", "CXX"] {
+                cmake_args.push(format!(
+                    "-DCMAKE_{}_COMPILER_LAUNCHER={}",
+                    lang,
+                    program.display()
+                ));
+            }
+        }
+
+        // The compilation database is used by clangd and other editor integrations.
+        if !cmake_args
+            .iter()
+            .any(|arg| arg.contains("CMAKE_EXPORT_COMPILE_COMMANDS"))
+        {
+            cmake_args.push("-DCMAKE_EXPORT_COMPILE_COMMANDS=ON".to_string());
+        }
+
+        if opts.offline() {
+            // Only use sources that were already downloaded by a previous configure.
+            cmake_args.push("-DFETCHCONTENT_FULLY_DISCONNECTED=ON".to_string());
+        }
+
+        if !opts.no_cpp_deps {
+            let dep_args = cpp_deps::provision(
+                hdk_dir.as_path(),
+                &build_dir,
+                build_type,
+                &cmake_args,
+                opts.offline(),
+            )?;
+            cmake_args.extend(dep_args);
+        }
+
+        let source_dir = if opts.corrosion {
+            let dir = build_dir.join("corrosion");
+            let mut cargo_args = opts.cargo_args().to_vec();
+            cargo_args.extend(
+                opts.jobs
+                    .filter(|_| jobserver.is_none())
+                    .map(|jobs| format!("--jobs={}", jobs)),
+            );
+            corrosion::write_superproject(&dir, package, &hdk_dir, &cargo_args, opts.offline())?;
+            dir
+        } else {
+            hdk_dir.clone()
+        };
+
+        info!("Configuring CMake.");
+
+        // Libraries copied next to the plugins are told apart using the targets of the build.
+        cmake::request_codemodel(build_dir.as_path())?;
+        let configure_log = log_dir.join("configure.log");
+        let status = cmake::run_logged_with_progress(
+            Command::new("cmake")
+                .current_dir(&build_dir)
+                .arg(&source_dir)
+                .args(&cmake_args)
+                .args(cmake_configure_verbosity_args(opts.cmake_verbose))
+                .arg(format!("-DCMAKE_BUILD_TYPE={}", build_type)),
+            &configure_log,
+            &progress::Phase::spinner("Configuring"),
+        )
+        .context("Failed to configure CMake.")?;
+        if !status.success() {
+            if let Some(hint) = long_paths::diagnose(&configure_log) {
+                bail!(
+                    "CMake configuration failed. {} See the full log at {}",
+                    hint,
+                    configure_log.display()
+                );
+            }
+            bail!(
+                "CMake configuration failed. See the full log at {}",
+                configure_log.display()
+            );
+        }
+        Ok(())
+    };
+
+    if opts.bindgen() {
+        if !opts.hdk_only && !opts.corrosion {
+            build_rust()?;
+        }
+        // The 'OUT_DIR' files are written by this build or, with '--hdk-only', by the last one.
+        let mut deps = deps::resolve(&metadata, &opts.deps, !opts.default_deps)?;
+        deps.extend(deps::resolve_transitive(&metadata, &opts.deps_of)?);
+        let out_dirs: Vec<_> = std::iter::once(&package.name)
+            .chain(deps.values())
+            .filter_map(|dep| {
+                let path = build_dir.join(format!("{}{}.txt", out_dir_file_prefix, dep));
+                let out_dir = fs::read_to_string(path).ok()?;
+                Some((dep.clone(), Utf8PathBuf::from(out_dir.trim())))
+            })
+            .collect();
+        return bindgen::sync(
+            &out_dirs,
+            &package.name,
+            manifest_dir.as_std_path(),
+            &build_dir,
+            &hdk_dir.join(bindgen::GENERATED_DIR),
+        );
+    }
+
+    long_paths::check_build_dir(&build_dir);
+    let signing = codesign::Signing::new(
+        opts.codesign.as_ref(),
+        opts.notarize,
+        config.codesign.as_ref(),
+    )?;
+
+    let rust_libs = if opts.overlap_configure && !opts.hdk_only && !opts.corrosion {
+        info!("Building Rust code while configuring CMake.");
+        std::thread::scope(|scope| {
+            let rust = scope.spawn(build_rust);
+            let configured = configure();
+            let rust_libs = rust.join().expect("Cargo build thread panicked")?;
+            configured.map(|()| rust_libs)
+        })?
+    } else {
+        let rust_libs = if !opts.hdk_only && !opts.corrosion {
+            build_rust()?
+        } else {
+            Vec::new()
+        };
+        configure()?;
+        rust_libs
+    };
+
+    check_msvc_toolset(build_dir.as_path(), &hfs, opts.allow_compiler_mismatch)?;
+    if windows_cross.is_none() {
+        check_gcc_version(build_dir.as_path(), &hfs, opts.allow_compiler_mismatch)?;
+    }
+
+    if opts.lint() {
+        let jobs = opts
+            .jobs()
+            .map(|jobs| jobs.get() as usize)
+            .or_else(|| std::thread::available_parallelism().ok().map(|n| n.get()))
+            .unwrap_or(1);
+        return lint::run(
+            build_dir.as_path(),
+            hdk_dir.as_path(),
+            config.lint.as_ref(),
+            &lint::LintArgs {
+                checks: opts.tidy_checks.as_deref(),
+                fix: opts.fix,
+                deny_warnings: opts.deny_warnings,
+                jobs,
+            },
+        );
+    }
+
+    if opts.check() {
+        info!("CMake configuration succeeded. Skipping the HDK plugin build.");
+        return Ok(());
+    }
+
+    info!("Building the C/C++ HDK plugin.");
+
+    let build_log = log_dir.join("build.log");
+    let mut build_cmd = Command::new("cmake");
+    build_cmd.current_dir(&build_dir).arg("--build").arg(".");
+    if let Some(jobserver) = &jobserver {
+        // An explicit job count would make the build tool create its own jobserver.
+        jobserver.configure_make(&mut build_cmd);
+    } else if let Some(jobs) = opts.jobs() {
+        build_cmd.arg("--parallel").arg(jobs.to_string());
+    }
+    if opts.cmake_verbose > 0 {
+        build_cmd.arg("--verbose");
+    }
+    let status = cmake::run_logged_with_progress(
+        &mut build_cmd,
+        &build_log,
+        &progress::Phase::spinner("Building"),
+    )
+    .context("Failed to build HDK plugin.")?;
+    if !status.success() {
+        if let Some(hint) = long_paths::diagnose(&build_log) {
+            bail!(
+                "HDK plugin build failed. {} See the full log at {}",
+                hint,
+                build_log.display()
+            );
+        }
+        bail!(
+            "HDK plugin build failed. See the full log at {}",
+            build_log.display()
+        );
+    }
+
+    if let Some(commands) = &config.post_build {
+        let rust_libs = env::join_paths(&rust_libs)
+            .context("Failed to pass the Rust library paths to hooks")?;
+        hook_env.push(("CARGO_HDK_RUST_ARTIFACTS", rust_libs));
+        hooks::run(
+            "post_build",
+            commands.as_slice(),
+            manifest_dir.as_std_path(),
+            &hook_env,
+        )?;
+    }
+
+    let runtime_dlls = if cfg!(windows) {
+        let mut search_dirs: Vec<PathBuf> = rust_libs
+            .iter()
+            .filter_map(|lib| lib.parent())
+            .map(|dir| dir.as_std_path().to_path_buf())
+            .collect();
+        search_dirs.extend(env::var_os("PATH").iter().flat_map(env::split_paths));
+        dlls::check(
+            &verify::find_plugins(&plugin_binary_dir),
+            Path::new(&hfs),
+            &search_dirs,
+            opts.runtime_dlls,
+        )?
+    } else {
+        Vec::new()
+    };
+
+    if cfg!(target_os = "linux") && windows_cross.is_none() {
+        symbol_versions::check(
+            &verify::find_plugins(&plugin_binary_dir),
+            Path::new(&hfs),
+            config.symbol_versions.as_ref(),
+        );
+    }
+    if !cfg!(windows) && windows_cross.is_none() {
+        let plugins = verify::find_plugins(&plugin_binary_dir);
+        let exports = config.exports.as_deref().unwrap_or_default();
+        exports::check(&plugins, exports);
+        collisions::check(&plugins, Path::new(&hfs), build_dir.as_path(), exports);
+    }
+
+    if let Some(signing) = &signing {
+        let plugins = verify::find_plugins(&plugin_binary_dir);
+        signing.sign(&plugins)?;
+        if opts.cmake_install.is_none() {
+            signing.notarize(&plugins, build_dir.as_path())?;
+        }
+    }
+
+    // The symbol files installed with the plugin, which are reported as artifacts.
+    let mut symbol_files = Vec::new();
+    if let Some(prefix) = &opts.cmake_install {
+        let prefix = match (prefix, config.install_dir()?) {
+            (Some(prefix), _) => manifest_dir.as_std_path().join(prefix),
+            (None, Some(dir)) => dir,
+            (None, None) => {
+                let version = houdini::version(Path::new(&hfs))?;
+                houdini::user_pref_dir(&version)
+                    .context("Failed to determine the Houdini user preference directory. Please specify the install prefix explicitly.")?
+            }
+        };
+
+        info!("Installing the HDK plugin to {}.", prefix.display());
+
+        let install_log = log_dir.join("install.log");
+        let status = cmake::run_logged(
+            Command::new("cmake")
+                .current_dir(&build_dir)
+                .arg("--install")
+                .arg(".")
+                .arg("--config")
+                .arg(build_type)
+                .arg("--prefix")
+                .arg(&prefix)
+                .args(
+                    ((opts.strip || opts.minimal_size) && !opts.split_debuginfo)
+                        .then_some("--strip"),
+                ),
+            &install_log,
+        )
+        .context("Failed to install HDK plugin.")?;
+        if !status.success() {
+            bail!(
+                "HDK plugin installation failed. See the full log at {}",
+                install_log.display()
+            );
+        }
+
+        let symbols_dir = opts
+            .symbols_dir
+            .as_ref()
+            .map(|dir| manifest_dir.as_std_path().join(dir));
+        let plugins = verify::find_plugins(&plugin_binary_dir);
+        if !opts.strip || opts.split_debuginfo {
+            symbol_files.extend(symbols::install_pdbs(
+                &plugins,
+                build_dir.as_path(),
+                symbols_dir.as_deref(),
+            )?);
+        }
+        if opts.split_debuginfo {
+            symbol_files.extend(symbols::split_installed(
+                &plugins,
+                build_dir.as_path(),
+                symbols_dir.as_deref(),
+            )?);
+        }
+        for path in &symbol_files {
+            info!("Installed {}", path.display());
+        }
+        let installed_dlls = if opts.runtime_dlls == dlls::RuntimeDlls::Copy {
+            dlls::install(&runtime_dlls, build_dir.as_path())?
+        } else {
+            Vec::new()
+        };
+        for dll in &installed_dlls {
+            info!("Installed {}", dll.display());
+        }
+
+        if let Some(signing) = &signing {
+            let names: Vec<_> = verify::find_plugins(&plugin_binary_dir)
+                .iter()
+                .filter_map(|plugin| plugin.file_name().map(|name| name.to_owned()))
+                .collect();
+            let installed: Vec<_> = cmake::installed_files(build_dir.as_path())
+                .into_iter()
+                .filter(|path| matches!(path.file_name(), Some(name) if names.iter().any(|n| n == name)))
+                .collect();
+            signing.sign(&installed)?;
+            signing.notarize(&installed, build_dir.as_path())?;
+        }
+
+        // Renamed last, since the steps above find the installed libraries by their names.
+        if let Some(naming) = &config.plugin_naming {
+            let moved = naming::rename_installed(
+                &naming::plugin_names(&plugin_binary_dir),
+                build_dir.as_path(),
+                naming,
+                cfg!(windows) || windows_cross.is_some(),
+                &installed_dlls,
+            )?;
+            for path in &mut symbol_files {
+                if let Some((_, to)) = moved.iter().find(|(from, _)| from == path) {
+                    *path = to.clone();
+                }
+            }
+        }
+
+        if let Some(commands) = &config.post_install {
+            hook_env.push(("CARGO_HDK_INSTALL_PREFIX", prefix.into_os_string()));
+            hooks::run(
+                "post_install",
+                commands.as_slice(),
+                manifest_dir.as_std_path(),
+                &hook_env,
+            )?;
+        }
+    }
+
+    if let Some(path) = &opts.artifact_json {
+        use report::{Artifact, Kind};
+        let mut artifacts = Vec::new();
+        for plugin in verify::find_plugins(&plugin_binary_dir) {
+            let symbols = report::debug_symbols(&plugin);
+            artifacts.push(Artifact::new(Kind::Plugin, plugin)?);
+            for path in symbols {
+                artifacts.push(Artifact::new(Kind::DebugSymbols, path)?);
+            }
+        }
+        for path in &symbol_files {
+            for path in report::files_of(path) {
+                artifacts.push(Artifact::new(Kind::DebugSymbols, path)?);
+            }
+        }
+        for lib in &rust_libs {
+            artifacts.push(Artifact::new(
+                Kind::RustLibrary,
+                lib.clone().into_std_path_buf(),
+            )?);
+        }
+        if opts.cmake_install.is_some() {
+            for path in report::installed_packages(build_dir.as_path()) {
+                artifacts.push(Artifact::new(Kind::Package, path)?);
+            }
+        }
+        let mut deps = deps::resolve(&metadata, &opts.deps, !opts.default_deps)?;
+        deps.extend(deps::resolve_transitive(&metadata, &opts.deps_of)?);
+        let mut generated = Vec::new();
+        for dep in std::iter::once(&package.name).chain(deps.values()) {
+            for prefix in std::iter::once(&out_dir_file_prefix).chain(&manifest_dir_file_prefix) {
+                generated.push(build_dir.join(format!("{}{}.txt", prefix, dep)));
+            }
+        }
+        for script in [
+            &opts.out_dir_cmake,
+            &opts.rust_targets_cmake,
+            &opts.artifacts_cmake,
+        ]
+        .iter()
+        .filter_map(|script| script.as_deref())
+        {
+            generated.push(build_dir.join(script));
+        }
+        for path in generated.into_iter().filter(|path| path.is_file()) {
+            artifacts.push(Artifact::new(Kind::Generated, path)?);
+        }
+        let report = report::Report {
+            package: package.name.clone(),
+            version: package.version.to_string(),
+            profile: cargo_profile.to_string(),
+            build_type: build_type.to_string(),
+            houdini_version: houdini::version(Path::new(&hfs))
+                .ok()
+                .map(|v| v.to_string()),
+            artifacts,
+        };
+        report::write(&report, path)?;
+    }
+
+    // The environment of the applications loading the plugin from the build directory.
+    let plugin_env = || {
+        let mut envs = launch::plugin_env(&plugin_binary_dir, manifest_dir.as_std_path(), &hdk_dir);
+        if let Some(sanitizer) = opts.sanitize {
+            envs.extend(sanitizer.runtime_env(build_dir.as_path()));
+        }
+        envs
+    };
+
+    if let Some(port) = opts.reload {
+        let commands = config
+            .reload
+            .as_ref()
+            .map(|c| c.as_slice())
+            .unwrap_or_default();
+        let nodes = config.reload_nodes.as_deref().unwrap_or_default();
+        reload::run(&hfs, port, commands, nodes);
+    }
+
+    if opts.verify() {
+        return verify::run(&hfs, &plugin_binary_dir, &plugin_env());
+    }
+
+    if opts.size() {
+        return size::run(&plugin_binary_dir, &build_dir);
+    }
+
+    if opts.debug() {
+        let envs = plugin_env();
+        return debugger::run(
+            opts.debugger
+                .unwrap_or_else(debugger::Debugger::for_platform),
+            &opts.app.path(&hfs),
+            &opts.app_args,
+            &envs,
+            &build_dir.join("debug"),
+            &verify::find_plugins(&plugin_binary_dir),
+            opts.break_on_load,
+        );
+    }
+
+    if opts.run() {
+        let envs = plugin_env();
+        return launch::run(
+            opts.app,
+            &hfs,
+            &opts.app_args,
+            &envs,
+            &verify::find_plugins(&plugin_binary_dir),
+        );
+    }
+
+    if opts.bench() {
+        let baseline = match &config.bench_baseline {
+            Some(path) => path.clone(),
+            None => hdk_dir.join("benches").join("baseline.toml"),
+        };
+        let envs = plugin_env();
+        return bench::run(
+            &hfs,
+            &config.benches,
+            manifest_dir.as_std_path(),
+            &build_dir.join("bench"),
+            &envs,
+            &baseline,
+            opts.save_baseline,
+        );
+    }
+
+    if opts.cov() {
+        if tests.iter().any(|test| test.script == snapshot_driver) {
+            testing::write_snapshot_driver(&snapshot_driver)?;
+        }
+        if tests.iter().any(|test| test.script == render_driver) {
+            testing::write_render_driver(&render_driver)?;
+        }
+        let coverage_dir = build_dir.join("report");
+        let (profile_dir, profile_var) = coverage::profile_env(&coverage_dir)?;
+        let mut envs = plugin_env();
+        envs.push(profile_var);
+        // Report the coverage of failing tests as well.
+        let tested = testing::run(
+            &hfs,
+            &tests,
+            manifest_dir.as_std_path(),
+            &log_dir.join("tests"),
+            &envs,
+            &verify::find_plugins(&plugin_binary_dir),
+        );
+        let libraries: Vec<_> = verify::find_plugins(&plugin_binary_dir)
+            .into_iter()
+            .chain(
+                rust_libs
+                    .iter()
+                    .filter(|lib| matches!(lib.extension(), Some("so" | "dylib" | "dll")))
+                    .map(|lib| lib.clone().into_std_path_buf()),
+            )
+            .collect();
+        coverage::report(
+            &profile_dir,
+            &libraries,
+            &coverage_dir,
+            opts.rust_toolchain(),
+        )?;
+        return tested;
+    }
+
+    if opts.test() {
+        if tests.iter().any(|test| test.script == snapshot_driver) {
+            testing::write_snapshot_driver(&snapshot_driver)?;
+        }
+        if tests.iter().any(|test| test.script == render_driver) {
+            testing::write_render_driver(&render_driver)?;
+        }
+        let envs = plugin_env();
+        return testing::run(
+            &hfs,
+            &tests,
+            manifest_dir.as_std_path(),
+            &log_dir.join("tests"),
+            &envs,
+            &verify::find_plugins(&plugin_binary_dir),
+        );
+    }
 
     Ok(())
 }
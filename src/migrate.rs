@@ -0,0 +1,615 @@
+//! Upgrading projects written for older conventions of cargo-hdk with `cargo hdk migrate`.
+//!
+//! The HDK plugin's CMake files and the configuration in the `Cargo.toml` and `hdk.toml` are
+//! rewritten line by line, so formatting and comments are kept:
+//!
+//! - 'file(READ .../rust/out_dir_<crate>.txt VAR)' becomes 'set(VAR ${<CRATE>_OUT_DIR})' with
+//!   the script written by '--out-dir-cmake' included once at the top level, outside of any
+//!   'if()' around the reads, and `out_dir_cmake` is enabled.
+//! - Paths into the fixed 'build_debug' and 'build_release' directories of the source tree use
+//!   '${CMAKE_BINARY_DIR}', since the build directory depends on the profile and '--build-dir'.
+//! - Configuration keys written with dashes like the command line options, which are ignored as
+//!   unknown keys, use underscores.
+//! - A `cmake` value in the old '[...]' bracket syntax loses its brackets, and a '-G' generator
+//!   in it moves to the `generator` key.
+//!
+//! With '--dry-run', the changes are only printed as a diff.
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use colored::Colorize;
+use log::*;
+
+use crate::cmake;
+
+/// Configuration keys containing underscores, which may have been written with dashes.
+const KEYS: &[&str] = &[
+    "hdk_path",
+    "build_dir",
+    "deps_of",
+    "out_dir_file_prefix",
+    "out_dir_cmake",
+    "rust_targets_cmake",
+    "artifacts_cmake",
+    "install_dir",
+    "test_dir",
+    "houdini_version",
+    "build_types",
+    "pre_build",
+    "post_build",
+    "post_install",
+    "reload_nodes",
+    "cargo_args",
+    "bench_baseline",
+    "rust_bindings",
+];
+
+/// Tables overriding the settings on a single platform.
+const PLATFORMS: &[&str] = &["windows", "macos", "linux"];
+
+/// The table of the package metadata in the `Cargo.toml`.
+const PACKAGE_TABLE: &str = "package.metadata.hdk";
+
+/// CMake commands opening and closing blocks.
+const BLOCK_START: &[&str] = &["if", "foreach", "while", "function", "macro", "block"];
+const BLOCK_END: &[&str] = &[
+    "endif",
+    "endforeach",
+    "endwhile",
+    "endfunction",
+    "endmacro",
+    "endblock",
+];
+
+/// The line including the 'OUT_DIR' script written by '--out-dir-cmake'.
+const INCLUDE_OUT_DIRS: &str = "include( ${CMAKE_BINARY_DIR}/rust/out_dirs.cmake )";
+
+/// A file rewritten by the migration.
+struct Change {
+    path: PathBuf,
+    original: String,
+    migrated: String,
+    /// Descriptions of the applied migrations.
+    notes: BTreeSet<&'static str>,
+}
+
+/// The arguments of a single line CMake command with the given name, along with the indentation
+/// of the line.
+fn cmake_command<'a>(line: &'a str, name: &str) -> Option<(&'a str, Vec<&'a str>)> {
+    let trimmed = line.trim_start();
+    let indent = &line[..line.len() - trimmed.len()];
+    if trimmed.len() < name.len() || !trimmed[..name.len()].eq_ignore_ascii_case(name) {
+        return None;
+    }
+    let args = trimmed[name.len()..]
+        .trim_start()
+        .strip_prefix('(')?
+        .trim_end()
+        .strip_suffix(')')?;
+    let args = args
+        .split_whitespace()
+        .map(|arg| arg.trim_matches('"'))
+        .collect();
+    Some((indent, args))
+}
+
+/// The lowercase name of the CMake command started on the line, if any.
+fn command_name(line: &str) -> Option<String> {
+    let trimmed = line.trim_start();
+    let end = trimmed.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))?;
+    let rest = trimmed[end..].trim_start();
+    (end > 0 && rest.starts_with('(')).then(|| trimmed[..end].to_ascii_lowercase())
+}
+
+/// Migrate a CMake file, returning the migrated contents, the applied migrations and whether
+/// the 'OUT_DIR' script needs to be written.
+fn migrate_cmake(contents: &str) -> (String, BTreeSet<&'static str>, bool) {
+    let mut notes = BTreeSet::new();
+    let mut included = contents.contains("rust/out_dirs.cmake");
+    let mut uses_script = included;
+    let mut lines = Vec::new();
+    // The script is included at the top level, before the statement containing the first
+    // 'file(READ)', so it is included in every branch of an 'if()' around it.
+    let mut depth = 0usize;
+    let mut statement = 0;
+    let mut include_at = None;
+    for line in contents.lines() {
+        if let Some(name) = command_name(line) {
+            if depth == 0 {
+                statement = lines.len();
+            }
+            if BLOCK_START.contains(&name.as_str()) {
+                depth += 1;
+            } else if BLOCK_END.contains(&name.as_str()) {
+                depth = depth.saturating_sub(1);
+            }
+        }
+        let out_dir_file = cmake_command(line, "file").and_then(|(indent, args)| match args[..] {
+            [mode, path, var] if mode.eq_ignore_ascii_case("READ") => {
+                let (_, file) = path.rsplit_once("/rust/out_dir_")?;
+                let dep = file.strip_suffix(".txt")?;
+                Some((indent, dep, var))
+            }
+            _ => None,
+        });
+        if let Some((indent, dep, var)) = out_dir_file {
+            if !included {
+                include_at = Some(statement);
+                included = true;
+            }
+            lines.push(format!(
+                "{}set( {} ${{{}_OUT_DIR}} )",
+                indent,
+                var,
+                cmake::var_prefix(dep)
+            ));
+            notes.insert("read the 'OUT_DIR's from the script written by '--out-dir-cmake'");
+            uses_script = true;
+            continue;
+        }
+
+        let mut line = line.to_string();
+        for source_dir in ["${CMAKE_SOURCE_DIR}", "${CMAKE_CURRENT_SOURCE_DIR}"] {
+            for build_dir in ["build_debug", "build_release"] {
+                let old = format!("{}/{}", source_dir, build_dir);
+                if line.contains(&old) {
+                    line = line.replace(&old, "${CMAKE_BINARY_DIR}");
+                    notes.insert("refer to the build directory as '${CMAKE_BINARY_DIR}'");
+                }
+            }
+        }
+        lines.push(line);
+    }
+    if let Some(mut index) = include_at {
+        // Keep the comments above the statement with it.
+        while index > 0 && lines[index - 1].trim_start().starts_with('#') {
+            index -= 1;
+        }
+        lines.insert(index, INCLUDE_OUT_DIRS.to_string());
+    }
+    (join_lines(lines, contents), notes, uses_script)
+}
+
+/// Join migrated lines, keeping the final newline of the original contents.
+fn join_lines(lines: Vec<String>, original: &str) -> String {
+    let mut joined = lines.join("\n");
+    if original.ends_with('\n') {
+        joined.push('\n');
+    }
+    joined
+}
+
+/// The path of a TOML table header like '[package.metadata.hdk.windows]', or None for other
+/// lines.
+fn table_header(line: &str) -> Option<Vec<String>> {
+    let name = line.trim().strip_prefix('[')?.strip_suffix(']')?;
+    // Arrays of tables are never part of the configuration.
+    if name.starts_with('[') {
+        return Some(vec!["[".to_string()]);
+    }
+    Some(
+        name.split('.')
+            .map(|part| part.trim().trim_matches('"').to_string())
+            .collect(),
+    )
+}
+
+/// Returns true if the table at the given path within the configuration holds configuration
+/// keys: the top level, a platform table or a profile, possibly for a single platform.
+fn is_config_table(path: &[String]) -> bool {
+    let is_platform = |name: &String| PLATFORMS.contains(&name.as_str());
+    match path {
+        [] => true,
+        [platform] => is_platform(platform),
+        [profiles, _] => profiles == "profiles",
+        [profiles, _, platform] => profiles == "profiles" && is_platform(platform),
+        _ => false,
+    }
+}
+
+/// The underscored configuration key for a key written with dashes.
+fn renamed_key(key: &str) -> Option<&'static str> {
+    let key = key.trim().trim_matches('"');
+    if !key.contains('-') {
+        return None;
+    }
+    let underscored = key.replace('-', "_");
+    KEYS.iter().copied().find(|known| *known == underscored)
+}
+
+/// Split a `cmake` value in the bracket syntax into the remaining arguments and the generator.
+fn split_cmake_value(value: &str) -> Option<(String, Option<String>)> {
+    let args = value.strip_prefix('[')?.strip_suffix(']')?;
+    let mut generator = None;
+    let mut rest = Vec::new();
+    let mut iter = cmake::parse_args(args).into_iter();
+    while let Some(arg) = iter.next() {
+        if arg == "-G" {
+            generator = iter.next();
+        } else if let Some(name) = arg.strip_prefix("-G").filter(|name| !name.is_empty()) {
+            generator = Some(name.to_string());
+        } else if arg.contains(char::is_whitespace) {
+            rest.push(format!("'{}'", arg));
+        } else {
+            rest.push(arg);
+        }
+    }
+    Some((rest.join(" "), generator))
+}
+
+/// Migrate the configuration in a TOML file. `root` is the path of the table holding the
+/// configuration, which is empty for the whole file. `enable_out_dir_cmake` adds the
+/// `out_dir_cmake` key to the top level configuration if it is missing.
+fn migrate_config(
+    contents: &str,
+    root: &[&str],
+    enable_out_dir_cmake: bool,
+) -> (String, BTreeSet<&'static str>) {
+    // The configuration table of each line, relative to the root, if it is in one.
+    let relative = |path: &[String]| -> Option<Vec<String>> {
+        let is_prefix = path.len() >= root.len() && path.iter().zip(root).all(|(a, b)| a == b);
+        is_prefix.then(|| path[root.len()..].to_vec())
+    };
+    let mut tables = Vec::new();
+    let mut current = relative(&[]);
+    for line in contents.lines() {
+        if let Some(path) = table_header(line) {
+            current = relative(&path);
+        }
+        tables.push(current.clone());
+    }
+    let has_key = |table: &[String], key: &str| {
+        contents.lines().zip(&tables).any(|(line, t)| {
+            t.as_deref() == Some(table) && line.split_once('=').map(|(k, _)| k.trim()) == Some(key)
+        })
+    };
+    // Renaming a key to one that is already set would make the file invalid.
+    let is_set = |table: &[String], key: &str| {
+        has_key(table, key)
+            || tables.iter().flatten().any(|t| {
+                t.len() == table.len() + 1 && t.starts_with(table) && t[table.len()] == key
+            })
+    };
+    let rename = |table: &[String], key: &str| {
+        let renamed = renamed_key(key).filter(|_| is_config_table(table))?;
+        if is_set(table, renamed) {
+            warn!(
+                "Not renaming '{}', since '{}' is already set",
+                key.trim(),
+                renamed
+            );
+            return None;
+        }
+        Some(renamed)
+    };
+
+    let mut notes = BTreeSet::new();
+    let mut lines = Vec::new();
+    let mut add_out_dir_cmake = enable_out_dir_cmake && !has_key(&[], "out_dir_cmake");
+    for (line, table) in contents.lines().zip(&tables) {
+        let table = match table {
+            Some(table) => table,
+            None => {
+                lines.push(line.to_string());
+                continue;
+            }
+        };
+        if let Some(path) = table_header(line) {
+            // Tables named by a key with dashes, like '[package.metadata.hdk.build-types]'.
+            let renamed = path.split_last().and_then(|(last, parent)| {
+                let parent = relative(parent)?;
+                let key = rename(&parent, last)?;
+                Some(format!("[{}.{}]", parent_path(root, &parent), key))
+            });
+            match renamed {
+                Some(header) => {
+                    lines.push(header);
+                    notes.insert("use underscores in configuration keys");
+                }
+                None => lines.push(line.to_string()),
+            }
+            if add_out_dir_cmake && table.is_empty() {
+                lines.push("out_dir_cmake = \"rust/out_dirs.cmake\"".to_string());
+                notes.insert("enable 'out_dir_cmake'");
+                add_out_dir_cmake = false;
+            }
+            continue;
+        }
+        if !is_config_table(table) {
+            lines.push(line.to_string());
+            continue;
+        }
+        let (key, value) = match line.split_once('=') {
+            Some(split) => split,
+            None => {
+                lines.push(line.to_string());
+                continue;
+            }
+        };
+        let indent = &key[..key.len() - key.trim_start().len()];
+        if let Some(renamed) = rename(table, key) {
+            lines.push(format!("{}{} ={}", indent, renamed, value));
+            notes.insert("use underscores in configuration keys");
+            continue;
+        }
+        let quoted = value.trim();
+        let unquoted = quoted
+            .strip_prefix('"')
+            .and_then(|v| v.strip_suffix('"'))
+            .filter(|v| !v.contains('\\'))
+            .or_else(|| quoted.strip_prefix('\'').and_then(|v| v.strip_suffix('\'')));
+        let split = unquoted
+            .filter(|_| key.trim() == "cmake")
+            .and_then(split_cmake_value);
+        if let Some((args, generator)) = split {
+            if !args.is_empty() {
+                lines.push(format!("{}cmake = \"{}\"", indent, args));
+            }
+            if let Some(generator) = generator.filter(|_| !has_key(table, "generator")) {
+                lines.push(format!("{}generator = \"{}\"", indent, generator));
+                notes.insert("set the CMake generator with 'generator'");
+            }
+            notes.insert("remove the brackets around 'cmake' arguments");
+            continue;
+        }
+        lines.push(line.to_string());
+    }
+    let mut migrated = join_lines(lines, contents);
+    if add_out_dir_cmake {
+        if !migrated.is_empty() && !migrated.ends_with('\n') {
+            migrated.push('\n');
+        }
+        if !root.is_empty() {
+            migrated.push_str(&format!("\n[{}]\n", root.join(".")));
+        }
+        migrated.push_str("out_dir_cmake = \"rust/out_dirs.cmake\"\n");
+        notes.insert("enable 'out_dir_cmake'");
+    }
+    (migrated, notes)
+}
+
+/// The dotted path of a table within the root table.
+fn parent_path(root: &[&str], table: &[String]) -> String {
+    root.iter()
+        .copied()
+        .chain(table.iter().map(String::as_str))
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+/// The CMake files of the HDK plugin, skipping build directories.
+fn cmake_files(hdk_dir: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut dirs = vec![hdk_dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        if dir.join("CMakeCache.txt").exists() {
+            continue;
+        }
+        let entries = match dir.read_dir() {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            let path = entry.path();
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            if path.is_dir() && !name.starts_with('.') {
+                dirs.push(path);
+            } else if name == "CMakeLists.txt" || name.ends_with(".cmake") {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    files
+}
+
+/// Print the changes of the file at `path` as a colored unified diff.
+pub fn print_diff(path: &str, original: &str, changed: &str) {
+    let diff = similar::TextDiff::from_lines(original, changed);
+    for line in diff
+        .unified_diff()
+        .context_radius(2)
+        .header(path, path)
+        .to_string()
+        .lines()
+    {
+        if line.starts_with("+++") || line.starts_with("---") {
+            println!("{}", line.bold());
+        } else if line.starts_with('+') {
+            println!("{}", line.green());
+        } else if line.starts_with('-') {
+            println!("{}", line.red());
+        } else if line.starts_with("@@") {
+            println!("{}", line.cyan());
+        } else {
+            println!("{}", line);
+        }
+    }
+}
+
+/// Migrate the HDK plugin in `hdk_dir` and the configuration in the manifest and the workspace
+/// configuration file. `has_out_dir_cmake` tells whether the 'OUT_DIR' script is already
+/// enabled.
+pub fn run(
+    manifest_path: &Path,
+    workspace_config: &Path,
+    hdk_dir: &Path,
+    has_out_dir_cmake: bool,
+    dry_run: bool,
+) -> Result<()> {
+    let read = |path: &Path| {
+        fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))
+    };
+    let mut changes = Vec::new();
+    let mut uses_script = false;
+    for path in cmake_files(hdk_dir) {
+        let original = read(&path)?;
+        let (migrated, notes, script) = migrate_cmake(&original);
+        uses_script |= script;
+        changes.push(Change {
+            path,
+            original,
+            migrated,
+            notes,
+        });
+    }
+
+    let original = read(manifest_path)?;
+    let root: Vec<_> = PACKAGE_TABLE.split('.').collect();
+    let (migrated, notes) = migrate_config(&original, &root, uses_script && !has_out_dir_cmake);
+    changes.push(Change {
+        path: manifest_path.to_path_buf(),
+        original,
+        migrated,
+        notes,
+    });
+    if workspace_config.is_file() {
+        let original = read(workspace_config)?;
+        let (migrated, notes) = migrate_config(&original, &[], false);
+        changes.push(Change {
+            path: workspace_config.to_path_buf(),
+            original,
+            migrated,
+            notes,
+        });
+    }
+
+    changes.retain(|change| change.original != change.migrated);
+    if changes.is_empty() {
+        println!("The project already follows the current conventions of cargo-hdk.");
+        return Ok(());
+    }
+    for change in &changes {
+        print_diff(
+            &change.path.display().to_string(),
+            &change.original,
+            &change.migrated,
+        );
+        for note in &change.notes {
+            println!("  * {}", note);
+        }
+        println!();
+    }
+    if dry_run {
+        println!("Run 'cargo hdk migrate' without '--dry-run' to apply these changes.");
+        return Ok(());
+    }
+    for change in &changes {
+        debug!("Writing {}", change.path.display());
+        fs::write(&change.path, &change.migrated)
+            .with_context(|| format!("Failed to write {}", change.path.display()))?;
+    }
+    println!("Migrated {} files.", changes.len());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cmake_include_outside_of_conditionals() {
+        let original = r#"project( SOP_Plug )
+# Read the OUT_DIR of the Rust build.
+if(CMAKE_BUILD_TYPE STREQUAL "Debug")
+    file( READ ${CMAKE_SOURCE_DIR}/build_debug/rust/out_dir_hdkrs.txt HDKRS_DIR )
+else()
+    file( READ ${CMAKE_SOURCE_DIR}/build_release/rust/out_dir_hdkrs.txt HDKRS_DIR )
+endif()
+include_directories( ${CMAKE_SOURCE_DIR}/build_debug/rust/include )
+"#;
+        let expected = r#"project( SOP_Plug )
+include( ${CMAKE_BINARY_DIR}/rust/out_dirs.cmake )
+# Read the OUT_DIR of the Rust build.
+if(CMAKE_BUILD_TYPE STREQUAL "Debug")
+    set( HDKRS_DIR ${HDKRS_OUT_DIR} )
+else()
+    set( HDKRS_DIR ${HDKRS_OUT_DIR} )
+endif()
+include_directories( ${CMAKE_BINARY_DIR}/rust/include )
+"#;
+        let (migrated, notes, uses_script) = migrate_cmake(original);
+        assert_eq!(migrated, expected);
+        assert_eq!(notes.len(), 2);
+        assert!(uses_script);
+    }
+
+    #[test]
+    fn cmake_include_at_top_level_read() {
+        let original = "file(READ build/rust/out_dir_my-crate.txt MY_DIR)";
+        let (migrated, _, _) = migrate_cmake(original);
+        assert_eq!(
+            migrated,
+            "include( ${CMAKE_BINARY_DIR}/rust/out_dirs.cmake )\nset( MY_DIR ${MY_CRATE_OUT_DIR} )"
+        );
+    }
+
+    #[test]
+    fn cmake_already_migrated() {
+        let original =
+            "include( ${CMAKE_BINARY_DIR}/rust/out_dirs.cmake )\nset( A ${A_OUT_DIR} )\n";
+        let (migrated, notes, uses_script) = migrate_cmake(original);
+        assert_eq!(migrated, original);
+        assert!(notes.is_empty());
+        assert!(uses_script);
+    }
+
+    #[test]
+    fn config_renames_dashed_keys() {
+        let original = r#"[package]
+name = "plug"
+
+[package.metadata.hdk]
+build-dir = "build"
+cmake = "[-G Ninja -DFOO=1]"
+
+[package.metadata.hdk.profiles.release]
+install-dir = "dso"
+
+[dependencies]
+some-crate = "1"
+"#;
+        let expected = r#"[package]
+name = "plug"
+
+[package.metadata.hdk]
+out_dir_cmake = "rust/out_dirs.cmake"
+build_dir = "build"
+cmake = "-DFOO=1"
+generator = "Ninja"
+
+[package.metadata.hdk.profiles.release]
+install_dir = "dso"
+
+[dependencies]
+some-crate = "1"
+"#;
+        let root: Vec<_> = PACKAGE_TABLE.split('.').collect();
+        let (migrated, notes) = migrate_config(original, &root, true);
+        assert_eq!(migrated, expected);
+        assert!(notes.contains("use underscores in configuration keys"));
+        assert!(notes.contains("enable 'out_dir_cmake'"));
+    }
+
+    #[test]
+    fn config_keeps_set_keys() {
+        let original = "build-dir = \"a\"\nbuild_dir = \"b\"\n";
+        let (migrated, notes) = migrate_config(original, &[], false);
+        assert_eq!(migrated, original);
+        assert!(notes.is_empty());
+    }
+
+    #[test]
+    fn config_adds_missing_table() {
+        let root: Vec<_> = PACKAGE_TABLE.split('.').collect();
+        let (migrated, _) = migrate_config("[package]\nname = \"plug\"\n", &root, true);
+        assert_eq!(
+            migrated,
+            "[package]\nname = \"plug\"\n\n[package.metadata.hdk]\nout_dir_cmake = \"rust/out_dirs.cmake\"\n"
+        );
+    }
+}
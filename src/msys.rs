@@ -0,0 +1,146 @@
+//! Paths of MSYS2, Cygwin and Git Bash shells on Windows.
+//!
+//! These shells show paths in POSIX form like '/c/Users/...' or '/cygdrive/c/Users/...', which
+//! end up in 'HFS' and other variables exported from the shell profile, and in paths typed on the
+//! command line. CMake, the compilers and the Houdini installation itself are native Windows
+//! programs that don't understand them, so when cargo-hdk runs in one of these shells, such paths
+//! are converted to native form like 'C:\Users\...' before they are used or passed on.
+
+use std::env;
+use std::ffi::OsString;
+use std::path::PathBuf;
+use std::process::Command;
+
+use log::*;
+
+use crate::tools;
+
+/// Environment variables holding a single path that are converted to native form.
+const PATH_VARS: &[&str] = &["HFS", "CARGO_HOME", "CARGO_TARGET_DIR", "RUSTUP_HOME"];
+
+/// Cargo options taking a path whose values are converted to native form.
+const PATH_ARGS: &[&str] = &["--manifest-path", "--target-dir"];
+
+/// The name of the POSIX shell environment cargo-hdk runs in on Windows, if any.
+pub fn shell() -> Option<String> {
+    if !cfg!(windows) {
+        return None;
+    }
+    // MSYS2 and Git for Windows set 'MSYSTEM' to the active environment, like 'MINGW64'.
+    if let Ok(system) = env::var("MSYSTEM") {
+        return Some(format!("MSYS2 ({})", system));
+    }
+    // Cygwin exports a POSIX 'SHELL', which native Windows shells don't set.
+    env::var("SHELL")
+        .ok()
+        .filter(|shell| shell.starts_with('/'))
+        .map(|_| "Cygwin".to_string())
+}
+
+/// The native form of the given POSIX path of an MSYS2 or Cygwin shell, or `None` if it isn't
+/// one, e.g. because it is native or relative already.
+///
+/// Drive paths like '/c/Users' and '/cygdrive/c/Users' are converted directly, other absolute
+/// paths like '/home/user' with 'cygpath', which knows where the shell's root is installed.
+pub fn native_path(path: &str) -> Option<String> {
+    // '//server/share' is a network path in either form.
+    if !path.starts_with('/') || path.starts_with("//") {
+        return None;
+    }
+    let rest = path
+        .strip_prefix("/cygdrive")
+        .filter(|rest| rest.starts_with('/'))
+        .unwrap_or(path);
+    let mut parts = rest[1..].splitn(2, '/');
+    let drive = parts.next().unwrap_or_default();
+    if drive.len() == 1 && drive.chars().all(|c| c.is_ascii_alphabetic()) {
+        let tail = parts.next().unwrap_or_default().replace('/', "\\");
+        return Some(format!("{}:\\{}", drive.to_ascii_uppercase(), tail));
+    }
+    let cygpath = tools::find_program("cygpath")?;
+    let output = Command::new(cygpath).arg("-w").arg(path).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let native = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (!native.is_empty()).then_some(native)
+}
+
+/// Convert the given path in place, if it is a POSIX path.
+pub fn normalize_path(path: &mut PathBuf) {
+    if let Some(native) = path.to_str().and_then(native_path) {
+        debug!("Using {} for {}", native, path.display());
+        *path = PathBuf::from(native);
+    }
+}
+
+/// Convert the values of the cargo options taking a path in `args`, given either as separate
+/// arguments or as '--option=value'.
+pub fn normalize_args(args: &mut [String]) {
+    let mut is_path = false;
+    for arg in args.iter_mut() {
+        if is_path {
+            is_path = false;
+            if let Some(native) = native_path(arg) {
+                *arg = native;
+            }
+            continue;
+        }
+        if PATH_ARGS.contains(&arg.as_str()) {
+            is_path = true;
+            continue;
+        }
+        if let Some((option, value)) = arg.split_once('=') {
+            if PATH_ARGS.contains(&option) {
+                if let Some(native) = native_path(value) {
+                    *arg = format!("{}={}", option, native);
+                }
+            }
+        }
+    }
+}
+
+/// Convert the environment variables holding paths that are read by cargo-hdk or passed on to
+/// cargo and CMake.
+pub fn normalize_env() {
+    for var in PATH_VARS {
+        let value = match env::var(var) {
+            Ok(value) => value,
+            Err(_) => continue,
+        };
+        if let Some(native) = native_path(&value) {
+            debug!("Using {}={} instead of {}", var, native, value);
+            env::set_var(var, OsString::from(native));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn native_drive_paths() {
+        assert_eq!(native_path("/c/x").as_deref(), Some("C:\\x"));
+        assert_eq!(native_path("/d/Users/me").as_deref(), Some("D:\\Users\\me"));
+        assert_eq!(native_path("/c").as_deref(), Some("C:\\"));
+        assert_eq!(native_path("/c/").as_deref(), Some("C:\\"));
+    }
+
+    #[test]
+    fn native_cygdrive_paths() {
+        assert_eq!(native_path("/cygdrive/c").as_deref(), Some("C:\\"));
+        assert_eq!(native_path("/cygdrive/c/x/y").as_deref(), Some("C:\\x\\y"));
+        // Not a drive path, so it is left to 'cygpath', if available.
+        let _ = native_path("/cygdrive");
+        let _ = native_path("/cygdrivec/x");
+    }
+
+    #[test]
+    fn native_paths_left_alone() {
+        assert_eq!(native_path("//server/share"), None);
+        assert_eq!(native_path("//server"), None);
+        assert_eq!(native_path("C:\\x"), None);
+        assert_eq!(native_path("relative/path"), None);
+    }
+}
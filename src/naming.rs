@@ -0,0 +1,237 @@
+//! Platform conventional names of the installed plugin libraries.
+//!
+//! CMake names a library 'libSOP_Twist.so' on Linux and 'libSOP_Twist.dylib' on macOS, or
+//! 'libSOP_Twist.so' for a 'MODULE' library on macOS, but 'SOP_Twist.dll' on Windows. With a
+//! 'plugin_naming' table in the configuration, the plugin libraries installed with
+//! '--cmake-install' are renamed to '<name>.so', '<name>.dylib' or '<name>.dll', and optionally
+//! moved into a subdirectory of 'dso' like 'dso/fs', after everything else is installed. Their
+//! symbol files and DLLs installed next to them are moved along, and the install manifests are
+//! updated, so '--clean-installed' removes the renamed files.
+
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use log::*;
+
+use crate::cmake;
+use crate::config::PluginNaming;
+
+/// Extensions of the libraries CMake builds on Linux, macOS and Windows.
+const LIBRARY_EXTENSIONS: &[&str] = &["so", "dylib", "dll"];
+
+/// The file names of the plugin libraries built in the 'dso' subdirectory of the binary directory
+/// or the binary directory itself, with any library extension. Other libraries there, like
+/// third-party DLLs, keep their names, since the plugins import them by name.
+pub fn plugin_names(binary_dir: &Path) -> Vec<OsString> {
+    let mut libraries: Vec<_> = [binary_dir.join("dso"), binary_dir.to_path_buf()]
+        .iter()
+        .filter_map(|dir| dir.read_dir().ok())
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.is_file()
+                && matches!(path.extension().and_then(|ext| ext.to_str()),
+                    Some(ext) if LIBRARY_EXTENSIONS.contains(&ext))
+        })
+        .collect();
+    cmake::retain_library_targets(binary_dir, &mut libraries);
+    let mut names: Vec<_> = libraries
+        .iter()
+        .filter_map(|path| path.file_name().map(|name| name.to_owned()))
+        .collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// The conventional file name of a plugin library named `name` for Windows if `windows` is true,
+/// or otherwise for this platform.
+fn conventional_name(name: &str, strip_lib_prefix: bool, windows: bool) -> String {
+    let stem = Path::new(name)
+        .file_stem()
+        .map_or(name.to_string(), |stem| stem.to_string_lossy().into_owned());
+    let stem = match stem.strip_prefix("lib") {
+        Some(rest) if strip_lib_prefix && !rest.is_empty() => rest.to_string(),
+        _ => stem,
+    };
+    let extension = if windows {
+        "dll"
+    } else if cfg!(target_os = "macos") {
+        "dylib"
+    } else {
+        "so"
+    };
+    format!("{}.{}", stem, extension)
+}
+
+/// The files installed next to a plugin library named `name` that belong to it, with their new
+/// names for the library named `new_name`.
+///
+/// Split debug information keeps its name, which the library refers to by its
+/// '.gnu_debuglink', as do PDBs, which are named in the DLL. A dSYM bundle is found by the name
+/// of its library.
+fn companions(name: &str, new_name: &str) -> Vec<(String, String)> {
+    let stem = Path::new(name)
+        .file_stem()
+        .map_or(name.to_string(), |stem| stem.to_string_lossy().into_owned());
+    vec![
+        (format!("{}.debug", name), format!("{}.debug", name)),
+        (format!("{}.dSYM", name), format!("{}.dSYM", new_name)),
+        (format!("{}.pdb", stem), format!("{}.pdb", stem)),
+    ]
+}
+
+/// Move the file or directory at `from` to `to`, replacing an older copy at `to`.
+fn move_path(from: &Path, to: &Path) -> Result<()> {
+    if to.is_dir() {
+        fs::remove_dir_all(to)
+    } else if to.exists() {
+        fs::remove_file(to)
+    } else {
+        Ok(())
+    }
+    .with_context(|| format!("Failed to remove {}", to.display()))?;
+    fs::rename(from, to)
+        .with_context(|| format!("Failed to move {} to {}", from.display(), to.display()))
+}
+
+/// Replace the moved paths in the install manifests of `build_dir`.
+fn update_manifests(build_dir: &Path, moved: &[(PathBuf, PathBuf)]) -> Result<()> {
+    for entry in fs::read_dir(build_dir).into_iter().flatten().flatten() {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if !(name.starts_with("install_manifest") && name.ends_with(".txt")) {
+            continue;
+        }
+        let path = entry.path();
+        let manifest = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read install manifest {}", path.display()))?;
+        let contents: String = manifest
+            .lines()
+            .map(
+                |line| match moved.iter().find(|(from, _)| from == Path::new(line)) {
+                    Some((_, to)) => format!("{}\n", to.display()),
+                    None => format!("{}\n", line),
+                },
+            )
+            .collect();
+        fs::write(&path, contents)
+            .with_context(|| format!("Failed to write install manifest {}", path.display()))?;
+    }
+    Ok(())
+}
+
+/// Rename the installed copies of the plugin libraries `plugins`, recorded in the install
+/// manifests of `build_dir`, following `naming`, and move their symbol files and the DLLs in
+/// `dlls` installed next to them along.
+///
+/// Returns the moved paths, each with its new path.
+pub fn rename_installed(
+    plugins: &[OsString],
+    build_dir: &Path,
+    naming: &PluginNaming,
+    windows: bool,
+    dlls: &[PathBuf],
+) -> Result<Vec<(PathBuf, PathBuf)>> {
+    let strip_lib_prefix = naming.strip_lib_prefix.unwrap_or(true);
+    let mut moved = Vec::new();
+    for library in cmake::installed_files(build_dir) {
+        let name = match library.file_name() {
+            Some(name) if plugins.iter().any(|plugin| plugin == name) => name,
+            _ => continue,
+        };
+        let name = name.to_string_lossy().into_owned();
+        let dir = match library.parent() {
+            Some(dir) => dir,
+            None => continue,
+        };
+        // Only plugins installed into a 'dso' directory are moved into its subdirectory.
+        let new_dir = match &naming.dso_subdir {
+            Some(subdir) if dir.file_name().is_some_and(|name| name == "dso") => dir.join(subdir),
+            _ => dir.to_path_buf(),
+        };
+        let new_name = conventional_name(&name, strip_lib_prefix, windows);
+        let new_library = new_dir.join(&new_name);
+        if new_library == library || !library.exists() {
+            continue;
+        }
+        fs::create_dir_all(&new_dir)
+            .with_context(|| format!("Failed to create directory {}", new_dir.display()))?;
+        move_path(&library, &new_library)?;
+        info!("Renamed {} to {}", library.display(), new_library.display());
+        moved.push((library.clone(), new_library));
+
+        for (from, to) in companions(&name, &new_name) {
+            let (from, to) = (dir.join(from), new_dir.join(to));
+            if from != to && from.exists() {
+                move_path(&from, &to)?;
+                moved.push((from, to));
+            }
+        }
+        // Windows finds the DLLs a plugin imports in its own directory.
+        if new_dir != dir {
+            for dll in dlls.iter().filter(|dll| dll.parent() == Some(dir)) {
+                let to = new_dir.join(dll.file_name().unwrap_or_default());
+                if dll.exists() {
+                    move_path(dll, &to)?;
+                    moved.push((dll.clone(), to));
+                }
+            }
+        }
+    }
+    if !moved.is_empty() {
+        debug!("Updating the install manifests with the renamed plugin files");
+        update_manifests(build_dir, &moved)?;
+    }
+    Ok(moved)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn native(stem: &str) -> String {
+        let extension = if cfg!(target_os = "macos") {
+            "dylib"
+        } else {
+            "so"
+        };
+        format!("{}.{}", stem, extension)
+    }
+
+    #[test]
+    fn conventional_name_strips_lib_prefix() {
+        assert_eq!(
+            conventional_name("libSOP_Twist.so", true, false),
+            native("SOP_Twist")
+        );
+        assert_eq!(
+            conventional_name("libSOP_Twist.dylib", true, false),
+            native("SOP_Twist")
+        );
+        assert_eq!(
+            conventional_name("libSOP_Twist.so", false, false),
+            native("libSOP_Twist")
+        );
+    }
+
+    #[test]
+    fn conventional_name_for_windows() {
+        assert_eq!(
+            conventional_name("libSOP_Twist.so", true, true),
+            "SOP_Twist.dll"
+        );
+        assert_eq!(
+            conventional_name("SOP_Twist.dll", true, true),
+            "SOP_Twist.dll"
+        );
+    }
+
+    #[test]
+    fn conventional_name_keeps_bare_lib() {
+        // A library named just 'lib' has nothing left to name it by.
+        assert_eq!(conventional_name("lib.so", true, false), native("lib"));
+    }
+}
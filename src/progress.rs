@@ -0,0 +1,305 @@
+//! Progress bars of the build phases on the terminal.
+//!
+//! Reading the cargo metadata, the cargo build, the CMake configure step and the CMake build each
+//! show a progress line while they run: a spinner with the latest output, or a bar with the
+//! number of crates compiled, parsed from cargo's JSON messages, or of the build steps of CMake,
+//! parsed from the '[12/40]' and '[ 30%]' prefixes of Ninja and Makefile output. Of the output of
+//! CMake, only the compiler diagnostics and CMake warnings and errors are printed above the bars,
+//! the rest only goes to its log, which is printed if the step fails. The bars are only shown on a
+//! terminal, and follow cargo's 'CARGO_TERM_PROGRESS_WHEN' setting, where the default 'auto'
+//! hides them with '-v', since log messages would be interleaved with them. Otherwise the output of
+//! cargo and CMake is shown as it is.
+
+use std::env;
+use std::io::{self, Write};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use indicatif::{HumanDuration, MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+
+/// The bars of all phases, which may run at the same time, like the cargo build and the CMake
+/// configure step with '--overlap-configure'. `None` when progress bars are disabled.
+static BARS: OnceLock<Option<MultiProgress>> = OnceLock::new();
+
+/// Interval of the spinner animation.
+const TICK: Duration = Duration::from_millis(100);
+
+/// Enable the progress bars following 'CARGO_TERM_PROGRESS_WHEN' ('auto', 'always' or 'never'),
+/// where 'auto' shows them if `logging` is false, and 'always' even if it is true. They can only
+/// be drawn if the error output is a terminal.
+pub fn init(logging: bool) {
+    use std::io::IsTerminal;
+    let enabled = io::stderr().is_terminal()
+        && match env::var("CARGO_TERM_PROGRESS_WHEN").as_deref() {
+            Ok("always") => true,
+            Ok("never") => false,
+            _ => !logging,
+        };
+    let bars = enabled.then(|| MultiProgress::with_draw_target(ProgressDrawTarget::stderr()));
+    let _ = BARS.set(bars);
+}
+
+/// Run `f` with the bars of all phases hidden, to print messages between them.
+pub fn suspend<R>(f: impl FnOnce() -> R) -> R {
+    match BARS.get() {
+        Some(Some(bars)) => bars.suspend(f),
+        _ => f(),
+    }
+}
+
+/// A build phase shown with a progress line, which does nothing if progress bars are disabled.
+#[derive(Clone)]
+pub struct Phase {
+    bar: Option<ProgressBar>,
+}
+
+impl Phase {
+    /// Start a phase named `name` of unknown length, showing a spinner.
+    pub fn spinner(name: &str) -> Phase {
+        Phase::start(name, None)
+    }
+
+    /// Start a phase named `name` with `len` steps, showing a bar.
+    pub fn bar(name: &str, len: u64) -> Phase {
+        Phase::start(name, Some(len))
+    }
+
+    /// A phase that isn't shown, for steps whose output is shown as it is.
+    pub fn hidden() -> Phase {
+        Phase { bar: None }
+    }
+
+    fn start(name: &str, len: Option<u64>) -> Phase {
+        let bars = match BARS.get() {
+            Some(Some(bars)) => bars,
+            _ => return Phase { bar: None },
+        };
+        let bar = match len {
+            Some(len) => ProgressBar::new(len).with_style(bar_style()),
+            None => ProgressBar::new_spinner().with_style(spinner_style()),
+        };
+        let bar = bars.add(bar.with_prefix(name.to_string()));
+        bar.enable_steady_tick(TICK);
+        Phase { bar: Some(bar) }
+    }
+
+    /// Returns true if the phase is shown.
+    pub fn is_shown(&self) -> bool {
+        self.bar.is_some()
+    }
+
+    /// Show `message` next to the bar, like the crate or target being built.
+    pub fn set_message(&self, message: &str) {
+        if let Some(bar) = &self.bar {
+            bar.set_message(message.trim().to_string());
+        }
+    }
+
+    /// Set the number of finished steps, and the number of all steps if it is known by now.
+    pub fn set_progress(&self, position: u64, len: Option<u64>) {
+        let bar = match &self.bar {
+            Some(bar) => bar,
+            None => return,
+        };
+        if bar.length().is_none() {
+            bar.set_style(bar_style());
+        }
+        // Estimated lengths may be too short.
+        let len = len.or(bar.length()).unwrap_or(position).max(position);
+        bar.set_length(len);
+        bar.set_position(position);
+    }
+
+    /// Advance the bar by one step, showing `message`.
+    pub fn step(&self, message: &str) {
+        if let Some(bar) = &self.bar {
+            let position = bar.position() + 1;
+            self.set_progress(position, None);
+            bar.set_message(message.trim().to_string());
+        }
+    }
+
+    /// Run `f` with the bars hidden, to print output like compiler diagnostics.
+    pub fn suspend<R>(&self, f: impl FnOnce() -> R) -> R {
+        suspend(f)
+    }
+
+    /// Update the phase from a line of CMake output: the step counts of Ninja ('[12/40] ...') and
+    /// Makefile ('[ 30%] ...') builds, or otherwise just the message.
+    pub fn cmake_line(&self, line: &str) {
+        if self.bar.is_none() || line.trim().is_empty() {
+            return;
+        }
+        match build_progress(line) {
+            Some((position, len, message)) => {
+                self.set_progress(position, Some(len));
+                self.set_message(message);
+            }
+            None => self.set_message(line),
+        }
+    }
+
+    /// A writer updating the phase with each line of CMake output written to it, and printing the
+    /// diagnostics among them.
+    pub fn cmake_writer(&self) -> CmakeWriter {
+        CmakeWriter {
+            phase: self.clone(),
+            line: Vec::new(),
+            in_diagnostic: false,
+        }
+    }
+
+    /// End the phase, leaving a line saying how long it took.
+    pub fn finish(&self) {
+        if let Some(bar) = &self.bar {
+            bar.set_style(done_style());
+            bar.finish_with_message(format!("done in {}", HumanDuration(bar.elapsed())));
+        }
+    }
+
+    /// End the phase after it failed, removing its line.
+    pub fn abandon(&self) {
+        if let Some(bar) = &self.bar {
+            bar.finish_and_clear();
+        }
+    }
+}
+
+/// The finished and total steps of a build, with the rest of the line, from the '[12/40]' and
+/// '[ 30%]' prefixes of Ninja and Makefile output.
+fn build_progress(line: &str) -> Option<(u64, u64, &str)> {
+    let (counts, message) = line.strip_prefix('[')?.split_once(']')?;
+    let counts = counts.trim();
+    let (position, len) = match counts.strip_suffix('%') {
+        Some(percent) => (percent.trim().parse().ok()?, 100),
+        None => {
+            let (position, len) = counts.split_once('/')?;
+            (position.parse().ok()?, len.parse().ok()?)
+        }
+    };
+    Some((position, len, message))
+}
+
+/// Returns true if the line of CMake output starts a compiler diagnostic, like
+/// 'plugin.cpp:12:5: warning: ...' or 'plugin.cpp(12): warning C4996: ...', or a CMake warning or
+/// error.
+fn starts_diagnostic(line: &str) -> bool {
+    let line = line.trim_start();
+    line.starts_with("CMake Warning")
+        || line.starts_with("CMake Deprecation Warning")
+        || line.starts_with("CMake Error")
+        || line.starts_with("In file included from")
+        || [
+            ": warning",
+            ": error",
+            ": fatal error",
+            "warning:",
+            "error:",
+        ]
+        .iter()
+        .any(|pattern| line.contains(pattern))
+}
+
+/// Writer passing the lines of CMake output to [`Phase::cmake_line`], and printing diagnostics
+/// above the bars, along with the lines following them up to the next build step or CMake
+/// status message, like the source lines shown by compilers.
+pub struct CmakeWriter {
+    phase: Phase,
+    line: Vec<u8>,
+    in_diagnostic: bool,
+}
+
+impl CmakeWriter {
+    fn write_line(&mut self) {
+        let line = String::from_utf8_lossy(&self.line).into_owned();
+        self.line.clear();
+        if build_progress(&line).is_some() || line.starts_with("-- ") {
+            self.in_diagnostic = false;
+        } else if starts_diagnostic(&line) {
+            self.in_diagnostic = true;
+        }
+        if self.in_diagnostic {
+            self.phase.suspend(|| eprintln!("{}", line));
+        } else {
+            self.phase.cmake_line(&line);
+        }
+    }
+}
+
+impl Drop for CmakeWriter {
+    fn drop(&mut self) {
+        if !self.line.is_empty() {
+            self.write_line();
+        }
+    }
+}
+
+impl Write for CmakeWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for &byte in buf {
+            match byte {
+                b'\n' => self.write_line(),
+                // Line endings of Windows tools.
+                b'\r' => {}
+                _ => self.line.push(byte),
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn spinner_style() -> ProgressStyle {
+    ProgressStyle::with_template("{prefix:>12.cyan.bold} {spinner} {wide_msg}")
+        .expect("Valid progress template")
+}
+
+fn bar_style() -> ProgressStyle {
+    ProgressStyle::with_template("{prefix:>12.cyan.bold} [{bar:25}] {pos}/{len} {wide_msg}")
+        .expect("Valid progress template")
+        .progress_chars("=> ")
+}
+
+fn done_style() -> ProgressStyle {
+    ProgressStyle::with_template("{prefix:>12.green.bold} {msg}").expect("Valid progress template")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn build_progress_of_ninja_and_make() {
+        assert_eq!(
+            build_progress("[12/40] Building CXX object plugin.cpp.o"),
+            Some((12, 40, " Building CXX object plugin.cpp.o"))
+        );
+        assert_eq!(
+            build_progress("[ 30%] Linking CXX shared library dso/libSOP_Plug.so"),
+            Some((30, 100, " Linking CXX shared library dso/libSOP_Plug.so"))
+        );
+        assert_eq!(build_progress("[ERROR] something"), None);
+        assert_eq!(build_progress("-- Configuring done"), None);
+    }
+
+    #[test]
+    fn diagnostics_of_compilers_and_cmake() {
+        assert!(starts_diagnostic(
+            "plugin.cpp:12:5: warning: unused variable 'x' [-Wunused-variable]"
+        ));
+        assert!(starts_diagnostic(
+            r"C:\plugin\plugin.cpp(12): warning C4996: 'strcpy': This function may be unsafe."
+        ));
+        assert!(starts_diagnostic("In file included from plugin.cpp:1:"));
+        assert!(starts_diagnostic(
+            "CMake Warning (dev) at CMakeLists.txt:3 (project):"
+        ));
+        assert!(!starts_diagnostic(
+            "Scanning dependencies of target SOP_Plug"
+        ));
+        assert!(!starts_diagnostic("Built target SOP_Plug"));
+    }
+}
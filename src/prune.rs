@@ -0,0 +1,251 @@
+//! Removing stale build state with `cargo hdk prune`.
+//!
+//! Unlike `--clean`, which removes the outputs of a build so it starts over, pruning only removes
+//! what can no longer be used: build directories configured for a Houdini installation that has
+//! since been removed, the cargo target directories of '--houdini-target-dir' for versions that
+//! are no longer installed, 'OUT_DIR' and manifest directory files of crates that left the
+//! `Cargo.lock`, and old logs.
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use anyhow::{Context, Result};
+use log::*;
+
+use crate::clean;
+use crate::cmake;
+use crate::houdini::Version;
+use crate::long_paths;
+use crate::size::format_size;
+
+/// Logs not written for this many days are removed, unless set with '--log-age'.
+const DEFAULT_LOG_AGE_DAYS: u64 = 7;
+
+/// Options of `cargo hdk prune`. Other arguments select the package like for a build.
+struct PruneArgs {
+    dry_run: bool,
+    log_age: Duration,
+}
+
+impl PruneArgs {
+    fn parse(args: &[String]) -> Result<Self> {
+        let mut dry_run = false;
+        let mut log_age_days = DEFAULT_LOG_AGE_DAYS;
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            if arg == "--dry-run" {
+                dry_run = true;
+            } else if arg == "--log-age" || arg.starts_with("--log-age=") {
+                let value = match arg.strip_prefix("--log-age=") {
+                    Some(value) => value,
+                    None => iter
+                        .next()
+                        .context("Missing value for '--log-age'")?
+                        .as_str(),
+                };
+                log_age_days = value.parse().with_context(|| {
+                    format!("Invalid number of days '{}' for '--log-age'", value)
+                })?;
+            }
+        }
+        Ok(PruneArgs {
+            dry_run,
+            log_age: Duration::from_secs(log_age_days * 24 * 60 * 60),
+        })
+    }
+}
+
+/// A file or directory to remove, with the reason for removing it.
+struct Stale {
+    path: PathBuf,
+    reason: String,
+}
+
+/// The total size of the files in a directory, or of a file. Symbolic links aren't followed.
+fn disk_usage(path: &Path) -> u64 {
+    let meta = match path.symlink_metadata() {
+        Ok(meta) => meta,
+        Err(_) => return 0,
+    };
+    if !meta.is_dir() {
+        return meta.len();
+    }
+    fs::read_dir(path)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| disk_usage(&entry.path()))
+                .sum()
+        })
+        .unwrap_or(0)
+}
+
+/// The files generated for CMake with the given prefix in `build_dir` that belong to crates not
+/// in `packages`.
+fn orphaned_files(
+    build_dir: &Path,
+    prefix: &str,
+    packages: &HashSet<&str>,
+) -> Vec<(PathBuf, String)> {
+    let (dir, name) = clean::split_prefix(build_dir, prefix);
+    let mut orphans = Vec::new();
+    for dir in clean::find_dirs(&dir) {
+        let entries = match dir.read_dir() {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            let file_name = entry.file_name();
+            let dep = file_name
+                .to_str()
+                .and_then(|file_name| file_name.strip_prefix(name.as_str()))
+                .and_then(|rest| rest.strip_suffix(".txt"));
+            if let Some(dep) = dep.filter(|dep| !packages.contains(dep)) {
+                orphans.push((entry.path(), dep.to_string()));
+            }
+        }
+    }
+    orphans
+}
+
+/// The logs in the build directory that weren't written since `cutoff`.
+fn old_logs(build_dir: &Path, cutoff: SystemTime) -> Vec<PathBuf> {
+    let mut logs = Vec::new();
+    let mut dirs = vec![build_dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let entries = match dir.read_dir() {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            let meta = match entry.path().symlink_metadata() {
+                Ok(meta) => meta,
+                Err(_) => continue,
+            };
+            let path = entry.path();
+            if meta.is_dir() {
+                dirs.push(path);
+                continue;
+            }
+            // Only the logs written by cargo-hdk, not those of CMake itself.
+            let relative = path.strip_prefix(build_dir).unwrap_or(&path);
+            let is_log = path.extension().is_some_and(|ext| ext == "log")
+                && relative
+                    .components()
+                    .any(|c| c.as_os_str() == "logs" || c.as_os_str() == "bench");
+            let is_old = matches!(meta.modified(), Ok(modified) if modified < cutoff);
+            if is_log && is_old {
+                logs.push(path);
+            }
+        }
+    }
+    logs
+}
+
+/// Remove the stale state of the given build directories and cargo target directory.
+///
+/// `prefixes` are the prefixes of the generated 'OUT_DIR' and manifest directory files,
+/// `packages` the names of the packages in the `Cargo.lock` and `installed` the versions of the
+/// Houdini installations found. `args` are the arguments following 'prune'.
+pub fn run(
+    build_dirs: &[PathBuf],
+    target_dir: &Path,
+    prefixes: &[String],
+    packages: &HashSet<&str>,
+    installed: &[Version],
+    args: &[String],
+) -> Result<()> {
+    let args = PruneArgs::parse(args)?;
+    let cutoff = SystemTime::now()
+        .checked_sub(args.log_age)
+        .unwrap_or(SystemTime::UNIX_EPOCH);
+
+    let mut stale = Vec::new();
+    for build_dir in build_dirs {
+        if let Some(houdini_dir) = cmake::cache_value(build_dir, "Houdini_DIR") {
+            if !Path::new(&houdini_dir).exists() {
+                stale.push(Stale {
+                    path: build_dir.clone(),
+                    reason: format!(
+                        "configured for the Houdini installation at {}, which no longer exists",
+                        houdini_dir
+                    ),
+                });
+                continue;
+            }
+        }
+        for prefix in prefixes {
+            for (path, dep) in orphaned_files(build_dir, prefix, packages) {
+                stale.push(Stale {
+                    path,
+                    reason: format!("'{}' is no longer in the Cargo.lock", dep),
+                });
+            }
+        }
+        for path in old_logs(build_dir, cutoff) {
+            stale.push(Stale {
+                path,
+                reason: format!("not written for {} days", args.log_age.as_secs() / 86400),
+            });
+        }
+    }
+    for dir in clean::houdini_target_dirs(target_dir) {
+        let version = dir
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_prefix("houdini"))
+            .and_then(|version| version.parse::<Version>().ok());
+        if let Some(version) = version.filter(|version| !installed.contains(version)) {
+            stale.push(Stale {
+                path: dir,
+                reason: format!("Houdini {} is no longer installed", version),
+            });
+        }
+    }
+
+    if stale.is_empty() {
+        println!("Nothing to prune.");
+        return Ok(());
+    }
+    let mut reclaimed = 0;
+    for Stale { path, reason } in &stale {
+        let size = disk_usage(path);
+        reclaimed += size;
+        println!(
+            "{} {} ({}): {}",
+            if args.dry_run {
+                "Would remove"
+            } else {
+                "Removing"
+            },
+            path.display(),
+            format_size(size),
+            reason
+        );
+        if args.dry_run {
+            continue;
+        }
+        let extended = long_paths::extended(path);
+        let removed = if path.is_dir() {
+            fs::remove_dir_all(extended)
+        } else {
+            fs::remove_file(extended)
+        };
+        if let Err(err) = removed {
+            warn!("Failed to remove {}: {}", path.display(), err);
+            reclaimed -= size;
+        }
+    }
+    println!();
+    if args.dry_run {
+        println!(
+            "Pruning would reclaim {}. Run 'cargo hdk prune' without '--dry-run' to remove these.",
+            format_size(reclaimed)
+        );
+    } else {
+        println!("Reclaimed {}.", format_size(reclaimed));
+    }
+    Ok(())
+}
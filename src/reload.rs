@@ -0,0 +1,74 @@
+//! Updating a running Houdini session after a build with `--reload <PORT>`.
+//!
+//! The session has to listen for hscript commands on the given port, which is opened in Houdini
+//! with 'openport <PORT>' (e.g. from '123.cmd' or the Textport). After a successful build, the
+//! commands configured in `reload` are sent with the 'hcommand' tool of the Houdini installation,
+//! followed by a forced cook of each node in `reload_nodes`:
+//!
+//! ```toml
+//! [package.metadata.hdk]
+//! reload = "otrefresh"
+//! reload_nodes = ["/obj/geo1/my_plugin1"]
+//! ```
+//!
+//! Houdini can't unload a plugin library it has already loaded, so the commands are meant to make
+//! the session pick up whatever can change at runtime, like operator type libraries, Python
+//! modules or a Rust library the plugin loads itself.
+
+use std::env;
+use std::path::Path;
+use std::process::Command;
+
+use anyhow::{Context, Result};
+use log::*;
+
+/// Send a single hscript command to the session listening on the given port.
+fn send(hcommand: &Path, port: u16, command: &str) -> Result<()> {
+    debug!("Sending '{}' to Houdini on port {}", command, port);
+    let output = Command::new(hcommand)
+        .arg(port.to_string())
+        .arg(command)
+        .output()
+        .with_context(|| format!("Failed to run {}", hcommand.display()))?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success() {
+        bail!(
+            "'{}' failed with {}: {}",
+            command,
+            output.status,
+            stderr.trim()
+        );
+    }
+    for line in stdout.lines().chain(stderr.lines()) {
+        info!("[houdini] {}", line);
+    }
+    Ok(())
+}
+
+/// Send the reload commands and recook the given nodes in the session listening on `port`.
+///
+/// Errors are only reported as warnings, since the build itself succeeded and the session may
+/// simply not be running.
+pub fn run(hfs: &str, port: u16, commands: &[String], nodes: &[String]) {
+    let hcommand = Path::new(hfs)
+        .join("bin")
+        .join(format!("hcommand{}", env::consts::EXE_SUFFIX));
+    if commands.is_empty() && nodes.is_empty() {
+        warn!("Nothing to reload. Set 'reload' commands or 'reload_nodes' in '[package.metadata.hdk]'.");
+        return;
+    }
+
+    info!("Reloading the plugin in Houdini on port {}.", port);
+    let recooks = nodes.iter().map(|node| format!("opcook -F {}", node));
+    for command in commands.iter().cloned().chain(recooks) {
+        if let Err(err) = send(&hcommand, port, &command) {
+            warn!(
+                "Failed to reload the plugin in Houdini on port {}: {:#}. Is a session listening with 'openport {}'?",
+                port, err, port
+            );
+            return;
+        }
+    }
+    println!("Reloaded the plugin in Houdini on port {}.", port);
+}
@@ -0,0 +1,161 @@
+"""Headless render test run with hython by 'cargo hdk test --render'.
+
+The test case is given by the CARGO_HDK_RENDER_* environment variables. The ROP is rendered with
+the plugin loaded, and the image is compared with the reference after downscaling both, if one is
+given.
+"""
+
+import os
+import runpy
+import shutil
+import sys
+
+import hou
+
+# Number of differing pixels reported.
+MAX_DIFFS = 10
+
+
+def fail(message):
+    print(message)
+    sys.exit(1)
+
+
+def load_pixels(path):
+    """The resolution and the interleaved RGB values of the image, read with a file COP."""
+    cop = hou.node("/img").createNode("img").createNode("file")
+    try:
+        cop.parm("filename1").set(path)
+        cop.cook(force=True)
+        errors = cop.errors()
+        if errors:
+            fail("Failed to read %s:\n%s" % (path, "\n".join(errors)))
+        return cop.xRes(), cop.yRes(), cop.allPixels("C")
+    finally:
+        cop.parent().destroy()
+
+
+def downscale(width, height, pixels, target_width):
+    """Average blocks of pixels into an image `target_width` pixels wide, keeping the aspect."""
+    target_width = max(1, min(target_width, width))
+    target_height = max(1, int(round(height * target_width / float(width))))
+    scaled = []
+    for ty in range(target_height):
+        y0 = ty * height // target_height
+        y1 = max(y0 + 1, (ty + 1) * height // target_height)
+        for tx in range(target_width):
+            x0 = tx * width // target_width
+            x1 = max(x0 + 1, (tx + 1) * width // target_width)
+            sums = [0.0, 0.0, 0.0]
+            for y in range(y0, y1):
+                row = y * width
+                for x in range(x0, x1):
+                    i = (row + x) * 3
+                    sums[0] += pixels[i]
+                    sums[1] += pixels[i + 1]
+                    sums[2] += pixels[i + 2]
+            count = float((y1 - y0) * (x1 - x0))
+            scaled.append(tuple(s / count for s in sums))
+    return target_width, target_height, scaled
+
+
+def compare(actual, expected, width, tolerance):
+    """Describe the pixels of the downscaled images differing by more than the tolerance."""
+    diffs = []
+    largest = 0.0
+    for i, (a, e) in enumerate(zip(actual, expected)):
+        difference = max(abs(x - y) for x, y in zip(a, e))
+        largest = max(largest, difference)
+        if difference > tolerance:
+            diffs.append((i % width, i // width, e, a))
+    if not diffs:
+        return []
+    lines = [
+        "%d of %d downscaled pixels differ (largest difference %g, tolerance %g):"
+        % (len(diffs), len(expected), largest, tolerance)
+    ]
+    for x, y, e, a in diffs[:MAX_DIFFS]:
+        lines.append(
+            "  pixel (%d, %d): expected (%.4f, %.4f, %.4f), got (%.4f, %.4f, %.4f)"
+            % ((x, y) + e + a)
+        )
+    if len(diffs) > MAX_DIFFS:
+        lines.append("  ... and %d more" % (len(diffs) - MAX_DIFFS))
+    return lines
+
+
+def main():
+    env = os.environ
+    hip = env.get("CARGO_HDK_RENDER_HIP")
+    script = env.get("CARGO_HDK_RENDER_SCRIPT")
+    rop_path = env["CARGO_HDK_RENDER_ROP"]
+    output = env["CARGO_HDK_RENDER_OUTPUT"]
+    frame = env.get("CARGO_HDK_RENDER_FRAME")
+    resolution = env.get("CARGO_HDK_RENDER_RESOLUTION")
+    reference = env.get("CARGO_HDK_RENDER_REFERENCE")
+    tolerance = float(env["CARGO_HDK_RENDER_TOLERANCE"])
+    compare_width = int(env["CARGO_HDK_RENDER_COMPARE_WIDTH"])
+
+    if hip:
+        hou.hipFile.load(hip, suppress_save_prompt=True, ignore_load_warnings=True)
+    if script:
+        runpy.run_path(script, run_name="__main__")
+
+    rop = hou.node(rop_path)
+    if rop is None:
+        fail("Node %s doesn't exist." % rop_path)
+    if not isinstance(rop, hou.RopNode):
+        fail("Node %s is not a render node." % rop_path)
+
+    directory = os.path.dirname(output)
+    if directory and not os.path.isdir(directory):
+        os.makedirs(directory)
+    if os.path.exists(output):
+        os.remove(output)
+    frame = float(frame) if frame else hou.frame()
+    options = {"frame_range": (frame, frame), "output_file": output}
+    if resolution:
+        options["res"] = tuple(int(r) for r in resolution.split("x"))
+    try:
+        rop.render(verbose=False, **options)
+    except hou.OperationFailed as error:
+        fail("Rendering %s failed: %s" % (rop_path, error))
+    errors = rop.errors()
+    if errors:
+        fail("Rendering %s failed:\n%s" % (rop_path, "\n".join(errors)))
+    if not os.path.exists(output):
+        fail("Rendering %s didn't write the image %s." % (rop_path, output))
+    print("Rendered %s to %s" % (rop_path, output))
+
+    if not reference:
+        return
+    if env.get("CARGO_HDK_RENDER_UPDATE") == "1":
+        directory = os.path.dirname(reference)
+        if directory and not os.path.isdir(directory):
+            os.makedirs(directory)
+        shutil.copyfile(output, reference)
+        print("Updated reference image %s" % reference)
+        return
+
+    if not os.path.exists(reference):
+        fail(
+            "Reference image %s doesn't exist. Run 'cargo hdk test --render --update-snapshots' to create it."
+            % reference
+        )
+    width, height, pixels = load_pixels(output)
+    ref_width, ref_height, ref_pixels = load_pixels(reference)
+    if (width, height) != (ref_width, ref_height):
+        fail(
+            "Resolution of %s differs: expected %dx%d, got %dx%d"
+            % (output, ref_width, ref_height, width, height)
+        )
+    scaled_width, _, actual = downscale(width, height, pixels, compare_width)
+    _, _, expected = downscale(width, height, ref_pixels, compare_width)
+    diffs = compare(actual, expected, scaled_width, tolerance)
+    if diffs:
+        print("Render of %s doesn't match %s:" % (rop_path, reference))
+        print("\n".join(diffs))
+        sys.exit(1)
+
+
+main()
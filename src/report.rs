@@ -0,0 +1,148 @@
+//! The machine-readable report of the artifacts of a build written with '--artifact-json'.
+//!
+//! Packaging pipelines get every file produced by the build from a single JSON document instead of
+//! guessing paths: the plugin libraries with their debug symbols, the libraries built by cargo,
+//! the Houdini package files installed by '--cmake-install' and the files exported for CMake.
+//! Each file is listed with its size and SHA-256 hash, so it can be verified after copying.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use log::*;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::cmake;
+
+/// Kinds of artifacts in the report.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Kind {
+    /// A plugin library loaded by Houdini.
+    Plugin,
+    /// Debug information split off a plugin library, like a PDB, '.debug' or dSYM file.
+    DebugSymbols,
+    /// A library built by cargo.
+    RustLibrary,
+    /// A Houdini package file installed with the plugin.
+    Package,
+    /// A file exported for CMake, like the 'OUT_DIR' files and scripts.
+    Generated,
+}
+
+/// A file produced by the build.
+#[derive(Debug, Serialize)]
+pub struct Artifact {
+    pub kind: Kind,
+    pub path: PathBuf,
+    pub size: u64,
+    pub sha256: String,
+}
+
+impl Artifact {
+    /// Describe the file at the given path, hashing its contents.
+    pub fn new(kind: Kind, path: PathBuf) -> Result<Artifact> {
+        let mut file =
+            fs::File::open(&path).with_context(|| format!("Failed to open {}", path.display()))?;
+        let mut hasher = Sha256::new();
+        let size = io::copy(&mut file, &mut hasher)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let sha256 = hasher
+            .finalize()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        Ok(Artifact {
+            kind,
+            path,
+            size,
+            sha256,
+        })
+    }
+}
+
+/// The report of a build.
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub package: String,
+    pub version: String,
+    pub profile: String,
+    pub build_type: String,
+    pub houdini_version: Option<String>,
+    pub artifacts: Vec<Artifact>,
+}
+
+/// The files in the given directory and its subdirectories.
+fn files_in(dir: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(&dir).into_iter().flatten().flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    files
+}
+
+/// The given file, or the files of the given directory like a dSYM bundle.
+pub fn files_of(path: &Path) -> Vec<PathBuf> {
+    if path.is_dir() {
+        files_in(path)
+    } else {
+        vec![path.to_path_buf()]
+    }
+}
+
+/// The debug symbols split off the given plugin library next to it: a PDB on Windows, a
+/// '<plugin>.debug' file on Linux or the files of a '<plugin>.dSYM' bundle on macOS.
+pub fn debug_symbols(plugin: &Path) -> Vec<PathBuf> {
+    let with_suffix = |suffix: &str| {
+        let mut path = plugin.as_os_str().to_owned();
+        path.push(suffix);
+        PathBuf::from(path)
+    };
+    let mut symbols: Vec<_> = vec![plugin.with_extension("pdb"), with_suffix(".debug")]
+        .into_iter()
+        .filter(|path| path.is_file())
+        .collect();
+    symbols.extend(files_in(&with_suffix(".dSYM")));
+    symbols
+}
+
+/// The Houdini package files among the files installed from the given build directory.
+pub fn installed_packages(build_dir: &Path) -> Vec<PathBuf> {
+    cmake::installed_files(build_dir)
+        .into_iter()
+        .filter(|path| matches!(path.extension(), Some(ext) if ext == "json"))
+        .collect()
+}
+
+/// Write the report as JSON to the given file, or to stdout if the path is '-'.
+pub fn write(report: &Report, path: &Path) -> Result<()> {
+    let json =
+        serde_json::to_string_pretty(report).context("Failed to serialize the artifact report")?;
+    if path == Path::new("-") {
+        println!("{}", json);
+        return Ok(());
+    }
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create directory {}", dir.display()))?;
+    }
+    fs::write(path, json + "\n")
+        .with_context(|| format!("Failed to write the artifact report {}", path.display()))?;
+    info!(
+        "Wrote the report of {} artifacts to {}.",
+        report.artifacts.len(),
+        path.display()
+    );
+    Ok(())
+}
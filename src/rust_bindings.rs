@@ -0,0 +1,112 @@
+//! Rust FFI for HDK headers generated with bindgen by `cargo hdk rust-bindings`.
+//!
+//! The headers listed in the `rust_bindings` configuration are parsed by the bindgen CLI (which
+//! needs libclang) with the defines and include paths the HDK is compiled with, as printed by
+//! 'hcustom -c' of the Houdini installation. This way the bindings match the Houdini version the
+//! plugin is built against. The resulting module is only rewritten when it changes.
+
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use anyhow::{Context, Result};
+use log::*;
+
+use crate::config::RustBindings;
+use crate::houdini;
+use crate::tools;
+
+/// The module written if no output is configured, relative to the crate root.
+const DEFAULT_OUTPUT: &str = "src/hdk_sys.rs";
+
+/// Generate the Rust bindings of the configured HDK headers for the Houdini installation in
+/// `hfs`, using `build_dir` for intermediate files.
+pub fn run(
+    hfs: &str,
+    bindings: &RustBindings,
+    manifest_dir: &Path,
+    build_dir: &Path,
+) -> Result<()> {
+    if bindings.headers.is_empty() {
+        bail!("No headers to generate bindings for. List them in 'rust_bindings.headers'.");
+    }
+    let hfs = Path::new(hfs);
+    let version = houdini::version(hfs)?;
+    let include = hfs.join("toolkit").join("include");
+    for header in &bindings.headers {
+        if !include.join(header).is_file() {
+            bail!(
+                "The HDK header '{}' doesn't exist in {}",
+                header,
+                include.display()
+            );
+        }
+    }
+    let bindgen = tools::find_program("bindgen").context(
+        "Couldn't find 'bindgen' on the 'PATH'. Install it with 'cargo install bindgen-cli'.",
+    )?;
+
+    let dir = build_dir.join("rust_bindings");
+    fs::create_dir_all(&dir)
+        .with_context(|| format!("Failed to create directory {}", dir.display()))?;
+    let wrapper = dir.join("wrapper.h");
+    let includes: String = bindings
+        .headers
+        .iter()
+        .map(|header| format!("#include <{}>\n", header))
+        .collect();
+    fs::write(&wrapper, includes)
+        .with_context(|| format!("Failed to write {}", wrapper.display()))?;
+
+    let generated = dir.join("bindings.rs");
+    let mut cmd = Command::new(&bindgen);
+    cmd.arg(&wrapper).arg("--output").arg(&generated).args([
+        "--raw-line",
+        &format!(
+            "// Generated by 'cargo hdk rust-bindings' for Houdini {}. Do not edit.",
+            version
+        ),
+    ]);
+    for (flag, patterns) in [
+        ("--allowlist-item", &bindings.allowlist),
+        ("--blocklist-item", &bindings.blocklist),
+        ("--opaque-type", &bindings.opaque),
+    ] {
+        for pattern in patterns {
+            cmd.arg(flag).arg(pattern);
+        }
+    }
+    cmd.args(&bindings.args)
+        .args(["--", "-x", "c++"])
+        .args(houdini::hdk_flags(hfs, &version));
+    info!(
+        "Generating Rust bindings for {} HDK headers.",
+        bindings.headers.len()
+    );
+    debug!("Running {:?}", cmd);
+    let status = cmd
+        .status()
+        .with_context(|| format!("Failed to run {}", bindgen.display()))?;
+    if !status.success() {
+        bail!("bindgen failed to generate the bindings of the HDK headers");
+    }
+
+    let output = bindings
+        .output
+        .clone()
+        .unwrap_or_else(|| manifest_dir.join(DEFAULT_OUTPUT));
+    let contents =
+        fs::read(&generated).with_context(|| format!("Failed to read {}", generated.display()))?;
+    if fs::read(&output).ok().as_ref() == Some(&contents) {
+        info!("{} is up to date.", output.display());
+        return Ok(());
+    }
+    if let Some(parent) = output.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create directory {}", parent.display()))?;
+    }
+    fs::write(&output, contents)
+        .with_context(|| format!("Failed to write {}", output.display()))?;
+    println!("Wrote the HDK bindings to {}", output.display());
+    Ok(())
+}
@@ -0,0 +1,225 @@
+//! Sanitizer builds of both the Rust and the C++ code with `--sanitize`.
+//!
+//! The Rust code is instrumented with '-Zsanitizer', which requires a nightly toolchain and an
+//! explicit target so build scripts and proc macros are left alone, and the C++ code with the
+//! '-fsanitize' flags of the compiler. Since Houdini itself isn't instrumented, the sanitizer
+//! runtime of the C++ compiler is preloaded into the applications launched by cargo-hdk, along
+//! with options suited for Houdini, e.g. without leak detection, which would report every
+//! allocation Houdini keeps until it exits.
+
+use std::env;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use log::*;
+
+use crate::cmake;
+
+/// Sanitizers supported by '--sanitize'.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Sanitizer {
+    /// AddressSanitizer, finding out of bounds accesses and use after free.
+    Address,
+    /// UndefinedBehaviorSanitizer, for the C++ code only.
+    Undefined,
+    /// ThreadSanitizer, finding data races.
+    Thread,
+}
+
+impl Sanitizer {
+    /// The name used by the '-fsanitize' and '-Zsanitizer' flags.
+    fn name(self) -> &'static str {
+        match self {
+            Sanitizer::Address => "address",
+            Sanitizer::Undefined => "undefined",
+            Sanitizer::Thread => "thread",
+        }
+    }
+
+    /// The usual abbreviation, naming the separate build directories.
+    pub fn short_name(self) -> &'static str {
+        match self {
+            Sanitizer::Address => "asan",
+            Sanitizer::Undefined => "ubsan",
+            Sanitizer::Thread => "tsan",
+        }
+    }
+
+    /// Instrument the Rust code built by cargo, if the toolchain supports it.
+    pub fn configure_rust(self, toolchain: Option<&str>, target: Option<&str>) -> Result<()> {
+        if self == Sanitizer::Undefined {
+            info!("Only the C++ code is checked for undefined behavior, since rustc has no such sanitizer.");
+            return Ok(());
+        }
+        let mut rustc = match toolchain {
+            Some(toolchain) => {
+                let mut cmd = Command::new("rustup");
+                cmd.args(["run", toolchain, "rustc"]);
+                cmd
+            }
+            None => Command::new("rustc"),
+        };
+        let output = rustc
+            .arg("-vV")
+            .output()
+            .context("Failed to determine the rustc version")?;
+        let version = String::from_utf8_lossy(&output.stdout);
+        if !version.contains("-nightly") {
+            warn!(
+                "Only the C++ code is instrumented by the {} sanitizer, since '-Zsanitizer' requires a nightly Rust toolchain (e.g. 'cargo hdk +nightly').",
+                self.name()
+            );
+            return Ok(());
+        }
+
+        let mut flags = env::var("RUSTFLAGS").unwrap_or_default();
+        flags.push_str(&format!(" -Zsanitizer={}", self.name()));
+        env::set_var("RUSTFLAGS", flags.trim());
+        if self == Sanitizer::Thread {
+            // Data races in the standard library are only seen if it is instrumented as well.
+            env::set_var("CARGO_UNSTABLE_BUILD_STD", "std");
+        }
+        // Build scripts and proc macros are only excluded with an explicit target.
+        if target.is_none() {
+            let host = version
+                .lines()
+                .find_map(|line| line.strip_prefix("host: "))
+                .context("Failed to determine the host target from rustc")?;
+            env::set_var("CARGO_BUILD_TARGET", host);
+        }
+        Ok(())
+    }
+
+    /// Compiler flags instrumenting the C and C++ code.
+    pub fn compiler_flags(self) -> &'static [&'static str] {
+        if cfg!(windows) {
+            return &["/fsanitize=address"];
+        }
+        match self {
+            Sanitizer::Address => &["-fsanitize=address", "-fno-omit-frame-pointer"],
+            Sanitizer::Undefined => &["-fsanitize=undefined", "-fno-omit-frame-pointer"],
+            Sanitizer::Thread => &["-fsanitize=thread"],
+        }
+    }
+
+    /// Add the CMake arguments instrumenting the C code and linking the plugin with the sanitizer
+    /// to `cmake_args`. The C++ flags are set along with the other C++ flags from
+    /// `compiler_flags`.
+    pub fn cmake_args(self, build_dir: &Path, cmake_args: &mut Vec<String>) -> Result<()> {
+        if cfg!(windows) && self != Sanitizer::Address {
+            bail!(
+                "The {} sanitizer isn't supported by MSVC. Use '--sanitize address' instead.",
+                self.name()
+            );
+        }
+        let flags = self.compiler_flags();
+        cmake::merge_flags_arg(
+            build_dir,
+            "CMAKE_C_FLAGS",
+            "CFLAGS",
+            flags,
+            flags,
+            cmake_args,
+        );
+        if !cfg!(windows) {
+            let linker_flags = &flags[..1];
+            for var in ["CMAKE_SHARED_LINKER_FLAGS", "CMAKE_MODULE_LINKER_FLAGS"] {
+                cmake::merge_flags_arg(
+                    build_dir,
+                    var,
+                    "LDFLAGS",
+                    linker_flags,
+                    linker_flags,
+                    cmake_args,
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// File names of the shared sanitizer runtime of GCC and clang.
+    fn runtime_names(self) -> Vec<String> {
+        let (gcc, clang) = match self {
+            Sanitizer::Address => ("libasan.so", "asan"),
+            Sanitizer::Undefined => ("libubsan.so", "ubsan_standalone"),
+            Sanitizer::Thread => ("libtsan.so", "tsan"),
+        };
+        if cfg!(target_os = "macos") {
+            let name = clang.trim_end_matches("_standalone");
+            return vec![format!("libclang_rt.{}_osx_dynamic.dylib", name)];
+        }
+        vec![
+            gcc.to_string(),
+            format!("libclang_rt.{}.so", clang),
+            format!("libclang_rt.{}-{}.so", clang, env::consts::ARCH),
+        ]
+    }
+
+    /// The shared runtime of the sanitizer used by the given compiler.
+    fn runtime(self, compiler: &str) -> Option<PathBuf> {
+        let query = |arg: &str| {
+            let output = Command::new(compiler).arg(arg).output().ok()?;
+            if !output.status.success() {
+                return None;
+            }
+            Some(PathBuf::from(
+                String::from_utf8_lossy(&output.stdout).trim(),
+            ))
+        };
+        let runtime_dir = query("-print-runtime-dir");
+        self.runtime_names().iter().find_map(|name| {
+            // An unknown file is printed back as just its name.
+            let path = query(&format!("-print-file-name={}", name)).filter(|p| p.is_absolute());
+            path.or_else(|| runtime_dir.as_ref().map(|dir| dir.join(name)))
+                .filter(|path| path.is_file())
+        })
+    }
+
+    /// The environment for applications loading the instrumented plugin: the sanitizer runtime of
+    /// the C++ compiler configured in `build_dir`, preloaded since Houdini isn't instrumented, and
+    /// the sanitizer options.
+    pub fn runtime_env(self, build_dir: &Path) -> Vec<(&'static str, OsString)> {
+        let (options_var, defaults) = match self {
+            Sanitizer::Address => (
+                "ASAN_OPTIONS",
+                "detect_leaks=0:verify_asan_link_order=0:protect_shadow_gap=0",
+            ),
+            Sanitizer::Undefined => ("UBSAN_OPTIONS", "print_stacktrace=1"),
+            Sanitizer::Thread => ("TSAN_OPTIONS", "second_deadlock_stack=1"),
+        };
+        // Options set by the user come last so they take precedence.
+        let options = match env::var(options_var) {
+            Ok(user) if !user.is_empty() => format!("{}:{}", defaults, user),
+            _ => defaults.to_string(),
+        };
+        let mut envs = vec![(options_var, OsString::from(options))];
+        if cfg!(windows) {
+            return envs;
+        }
+
+        let compiler = cmake::cache_value(build_dir, "CMAKE_CXX_COMPILER");
+        match compiler.as_deref().and_then(|compiler| self.runtime(compiler)) {
+            Some(runtime) => {
+                let preload_var = if cfg!(target_os = "macos") {
+                    "DYLD_INSERT_LIBRARIES"
+                } else {
+                    "LD_PRELOAD"
+                };
+                let mut preload = runtime.into_os_string();
+                if let Some(user) = env::var_os(preload_var).filter(|user| !user.is_empty()) {
+                    preload.push(":");
+                    preload.push(user);
+                }
+                envs.push((preload_var, preload));
+            }
+            None => warn!(
+                "Couldn't find the {} sanitizer runtime of the C++ compiler to preload into Houdini.",
+                self.name()
+            ),
+        }
+        envs
+    }
+}
@@ -0,0 +1,392 @@
+//! Scaffolding of new HDK plugin crates with `cargo hdk new`.
+//!
+//! Templates are sets of files whose paths and contents may contain placeholders in the style of
+//! cargo-generate, e.g. `{{crate_name}}`, which are replaced when the project is generated.
+//! Besides the built-in templates, '--template' accepts a git repository URL or a local
+//! directory, which lets studios maintain their own plugin templates.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{Context, Result};
+use log::*;
+
+use crate::{houdini, tools};
+
+/// Files shared by all built-in templates.
+const COMMON_FILES: &[(&str, &str)] = &[
+    (
+        "Cargo.toml",
+        include_str!("../templates/common/Cargo.toml.in"),
+    ),
+    (".gitignore", include_str!("../templates/common/gitignore")),
+    (
+        "hdk/CMakeLists.txt",
+        include_str!("../templates/common/CMakeLists.txt"),
+    ),
+];
+
+/// A built-in template for one kind of Houdini operator.
+struct Template {
+    /// Name used with '--template'.
+    name: &'static str,
+    /// Prefix of the C++ class name following the HDK naming convention.
+    class_prefix: &'static str,
+    /// Files specific to this kind of operator.
+    files: &'static [(&'static str, &'static str)],
+}
+
+const TEMPLATES: &[Template] = &[
+    Template {
+        name: "sop",
+        class_prefix: "SOP",
+        files: &[
+            ("src/lib.rs", include_str!("../templates/sop/lib.rs")),
+            (
+                "hdk/src/{{class_name}}.h",
+                include_str!("../templates/sop/plugin.h"),
+            ),
+            (
+                "hdk/src/{{class_name}}.C",
+                include_str!("../templates/sop/plugin.C"),
+            ),
+        ],
+    },
+    Template {
+        name: "rop",
+        class_prefix: "ROP",
+        files: &[
+            ("src/lib.rs", include_str!("../templates/rop/lib.rs")),
+            (
+                "hdk/src/{{class_name}}.h",
+                include_str!("../templates/rop/plugin.h"),
+            ),
+            (
+                "hdk/src/{{class_name}}.C",
+                include_str!("../templates/rop/plugin.C"),
+            ),
+        ],
+    },
+    Template {
+        name: "dop",
+        class_prefix: "GAS",
+        files: &[
+            ("src/lib.rs", include_str!("../templates/dop/lib.rs")),
+            (
+                "hdk/src/{{class_name}}.h",
+                include_str!("../templates/dop/plugin.h"),
+            ),
+            (
+                "hdk/src/{{class_name}}.C",
+                include_str!("../templates/dop/plugin.C"),
+            ),
+        ],
+    },
+    Template {
+        name: "chop",
+        class_prefix: "CHOP",
+        files: &[
+            ("src/lib.rs", include_str!("../templates/chop/lib.rs")),
+            (
+                "hdk/src/{{class_name}}.h",
+                include_str!("../templates/chop/plugin.h"),
+            ),
+            (
+                "hdk/src/{{class_name}}.C",
+                include_str!("../templates/chop/plugin.C"),
+            ),
+        ],
+    },
+    Template {
+        name: "vop",
+        class_prefix: "VEX",
+        files: &[
+            ("src/lib.rs", include_str!("../templates/vop/lib.rs")),
+            (
+                "hdk/src/{{class_name}}.C",
+                include_str!("../templates/vop/plugin.C"),
+            ),
+        ],
+    },
+];
+
+/// Code generation for the parameters of verb SOPs, which are declared in an embedded DS file.
+const SOP_PROTO_HEADERS: &str = "
+# Code generation for the embedded DS file in {{class_name}}.C.
+houdini_generate_proto_headers( FILES src/{{class_name}}.C )
+";
+
+/// Options of `cargo hdk new`.
+struct NewArgs<'a> {
+    path: &'a str,
+    name: Option<&'a str>,
+    template: &'a str,
+    branch: Option<&'a str>,
+}
+
+impl<'a> NewArgs<'a> {
+    fn parse(args: &'a [String]) -> Result<Self> {
+        let mut path = None;
+        let mut name = None;
+        let mut template = "sop";
+        let mut branch = None;
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            let mut value = |option: &str| -> Result<&'a str> {
+                match arg.strip_prefix(option).and_then(|v| v.strip_prefix('=')) {
+                    Some(value) => Ok(value),
+                    None => iter
+                        .next()
+                        .map(String::as_str)
+                        .with_context(|| format!("Missing value for '{}'", option)),
+                }
+            };
+            if arg == "--template" || arg.starts_with("--template=") {
+                template = value("--template")?;
+            } else if arg == "--name" || arg.starts_with("--name=") {
+                name = Some(value("--name")?);
+            } else if arg == "--branch" || arg.starts_with("--branch=") {
+                branch = Some(value("--branch")?);
+            } else if arg.starts_with('-') {
+                bail!("Unknown option '{}' for 'cargo hdk new'", arg);
+            } else if path.is_none() {
+                path = Some(arg.as_str());
+            } else {
+                bail!("Unexpected argument '{}' for 'cargo hdk new'", arg);
+            }
+        }
+        let path = path.context(
+            "Missing the path of the new plugin. Usage: cargo hdk new <PATH> [--template <TEMPLATE>] [--branch <BRANCH>] [--name <NAME>]",
+        )?;
+        Ok(NewArgs {
+            path,
+            name,
+            template,
+            branch,
+        })
+    }
+}
+
+/// Check that the given name can be used as a crate name.
+fn validate_crate_name(name: &str) -> Result<()> {
+    let valid = name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        && matches!(name.chars().next(), Some(c) if c.is_ascii_alphabetic());
+    if !valid {
+        bail!(
+            "'{}' is not a valid crate name. Use letters, digits, '-' and '_', starting with a letter, or pass '--name <NAME>'.",
+            name
+        );
+    }
+    Ok(())
+}
+
+/// Split a crate name into its words.
+fn words(name: &str) -> impl Iterator<Item = &str> {
+    name.split(['-', '_']).filter(|w| !w.is_empty())
+}
+
+/// Capitalize the first letter of the given word.
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_ascii_uppercase().to_string() + chars.as_str(),
+        None => String::new(),
+    }
+}
+
+/// Replace each '{{name}}' in the given text with the corresponding value.
+pub fn substitute(text: &str, values: &[(&str, &str)]) -> String {
+    values.iter().fold(text.to_string(), |acc, (name, value)| {
+        acc.replace(&format!("{{{{{}}}}}", name), value)
+    })
+}
+
+/// Whether the template argument refers to a git repository.
+fn is_git_url(template: &str) -> bool {
+    template.contains("://") || template.starts_with("git@") || template.ends_with(".git")
+}
+
+/// Clone the given template repository into a temporary directory, returning its path.
+fn clone_template(url: &str, branch: Option<&str>) -> Result<PathBuf> {
+    let git = tools::find_program("git")
+        .context("Failed to find 'git', which is needed to use a template from a repository")?;
+    let dir = env::temp_dir().join(format!("cargo-hdk-template-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+
+    let mut cmd = Command::new(git);
+    cmd.args(["clone", "--depth", "1"]);
+    if let Some(branch) = branch {
+        cmd.arg("--branch").arg(branch);
+    }
+    cmd.arg(url).arg(&dir);
+    debug!("Running {:?}", cmd);
+    let status = cmd
+        .status()
+        .with_context(|| format!("Failed to clone template {}", url))?;
+    if !status.success() {
+        let _ = fs::remove_dir_all(&dir);
+        bail!(
+            "Failed to clone template {}: git exited with {}",
+            url,
+            status
+        );
+    }
+    Ok(dir)
+}
+
+/// Write a generated file, creating its parent directories as needed.
+fn write_file(path: &Path, contents: &[u8]) -> Result<()> {
+    debug!("Writing {}", path.display());
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create directory {}", dir.display()))?;
+    }
+    fs::write(path, contents).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Replace the placeholders in a template file, reporting any that have no value.
+fn expand(text: &str, values: &[(&str, &str)], file: &Path) -> Result<String> {
+    let text = substitute(text, values);
+    if text.contains("{{houdini_version}}") {
+        bail!(
+            "Template file {} uses '{{{{houdini_version}}}}', but the Houdini version is unknown. Set HFS to your Houdini installation.",
+            file.display()
+        );
+    }
+    Ok(text)
+}
+
+/// Copy a template directory into `dst`, replacing placeholders in paths and text files.
+///
+/// Version control data and the cargo-generate configuration are not copied.
+fn copy_template(src: &Path, dst: &Path, values: &[(&str, &str)]) -> Result<()> {
+    let entries =
+        fs::read_dir(src).with_context(|| format!("Failed to read directory {}", src.display()))?;
+    for entry in entries {
+        let entry = entry?;
+        let file_name = entry.file_name();
+        if file_name == ".git" || file_name == "cargo-generate.toml" {
+            continue;
+        }
+        let src_path = entry.path();
+        let dst_path = dst.join(expand(&file_name.to_string_lossy(), values, &src_path)?);
+        if entry.file_type()?.is_dir() {
+            copy_template(&src_path, &dst_path, values)?;
+            continue;
+        }
+        let contents = fs::read(&src_path)
+            .with_context(|| format!("Failed to read {}", src_path.display()))?;
+        match String::from_utf8(contents) {
+            Ok(text) => write_file(&dst_path, expand(&text, values, &src_path)?.as_bytes())?,
+            // Binary files are copied as is.
+            Err(err) => write_file(&dst_path, err.as_bytes())?,
+        }
+    }
+    Ok(())
+}
+
+/// Generate a new plugin crate as requested by the arguments following 'cargo hdk new'.
+pub fn new_project(args: &[String]) -> Result<()> {
+    let args = NewArgs::parse(args)?;
+    let builtin = TEMPLATES.iter().find(|t| t.name == args.template);
+    let local = Path::new(args.template);
+    if builtin.is_none() && !is_git_url(args.template) && !local.is_dir() {
+        let names: Vec<_> = TEMPLATES.iter().map(|t| t.name).collect();
+        bail!(
+            "Unknown template '{}'. Available templates are: {}, or a git repository URL or directory.",
+            args.template,
+            names.join(", ")
+        );
+    }
+
+    let path = Path::new(args.path);
+    let name = match args.name {
+        Some(name) => name.to_string(),
+        None => path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .with_context(|| format!("Failed to determine a crate name from '{}'", args.path))?
+            .to_string(),
+    };
+    validate_crate_name(&name)?;
+
+    if path
+        .read_dir()
+        .ok()
+        .and_then(|mut dir| dir.next())
+        .is_some()
+    {
+        bail!(
+            "Destination '{}' already exists and is not empty",
+            path.display()
+        );
+    }
+
+    let crate_name = name.replace('-', "_");
+    let label = words(&name).map(capitalize).collect::<Vec<_>>().join(" ");
+    // Custom templates may refer to the Houdini version of the installation in HFS.
+    let houdini_version = env::var_os("HFS")
+        .and_then(|hfs| houdini::version(Path::new(&hfs)).ok())
+        .map(|version| version.to_string());
+    let mut values = vec![
+        ("project-name", name.as_str()),
+        ("crate_name", crate_name.as_str()),
+        ("operator_name", crate_name.as_str()),
+        ("operator_label", label.as_str()),
+    ];
+    if let Some(version) = &houdini_version {
+        values.push(("houdini_version", version.as_str()));
+    }
+
+    let template = match builtin {
+        Some(template) => template,
+        None => {
+            if is_git_url(args.template) {
+                let dir = clone_template(args.template, args.branch)?;
+                let result = copy_template(&dir, path, &values);
+                let _ = fs::remove_dir_all(&dir);
+                result?;
+            } else {
+                copy_template(local, path, &values)?;
+            }
+            println!(
+                "Created plugin '{}' in {} from template {}.",
+                name,
+                path.display(),
+                args.template
+            );
+            return Ok(());
+        }
+    };
+
+    let class_name = format!(
+        "{}_{}",
+        template.class_prefix,
+        words(&name).map(capitalize).collect::<String>()
+    );
+    let proto_headers = if template.name == "sop" {
+        SOP_PROTO_HEADERS
+    } else {
+        ""
+    };
+    // Expanded first, since it contains other placeholders.
+    values.insert(0, ("proto_headers", proto_headers));
+    values.push(("class_name", class_name.as_str()));
+
+    for (file, contents) in COMMON_FILES.iter().chain(template.files) {
+        let file_path = path.join(substitute(file, &values));
+        write_file(&file_path, substitute(contents, &values).as_bytes())?;
+    }
+
+    println!(
+        "Created {} plugin '{}' in {}. Build it with 'cargo hdk' from that directory.",
+        template.name.to_uppercase(),
+        name,
+        path.display()
+    );
+    Ok(())
+}
@@ -0,0 +1,341 @@
+//! Binary size breakdown of the built plugin with `cargo hdk size`.
+//!
+//! The symbols of each plugin library are read with 'nm' and grouped by the Rust crate they come
+//! from, using their demangled names, while C++ code is attributed to its translation units using
+//! the object files in the build directory. Object files are measured before linking, so code the
+//! linker removes as unused still counts towards their size. The section sizes are read with
+//! 'size'. The LLVM versions of these tools are preferred, since they also read Mach-O and COFF
+//! files.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{Context, Result};
+use log::*;
+
+use crate::tools;
+use crate::verify;
+
+/// Number of entries printed for each breakdown.
+const TOP: usize = 20;
+
+/// A symbol with a size, as listed by 'nm'.
+struct Symbol {
+    name: String,
+    size: u64,
+}
+
+/// Find the first of the given tools on the 'PATH'.
+fn find_tool(names: &[&str]) -> Result<PathBuf> {
+    names
+        .iter()
+        .find_map(|name| tools::find_program(name))
+        .with_context(|| {
+            format!(
+                "Couldn't find '{}' on the 'PATH'. Install LLVM or GNU binutils.",
+                names.join("' or '")
+            )
+        })
+}
+
+/// The defined symbols with a size in the given binary, or only the exported symbols if
+/// `dynamic` is true.
+fn read_symbols(nm: &Path, path: &Path, dynamic: bool) -> Result<Vec<Symbol>> {
+    let output = Command::new(nm)
+        .arg("--print-size")
+        .arg("--defined-only")
+        .args(dynamic.then_some("--dynamic"))
+        .arg(path)
+        .output()
+        .with_context(|| format!("Failed to run {}", nm.display()))?;
+    if !output.status.success() {
+        bail!(
+            "Failed to read the symbols of {}: {}",
+            path.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    // Lines have the form '<address> <size> <type> <name>'.
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let _address = fields.next()?;
+            let size = u64::from_str_radix(fields.next()?, 16).ok()?;
+            let _kind = fields.next()?;
+            let name = fields.next()?.to_string();
+            Some(Symbol { name, size })
+        })
+        .filter(|symbol| symbol.size > 0)
+        .collect())
+}
+
+/// The sizes of the sections in the given binary.
+fn read_sections(size_tool: &Path, path: &Path) -> Result<Vec<(String, u64)>> {
+    let output = Command::new(size_tool)
+        .arg("-A")
+        .arg(path)
+        .output()
+        .with_context(|| format!("Failed to run {}", size_tool.display()))?;
+    if !output.status.success() {
+        bail!(
+            "Failed to read the sections of {}: {}",
+            path.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    // Lines have the form '<section> <size> <address>', after a header and followed by a total.
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<_> = line.split_whitespace().collect();
+            match fields.as_slice() {
+                [name, size, _address] if *name != "section" => {
+                    Some((name.to_string(), size.parse().ok()?))
+                }
+                _ => None,
+            }
+        })
+        .filter(|(_, size)| *size > 0)
+        .collect())
+}
+
+/// Returns true if the mangled name is a Rust symbol, in either the legacy or the v0 mangling.
+fn is_rust_symbol(name: &str) -> bool {
+    // Mach-O symbols start with an additional underscore.
+    let name = match name.strip_prefix('_') {
+        Some(rest) if rest.starts_with('_') => rest,
+        _ => name,
+    };
+    if name.starts_with("_R") {
+        return true;
+    }
+    // Legacy symbols are C++ style names ending in a hash, as in '_ZN4core3fmt5write17h<hash>E'.
+    name.starts_with("_ZN")
+        && name.len() > 20
+        && name.ends_with('E')
+        && name[name.len() - 20..].starts_with("17h")
+}
+
+/// Remove the hashes from a demangled Rust symbol, i.e. the crate disambiguators in brackets of
+/// the v0 mangling and the '::h<hash>' suffix of the legacy mangling.
+fn strip_hashes(symbol: &str) -> String {
+    let is_hash = |hash: &str| !hash.is_empty() && hash.chars().all(|c| c.is_ascii_hexdigit());
+    let mut stripped = String::with_capacity(symbol.len());
+    let mut rest = symbol;
+    while let Some(start) = rest.find('[') {
+        let end = rest[start..].find(']').map(|end| start + end);
+        match end {
+            Some(end) if is_hash(&rest[start + 1..end]) => {
+                stripped.push_str(&rest[..start]);
+                rest = &rest[end + 1..];
+            }
+            _ => {
+                stripped.push_str(&rest[..=start]);
+                rest = &rest[start + 1..];
+            }
+        }
+    }
+    stripped.push_str(rest);
+    match stripped.rsplit_once("::h") {
+        Some((path, hash)) if hash.len() == 16 && is_hash(hash) => path.to_string(),
+        _ => stripped,
+    }
+}
+
+/// The crate a demangled Rust symbol belongs to, e.g. 'core' for 'core::fmt::write' or
+/// 'mycrate' for '<mycrate::Foo as core::fmt::Debug>::fmt'. Methods of primitive types like
+/// '<str>::find' belong to 'core'.
+fn crate_name(symbol: &str) -> &str {
+    let path = symbol.trim_start_matches(['<', '&', '*', '(', '[']);
+    let path = path.strip_prefix("mut ").unwrap_or(path);
+    let path = path.strip_prefix("const ").unwrap_or(path);
+    let path = path.strip_prefix("dyn ").unwrap_or(path);
+    let end = path
+        .find([':', '[', ']', '<', '>', ',', ';', ' '])
+        .unwrap_or(path.len());
+    match &path[..end] {
+        "" | ")" | "!" | "bool" | "char" | "str" | "f16" | "f32" | "f64" | "f128" | "u8"
+        | "u16" | "u32" | "u64" | "u128" | "usize" | "i8" | "i16" | "i32" | "i64" | "i128"
+        | "isize" => "core",
+        name => name,
+    }
+}
+
+/// The object files of the CMake targets in the build directory.
+fn object_files(build_dir: &Path) -> Vec<PathBuf> {
+    let extension = if cfg!(windows) { "obj" } else { "o" };
+    let mut objects = Vec::new();
+    let mut dirs = vec![build_dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let entries = match dir.read_dir() {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else if path.extension().is_some_and(|ext| ext == extension)
+                // Objects of a target are in 'CMakeFiles/<target>.dir', unlike the objects CMake
+                // builds to detect the compiler.
+                && matches!(path.strip_prefix(build_dir), Ok(relative)
+                    if relative.components().any(|c| c.as_os_str().to_string_lossy().ends_with(".dir")))
+            {
+                objects.push(path);
+            }
+        }
+    }
+    objects.sort();
+    objects
+}
+
+/// The source file an object file was compiled from, e.g. 'src/SOP_Foo.cpp' for
+/// 'CMakeFiles/SOP_Foo.dir/src/SOP_Foo.cpp.o'.
+fn translation_unit(build_dir: &Path, object: &Path) -> String {
+    let relative = object.strip_prefix(build_dir).unwrap_or(object);
+    let components: Vec<_> = relative
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect();
+    let start = components
+        .iter()
+        .position(|c| c.ends_with(".dir"))
+        .map_or(0, |i| i + 1);
+    let source = components[start..].join("/");
+    match source.rsplit_once('.') {
+        Some((source, _)) => source.to_string(),
+        None => source,
+    }
+}
+
+/// Format a size in bytes for humans.
+pub fn format_size(size: u64) -> String {
+    if size >= 1 << 30 {
+        format!("{:.1} GiB", size as f64 / (1 << 30) as f64)
+    } else if size >= 1 << 20 {
+        format!("{:.1} MiB", size as f64 / (1 << 20) as f64)
+    } else if size >= 1 << 10 {
+        format!("{:.1} KiB", size as f64 / (1 << 10) as f64)
+    } else {
+        format!("{} B", size)
+    }
+}
+
+/// Print the largest entries of a breakdown, with their share of the given total.
+fn print_table(title: &str, mut entries: Vec<(String, u64)>, total: u64) {
+    entries.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    println!();
+    println!("{:>12} {:>7}  {}", "size", "share", title);
+    let share = |size: u64| 100.0 * size as f64 / total.max(1) as f64;
+    for (name, size) in entries.iter().take(TOP) {
+        println!(
+            "{:>12} {:>6.1}%  {}",
+            format_size(*size),
+            share(*size),
+            name
+        );
+    }
+    if entries.len() > TOP {
+        let rest = entries[TOP..].iter().map(|(_, size)| size).sum();
+        println!(
+            "{:>12} {:>6.1}%  ({} more)",
+            format_size(rest),
+            share(rest),
+            entries.len() - TOP
+        );
+    }
+}
+
+/// Print the size breakdown of each plugin library in the binary directory, attributing C++ code
+/// to the translation units built in `build_dir`.
+pub fn run(binary_dir: &Path, build_dir: &Path) -> Result<()> {
+    let plugins = verify::find_plugins(binary_dir);
+    if plugins.is_empty() {
+        bail!(
+            "No plugin libraries found in {0}/dso or {0}. Use 'houdini_configure_target' in the 'CMakeLists.txt' to place the plugin there.",
+            binary_dir.display()
+        );
+    }
+    let nm = find_tool(&["llvm-nm", "nm"])?;
+    let size_tool = find_tool(&["llvm-size", "size"])?;
+
+    for plugin in &plugins {
+        let file_size = plugin
+            .metadata()
+            .with_context(|| format!("Failed to read {}", plugin.display()))?
+            .len();
+        println!();
+        println!("{} ({})", plugin.display(), format_size(file_size));
+
+        let sections = read_sections(&size_tool, plugin)?;
+        let sections_total = sections.iter().map(|(_, size)| size).sum();
+        print_table("section", sections, sections_total);
+
+        let mut symbols = read_symbols(&nm, plugin, false)?;
+        if symbols.is_empty() {
+            warn!(
+                "{} is stripped, so only its exported symbols are listed.",
+                plugin.display()
+            );
+            symbols = read_symbols(&nm, plugin, true)?;
+        }
+        let total: u64 = symbols.iter().map(|symbol| symbol.size).sum();
+        let rust: Vec<bool> = symbols
+            .iter()
+            .map(|symbol| is_rust_symbol(&symbol.name))
+            .collect();
+        if tools::find_program("c++filt").is_none() {
+            info!("Install 'c++filt' to see demangled symbol names.");
+        }
+        let names: Vec<_> =
+            verify::demangle_names(symbols.iter().map(|symbol| symbol.name.clone()).collect())
+                .into_iter()
+                .zip(&rust)
+                .map(|(name, rust)| if *rust { strip_hashes(&name) } else { name })
+                .collect();
+
+        let mut crates: HashMap<String, u64> = HashMap::new();
+        let mut cpp = 0;
+        for ((name, symbol), rust) in names.iter().zip(&symbols).zip(&rust) {
+            if *rust {
+                *crates.entry(crate_name(name).to_string()).or_default() += symbol.size;
+            } else {
+                cpp += symbol.size;
+            }
+        }
+        if cpp > 0 {
+            crates.insert("(C++ and C)".to_string(), cpp);
+        }
+        print_table("crate", crates.into_iter().collect(), total);
+
+        let largest = names
+            .into_iter()
+            .zip(&symbols)
+            .map(|(name, symbol)| (name, symbol.size))
+            .collect();
+        print_table("symbol", largest, total);
+    }
+
+    let objects = object_files(build_dir);
+    if objects.is_empty() {
+        info!(
+            "No object files found in {} to attribute the C++ code to.",
+            build_dir.display()
+        );
+    } else {
+        let mut units = Vec::new();
+        for object in &objects {
+            let size = read_symbols(&nm, object, false)?
+                .iter()
+                .map(|symbol| symbol.size)
+                .sum();
+            units.push((translation_unit(build_dir, object), size));
+        }
+        let total = units.iter().map(|(_, size)| size).sum();
+        print_table("C++ translation unit (before linking)", units, total);
+    }
+    println!();
+    Ok(())
+}
@@ -0,0 +1,213 @@
+"""Golden geometry snapshot test run with hython by 'cargo hdk test'.
+
+The test case is given by the CARGO_HDK_SNAPSHOT_* environment variables. The geometry cooked by
+the node is compared with the golden file, and the differences are printed if they don't match.
+"""
+
+import os
+import runpy
+import sys
+
+import hou
+
+# Number of differing elements reported for each attribute.
+MAX_DIFFS = 10
+
+# Attribute classes along with the geometry methods listing and reading their attributes.
+ATTRIB_CLASSES = (
+    ("point", "pointAttribs", "point"),
+    ("primitive", "primAttribs", "prim"),
+    ("vertex", "vertexAttribs", "vertex"),
+    ("detail", "globalAttribs", None),
+)
+
+VALUE_METHODS = {
+    hou.attribData.Float: "FloatAttribValues",
+    hou.attribData.Int: "IntAttribValues",
+    hou.attribData.String: "StringAttribValues",
+}
+
+
+def fail(message):
+    print(message)
+    sys.exit(1)
+
+
+def parse_tolerances(value):
+    """Parse per attribute tolerances given as 'name=tolerance,...'."""
+    tolerances = {}
+    for item in filter(None, value.split(",")):
+        name, tolerance = item.split("=", 1)
+        tolerances[name] = float(tolerance)
+    return tolerances
+
+
+def attrib_values(geo, attrib, method_prefix):
+    """All values of the attribute as a flat tuple, or None if it can't be compared."""
+    if method_prefix is None:
+        value = geo.attribValue(attrib.name())
+        return value if isinstance(value, tuple) else (value,)
+    method = VALUE_METHODS.get(attrib.dataType())
+    if method is None:
+        return None
+    return getattr(geo, method_prefix + method)(attrib.name())
+
+
+def format_values(values):
+    return values[0] if len(values) == 1 else "(%s)" % ", ".join(str(v) for v in values)
+
+
+def compare_values(label, name, size, actual, expected, tolerance, diffs):
+    mismatched = []
+    largest = 0.0
+    for i, (a, e) in enumerate(zip(actual, expected)):
+        if isinstance(e, float):
+            difference = abs(a - e)
+            largest = max(largest, difference)
+            matches = difference <= tolerance
+        else:
+            matches = a == e
+        element = i // size
+        if not matches and (not mismatched or mismatched[-1] != element):
+            mismatched.append(element)
+    if not mismatched:
+        return
+    summary = "%s attribute '%s' differs in %d of %d elements" % (
+        label.capitalize(),
+        name,
+        len(mismatched),
+        len(expected) // size,
+    )
+    if largest > tolerance:
+        summary += " (largest difference %g, tolerance %g)" % (largest, tolerance)
+    diffs.append(summary + ":")
+    for element in mismatched[:MAX_DIFFS]:
+        values = slice(element * size, (element + 1) * size)
+        diffs.append(
+            "  %s %d: expected %s, got %s"
+            % (
+                label,
+                element,
+                format_values(expected[values]),
+                format_values(actual[values]),
+            )
+        )
+    if len(mismatched) > MAX_DIFFS:
+        diffs.append("  ... and %d more" % (len(mismatched) - MAX_DIFFS))
+
+
+def topology(geo):
+    return [
+        (prim.type().name(), tuple(v.point().number() for v in prim.vertices()))
+        for prim in geo.prims()
+    ]
+
+
+def compare(actual, expected, tolerance, tolerances):
+    """Describe the differences between the actual and the expected geometry."""
+    diffs = []
+    counts_match = True
+    for label, intrinsic in (
+        ("points", "pointcount"),
+        ("primitives", "primitivecount"),
+        ("vertices", "vertexcount"),
+    ):
+        a = actual.intrinsicValue(intrinsic)
+        e = expected.intrinsicValue(intrinsic)
+        if a != e:
+            counts_match = False
+            diffs.append("Number of %s differs: expected %d, got %d" % (label, e, a))
+    # Values can only be compared element by element if the counts match.
+    if not counts_match:
+        return diffs
+
+    actual_prims = topology(actual)
+    expected_prims = topology(expected)
+    mismatched = [
+        i for i, (a, e) in enumerate(zip(actual_prims, expected_prims)) if a != e
+    ]
+    if mismatched:
+        diffs.append("Topology differs in %d primitives:" % len(mismatched))
+        for i in mismatched[:MAX_DIFFS]:
+            diffs.append(
+                "  primitive %d: expected %s, got %s"
+                % (i, expected_prims[i], actual_prims[i])
+            )
+
+    for label, attribs_method, method_prefix in ATTRIB_CLASSES:
+        actual_attribs = {a.name(): a for a in getattr(actual, attribs_method)()}
+        expected_attribs = {a.name(): a for a in getattr(expected, attribs_method)()}
+        for name in sorted(set(expected_attribs) - set(actual_attribs)):
+            diffs.append("Missing %s attribute '%s'" % (label, name))
+        for name in sorted(set(actual_attribs) - set(expected_attribs)):
+            diffs.append("Unexpected %s attribute '%s'" % (label, name))
+        for name in sorted(set(actual_attribs) & set(expected_attribs)):
+            a, e = actual_attribs[name], expected_attribs[name]
+            if a.dataType() != e.dataType() or a.size() != e.size():
+                diffs.append(
+                    "Type of %s attribute '%s' differs: expected %s[%d], got %s[%d]"
+                    % (label, name, e.dataType().name(), e.size(), a.dataType().name(), a.size())
+                )
+                continue
+            actual_values = attrib_values(actual, a, method_prefix)
+            expected_values = attrib_values(expected, e, method_prefix)
+            if actual_values is None or expected_values is None:
+                continue
+            compare_values(
+                label,
+                name,
+                max(e.size(), 1),
+                actual_values,
+                expected_values,
+                tolerances.get(name, tolerance),
+                diffs,
+            )
+    return diffs
+
+
+def main():
+    env = os.environ
+    hip = env.get("CARGO_HDK_SNAPSHOT_HIP")
+    script = env.get("CARGO_HDK_SNAPSHOT_SCRIPT")
+    node_path = env["CARGO_HDK_SNAPSHOT_NODE"]
+    golden = env["CARGO_HDK_SNAPSHOT_GOLDEN"]
+    tolerance = float(env["CARGO_HDK_SNAPSHOT_TOLERANCE"])
+    tolerances = parse_tolerances(env.get("CARGO_HDK_SNAPSHOT_TOLERANCES", ""))
+
+    if hip:
+        hou.hipFile.load(hip, suppress_save_prompt=True, ignore_load_warnings=True)
+    if script:
+        runpy.run_path(script, run_name="__main__")
+
+    node = hou.node(node_path)
+    if node is None:
+        fail("Node %s doesn't exist." % node_path)
+    if not isinstance(node, hou.SopNode):
+        fail("Node %s is not a SOP node." % node_path)
+    node.cook(force=True)
+    geo = node.geometry()
+
+    if env.get("CARGO_HDK_SNAPSHOT_UPDATE") == "1":
+        directory = os.path.dirname(golden)
+        if directory and not os.path.isdir(directory):
+            os.makedirs(directory)
+        geo.saveToFile(golden)
+        print("Updated golden file %s" % golden)
+        return
+
+    if not os.path.exists(golden):
+        fail(
+            "Golden file %s doesn't exist. Run 'cargo hdk test --update-snapshots' to create it."
+            % golden
+        )
+    expected = hou.Geometry()
+    expected.loadFromFile(golden)
+
+    diffs = compare(geo, expected, tolerance, tolerances)
+    if diffs:
+        print("Geometry of %s doesn't match %s:" % (node_path, golden))
+        print("\n".join(diffs))
+        sys.exit(1)
+
+
+main()
@@ -0,0 +1,73 @@
+//! Static linking of the C++ runtime with '--static-crt'.
+//!
+//! On Windows the plugin is built with the static MSVC runtime selected by
+//! 'CMAKE_MSVC_RUNTIME_LIBRARY', and the Rust code with '+crt-static' so both link the same one.
+//! On Linux libstdc++ and libgcc are linked statically with '-static-libstdc++ -static-libgcc'.
+//! This lets the plugin load on machines with older runtimes than the build machine, at the cost
+//! of a copy of the runtime that isn't shared with Houdini.
+
+use std::env;
+use std::path::Path;
+
+use log::*;
+
+use crate::cmake;
+
+/// Linker flags linking the GCC runtime libraries statically.
+const GCC_LINKER_FLAGS: &[&str] = &["-static-libstdc++", "-static-libgcc"];
+
+/// The static MSVC runtime, with the debug one for Debug builds like CMake's default.
+const MSVC_STATIC_RUNTIME: &str = "MultiThreaded$<$<CONFIG:Debug>:Debug>";
+
+/// The default shared MSVC runtime of CMake.
+const MSVC_SHARED_RUNTIME: &str = "MultiThreaded$<$<CONFIG:Debug>:Debug>DLL";
+
+/// rustc flag linking the C runtime statically on MSVC targets.
+const RUST_CRT_STATIC: &str = "-C target-feature=+crt-static";
+
+/// Warn about the caveats of a runtime that isn't shared with Houdini.
+pub fn warn_abi(msvc: bool) {
+    if msvc {
+        warn!("With '--static-crt' the plugin has its own C runtime with its own heap. Memory, file handles and other runtime objects must not be passed between the plugin and Houdini, e.g. memory allocated by the plugin mustn't be freed by Houdini.");
+    } else if cfg!(target_os = "linux") {
+        warn!("With '--static-crt' the plugin has its own copy of libstdc++. Standard library objects and exceptions passed between the plugin and Houdini rely on both copies agreeing, so build with a GCC Houdini is built with, and don't throw exceptions across the HDK.");
+    } else {
+        warn!("'--static-crt' is ignored on macOS, whose C++ runtime is part of the system.");
+    }
+}
+
+/// Link the Rust code with the static C runtime on MSVC targets, so it matches the plugin.
+pub fn configure_rust() {
+    let mut flags = env::var("RUSTFLAGS").unwrap_or_default();
+    if !flags.contains("crt-static") {
+        flags.push_str(&format!(" {}", RUST_CRT_STATIC));
+    }
+    env::set_var("RUSTFLAGS", flags.trim());
+}
+
+/// CMake arguments selecting the static or, if it was static before, the shared runtime of the
+/// plugin, leaving out the variable if it is set in `cmake_args`.
+///
+/// On Linux the linker flags are added to or removed from the linker flags of the build
+/// directory, or of the arguments in `cmake_args` setting them already.
+pub fn cmake_args(build_dir: &Path, enabled: bool, msvc: bool, cmake_args: &mut Vec<String>) {
+    if msvc {
+        let var = "CMAKE_MSVC_RUNTIME_LIBRARY";
+        let runtime = if enabled {
+            Some(MSVC_STATIC_RUNTIME)
+        } else {
+            // Only a runtime selected by an earlier build with '--static-crt' is reset.
+            cmake::cache_value(build_dir, var)
+                .filter(|runtime| runtime == MSVC_STATIC_RUNTIME)
+                .map(|_| MSVC_SHARED_RUNTIME)
+        };
+        let args = cmake::default_var_args(vec![(var, runtime.map(String::from))], cmake_args);
+        cmake_args.extend(args);
+    } else if cfg!(target_os = "linux") {
+        let add = if enabled { GCC_LINKER_FLAGS } else { &[] };
+        for var in ["CMAKE_SHARED_LINKER_FLAGS", "CMAKE_MODULE_LINKER_FLAGS"] {
+            // Other options like '--sanitize' may have changed the flags already.
+            cmake::merge_flags_arg(build_dir, var, "LDFLAGS", GCC_LINKER_FLAGS, add, cmake_args);
+        }
+    }
+}
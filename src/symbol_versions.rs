@@ -0,0 +1,271 @@
+//! Audit of the glibc and libstdc++ symbol versions required by the plugin libraries on Linux.
+//!
+//! A plugin linked on a newer distribution than the machines running Houdini may require symbol
+//! versions like 'GLIBC_2.34' or 'GLIBCXX_3.4.29' that the C and C++ runtime of those machines
+//! (e.g. a CentOS or Rocky Linux render farm) doesn't provide, so it fails to load there. After a
+//! build, the versions required by the plugin libraries are compared with the newest ones the
+//! Houdini libraries require themselves, which every machine running Houdini provides, or with
+//! those configured in the 'symbol_versions' table for the oldest platform the plugin supports.
+
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use log::*;
+
+use crate::binary::{read_u16, read_u32, read_u64};
+use crate::config::SymbolVersions;
+use crate::verify;
+
+/// Version families of the C and C++ runtime checked, with the keys of the 'symbol_versions'
+/// table.
+const FAMILIES: &[(&str, &str)] = &[
+    ("GLIBC", "glibc"),
+    ("GLIBCXX", "glibcxx"),
+    ("CXXABI", "cxxabi"),
+];
+
+/// Number of symbols listed for each version that is too new.
+const MAX_SYMBOLS: usize = 5;
+
+/// ELF section types of the dynamic symbols, the version requirements and the symbol versions.
+const SHT_DYNSYM: u32 = 11;
+const SHT_GNU_VERNEED: u32 = 0x6fff_fffe;
+const SHT_GNU_VERSYM: u32 = 0x6fff_ffff;
+
+/// A section of an ELF file.
+struct Section {
+    kind: u32,
+    offset: u64,
+    size: u64,
+    link: u32,
+    info: u32,
+}
+
+fn read_at(file: &mut File, offset: u64, len: u64) -> io::Result<Vec<u8>> {
+    file.seek(SeekFrom::Start(offset))?;
+    let mut data = Vec::new();
+    file.take(len).read_to_end(&mut data)?;
+    Ok(data)
+}
+
+/// The null terminated string at `offset` of a string table.
+fn string_at(table: &[u8], offset: u32) -> Option<String> {
+    let start = offset as usize;
+    let length = table.get(start..)?.iter().position(|&b| b == 0)?;
+    Some(String::from_utf8_lossy(&table[start..start + length]).into_owned())
+}
+
+/// The symbol versions the given 64-bit little-endian ELF library requires from other libraries,
+/// with the undefined symbols requiring each of them if `with_symbols` is true.
+fn requirements(path: &Path, with_symbols: bool) -> Result<BTreeMap<String, Vec<String>>> {
+    let invalid = || format!("{} is not a 64-bit little-endian ELF file", path.display());
+    let mut file =
+        File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let header = read_at(&mut file, 0, 64)?;
+    if header.get(0..6) != Some(b"\x7fELF\x02\x01") {
+        bail!(invalid());
+    }
+    let section_offset = read_u64(&header, 0x28).with_context(invalid)?;
+    let entry_size = read_u16(&header, 0x3a).with_context(invalid)? as usize;
+    let count = read_u16(&header, 0x3c).with_context(invalid)? as usize;
+    let table = read_at(&mut file, section_offset, (entry_size * count) as u64)?;
+    let sections: Vec<Section> = (0..count)
+        .map(|index| {
+            let header = index * entry_size;
+            Some(Section {
+                kind: read_u32(&table, header + 4)?,
+                offset: read_u64(&table, header + 24)?,
+                size: read_u64(&table, header + 32)?,
+                link: read_u32(&table, header + 40)?,
+                info: read_u32(&table, header + 44)?,
+            })
+        })
+        .collect::<Option<_>>()
+        .with_context(invalid)?;
+    let mut read_section = |index: u32| -> Result<Vec<u8>> {
+        let section = sections.get(index as usize).with_context(invalid)?;
+        Ok(read_at(&mut file, section.offset, section.size)?)
+    };
+
+    let mut required = BTreeMap::new();
+    let verneed = match sections.iter().position(|s| s.kind == SHT_GNU_VERNEED) {
+        Some(index) => index,
+        None => return Ok(required),
+    };
+    let strings = read_section(sections[verneed].link)?;
+    let data = read_section(verneed as u32)?;
+    // The names of the required versions by their index in the symbol version table.
+    let mut names = BTreeMap::new();
+    let mut entry = 0;
+    for _ in 0..sections[verneed].info {
+        let aux_count = read_u16(&data, entry + 2).with_context(invalid)?;
+        let mut aux = entry + read_u32(&data, entry + 8).with_context(invalid)? as usize;
+        for _ in 0..aux_count {
+            let index = read_u16(&data, aux + 6).with_context(invalid)?;
+            let name = read_u32(&data, aux + 8).and_then(|name| string_at(&strings, name));
+            let name = name.with_context(invalid)?;
+            required.insert(name.clone(), Vec::new());
+            names.insert(index, name);
+            aux += read_u32(&data, aux + 12).with_context(invalid)? as usize;
+        }
+        entry += read_u32(&data, entry + 12).with_context(invalid)? as usize;
+    }
+    if !with_symbols {
+        return Ok(required);
+    }
+
+    let (dynsym, versym) = match (
+        sections.iter().position(|s| s.kind == SHT_DYNSYM),
+        sections.iter().position(|s| s.kind == SHT_GNU_VERSYM),
+    ) {
+        (Some(dynsym), Some(versym)) => (dynsym, versym),
+        _ => return Ok(required),
+    };
+    let symbol_names = read_section(sections[dynsym].link)?;
+    let symbols = read_section(dynsym as u32)?;
+    let versions = read_section(versym as u32)?;
+    for (index, symbol) in symbols.chunks_exact(24).enumerate() {
+        // Only undefined symbols are required from other libraries.
+        if read_u16(symbol, 6) != Some(0) {
+            continue;
+        }
+        let version = read_u16(&versions, index * 2).map(|version| version & 0x7fff);
+        let name = version.and_then(|version| names.get(&version));
+        let symbol = read_u32(symbol, 0).and_then(|name| string_at(&symbol_names, name));
+        if let (Some(name), Some(symbol)) = (name, symbol) {
+            required.entry(name.clone()).or_default().push(symbol);
+        }
+    }
+    Ok(required)
+}
+
+/// Split a version like 'GLIBCXX_3.4.29' into its family and comparable numbers, or `None` for
+/// versions like 'GLIBC_PRIVATE'.
+fn parse_version(version: &str) -> Option<(&str, Vec<u32>)> {
+    let (family, number) = version.split_once('_')?;
+    let numbers = number
+        .split('.')
+        .map(|part| part.parse().ok())
+        .collect::<Option<_>>()?;
+    Some((family, numbers))
+}
+
+/// The newest versions of the checked families required by the Houdini libraries.
+fn houdini_baseline(hfs: &Path) -> BTreeMap<String, Vec<u32>> {
+    let mut newest: BTreeMap<String, Vec<u32>> = BTreeMap::new();
+    let libraries = fs::read_dir(hfs.join("dsolib"))
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            name.starts_with("libHoudini") && name.ends_with(".so")
+        });
+    for library in libraries {
+        let required = match requirements(&library, false) {
+            Ok(required) => required,
+            Err(err) => {
+                debug!("{:#}", err);
+                continue;
+            }
+        };
+        for (family, numbers) in required.keys().filter_map(|v| parse_version(v)) {
+            let entry = newest.entry(family.to_string()).or_default();
+            if numbers > *entry {
+                *entry = numbers;
+            }
+        }
+    }
+    newest
+}
+
+/// Warn about symbol versions required by the given plugin libraries that are newer than those
+/// configured in `config`, or otherwise those required by the Houdini libraries in `hfs`.
+pub fn check(plugins: &[PathBuf], hfs: &Path, config: Option<&SymbolVersions>) {
+    let houdini = houdini_baseline(hfs);
+    let mut baseline = BTreeMap::new();
+    for (family, key) in FAMILIES {
+        let configured = config.and_then(|config| match *key {
+            "glibc" => config.glibc.as_deref(),
+            "glibcxx" => config.glibcxx.as_deref(),
+            _ => config.cxxabi.as_deref(),
+        });
+        let limit = match configured {
+            Some(version) => match parse_version(&format!("{}_{}", family, version)) {
+                Some((_, numbers)) => (numbers, format!("'symbol_versions.{}'", key)),
+                None => {
+                    warn!("Invalid version '{}' of 'symbol_versions.{}'", version, key);
+                    continue;
+                }
+            },
+            None => match houdini.get(*family) {
+                Some(numbers) => (numbers.clone(), "Houdini".to_string()),
+                None => continue,
+            },
+        };
+        baseline.insert(*family, limit);
+    }
+    if baseline.is_empty() {
+        debug!("No symbol versions to check the plugin libraries against");
+        return;
+    }
+
+    for plugin in plugins {
+        let required = match requirements(plugin, true) {
+            Ok(required) => required,
+            Err(err) => {
+                warn!(
+                    "Failed to read the symbol versions of the plugin: {:#}",
+                    err
+                );
+                continue;
+            }
+        };
+        let mut problems = Vec::new();
+        for (version, symbols) in &required {
+            let (family, numbers) = match parse_version(version) {
+                Some(parsed) => parsed,
+                None => continue,
+            };
+            let (limit, source) = match baseline.get(family) {
+                Some(limit) => limit,
+                None => continue,
+            };
+            if numbers <= *limit {
+                continue;
+            }
+            let limit = limit
+                .iter()
+                .map(|n| n.to_string())
+                .collect::<Vec<_>>()
+                .join(".");
+            let mut line = format!(
+                "  {} (newer than {}_{} of {})",
+                version, family, limit, source
+            );
+            if !symbols.is_empty() {
+                let listed: Vec<_> = symbols
+                    .iter()
+                    .take(MAX_SYMBOLS)
+                    .map(String::as_str)
+                    .collect();
+                line.push_str(&format!(": {}", verify::demangle(&listed.join(", "))));
+                if symbols.len() > MAX_SYMBOLS {
+                    line.push_str(&format!(" and {} more", symbols.len() - MAX_SYMBOLS));
+                }
+            }
+            problems.push(line);
+        }
+        if !problems.is_empty() {
+            warn!(
+                "{} requires symbol versions that machines running Houdini may not provide, so it may fail to load there:\n{}\nBuild on the oldest distribution the plugin must run on, e.g. in a container, or with a GCC toolset that links newer libstdc++ parts statically like the one Houdini is built with.",
+                plugin.display(),
+                problems.join("\n")
+            );
+        }
+    }
+}
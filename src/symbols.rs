@@ -0,0 +1,194 @@
+//! Debug symbols of the plugin libraries.
+//!
+//! On Windows, MSVC writes the debug information of a DLL to a separate PDB file, which is needed
+//! to symbolize the crash dumps of Houdini sessions. Debug and RelWithDebInfo builds are
+//! configured to compile with debug information and link with '/DEBUG', and the PDBs are
+//! installed along with the plugin libraries by '--cmake-install'.
+//!
+//! On Linux and macOS, '--split-debuginfo' moves the debug information of the installed plugin
+//! libraries into separate symbol files, so the shipped libraries are small while crashes can
+//! still be symbolized: a '.debug' file linked to the library with '.gnu_debuglink' on Linux, and
+//! a dSYM bundle on macOS.
+
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{Context, Result};
+use log::*;
+
+use crate::cmake;
+use crate::tools;
+
+/// MSVC flags selecting the format of the debug information.
+pub const MSVC_DEBUG_INFO_FLAGS: &[&str] = &["/Zi", "/Z7"];
+
+/// MSVC linker flags writing a PDB.
+pub const MSVC_DEBUG_LINKER_FLAGS: &[&str] = &["/DEBUG"];
+
+/// Returns true if builds of the given CMake build type have debug information.
+pub fn has_debug_info(build_type: &str) -> bool {
+    matches!(build_type, "Debug" | "RelWithDebInfo")
+}
+
+/// The MSVC flag compiling with debug information.
+///
+/// '/Zi' writes it to a PDB shared by all compilations, which compiler caches can't handle, so
+/// with a compiler cache '/Z7' embeds it in the object files instead. The linker writes the PDB
+/// of the DLL either way.
+pub fn msvc_debug_info_flag(compiler_cache: bool) -> &'static str {
+    if compiler_cache {
+        "/Z7"
+    } else {
+        "/Zi"
+    }
+}
+
+/// CMake arguments linking the plugin libraries with '/DEBUG' if `debug_info` is true, and
+/// without it otherwise.
+pub fn msvc_linker_args(build_dir: &Path, debug_info: bool) -> Vec<String> {
+    let add = if debug_info {
+        MSVC_DEBUG_LINKER_FLAGS
+    } else {
+        &[]
+    };
+    ["CMAKE_SHARED_LINKER_FLAGS", "CMAKE_MODULE_LINKER_FLAGS"]
+        .iter()
+        .filter_map(|var| cmake::flags_arg(build_dir, var, "LDFLAGS", MSVC_DEBUG_LINKER_FLAGS, add))
+        .collect()
+}
+
+/// Copy the PDBs of the given plugin libraries next to the installed copies of the libraries
+/// recorded in the install manifests of `build_dir`, or into `symbols_dir` if given.
+///
+/// Returns the paths of the copied PDBs.
+pub fn install_pdbs(
+    plugins: &[PathBuf],
+    build_dir: &Path,
+    symbols_dir: Option<&Path>,
+) -> Result<Vec<PathBuf>> {
+    let installed = cmake::installed_files(build_dir);
+    let mut copied = Vec::new();
+    for plugin in plugins {
+        let pdb = plugin.with_extension("pdb");
+        if !pdb.is_file() {
+            continue;
+        }
+        let name = plugin.file_name();
+        let targets: Vec<PathBuf> = match symbols_dir {
+            Some(dir) => vec![dir.to_path_buf()],
+            None => installed
+                .iter()
+                .filter(|path| path.file_name() == name)
+                .filter_map(|path| path.parent().map(Path::to_path_buf))
+                .collect(),
+        };
+        for dir in targets {
+            let target = dir.join(pdb.file_name().unwrap_or_default());
+            fs::create_dir_all(&dir)
+                .with_context(|| format!("Failed to create directory {}", dir.display()))?;
+            debug!("Copying {} to {}", pdb.display(), target.display());
+            fs::copy(&pdb, &target).with_context(|| {
+                format!("Failed to copy {} to {}", pdb.display(), target.display())
+            })?;
+            copied.push(target);
+        }
+    }
+    Ok(copied)
+}
+
+/// The installed copies of the given plugin library recorded in the install manifests.
+fn installed_copies<'a>(plugin: &Path, installed: &'a [PathBuf]) -> Vec<&'a PathBuf> {
+    installed
+        .iter()
+        .filter(|path| path.file_name() == plugin.file_name())
+        .collect()
+}
+
+/// Run a tool working on the library at `path`, failing with its name if it doesn't succeed.
+fn run_tool(cmd: &mut Command, path: &Path) -> Result<()> {
+    let program = cmd.get_program().to_string_lossy().into_owned();
+    debug!("Running {:?}", cmd);
+    let status = cmd
+        .status()
+        .with_context(|| format!("Failed to run {}", program))?;
+    if !status.success() {
+        bail!("{} failed on {}", program, path.display());
+    }
+    Ok(())
+}
+
+/// Move the debug information of the ELF library at `library` to `symbols` with 'objcopy',
+/// leaving a stripped library that refers to the symbol file by name.
+fn split_elf(library: &Path, symbols: &Path) -> Result<()> {
+    let objcopy = tools::find_program("objcopy")
+        .or_else(|| tools::find_program("llvm-objcopy"))
+        .context("Couldn't find 'objcopy' or 'llvm-objcopy' on the 'PATH' for '--split-debuginfo'. It is part of binutils.")?;
+    run_tool(
+        Command::new(&objcopy)
+            .arg("--only-keep-debug")
+            .arg(library)
+            .arg(symbols),
+        library,
+    )?;
+    let mut debuglink = OsString::from("--add-gnu-debuglink=");
+    debuglink.push(symbols);
+    run_tool(
+        Command::new(&objcopy)
+            .arg("--strip-unneeded")
+            .arg(debuglink)
+            .arg(library),
+        library,
+    )
+}
+
+/// Write the debug information of the Mach-O library at `library` into the dSYM bundle
+/// `symbols` with 'dsymutil', and strip the local symbols of the library.
+fn split_macho(library: &Path, symbols: &Path) -> Result<()> {
+    let dsymutil = tools::find_program("dsymutil").context(
+        "Couldn't find 'dsymutil' on the 'PATH'. It is installed with the Xcode command line tools.",
+    )?;
+    run_tool(
+        Command::new(dsymutil).arg(library).arg("-o").arg(symbols),
+        library,
+    )?;
+    let strip = tools::find_program("strip").context("Couldn't find 'strip' on the 'PATH'")?;
+    run_tool(Command::new(strip).arg("-x").arg(library), library)
+}
+
+/// Move the debug information of the installed copies of the given plugin libraries into symbol
+/// files next to them, or into `symbols_dir` if given, and strip the libraries.
+///
+/// The symbol file of a library is '<library>.debug' on Linux and '<library>.dSYM' on macOS. The
+/// PDBs of Windows libraries are left to `install_pdbs`. Returns the paths of the symbol files.
+pub fn split_installed(
+    plugins: &[PathBuf],
+    build_dir: &Path,
+    symbols_dir: Option<&Path>,
+) -> Result<Vec<PathBuf>> {
+    let installed = cmake::installed_files(build_dir);
+    let mut symbol_files = Vec::new();
+    for plugin in plugins {
+        for library in installed_copies(plugin, &installed) {
+            let (split, suffix): (fn(&Path, &Path) -> Result<()>, _) =
+                match library.extension().and_then(|ext| ext.to_str()) {
+                    Some("so") => (split_elf, ".debug"),
+                    Some("dylib") => (split_macho, ".dSYM"),
+                    _ => continue,
+                };
+            let dir = match symbols_dir.or_else(|| library.parent()) {
+                Some(dir) => dir,
+                None => continue,
+            };
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create directory {}", dir.display()))?;
+            let mut name = library.file_name().unwrap_or_default().to_owned();
+            name.push(suffix);
+            let symbols = dir.join(name);
+            split(library, &symbols)?;
+            symbol_files.push(symbols);
+        }
+    }
+    Ok(symbol_files)
+}
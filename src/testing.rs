@@ -0,0 +1,353 @@
+//! Integration tests of the plugin run with hython using `cargo hdk test`.
+//!
+//! Tests are Python scripts named `test_*.py` in the test directory (`hdk/tests` by default) or
+//! its subdirectories. Each script is run by its own hython process with the plugin loaded from
+//! the build directory, and fails if hython exits with a non-zero status, e.g. because of an
+//! uncaught exception or a failed assertion. Other Python files in the test directory can be
+//! imported by the tests as helper modules.
+//!
+//! Golden geometry tests are declared in the `snapshots` table of the configuration. Each one
+//! loads a scene and/or runs a script building a node network, cooks a SOP node and compares the
+//! geometry with a checked-in golden file using a driver script run by hython.
+//!
+//! Render tests are declared in the `renders` table and run with `--render`. Each one renders a
+//! ROP headlessly with the plugin loaded and optionally compares the downscaled image with a
+//! reference, since rendering is slow and needs a render license.
+
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{Context, Result};
+use log::*;
+
+use crate::config::{Render, Snapshot};
+use crate::dso_errors;
+use crate::launch::App;
+
+/// Script cooking and comparing the geometry of snapshot tests.
+const SNAPSHOT_DRIVER: &str = include_str!("snapshot.py");
+
+/// Script rendering and comparing the images of render tests.
+const RENDER_DRIVER: &str = include_str!("render.py");
+
+/// Default tolerance of floating point attribute values in snapshot tests.
+const DEFAULT_TOLERANCE: f64 = 1e-6;
+
+/// Default tolerance of channel values of the downscaled images in render tests.
+const DEFAULT_RENDER_TOLERANCE: f64 = 0.02;
+
+/// Default width of the downscaled images compared in render tests.
+const DEFAULT_COMPARE_WIDTH: u32 = 64;
+
+/// A test run by hython.
+pub struct Test {
+    /// Name printed in the test results.
+    pub name: String,
+    /// Script run by hython.
+    pub script: PathBuf,
+    /// Environment describing the test case.
+    pub envs: Vec<(&'static str, OsString)>,
+}
+
+impl Test {
+    /// A test script, named by its path relative to `root`.
+    pub fn script(script: PathBuf, root: &Path) -> Test {
+        Test {
+            name: script
+                .strip_prefix(root)
+                .unwrap_or(&script)
+                .display()
+                .to_string(),
+            script,
+            envs: Vec::new(),
+        }
+    }
+}
+
+/// Returns true if the given file is a test script.
+fn is_test(path: &Path) -> bool {
+    let name = path.file_name().and_then(|name| name.to_str());
+    matches!(name, Some(name) if name.starts_with("test_") && name.ends_with(".py"))
+}
+
+/// Find all test scripts in the given directory and its subdirectories, sorted by path.
+///
+/// A missing directory has no tests.
+pub fn discover(dir: &Path, root: &Path) -> Result<Vec<Test>> {
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut tests = Vec::new();
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let entries = fs::read_dir(&dir)
+            .with_context(|| format!("Failed to read test directory {}", dir.display()))?;
+        for entry in entries {
+            let path = entry?.path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else if is_test(&path) {
+                tests.push(path);
+            }
+        }
+    }
+    tests.sort();
+    Ok(tests
+        .into_iter()
+        .map(|test| Test::script(test, root))
+        .collect())
+}
+
+/// The tests comparing the geometry of the configured snapshots with their golden files.
+///
+/// With `update`, the golden files are overwritten with the cooked geometry instead.
+pub fn snapshots(
+    snapshots: &BTreeMap<String, Snapshot>,
+    driver: &Path,
+    update: bool,
+) -> Result<Vec<Test>> {
+    let mut tests = Vec::new();
+    for (name, snapshot) in snapshots {
+        if snapshot.hip.is_none() && snapshot.script.is_none() {
+            bail!(
+                "Snapshot '{}' needs a 'hip' file or a 'script' building the node network.",
+                name
+            );
+        }
+        let tolerances: Vec<_> = snapshot
+            .tolerances
+            .iter()
+            .map(|(attrib, tolerance)| format!("{}={}", attrib, tolerance))
+            .collect();
+        let mut envs = vec![
+            ("CARGO_HDK_SNAPSHOT_NODE", OsString::from(&snapshot.node)),
+            (
+                "CARGO_HDK_SNAPSHOT_GOLDEN",
+                snapshot.golden.clone().into_os_string(),
+            ),
+            (
+                "CARGO_HDK_SNAPSHOT_TOLERANCE",
+                snapshot
+                    .tolerance
+                    .unwrap_or(DEFAULT_TOLERANCE)
+                    .to_string()
+                    .into(),
+            ),
+            ("CARGO_HDK_SNAPSHOT_TOLERANCES", tolerances.join(",").into()),
+            (
+                "CARGO_HDK_SNAPSHOT_UPDATE",
+                if update { "1" } else { "0" }.into(),
+            ),
+        ];
+        if let Some(hip) = &snapshot.hip {
+            envs.push(("CARGO_HDK_SNAPSHOT_HIP", hip.clone().into_os_string()));
+        }
+        if let Some(script) = &snapshot.script {
+            envs.push(("CARGO_HDK_SNAPSHOT_SCRIPT", script.clone().into_os_string()));
+        }
+        tests.push(Test {
+            name: format!("snapshot {}", name),
+            script: driver.to_path_buf(),
+            envs,
+        });
+    }
+    Ok(tests)
+}
+
+/// The tests rendering the configured ROPs, writing the images to `output_dir`.
+///
+/// With `update`, the reference images are overwritten with the renders instead.
+pub fn renders(
+    renders: &BTreeMap<String, Render>,
+    driver: &Path,
+    output_dir: &Path,
+    update: bool,
+) -> Result<Vec<Test>> {
+    let mut tests = Vec::new();
+    for (name, render) in renders {
+        if render.hip.is_none() && render.script.is_none() {
+            bail!(
+                "Render '{}' needs a 'hip' file or a 'script' building the node network.",
+                name
+            );
+        }
+        if render.compare_width == Some(0) {
+            bail!("Render '{}' needs a 'compare_width' of at least 1.", name);
+        }
+        // Render to the format of the reference, so it can be updated by copying the image.
+        let extension = render
+            .reference
+            .as_deref()
+            .and_then(Path::extension)
+            .and_then(|ext| ext.to_str())
+            .unwrap_or("exr");
+        let output = output_dir.join(format!("{}.{}", name, extension));
+        let mut envs = vec![
+            ("CARGO_HDK_RENDER_ROP", OsString::from(&render.rop)),
+            ("CARGO_HDK_RENDER_OUTPUT", output.into_os_string()),
+            (
+                "CARGO_HDK_RENDER_TOLERANCE",
+                render
+                    .tolerance
+                    .unwrap_or(DEFAULT_RENDER_TOLERANCE)
+                    .to_string()
+                    .into(),
+            ),
+            (
+                "CARGO_HDK_RENDER_COMPARE_WIDTH",
+                render
+                    .compare_width
+                    .unwrap_or(DEFAULT_COMPARE_WIDTH)
+                    .to_string()
+                    .into(),
+            ),
+            (
+                "CARGO_HDK_RENDER_UPDATE",
+                if update { "1" } else { "0" }.into(),
+            ),
+        ];
+        if let Some(hip) = &render.hip {
+            envs.push(("CARGO_HDK_RENDER_HIP", hip.clone().into_os_string()));
+        }
+        if let Some(script) = &render.script {
+            envs.push(("CARGO_HDK_RENDER_SCRIPT", script.clone().into_os_string()));
+        }
+        if let Some(frame) = render.frame {
+            envs.push(("CARGO_HDK_RENDER_FRAME", frame.to_string().into()));
+        }
+        if let Some([width, height]) = render.resolution {
+            envs.push((
+                "CARGO_HDK_RENDER_RESOLUTION",
+                format!("{}x{}", width, height).into(),
+            ));
+        }
+        if let Some(reference) = &render.reference {
+            envs.push((
+                "CARGO_HDK_RENDER_REFERENCE",
+                reference.clone().into_os_string(),
+            ));
+        }
+        tests.push(Test {
+            name: format!("render {}", name),
+            script: driver.to_path_buf(),
+            envs,
+        });
+    }
+    Ok(tests)
+}
+
+/// Write the script run by snapshot tests.
+pub fn write_snapshot_driver(path: &Path) -> Result<()> {
+    fs::write(path, SNAPSHOT_DRIVER)
+        .with_context(|| format!("Failed to write snapshot test driver {}", path.display()))
+}
+
+/// Write the script run by render tests.
+pub fn write_render_driver(path: &Path) -> Result<()> {
+    fs::write(path, RENDER_DRIVER)
+        .with_context(|| format!("Failed to write render test driver {}", path.display()))
+}
+
+/// Run the given tests with hython, printing a summary in the style of 'cargo test'.
+///
+/// The output of each test is written to a log file in `log_dir` and printed for failed tests.
+/// A test also fails if hython failed to load one of the given plugin libraries. Returns an error
+/// if any of the tests failed.
+pub fn run(
+    hfs: &str,
+    tests: &[Test],
+    dir: &Path,
+    log_dir: &Path,
+    envs: &[(&str, OsString)],
+    plugins: &[PathBuf],
+) -> Result<()> {
+    let hython = App::Hython.path(hfs);
+    fs::create_dir_all(log_dir)
+        .with_context(|| format!("Failed to create log directory: {}", log_dir.display()))?;
+
+    println!();
+    println!(
+        "running {} test{}",
+        tests.len(),
+        if tests.len() == 1 { "" } else { "s" }
+    );
+    let mut failures = Vec::new();
+    for test in tests {
+        let name = &test.name;
+        debug!("Running {} {}", hython.display(), test.script.display());
+        let output = Command::new(&hython)
+            .arg(&test.script)
+            .current_dir(dir)
+            .envs(
+                envs.iter()
+                    .chain(&test.envs)
+                    .map(|(key, value)| (key, value)),
+            )
+            .envs(dso_errors::env())
+            .output()
+            .with_context(|| format!("Failed to run {}", hython.display()))?;
+
+        let mut log = output.stdout;
+        log.extend_from_slice(&output.stderr);
+        let log_name = name.replace(['/', '\\', ' '], "_");
+        let log_path = log_dir.join(format!("{}.log", log_name));
+        fs::write(&log_path, &log)
+            .with_context(|| format!("Failed to write test log {}", log_path.display()))?;
+
+        let log = String::from_utf8_lossy(&log);
+        let load_errors = dso_errors::scan(&log, plugins);
+        if output.status.success() && load_errors.is_empty() {
+            println!("test {} ... ok", name);
+        } else {
+            println!("test {} ... FAILED", name);
+            let mut report = String::new();
+            if !load_errors.is_empty() {
+                report.push_str("hython failed to load the plugin:\n");
+                for error in &load_errors {
+                    report.push_str(&format!("  {}\n", error));
+                }
+                report.push('\n');
+            }
+            report.push_str(&log);
+            failures.push((name, output.status, report));
+        }
+    }
+
+    if !failures.is_empty() {
+        println!();
+        println!("failures:");
+        for (name, status, log) in &failures {
+            println!();
+            println!("---- {} ({}) ----", name, status);
+            print!("{}", log);
+        }
+        println!();
+        println!("failures:");
+        for (name, _, _) in &failures {
+            println!("    {}", name);
+        }
+    }
+
+    let passed = tests.len() - failures.len();
+    println!();
+    println!(
+        "test result: {}. {} passed; {} failed",
+        if failures.is_empty() { "ok" } else { "FAILED" },
+        passed,
+        failures.len()
+    );
+    println!();
+
+    if !failures.is_empty() {
+        bail!(
+            "{} of {} tests failed. See the logs in {}",
+            failures.len(),
+            tests.len(),
+            log_dir.display()
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,111 @@
+//! Universal macOS builds with `--universal`, running natively on Apple silicon and Intel Macs.
+//!
+//! The Rust code is built once for each architecture and the libraries are merged with 'lipo'
+//! into universal libraries in '<target dir>/universal-apple-darwin/<profile>', while CMake builds
+//! the C++ code for both architectures at once with 'CMAKE_OSX_ARCHITECTURES', linking the merged
+//! libraries into a universal plugin.
+
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use anyhow::{Context, Result};
+use cargo_metadata::camino::{Utf8Path, Utf8PathBuf};
+use log::*;
+
+use crate::macos;
+use crate::tools;
+
+/// Name of the target directory of the merged libraries, which is also used in place of the
+/// target triple for naming the build directory.
+pub const TARGET: &str = "universal-apple-darwin";
+
+/// Rust targets of the architectures of a universal build.
+pub const TARGETS: &[&str] = &["aarch64-apple-darwin", "x86_64-apple-darwin"];
+
+/// Architectures of a universal build as named by CMake and 'lipo'.
+const ARCHITECTURES: &[&str] = &["arm64", "x86_64"];
+
+/// CMake arguments building the C++ code for all architectures of a universal build.
+pub fn cmake_args() -> Vec<String> {
+    vec![format!(
+        "-DCMAKE_OSX_ARCHITECTURES={}",
+        ARCHITECTURES.join(";")
+    )]
+}
+
+/// Check that the standard libraries of all targets of a universal build are installed, if the
+/// toolchain is managed by rustup.
+pub fn check_targets(toolchain: Option<&str>) -> Result<()> {
+    let missing = macos::missing_targets(toolchain, TARGETS);
+    if !missing.is_empty() {
+        bail!(
+            "A universal build needs the Rust standard library of {}. Install it with 'rustup target add {}'.",
+            missing.join(" and "),
+            missing.join(" ")
+        );
+    }
+    Ok(())
+}
+
+/// Warn if the Houdini libraries in `hfs` don't support all architectures of a universal build,
+/// since the plugin can't be linked for the missing ones.
+pub fn check_houdini(hfs: &Path) {
+    let library = macos::houdini_libraries(hfs).join("libHoudiniUT.dylib");
+    let archs = match macos::architectures(&library) {
+        Some(archs) => archs,
+        None => return,
+    };
+    let missing: Vec<_> = ARCHITECTURES
+        .iter()
+        .filter(|arch| !archs.iter().any(|a| a == *arch))
+        .copied()
+        .collect();
+    if !missing.is_empty() {
+        warn!(
+            "The Houdini installation at {} isn't a universal build and has no {} libraries, so the plugin can't be linked for {}. Use a universal build of Houdini, or build for one architecture without '--universal'.",
+            hfs.display(),
+            missing.join(" or "),
+            missing.join(" and ")
+        );
+    }
+}
+
+/// Merge the given libraries, built for each of the `TARGETS`, into a universal library at
+/// `output` with 'lipo'. The library is left alone if it is newer than all of them.
+pub fn merge(libraries: &[&Utf8Path], output: &Utf8Path) -> Result<()> {
+    let modified = |path: &Utf8Path| path.metadata().and_then(|m| m.modified()).ok();
+    if let Some(merged) = modified(output) {
+        if libraries
+            .iter()
+            .all(|lib| modified(lib).is_some_and(|time| time <= merged))
+        {
+            debug!("Universal library {} is up to date", output);
+            return Ok(());
+        }
+    }
+    if let Some(dir) = output.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir))?;
+    }
+    let lipo = tools::find_program("lipo").context(
+        "Couldn't find 'lipo' on the 'PATH'. It is installed with the Xcode command line tools.",
+    )?;
+    debug!("Merging {:?} into {}", libraries, output);
+    let status = Command::new(lipo)
+        .arg("-create")
+        .arg("-output")
+        .arg(output)
+        .args(libraries)
+        .status()
+        .context("Failed to run lipo")?;
+    if !status.success() {
+        bail!("lipo failed to merge {:?} into {}", libraries, output);
+    }
+    Ok(())
+}
+
+/// The path of the universal library merged from the libraries with the given file name, in the
+/// directory of the universal target for `profile`.
+pub fn merged_path(target_dir: &Utf8Path, profile: &str, file_name: &str) -> Utf8PathBuf {
+    target_dir.join(TARGET).join(profile).join(file_name)
+}
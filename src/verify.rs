@@ -0,0 +1,332 @@
+//! Sanity checks of the built plugin with `cargo hdk verify`.
+//!
+//! Each plugin library in the build directory is loaded in a child process with the Houdini
+//! libraries on the loader path, resolving all symbols right away. This catches missing symbols,
+//! e.g. from a Rust function that isn't exported or a Houdini library that isn't linked, which
+//! Houdini would otherwise only report as a warning when it starts. The child then checks that the
+//! plugin exports the DSO version tag and at least one of the functions Houdini calls to register
+//! its contents.
+//!
+//! The child is cargo-hdk itself, started with the path of the library in `CARGO_HDK_VERIFY_LOAD`,
+//! so a plugin crashing while it is loaded doesn't take down the build.
+
+use std::env;
+use std::ffi::OsString;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use anyhow::{Context, Result};
+use log::*;
+
+use crate::cmake;
+use crate::tools;
+
+/// Environment variable making cargo-hdk load the given library instead of running a build.
+pub const LOAD_ENV: &str = "CARGO_HDK_VERIFY_LOAD";
+
+/// The function every plugin exports to tell Houdini which version it was built for, defined by
+/// including 'UT/UT_DSOVersion.h'.
+const VERSION_TAG: &str = "HoudiniDSOVersion";
+
+/// Functions through which Houdini registers the contents of a plugin.
+pub const REGISTER_FUNCTIONS: &[&str] = &[
+    "HoudiniDSOInit",
+    "newSopOperator",
+    "newObjectOperator",
+    "newDriverOperator",
+    "newDopOperator",
+    "newChopOperator",
+    "newChopNetOperator",
+    "newCop2Operator",
+    "newVopOperator",
+    "newShopOperator",
+    "newLopOperator",
+    "newTopOperator",
+    "newVEXOp",
+    "newGeometryIO",
+    "newGeometryPrim",
+    "CMDextendLibrary",
+];
+
+#[cfg(unix)]
+mod loader {
+    use std::ffi::{CStr, CString};
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::Path;
+
+    /// Load the library, resolving all of its symbols.
+    pub fn open(path: &Path) -> Result<*mut libc::c_void, String> {
+        // The path is passed on as it is, even if it isn't valid Unicode.
+        let path = CString::new(path.as_os_str().as_bytes())
+            .map_err(|_| "the library path contains a null byte".to_string())?;
+        let handle = unsafe { libc::dlopen(path.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL) };
+        if handle.is_null() {
+            Err(last_error())
+        } else {
+            Ok(handle)
+        }
+    }
+
+    /// Returns true if the library exports the given symbol.
+    pub fn has_symbol(handle: *mut libc::c_void, name: &str) -> bool {
+        let name = CString::new(name).expect("Symbol names contain no null bytes");
+        !unsafe { libc::dlsym(handle, name.as_ptr()) }.is_null()
+    }
+
+    fn last_error() -> String {
+        let error = unsafe { libc::dlerror() };
+        if error.is_null() {
+            "unknown error".to_string()
+        } else {
+            unsafe { CStr::from_ptr(error) }
+                .to_string_lossy()
+                .into_owned()
+        }
+    }
+}
+
+#[cfg(windows)]
+mod loader {
+    use std::ffi::{c_void, CString};
+    use std::os::raw::c_char;
+    use std::os::windows::ffi::OsStrExt;
+    use std::path::Path;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn LoadLibraryW(name: *const u16) -> *mut c_void;
+        fn GetProcAddress(module: *mut c_void, name: *const c_char) -> *mut c_void;
+        fn GetLastError() -> u32;
+    }
+
+    /// Load the library along with its dependencies.
+    pub fn open(path: &Path) -> Result<*mut c_void, String> {
+        // The wide version of the loader takes any path, unlike the one using the ANSI code page.
+        let mut wide: Vec<u16> = path.as_os_str().encode_wide().collect();
+        if wide.contains(&0) {
+            return Err("the library path contains a null character".to_string());
+        }
+        wide.push(0);
+        let handle = unsafe { LoadLibraryW(wide.as_ptr()) };
+        if handle.is_null() {
+            let code = unsafe { GetLastError() };
+            Err(match code {
+                126 => "a DLL it depends on was not found (error 126)".to_string(),
+                127 => "a function it imports was not found in its DLLs (error 127)".to_string(),
+                193 => "it is not a valid DLL for this architecture (error 193)".to_string(),
+                _ => format!("error {}", code),
+            })
+        } else {
+            Ok(handle)
+        }
+    }
+
+    /// Returns true if the library exports the given symbol.
+    pub fn has_symbol(handle: *mut c_void, name: &str) -> bool {
+        let name = CString::new(name).expect("Symbol names contain no null bytes");
+        !unsafe { GetProcAddress(handle, name.as_ptr()) }.is_null()
+    }
+}
+
+/// Load the library in this process and print the results for the parent process.
+///
+/// Each line of the output is either 'error <message>' or 'missing <symbol>' or
+/// 'registers <function>'.
+pub fn load(path: &Path) -> Result<()> {
+    let handle = match loader::open(path) {
+        Ok(handle) => handle,
+        Err(err) => {
+            println!("error {}", err.replace('\n', " "));
+            std::process::exit(1);
+        }
+    };
+    if !loader::has_symbol(handle, VERSION_TAG) {
+        println!("missing {}", VERSION_TAG);
+    }
+    for function in REGISTER_FUNCTIONS {
+        if loader::has_symbol(handle, function) {
+            println!("registers {}", function);
+        }
+    }
+    Ok(())
+}
+
+/// The plugin libraries in the 'dso' subdirectory of the binary directory or the binary directory
+/// itself, i.e. the libraries there built by CMake library targets.
+pub fn find_plugins(binary_dir: &Path) -> Vec<PathBuf> {
+    let extension = if cfg!(windows) {
+        "dll"
+    } else if cfg!(target_os = "macos") {
+        "dylib"
+    } else {
+        "so"
+    };
+    let mut plugins: Vec<_> = [binary_dir.join("dso"), binary_dir.to_path_buf()]
+        .iter()
+        .filter_map(|dir| dir.read_dir().ok())
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file() && matches!(path.extension(), Some(ext) if ext == extension))
+        .collect();
+    cmake::retain_library_targets(binary_dir, &mut plugins);
+    plugins.sort();
+    plugins
+}
+
+/// The loader path variable making the Houdini libraries of `hfs` available to a plugin.
+fn library_path(hfs: &str) -> (&'static str, OsString) {
+    let hfs = Path::new(hfs);
+    let (name, dir) = if cfg!(windows) {
+        ("PATH", hfs.join("bin"))
+    } else if cfg!(target_os = "macos") {
+        // 'HFS' is the 'Resources' directory of the framework, next to 'Libraries'.
+        (
+            "DYLD_LIBRARY_PATH",
+            hfs.parent().unwrap_or(hfs).join("Libraries"),
+        )
+    } else {
+        ("LD_LIBRARY_PATH", hfs.join("dsolib"))
+    };
+    let mut paths = vec![dir];
+    if let Some(path) = env::var_os(name) {
+        paths.extend(env::split_paths(&path));
+    }
+    (name, env::join_paths(paths).unwrap_or_default())
+}
+
+/// Demangle the given names with 'c++filt', keeping the names as they are if it isn't available.
+pub fn demangle_names(names: Vec<String>) -> Vec<String> {
+    let cxxfilt = match tools::find_program("c++filt") {
+        Some(cxxfilt) => cxxfilt,
+        None => return names,
+    };
+    let child = Command::new(cxxfilt)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn();
+    let mut child = match child {
+        Ok(child) => child,
+        Err(_) => return names,
+    };
+    // Write from another thread, since 'c++filt' may fill the output pipe before reading all
+    // of its input.
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let input = names.join("\n");
+    let writer = std::thread::spawn(move || stdin.write_all(input.as_bytes()));
+    let output = child.wait_with_output();
+    let _ = writer.join();
+    match output {
+        Ok(output) if output.status.success() => {
+            let demangled: Vec<_> = String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .map(|line| line.to_string())
+                .collect();
+            if demangled.len() == names.len() {
+                demangled
+            } else {
+                names
+            }
+        }
+        _ => names,
+    }
+}
+
+/// Replace the mangled C++ names in the message with their demangled form using 'c++filt', if
+/// available.
+pub fn demangle(message: &str) -> String {
+    let mangled: Vec<String> = message
+        .split(|c: char| c.is_whitespace() || c == '\'' || c == '"' || c == ',')
+        .filter(|word| word.starts_with("_Z") || word.starts_with("__Z"))
+        .map(|word| word.to_string())
+        .collect();
+    if mangled.is_empty() {
+        return message.to_string();
+    }
+    let demangled = demangle_names(mangled.clone());
+    mangled
+        .iter()
+        .zip(&demangled)
+        .filter(|(mangled, demangled)| mangled != demangled)
+        .fold(message.to_string(), |message, (mangled, demangled)| {
+            message.replace(mangled.as_str(), &format!("{} ({})", demangled, mangled))
+        })
+}
+
+/// Load each plugin in a child process with the given environment and report the problems found.
+///
+/// Returns an error if any plugin fails to load or misses the DSO version tag.
+pub fn run(hfs: &str, binary_dir: &Path, envs: &[(&str, OsString)]) -> Result<()> {
+    let plugins = find_plugins(binary_dir);
+    if plugins.is_empty() {
+        bail!(
+            "No plugin libraries found in {0}/dso or {0}. Use 'houdini_configure_target' in the 'CMakeLists.txt' to place the plugin there.",
+            binary_dir.display()
+        );
+    }
+    let exe = env::current_exe().context("Failed to find the cargo-hdk executable")?;
+    let (path_var, path) = library_path(hfs);
+
+    let mut failed = 0;
+    println!();
+    for plugin in &plugins {
+        debug!("Loading {} with {}={:?}", plugin.display(), path_var, path);
+        let output = Command::new(&exe)
+            .envs(envs.iter().map(|(key, value)| (key, value)))
+            .env(LOAD_ENV, plugin)
+            .env(path_var, &path)
+            .output()
+            .context("Failed to run cargo-hdk to load the plugin")?;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let mut problems = Vec::new();
+        let mut registers = Vec::new();
+        for line in stdout.lines() {
+            if let Some(error) = line.strip_prefix("error ") {
+                problems.push(format!("failed to load: {}", demangle(error)));
+            } else if let Some(symbol) = line.strip_prefix("missing ") {
+                problems.push(format!(
+                    "doesn't export '{}'; include 'UT/UT_DSOVersion.h' in one of its sources",
+                    symbol
+                ));
+            } else if let Some(function) = line.strip_prefix("registers ") {
+                registers.push(function);
+            }
+        }
+        if problems.is_empty() && !output.status.success() {
+            problems.push(format!(
+                "crashed while loading ({}): {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+
+        if problems.is_empty() {
+            println!("verify {} ... ok", plugin.display());
+            if registers.is_empty() {
+                warn!(
+                    "{} exports no function Houdini registers operators or other contents with, like 'newSopOperator'",
+                    plugin.display()
+                );
+            } else {
+                debug!("{} exports {}", plugin.display(), registers.join(", "));
+            }
+        } else {
+            failed += 1;
+            println!("verify {} ... FAILED", plugin.display());
+            for problem in problems {
+                println!("    {}", problem);
+            }
+        }
+    }
+    println!();
+
+    if failed > 0 {
+        bail!(
+            "{} of {} plugins failed verification",
+            failed,
+            plugins.len()
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,177 @@
+//! Continuous rebuilds with `cargo hdk watch`.
+//!
+//! The crate sources and the HDK plugin directory are watched for changes. After a change to the
+//! Rust code, the whole build is run again, while a change to the HDK plugin only reruns the CMake
+//! build using '--hdk-only'. Each build is run by a new cargo-hdk process with the original
+//! arguments, so a failed build doesn't stop watching.
+
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use log::*;
+use notify_debouncer_mini::new_debouncer;
+use notify_debouncer_mini::notify::RecursiveMode;
+
+/// Time without further changes before a build is started, so that saving several files at once
+/// results in a single build.
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// The steps of the build that need to be rerun after a change.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Rebuild {
+    /// Build the Rust code and the HDK plugin.
+    All,
+    /// Only build the HDK plugin.
+    Hdk,
+}
+
+/// Paths whose changes require a rebuild, and which build steps they affect.
+struct Watched {
+    root: PathBuf,
+    rust: Vec<PathBuf>,
+    hdk_dir: PathBuf,
+    target_dir: PathBuf,
+}
+
+impl Watched {
+    /// The build steps affected by a change of the given path, if any.
+    fn rebuild(&self, path: &Path) -> Option<Rebuild> {
+        let relative = path.strip_prefix(&self.root).unwrap_or(path);
+        if path.starts_with(&self.target_dir) || is_hidden(relative) {
+            return None;
+        }
+        if self.rust.iter().any(|rust| path.starts_with(rust)) {
+            return Some(Rebuild::All);
+        }
+        if path.starts_with(&self.hdk_dir) && !in_build_dir(path, &self.hdk_dir) {
+            return Some(Rebuild::Hdk);
+        }
+        None
+    }
+}
+
+/// Returns true for hidden files, backups like editor swap files, and files in hidden directories.
+fn is_hidden(path: &Path) -> bool {
+    path.components().any(|c| {
+        let name = c.as_os_str().to_string_lossy();
+        (name.starts_with('.') && name != "." && name != "..") || name.ends_with('~')
+    })
+}
+
+/// Returns true if the path is inside a CMake build directory below `root`.
+fn in_build_dir(path: &Path, root: &Path) -> bool {
+    path.ancestors()
+        .skip(1)
+        .take_while(|dir| dir.starts_with(root))
+        .any(|dir| dir.join("CMakeCache.txt").exists())
+}
+
+/// The arguments of this cargo-hdk process without the 'watch' command.
+fn build_args() -> Vec<OsString> {
+    let mut args: Vec<_> = std::env::args_os().skip(1).collect();
+    if let Some(pos) = args.iter().position(|arg| arg == "watch") {
+        args.remove(pos);
+    }
+    args
+}
+
+/// Run a build with the given steps in a new cargo-hdk process and print a summary.
+fn build(rebuild: Rebuild) -> Result<()> {
+    let exe = std::env::current_exe().context("Failed to find the cargo-hdk executable")?;
+    let mut cmd = Command::new(exe);
+    if rebuild == Rebuild::Hdk {
+        cmd.arg("--hdk-only");
+    }
+    cmd.args(build_args());
+    debug!("Running {:?}", cmd);
+
+    let start = Instant::now();
+    let status = cmd.status().context("Failed to run cargo-hdk")?;
+    let elapsed = start.elapsed().as_secs_f64();
+    if status.success() {
+        println!("[watch] Build succeeded in {:.1}s.", elapsed);
+    } else {
+        println!(
+            "[watch] Build failed with {} after {:.1}s.",
+            status, elapsed
+        );
+    }
+    Ok(())
+}
+
+/// Build once, then rebuild whenever the Rust sources or the HDK plugin change until interrupted.
+pub fn run(manifest_dir: &Path, hdk_dir: &Path, target_dir: &Path) -> Result<()> {
+    let watched = Watched {
+        root: manifest_dir.to_path_buf(),
+        rust: vec![
+            manifest_dir.join("src"),
+            manifest_dir.join("Cargo.toml"),
+            manifest_dir.join("build.rs"),
+        ],
+        hdk_dir: hdk_dir.to_path_buf(),
+        target_dir: target_dir.to_path_buf(),
+    };
+
+    let (tx, rx) = mpsc::channel();
+    let mut debouncer = new_debouncer(DEBOUNCE, tx).context("Failed to watch for changes")?;
+    let mut watching = Vec::new();
+    for path in watched.rust.iter().chain(Some(&watched.hdk_dir)) {
+        if path.exists() {
+            debouncer
+                .watcher()
+                .watch(path, RecursiveMode::Recursive)
+                .with_context(|| format!("Failed to watch {}", path.display()))?;
+            watching.push(path.display().to_string());
+        }
+    }
+
+    println!("[watch] Building.");
+    build(Rebuild::All)?;
+    println!("[watch] Watching {} for changes.", watching.join(", "));
+
+    for events in rx {
+        let events = match events {
+            Ok(events) => events,
+            Err(err) => {
+                warn!("Error while watching for changes: {}", err);
+                continue;
+            }
+        };
+        let changed: Vec<_> = events
+            .iter()
+            .filter_map(|event| Some((&event.path, watched.rebuild(&event.path)?)))
+            .collect();
+        if changed.is_empty() {
+            continue;
+        }
+        let rebuild = if changed.iter().any(|(_, rebuild)| *rebuild == Rebuild::All) {
+            Rebuild::All
+        } else {
+            Rebuild::Hdk
+        };
+        let first = changed[0].0;
+        let first = first.strip_prefix(manifest_dir).unwrap_or(first);
+        let more = if changed.len() > 1 {
+            format!(" and {} more", changed.len() - 1)
+        } else {
+            String::new()
+        };
+        let steps = match rebuild {
+            Rebuild::All => "the Rust code and the HDK plugin",
+            Rebuild::Hdk => "the HDK plugin",
+        };
+        println!(
+            "[watch] {}{} changed, rebuilding {}.",
+            first.display(),
+            more,
+            steps
+        );
+        build(rebuild)?;
+        println!("[watch] Waiting for changes.");
+    }
+    Ok(())
+}
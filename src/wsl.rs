@@ -0,0 +1,161 @@
+//! Builds for a Windows Houdini installation from inside WSL.
+//!
+//! In WSL, 'HFS' may point at the Windows installation of Houdini through '/mnt/c/...'. Building
+//! against it with the Linux tools of WSL would produce a Linux library that Windows Houdini
+//! can't load, if the HDK headers of the Windows installation compiled with GCC at all. Instead,
+//! cargo-hdk runs itself on the Windows side with the same arguments through 'cargo.exe', which
+//! builds with MSVC and CMake like on Windows, and can launch Windows Houdini for 'cargo hdk run'
+//! and 'cargo hdk test'. When that isn't possible, the build fails with an explanation.
+
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{Context, Result};
+use log::*;
+
+use crate::tools;
+
+/// Returns true if cargo-hdk runs in the Windows Subsystem for Linux.
+pub fn is_wsl() -> bool {
+    if !cfg!(target_os = "linux") {
+        return false;
+    }
+    env::var_os("WSL_DISTRO_NAME").is_some()
+        || Path::new("/proc/sys/fs/binfmt_misc/WSLInterop").exists()
+        || fs::read_to_string("/proc/sys/kernel/osrelease")
+            .is_ok_and(|release| release.to_lowercase().contains("microsoft"))
+}
+
+/// Returns true if `hfs` is a Windows installation of Houdini, like
+/// '/mnt/c/Program Files/Side Effects Software/Houdini 20.0.547'.
+pub fn is_windows_houdini(hfs: &Path) -> bool {
+    ["houdini.exe", "hython.exe", "hcustom.exe"]
+        .iter()
+        .any(|program| hfs.join("bin").join(program).is_file())
+}
+
+/// The Windows path of the given WSL path according to 'wslpath', like 'C:\Users' for
+/// '/mnt/c/Users', or '\\wsl.localhost\...' for a path in the Linux file system.
+fn windows_path(path: &Path) -> Option<String> {
+    let output = Command::new("wslpath").arg("-w").arg(path).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let path = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (!path.is_empty()).then_some(path)
+}
+
+/// The arguments of this process for 'cargo.exe', with the absolute paths among them, also as
+/// values of '--option=path', converted to Windows paths.
+fn forwarded_args() -> Vec<OsString> {
+    let convert = |value: &str| {
+        if value.starts_with('/') {
+            windows_path(Path::new(value))
+        } else {
+            None
+        }
+    };
+    let mut args: Vec<OsString> = env::args_os()
+        .skip(1)
+        .map(|arg| {
+            let text = arg.to_string_lossy();
+            if let Some(path) = convert(&text) {
+                return path.into();
+            }
+            if let Some((option, value)) = text.split_once('=') {
+                if let Some(path) = convert(value) {
+                    return format!("{}={}", option, path).into();
+                }
+            }
+            arg
+        })
+        .collect();
+    // cargo passes the subcommand on, but cargo-hdk may be run directly as well.
+    if args.first().is_none_or(|arg| arg != "hdk") {
+        args.insert(0, "hdk".into());
+    }
+    args
+}
+
+/// The Windows cargo found through the Windows 'PATH' appended to the 'PATH' of WSL, along with
+/// whether cargo-hdk is installed next to it.
+fn windows_cargo() -> Option<(PathBuf, bool)> {
+    let cargo = tools::find_program("cargo.exe")?;
+    let has_hdk = cargo.with_file_name("cargo-hdk.exe").is_file();
+    Some((cargo, has_hdk))
+}
+
+/// Run cargo-hdk with the arguments of this process on the Windows side of WSL, building the
+/// crate in `workspace_root` for the Windows Houdini installation at `hfs`.
+pub fn run(hfs: &Path, workspace_root: &Path) -> Result<()> {
+    let explanation = format!(
+        "HFS={} is a Windows installation of Houdini, and a plugin built with the Linux tools of WSL can't be loaded by it",
+        hfs.display()
+    );
+    let (cargo, has_hdk) = match windows_cargo() {
+        Some(found) => found,
+        None => bail!("{}. Building on the Windows side needs 'cargo.exe' on the 'PATH', which WSL appends the Windows 'PATH' to unless 'appendWindowsPath' is disabled in '/etc/wsl.conf'. Install Rust on Windows, or point 'HFS' at a Linux installation of Houdini in WSL.", explanation),
+    };
+    if !has_hdk {
+        bail!(
+            "{}. Building on the Windows side with {} needs cargo-hdk on Windows as well; install it with 'cargo.exe install cargo-hdk', or point 'HFS' at a Linux installation of Houdini in WSL.",
+            explanation,
+            cargo.display()
+        );
+    }
+    // MSBuild and CMake can't build in the Linux file system, which Windows sees as a network share.
+    let windows_root = windows_path(workspace_root).unwrap_or_default();
+    if !workspace_root.starts_with("/mnt") || windows_root.starts_with(r"\\") {
+        bail!(
+            "{}. Building on the Windows side needs the crate on a Windows drive, but {} is in the Linux file system of WSL. Move it to a Windows drive, e.g. under '/mnt/c/Users', or point 'HFS' at a Linux installation of Houdini in WSL.",
+            explanation,
+            workspace_root.display()
+        );
+    }
+    let windows_hfs = windows_path(hfs)
+        .with_context(|| format!("Failed to convert {} to a Windows path", hfs.display()))?;
+
+    let mut cmd = Command::new(&cargo);
+    cmd.args(forwarded_args());
+    // Variables only reach Windows processes when listed in 'WSLENV', where '/p' converts paths.
+    let mut wslenv: Vec<String> = env::var("WSLENV")
+        .unwrap_or_default()
+        .split(':')
+        .filter(|entry| !entry.is_empty() && entry.split('/').next() != Some("HFS"))
+        .map(String::from)
+        .collect();
+    cmd.env("HFS", &windows_hfs);
+    wslenv.push("HFS".to_string());
+    for (var, value) in env::vars_os() {
+        let var = var.to_string_lossy();
+        if var.starts_with("CARGO_HDK_")
+            || matches!(
+                &*var,
+                "CARGO_TARGET_DIR" | "CARGO_TERM_COLOR" | "CARGO_TERM_PROGRESS_WHEN" | "RUST_LOG"
+            )
+        {
+            if value.to_string_lossy().starts_with('/') {
+                wslenv.push(format!("{}/p", var));
+            } else {
+                wslenv.push(var.into_owned());
+            }
+        }
+    }
+    cmd.env("WSLENV", wslenv.join(":"));
+
+    info!(
+        "Building on the Windows side of WSL for Houdini at {}.",
+        windows_hfs
+    );
+    debug!("Running {:?}", cmd);
+    let status = cmd
+        .status()
+        .with_context(|| format!("Failed to run {}", cargo.display()))?;
+    if !status.success() {
+        bail!("The build on the Windows side of WSL failed");
+    }
+    Ok(())
+}
@@ -0,0 +1,7 @@
+//! Rust implementation of the {{operator_label}} CHOP.
+
+/// Evaluate the generated channel at the given time in seconds.
+#[no_mangle]
+pub extern "C" fn {{crate_name}}_sample(time: f64) -> f64 {
+    (2.0 * std::f64::consts::PI * time).sin()
+}
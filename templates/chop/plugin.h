@@ -0,0 +1,23 @@
+#pragma once
+
+#include <CHOP/CHOP_Node.h>
+
+class {{class_name}} : public CHOP_Node
+{
+public:
+    static PRM_Template theTemplates[];
+    static OP_Node *myConstructor(OP_Network *net, const char *name, OP_Operator *op)
+    {
+        return new {{class_name}}(net, name, op);
+    }
+
+protected:
+    {{class_name}}(OP_Network *net, const char *name, OP_Operator *op)
+        : CHOP_Node(net, name, op)
+    {
+    }
+
+    ~{{class_name}}() override {}
+
+    OP_ERROR cookMyChop(OP_Context &context) override;
+};
@@ -0,0 +1,8 @@
+//! Rust implementation of the {{operator_label}} DOP.
+
+/// Advance the simulation by `timestep` seconds from `time`, returning `false` on failure.
+#[no_mangle]
+pub extern "C" fn {{crate_name}}_solve(time: f64, timestep: f64) -> bool {
+    let _ = (time, timestep);
+    true
+}
@@ -0,0 +1,25 @@
+#pragma once
+
+#include <GAS/GAS_SubSolver.h>
+#include <GAS/GAS_Utils.h>
+
+class {{class_name}} : public GAS_SubSolver
+{
+protected:
+    explicit {{class_name}}(const SIM_DataFactory *factory)
+        : BaseClass(factory)
+    {
+    }
+
+    ~{{class_name}}() override {}
+
+    bool solveGasSubclass(SIM_Engine &engine, SIM_Object *obj, SIM_Time time,
+                          SIM_Time timestep) override;
+
+private:
+    static const SIM_DopDescription *getDopDescription();
+
+    DECLARE_STANDARD_GETCASTTOTYPE();
+    DECLARE_DATAFACTORY({{class_name}}, GAS_SubSolver, "{{operator_label}}",
+                        getDopDescription());
+};
@@ -0,0 +1,8 @@
+//! Rust implementation of the {{operator_label}} ROP.
+
+/// Render the frame at the given time in seconds, returning `false` on failure.
+#[no_mangle]
+pub extern "C" fn {{crate_name}}_render_frame(time: f64) -> bool {
+    println!("{{operator_label}}: rendering the frame at {:.3}s", time);
+    true
+}
@@ -0,0 +1,25 @@
+#pragma once
+
+#include <ROP/ROP_Node.h>
+
+class {{class_name}} : public ROP_Node
+{
+public:
+    static PRM_Template *buildTemplates();
+    static OP_Node *myConstructor(OP_Network *net, const char *name, OP_Operator *op)
+    {
+        return new {{class_name}}(net, name, op);
+    }
+
+protected:
+    {{class_name}}(OP_Network *net, const char *name, OP_Operator *op)
+        : ROP_Node(net, name, op)
+    {
+    }
+
+    ~{{class_name}}() override {}
+
+    int startRender(int nframes, fpreal tstart, fpreal tend) override;
+    ROP_RENDER_CODE renderFrame(fpreal time, UT_Interrupt *boss) override;
+    ROP_RENDER_CODE endRender() override;
+};
@@ -0,0 +1,20 @@
+//! Rust implementation of the {{operator_label}} SOP.
+
+use std::f32::consts::PI;
+
+/// Compute the positions of `count` points evenly spaced on a unit circle.
+///
+/// # Safety
+///
+/// `positions` must point to `3 * count` writable floats.
+#[no_mangle]
+pub unsafe extern "C" fn {{crate_name}}_point_positions(count: u32, positions: *mut f32) {
+    if positions.is_null() {
+        return;
+    }
+    let positions = std::slice::from_raw_parts_mut(positions, 3 * count as usize);
+    for (i, p) in positions.chunks_exact_mut(3).enumerate() {
+        let angle = 2.0 * PI * i as f32 / count as f32;
+        p.copy_from_slice(&[angle.cos(), 0.0, angle.sin()]);
+    }
+}
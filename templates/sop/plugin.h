@@ -0,0 +1,35 @@
+#pragma once
+
+#include <SOP/SOP_Node.h>
+#include <UT/UT_StringHolder.h>
+
+class {{class_name}} : public SOP_Node
+{
+public:
+    static PRM_Template *buildTemplates();
+    static OP_Node *myConstructor(OP_Network *net, const char *name, OP_Operator *op)
+    {
+        return new {{class_name}}(net, name, op);
+    }
+
+    static const UT_StringHolder theSOPTypeName;
+
+    const SOP_NodeVerb *cookVerb() const override;
+
+protected:
+    {{class_name}}(OP_Network *net, const char *name, OP_Operator *op)
+        : SOP_Node(net, name, op)
+    {
+        // All verb SOPs must manage data IDs to track what's changed
+        // from cook to cook
+        mySopFlags.setManagesDataIDs(true);
+    }
+
+    ~{{class_name}}() override {}
+
+    // Since this SOP implements a verb, cookMySop just delegates to the verb.
+    OP_ERROR cookMySop(OP_Context &context) override
+    {
+        return cookMyselfAsVerb(context);
+    }
+};
@@ -0,0 +1,7 @@
+//! Rust implementation of the '{{operator_name}}' VEX function.
+
+/// Evaluate the function for the given value.
+#[no_mangle]
+pub extern "C" fn {{crate_name}}_eval(x: f64) -> f64 {
+    x * x
+}